@@ -81,8 +81,14 @@ in
       } // cfg.extraEnvironment;
 
       serviceConfig = {
-        Type = if cfg.socketActivation then "notify" else "simple";
-        ExecStart = "${cfg.package}/bin/onix-mcp";
+        # The server sends READY=1 once it accepts connections on the socket
+        Type = "notify";
+        # With socket activation the listener is inherited from onix-mcp.socket;
+        # otherwise the service binds socketPath itself.
+        ExecStart =
+          if cfg.socketActivation
+          then "${cfg.package}/bin/onix-mcp"
+          else "${cfg.package}/bin/onix-mcp --socket ${cfg.socketPath}";
         User = cfg.user;
         Group = cfg.group;
         Restart = "on-failure";
@@ -98,7 +104,8 @@ in
         PrivateTmp = true;
         ProtectSystem = "strict";
         ProtectHome = true;
-        ReadWritePaths = [ "/var/lib/onix-mcp" ];
+        ReadWritePaths = [ "/var/lib/onix-mcp" ]
+          ++ lib.optional (!cfg.socketActivation) (dirOf cfg.socketPath);
         PrivateDevices = true;
         ProtectKernelTunables = true;
         ProtectKernelModules = true;
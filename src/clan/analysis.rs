@@ -1,3 +1,7 @@
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
 use crate::common::security::helpers::{audit_tool_execution, with_timeout};
 use crate::common::security::{validate_flake_ref, validation_error_to_mcp, AuditLogger};
 use rmcp::{
@@ -57,6 +61,7 @@ use super::types::{
 /// ```no_run
 /// use onix_mcp::clan::AnalysisTools;
 /// use onix_mcp::clan::types::ClanAnalyzeSecretsArgs;
+/// use onix_mcp::common::roots::Workspace;
 /// use rmcp::handler::server::wrapper::Parameters;
 /// use std::sync::Arc;
 ///
@@ -64,12 +69,13 @@ use super::types::{
 /// // Analyze secret ownership across machines
 /// let result = tools.clan_analyze_secrets(Parameters(ClanAnalyzeSecretsArgs {
 ///     flake: Some(".".to_string()),
-/// })).await?;
+/// }), Workspace::default()).await?;
 /// # Ok(())
 /// # }
 /// ```
 pub struct AnalysisTools {
     audit: Arc<AuditLogger>,
+    config: Arc<Config>,
 }
 
 impl AnalysisTools {
@@ -84,8 +90,50 @@ impl AnalysisTools {
     /// AnalysisTools does not use caching as infrastructure analysis
     /// must reflect current state, which changes frequently.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
     }
+
+    /// Creates a new `AnalysisTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
+    }
+}
+
+/// Flake providing the analysis apps for Clan flakes that do not define their own.
+const ANALYSIS_FALLBACK_FLAKE: &str = "github:onixcomputer/onix-core";
+
+/// Run the analysis app `app` from the flake at `flake`.
+///
+/// Clan flakes may define their own `acl`, `vars`, `tags` and `roster` apps;
+/// when the local app is missing or fails, the one from
+/// [`ANALYSIS_FALLBACK_FLAKE`] is run against the same directory instead.
+async fn run_analysis_app(flake: &str, app: &str) -> std::io::Result<std::process::Output> {
+    let local = tokio::process::Command::new("nix")
+        .args(["run", &format!(".#{}", app)])
+        .current_dir(flake)
+        .group_output()
+        .await?;
+
+    if local.status.success() {
+        return Ok(local);
+    }
+
+    let stderr = String::from_utf8_lossy(&local.stderr);
+    tracing::warn!(
+        flake,
+        app,
+        reason = stderr.lines().last().unwrap_or_default(),
+        "Local .#{} app failed, falling back to {}#{}",
+        app,
+        ANALYSIS_FALLBACK_FLAKE,
+        app
+    );
+
+    tokio::process::Command::new("nix")
+        .args(["run", &format!("{}#{}", ANALYSIS_FALLBACK_FLAKE, app)])
+        .current_dir(flake)
+        .group_output()
+        .await
 }
 
 #[tool_router]
@@ -94,153 +142,236 @@ impl AnalysisTools {
     pub async fn clan_analyze_secrets(
         &self,
         Parameters(ClanAnalyzeSecretsArgs { flake }): Parameters<ClanAnalyzeSecretsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
 
         // Validate flake path to prevent path traversal
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-        audit_tool_execution(&self.audit, "clan_analyze_secrets", Some(serde_json::json!({"flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_analyze_secrets", 60, || async {
-                // Try local flake first, then fall back to onix-core
-                let mut cmd = tokio::process::Command::new("sh");
-                cmd.args(["-c", &format!(
-                    "cd {} && (nix run .#acl 2>/dev/null || nix run github:onixcomputer/onix-core#acl) 2>&1",
-                    flake_str
-                )]);
-
-                let output = cmd.output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to execute acl command: {}", e), None))?;
+        audit_tool_execution(
+            &self.audit,
+            "clan_analyze_secrets",
+            Some(serde_json::json!({"flake": &flake_str})),
+            || async {
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_analyze_secrets",
+                        CommandCategory::Evaluation,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_analyze_secrets",
+                    self.config.timeout("clan_analyze_secrets", 60),
+                    || async {
+                        let output = run_analysis_app(&flake_str, "acl").await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute acl command: {}", e),
+                                None,
+                            )
+                        })?;
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                if !output.status.success() {
-                    return Ok(CallToolResult::success(vec![Content::text(
-                        format!("ACL analysis failed.\n\nError:\n{}{}", stdout, stderr)
-                    )]));
-                }
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "ACL analysis failed.\n\nError:\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                Ok(CallToolResult::success(vec![Content::text(
-                    format!("Clan Secret (ACL) Ownership Analysis:\n\n{}{}", stdout, stderr)
-                )]))
-            }).await
-        }).await
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Clan Secret (ACL) Ownership Analysis:\n\n{}{}",
+                            stdout, stderr
+                        ))]))
+                    },
+                )
+                .await
+            },
+        )
+        .await
     }
 
     #[tool(description = "Analyze Clan vars ownership across machines")]
     pub async fn clan_analyze_vars(
         &self,
         Parameters(ClanAnalyzeVarsArgs { flake }): Parameters<ClanAnalyzeVarsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
 
         // Validate flake path to prevent path traversal
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-        audit_tool_execution(&self.audit, "clan_analyze_vars", Some(serde_json::json!({"flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_analyze_vars", 60, || async {
-                let mut cmd = tokio::process::Command::new("sh");
-                cmd.args(["-c", &format!(
-                    "cd {} && (nix run .#vars 2>/dev/null || nix run github:onixcomputer/onix-core#vars) 2>&1",
-                    flake_str
-                )]);
+        audit_tool_execution(
+            &self.audit,
+            "clan_analyze_vars",
+            Some(serde_json::json!({"flake": &flake_str})),
+            || async {
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_analyze_vars",
+                        CommandCategory::Evaluation,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_analyze_vars",
+                    self.config.timeout("clan_analyze_vars", 60),
+                    || async {
+                        let output = run_analysis_app(&flake_str, "vars").await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute vars command: {}", e),
+                                None,
+                            )
+                        })?;
 
-                let output = cmd.output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to execute vars command: {}", e), None))?;
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Vars analysis failed.\n\nError:\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                if !output.status.success() {
-                    return Ok(CallToolResult::success(vec![Content::text(
-                        format!("Vars analysis failed.\n\nError:\n{}{}", stdout, stderr)
-                    )]));
-                }
-
-                Ok(CallToolResult::success(vec![Content::text(
-                    format!("Clan Vars Ownership Analysis:\n\n{}{}", stdout, stderr)
-                )]))
-            }).await
-        }).await
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Clan Vars Ownership Analysis:\n\n{}{}",
+                            stdout, stderr
+                        ))]))
+                    },
+                )
+                .await
+            },
+        )
+        .await
     }
 
     #[tool(description = "Analyze Clan machine tags across the infrastructure")]
     pub async fn clan_analyze_tags(
         &self,
         Parameters(ClanAnalyzeTagsArgs { flake }): Parameters<ClanAnalyzeTagsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
 
         // Validate flake path to prevent path traversal
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-        audit_tool_execution(&self.audit, "clan_analyze_tags", Some(serde_json::json!({"flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_analyze_tags", 60, || async {
-                let mut cmd = tokio::process::Command::new("sh");
-                cmd.args(["-c", &format!(
-                    "cd {} && (nix run .#tags 2>/dev/null || nix run github:onixcomputer/onix-core#tags) 2>&1",
-                    flake_str
-                )]);
-
-                let output = cmd.output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to execute tags command: {}", e), None))?;
+        audit_tool_execution(
+            &self.audit,
+            "clan_analyze_tags",
+            Some(serde_json::json!({"flake": &flake_str})),
+            || async {
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_analyze_tags",
+                        CommandCategory::Evaluation,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_analyze_tags",
+                    self.config.timeout("clan_analyze_tags", 60),
+                    || async {
+                        let output = run_analysis_app(&flake_str, "tags").await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute tags command: {}", e),
+                                None,
+                            )
+                        })?;
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                if !output.status.success() {
-                    return Ok(CallToolResult::success(vec![Content::text(
-                        format!("Tags analysis failed.\n\nError:\n{}{}", stdout, stderr)
-                    )]));
-                }
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Tags analysis failed.\n\nError:\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                Ok(CallToolResult::success(vec![Content::text(
-                    format!("Clan Machine Tags Analysis:\n\n{}{}", stdout, stderr)
-                )]))
-            }).await
-        }).await
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Clan Machine Tags Analysis:\n\n{}{}",
+                            stdout, stderr
+                        ))]))
+                    },
+                )
+                .await
+            },
+        )
+        .await
     }
 
     #[tool(description = "Analyze Clan user roster configurations")]
     pub async fn clan_analyze_roster(
         &self,
         Parameters(ClanAnalyzeRosterArgs { flake }): Parameters<ClanAnalyzeRosterArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
 
         // Validate flake path to prevent path traversal
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-        audit_tool_execution(&self.audit, "clan_analyze_roster", Some(serde_json::json!({"flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_analyze_roster", 60, || async {
-                let mut cmd = tokio::process::Command::new("sh");
-                cmd.args(["-c", &format!(
-                    "cd {} && (nix run .#roster 2>/dev/null || nix run github:onixcomputer/onix-core#roster) 2>&1",
-                    flake_str
-                )]);
-
-                let output = cmd.output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to execute roster command: {}", e), None))?;
+        audit_tool_execution(
+            &self.audit,
+            "clan_analyze_roster",
+            Some(serde_json::json!({"flake": &flake_str})),
+            || async {
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_analyze_roster",
+                        CommandCategory::Evaluation,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_analyze_roster",
+                    self.config.timeout("clan_analyze_roster", 60),
+                    || async {
+                        let output = run_analysis_app(&flake_str, "roster").await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute roster command: {}", e),
+                                None,
+                            )
+                        })?;
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                if !output.status.success() {
-                    return Ok(CallToolResult::success(vec![Content::text(
-                        format!("Roster analysis failed.\n\nError:\n{}{}", stdout, stderr)
-                    )]));
-                }
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Roster analysis failed.\n\nError:\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                Ok(CallToolResult::success(vec![Content::text(
-                    format!("Clan User Roster Analysis:\n\n{}{}", stdout, stderr)
-                )]))
-            }).await
-        }).await
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Clan User Roster Analysis:\n\n{}{}",
+                            stdout, stderr
+                        ))]))
+                    },
+                )
+                .await
+            },
+        )
+        .await
     }
 
     #[tool(
@@ -250,12 +381,15 @@ impl AnalysisTools {
     pub async fn clan_secrets_list(
         &self,
         Parameters(ClanSecretsListArgs { flake }): Parameters<ClanSecretsListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::{validate_flake_ref, validation_error_to_mcp};
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(
@@ -263,33 +397,41 @@ impl AnalysisTools {
             "clan_secrets_list",
             Some(serde_json::json!({"flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_secrets_list", 30, || async {
-                    let output = tokio::process::Command::new("clan")
-                        .args(["secrets", "list", "--flake", &flake_str])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to list secrets:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    let result = if stdout.trim().is_empty() {
-                        "No secrets configured.".to_string()
-                    } else {
-                        format!("Clan Secrets:\n\n{}", stdout)
-                    };
-
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                with_timeout(
+                    &self.audit,
+                    "clan_secrets_list",
+                    self.config.timeout("clan_secrets_list", 30),
+                    || async {
+                        let output = tokio::process::Command::new("clan")
+                            .args(["secrets", "list", "--flake", &flake_str])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Failed to list secrets:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
+
+                        let result = if stdout.trim().is_empty() {
+                            "No secrets configured.".to_string()
+                        } else {
+                            format!("Clan Secrets:\n\n{}", stdout)
+                        };
+
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -303,51 +445,67 @@ impl AnalysisTools {
             directory,
             template,
         }): Parameters<ClanFlakeCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::{validate_path, validation_error_to_mcp};
 
         // Validate directory path
         validate_path(&directory).map_err(validation_error_to_mcp)?;
 
+        // Resolve against the client's workspace roots
+        let directory = workspace
+            .resolve_dir(Some(&directory))
+            .await?
+            .to_string_lossy()
+            .into_owned();
+
         // Execute with security features (audit logging + 60s timeout)
         audit_tool_execution(
             &self.audit,
             "clan_flake_create",
             Some(serde_json::json!({"directory": &directory})),
             || async {
-                with_timeout(&self.audit, "clan_flake_create", 60, || async {
-                    let mut args = vec!["flakes", "create", &directory];
-
-                    let template_str;
-                    if let Some(ref t) = template {
-                        template_str = t.clone();
-                        args.push("--template");
-                        args.push(&template_str);
-                    }
-
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to create Clan flake:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Clan flake created in '{}'.\n\n{}{}",
-                        directory, stdout, stderr
-                    ))]))
-                })
+                with_timeout(
+                    &self.audit,
+                    "clan_flake_create",
+                    self.config.timeout("clan_flake_create", 60),
+                    || async {
+                        let mut args = vec!["flakes", "create", &directory];
+
+                        let template_str;
+                        if let Some(ref t) = template {
+                            template_str = t.clone();
+                            args.push("--template");
+                            args.push(&template_str);
+                        }
+
+                        let output = tokio::process::Command::new("clan")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Failed to create Clan flake:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Clan flake created in '{}'.\n\n{}{}",
+                            directory, stdout, stderr
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -358,6 +516,7 @@ impl AnalysisTools {
     pub async fn clan_vm_create(
         &self,
         Parameters(ClanVmCreateArgs { machine, flake }): Parameters<ClanVmCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::{
             validate_flake_ref, validate_machine_name, validation_error_to_mcp,
@@ -367,15 +526,20 @@ impl AnalysisTools {
         validate_machine_name(&machine).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 120s timeout)
         audit_tool_execution(&self.audit, "clan_vm_create", Some(serde_json::json!({"machine": &machine, "flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_vm_create", 120, || async {
+            let _slot = scheduler()
+                .acquire(&self.audit, "clan_vm_create", CommandCategory::Build)
+                .await;
+            with_timeout(&self.audit, "clan_vm_create", self.config.timeout("clan_vm_create", 120), || async {
                 let output = tokio::process::Command::new("clan")
                     .args(["vms", "create", &machine, "--flake", &flake_str])
-                    .output()
+                    .group_output()
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to execute clan: {}", e), None))?;
 
@@ -470,7 +634,8 @@ COMMON WORKFLOWS:
    clan_machine_create(name="webserver", target_host="192.168.1.10")
 
 3. Deploying to Production:
-   clan_machine_install(machine="webserver", target_host="192.168.1.10", confirm=true)
+   clan_machine_install(machine="webserver", target_host="192.168.1.10")
+   (destructive tools ask for confirmation before running)
 
 4. Regular Updates:
    clan_machine_update(machines=["webserver"])
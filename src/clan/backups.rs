@@ -1,3 +1,8 @@
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::confirmation::{Confirmation, ConfirmationOutcome, DestructiveOperation};
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
 use crate::common::security::helpers::validation_error_to_mcp;
 use crate::common::security::input_validation::validate_flake_ref;
 use crate::common::security::AuditLogger;
@@ -39,7 +44,8 @@ use super::types::{ClanBackupCreateArgs, ClanBackupListArgs, ClanBackupRestoreAr
 ///
 /// # Destructive Operations
 ///
-/// **WARNING**: Restore operation modifies data:
+/// **WARNING**: Restore operation modifies data and only runs once the user has
+/// confirmed it (see [`confirmation`](crate::common::confirmation)):
 /// - `clan_backup_restore` - Overwrites service or machine data
 ///
 /// # Examples
@@ -47,6 +53,7 @@ use super::types::{ClanBackupCreateArgs, ClanBackupListArgs, ClanBackupRestoreAr
 /// ```no_run
 /// use onix_mcp::clan::BackupTools;
 /// use onix_mcp::clan::types::ClanBackupCreateArgs;
+/// use onix_mcp::common::roots::Workspace;
 /// use rmcp::handler::server::wrapper::Parameters;
 /// use std::sync::Arc;
 ///
@@ -56,12 +63,13 @@ use super::types::{ClanBackupCreateArgs, ClanBackupListArgs, ClanBackupRestoreAr
 ///     machine: "webserver".to_string(),
 ///     provider: Some("local".to_string()),
 ///     flake: None,
-/// })).await?;
+/// }), Workspace::default()).await?;
 /// # Ok(())
 /// # }
 /// ```
 pub struct BackupTools {
     audit: Arc<AuditLogger>,
+    config: Arc<Config>,
 }
 
 impl BackupTools {
@@ -76,7 +84,12 @@ impl BackupTools {
     /// BackupTools does not use caching as backup state changes
     /// frequently and operations must reflect current state.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `BackupTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
     }
 }
 
@@ -90,6 +103,7 @@ impl BackupTools {
             provider,
             flake,
         }): Parameters<ClanBackupCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
         use crate::common::security::validate_machine_name;
@@ -98,8 +112,10 @@ impl BackupTools {
         validate_machine_name(&machine).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 120s timeout)
         audit_tool_execution(
@@ -107,42 +123,57 @@ impl BackupTools {
             "clan_backup_create",
             Some(serde_json::json!({"machine": &machine, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_backup_create", 120, || async {
-                    let mut args = vec!["backups", "create", &machine];
-
-                    args.push("--flake");
-                    args.push(&flake_str);
-
-                    let provider_str;
-                    if let Some(ref p) = provider {
-                        provider_str = p.clone();
-                        args.push("--provider");
-                        args.push(&provider_str);
-                    }
-
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Backup creation failed:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Backup created for machine '{}'.\n\n{}{}",
-                        machine, stdout, stderr
-                    ))]))
-                })
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_backup_create",
+                        CommandCategory::ClanDeploy,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_backup_create",
+                    self.config.timeout("clan_backup_create", 120),
+                    || async {
+                        let mut args = vec!["backups", "create", &machine];
+
+                        args.push("--flake");
+                        args.push(&flake_str);
+
+                        let provider_str;
+                        if let Some(ref p) = provider {
+                            provider_str = p.clone();
+                            args.push("--provider");
+                            args.push(&provider_str);
+                        }
+
+                        let output = tokio::process::Command::new("clan")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Backup creation failed:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Backup created for machine '{}'.\n\n{}{}",
+                            machine, stdout, stderr
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -160,6 +191,7 @@ impl BackupTools {
             provider,
             flake,
         }): Parameters<ClanBackupListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
         use crate::common::security::validate_machine_name;
@@ -168,8 +200,10 @@ impl BackupTools {
         validate_machine_name(&machine).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(
@@ -177,45 +211,53 @@ impl BackupTools {
             "clan_backup_list",
             Some(serde_json::json!({"machine": &machine, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_backup_list", 30, || async {
-                    let mut args = vec!["backups", "list", &machine];
-
-                    args.push("--flake");
-                    args.push(&flake_str);
-
-                    let provider_str;
-                    if let Some(ref p) = provider {
-                        provider_str = p.clone();
-                        args.push("--provider");
-                        args.push(&provider_str);
-                    }
-
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to list backups:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    let result = if stdout.trim().is_empty() {
-                        format!("No backups found for machine '{}'.", machine)
-                    } else {
-                        format!("Backups for machine '{}':\n\n{}", machine, stdout)
-                    };
-
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                with_timeout(
+                    &self.audit,
+                    "clan_backup_list",
+                    self.config.timeout("clan_backup_list", 30),
+                    || async {
+                        let mut args = vec!["backups", "list", &machine];
+
+                        args.push("--flake");
+                        args.push(&flake_str);
+
+                        let provider_str;
+                        if let Some(ref p) = provider {
+                            provider_str = p.clone();
+                            args.push("--provider");
+                            args.push(&provider_str);
+                        }
+
+                        let output = tokio::process::Command::new("clan")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Failed to list backups:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
+
+                        let result = if stdout.trim().is_empty() {
+                            format!("No backups found for machine '{}'.", machine)
+                        } else {
+                            format!("Backups for machine '{}':\n\n{}", machine, stdout)
+                        };
+
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -234,7 +276,10 @@ impl BackupTools {
             name,
             service,
             flake,
+            confirmation_token,
         }): Parameters<ClanBackupRestoreArgs>,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
         use crate::common::security::validate_machine_name;
@@ -243,8 +288,10 @@ impl BackupTools {
         validate_machine_name(&machine).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Validate backup name (basic alphanumeric check)
         if name.is_empty()
@@ -258,12 +305,23 @@ impl BackupTools {
             ));
         }
 
-        // Log dangerous operation
-        self.audit.log_dangerous_operation(
+        // Require user confirmation for this destructive operation
+        let operation = DestructiveOperation::new(
             "clan_backup_restore",
-            true,
-            &format!("Restoring backup '{}' for machine '{}'", name, machine),
-        );
+            format!("Restoring backup '{}' for machine '{}'", name, machine),
+        )
+        .detail("Machine", &machine)
+        .detail("Provider", &provider)
+        .detail("Backup", &name)
+        .detail("Service", service.as_deref().unwrap_or("all services"))
+        .detail("Flake", &flake_str)
+        .effect("Overwrite the current service data with the backup");
+        if let ConfirmationOutcome::Declined(result) = confirmation
+            .confirm(&self.audit, &operation, confirmation_token.as_deref())
+            .await?
+        {
+            return Ok(result);
+        }
 
         // Execute with security features (audit logging + 120s timeout)
         audit_tool_execution(
@@ -271,42 +329,57 @@ impl BackupTools {
             "clan_backup_restore",
             Some(serde_json::json!({"machine": &machine, "backup": &name, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_backup_restore", 120, || async {
-                    let mut args = vec!["backups", "restore", &machine, &provider, &name];
-
-                    args.push("--flake");
-                    args.push(&flake_str);
-
-                    let service_str;
-                    if let Some(ref s) = service {
-                        service_str = s.clone();
-                        args.push("--service");
-                        args.push(&service_str);
-                    }
-
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Backup restore failed:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Backup '{}' restored for machine '{}'.\n\n{}{}",
-                        name, machine, stdout, stderr
-                    ))]))
-                })
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_backup_restore",
+                        CommandCategory::ClanDeploy,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_backup_restore",
+                    self.config.timeout("clan_backup_restore", 120),
+                    || async {
+                        let mut args = vec!["backups", "restore", &machine, &provider, &name];
+
+                        args.push("--flake");
+                        args.push(&flake_str);
+
+                        let service_str;
+                        if let Some(ref s) = service {
+                            service_str = s.clone();
+                            args.push("--service");
+                            args.push(&service_str);
+                        }
+
+                        let output = tokio::process::Command::new("clan")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Backup restore failed:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Backup '{}' restored for machine '{}'.\n\n{}{}",
+                            name, machine, stdout, stderr
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -1,8 +1,15 @@
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::confirmation::{Confirmation, ConfirmationOutcome, DestructiveOperation};
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::progress::{output_with_progress, ProgressReporter};
+use crate::common::roots::Workspace;
 use crate::common::security::helpers::{
     audit_tool_execution, validation_error_to_mcp, with_timeout,
 };
 use crate::common::security::input_validation::validate_flake_ref;
 use crate::common::security::{validate_machine_name, AuditLogger};
+use crate::common::structured::{output_schema, ToolOutput};
 use rmcp::{
     handler::server::wrapper::Parameters, model::*, tool, tool_router, ErrorData as McpError,
 };
@@ -10,7 +17,7 @@ use std::sync::Arc;
 
 use super::types::{
     ClanMachineBuildArgs, ClanMachineCreateArgs, ClanMachineDeleteArgs, ClanMachineInstallArgs,
-    ClanMachineListArgs, ClanMachineUpdateArgs,
+    ClanMachineListArgs, ClanMachineUpdateArgs, MachineListResult,
 };
 
 /// Tools for managing Clan machine configurations.
@@ -45,13 +52,14 @@ use super::types::{
 /// - Machine names validated for hostname compliance
 /// - Flake references checked for shell metacharacters
 /// - Destructive operations (update, delete, install) are marked and logged
-/// - Install operation requires explicit confirmation
+/// - Destructive operations require user confirmation (see [`confirmation`](crate::common::confirmation))
 /// - All operations audited with parameters
 ///
 /// # Destructive Operations
 ///
-/// **WARNING**: These operations modify or destroy data:
-/// - `clan_machine_install` - Overwrites target disk (requires confirmation)
+/// **WARNING**: These operations modify or destroy data and only run once the
+/// user has confirmed them:
+/// - `clan_machine_install` - Overwrites target disk
 /// - `clan_machine_update` - Rebuilds and deploys configuration
 /// - `clan_machine_delete` - Removes machine configuration
 ///
@@ -60,6 +68,7 @@ use super::types::{
 /// ```no_run
 /// use onix_mcp::clan::MachineTools;
 /// use onix_mcp::clan::types::ClanMachineCreateArgs;
+/// use onix_mcp::common::roots::Workspace;
 /// use rmcp::handler::server::wrapper::Parameters;
 /// use std::sync::Arc;
 ///
@@ -70,12 +79,13 @@ use super::types::{
 ///     template: Some("new-machine".to_string()),
 ///     target_host: Some("192.168.1.10".to_string()),
 ///     flake: None,
-/// })).await?;
+/// }), Workspace::default()).await?;
 /// # Ok(())
 /// # }
 /// ```
 pub struct MachineTools {
     audit: Arc<AuditLogger>,
+    config: Arc<Config>,
 }
 
 impl MachineTools {
@@ -90,7 +100,22 @@ impl MachineTools {
     /// MachineTools does not use caching as machine configurations
     /// change frequently and operations must reflect current state.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `MachineTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
+    }
+}
+
+impl ToolOutput for MachineListResult {
+    fn to_text(&self) -> String {
+        if self.machines.is_empty() {
+            "No machines configured in this Clan flake.".to_string()
+        } else {
+            format!("Clan Machines:\n\n{}\n", self.machines.join("\n"))
+        }
     }
 }
 
@@ -105,13 +130,16 @@ impl MachineTools {
             target_host,
             flake,
         }): Parameters<ClanMachineCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate machine name
         validate_machine_name(&name).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 60s timeout)
         audit_tool_execution(
@@ -119,46 +147,54 @@ impl MachineTools {
             "clan_machine_create",
             Some(serde_json::json!({"name": &name, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_machine_create", 60, || async {
-                    let mut args = vec!["machines", "create", &name];
-
-                    let template_str = template.unwrap_or_else(|| "new-machine".to_string());
-                    args.push("-t");
-                    args.push(&template_str);
-
-                    args.push("--flake");
-                    args.push(&flake_str);
-
-                    let target_host_str;
-                    if let Some(ref host) = target_host {
-                        target_host_str = host.clone();
-                        args.push("--target-host");
-                        args.push(&target_host_str);
-                    }
-
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
+                with_timeout(
+                    &self.audit,
+                    "clan_machine_create",
+                    self.config.timeout("clan_machine_create", 60),
+                    || async {
+                        let mut args = vec!["machines", "create", &name];
+
+                        let template_str = template.unwrap_or_else(|| "new-machine".to_string());
+                        args.push("-t");
+                        args.push(&template_str);
+
+                        args.push("--flake");
+                        args.push(&flake_str);
+
+                        let target_host_str;
+                        if let Some(ref host) = target_host {
+                            target_host_str = host.clone();
+                            args.push("--target-host");
+                            args.push(&target_host_str);
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                        let output = tokio::process::Command::new("clan")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Failed to create machine '{}':\n\n{}{}",
+                                name, stdout, stderr
+                            ))]));
+                        }
 
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to create machine '{}':\n\n{}{}",
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Successfully created machine '{}'.\n\n{}{}",
                             name, stdout, stderr
-                        ))]));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Successfully created machine '{}'.\n\n{}{}",
-                        name, stdout, stderr
-                    ))]))
-                })
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -167,15 +203,19 @@ impl MachineTools {
 
     #[tool(
         description = "List all Clan machines in the flake",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true),
+        output_schema = output_schema::<MachineListResult>()
     )]
     pub async fn clan_machine_list(
         &self,
         Parameters(ClanMachineListArgs { flake }): Parameters<ClanMachineListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(
@@ -183,33 +223,52 @@ impl MachineTools {
             "clan_machine_list",
             Some(serde_json::json!({"flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_machine_list", 30, || async {
-                    let output = tokio::process::Command::new("clan")
-                        .args(["machines", "list", "--flake", &flake_str])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to list machines:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
-
-                    let result = if stdout.trim().is_empty() {
-                        "No machines configured in this Clan flake.".to_string()
-                    } else {
-                        format!("Clan Machines:\n\n{}", stdout)
-                    };
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_machine_list",
+                        CommandCategory::Evaluation,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_machine_list",
+                    self.config.timeout("clan_machine_list", 30),
+                    || async {
+                        let output = tokio::process::Command::new("clan")
+                            .args(["machines", "list", "--flake", &flake_str])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::error(vec![Content::text(format!(
+                                "Failed to list machines:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                        let result = MachineListResult {
+                            flake: flake_str.clone(),
+                            machines: stdout
+                                .lines()
+                                .map(str::trim)
+                                .filter(|line| !line.is_empty())
+                                .map(str::to_string)
+                                .collect(),
+                        };
+
+                        Ok(result.to_tool_result())
+                    },
+                )
                 .await
             },
         )
@@ -222,11 +281,20 @@ impl MachineTools {
     )]
     pub async fn clan_machine_update(
         &self,
-        Parameters(ClanMachineUpdateArgs { machines, flake }): Parameters<ClanMachineUpdateArgs>,
+        Parameters(ClanMachineUpdateArgs {
+            machines,
+            flake,
+            confirmation_token,
+        }): Parameters<ClanMachineUpdateArgs>,
+        progress: ProgressReporter,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Validate machine names if provided
         if let Some(ref m) = machines {
@@ -235,16 +303,25 @@ impl MachineTools {
             }
         }
 
-        // Log dangerous operation
+        // Require user confirmation for this destructive operation
         let machines_desc = machines
             .as_ref()
             .map(|m| m.join(", "))
             .unwrap_or_else(|| "all machines".to_string());
-        self.audit.log_dangerous_operation(
+        let operation = DestructiveOperation::new(
             "clan_machine_update",
-            true,
-            &format!("Updating machines: {}", machines_desc),
-        );
+            format!("Updating machines: {}", machines_desc),
+        )
+        .detail("Machines", &machines_desc)
+        .detail("Flake", &flake_str)
+        .effect("Rebuild the machine configurations")
+        .effect("Deploy and activate them on the running machines");
+        if let ConfirmationOutcome::Declined(result) = confirmation
+            .confirm(&self.audit, &operation, confirmation_token.as_deref())
+            .await?
+        {
+            return Ok(result);
+        }
 
         // Execute with security features (audit logging + 300s timeout)
         audit_tool_execution(
@@ -252,43 +329,56 @@ impl MachineTools {
             "clan_machine_update",
             Some(serde_json::json!({"machines": &machines, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_machine_update", 300, || async {
-                    let mut args = vec!["machines", "update"];
-
-                    args.push("--flake");
-                    args.push(&flake_str);
-
-                    let machine_names: Vec<String>;
-                    if let Some(ref m) = machines {
-                        machine_names = m.clone();
-                        for machine in &machine_names {
-                            args.push(machine);
+                let _slot = scheduler()
+                    .acquire(
+                        &self.audit,
+                        "clan_machine_update",
+                        CommandCategory::ClanDeploy,
+                    )
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "clan_machine_update",
+                    self.config.timeout("clan_machine_update", 300),
+                    || async {
+                        let mut args = vec!["machines", "update"];
+
+                        args.push("--flake");
+                        args.push(&flake_str);
+
+                        let machine_names: Vec<String>;
+                        if let Some(ref m) = machines {
+                            machine_names = m.clone();
+                            for machine in &machine_names {
+                                args.push(machine);
+                            }
                         }
-                    }
 
-                    let output = tokio::process::Command::new("clan")
-                        .args(&args)
-                        .output()
+                        let output = output_with_progress(
+                            tokio::process::Command::new("clan").args(&args),
+                            &progress,
+                        )
                         .await
                         .map_err(|e| {
                             McpError::internal_error(format!("Failed to execute clan: {}", e), None)
                         })?;
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Machine update failed:\n\n{}{}",
-                            stdout, stderr
-                        ))]));
-                    }
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Machine update failed:\n\n{}{}",
+                                stdout, stderr
+                            ))]));
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Machine update completed.\n\n{}{}",
-                        stdout, stderr
-                    ))]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Machine update completed.\n\n{}{}",
+                            stdout, stderr
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -301,21 +391,35 @@ impl MachineTools {
     )]
     pub async fn clan_machine_delete(
         &self,
-        Parameters(ClanMachineDeleteArgs { name, flake }): Parameters<ClanMachineDeleteArgs>,
+        Parameters(ClanMachineDeleteArgs {
+            name,
+            flake,
+            confirmation_token,
+        }): Parameters<ClanMachineDeleteArgs>,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate machine name
         validate_machine_name(&name).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-        // Log dangerous operation
-        self.audit.log_dangerous_operation(
-            "clan_machine_delete",
-            true,
-            &format!("Deleting machine: {}", name),
-        );
+        // Require user confirmation for this destructive operation
+        let operation =
+            DestructiveOperation::new("clan_machine_delete", format!("Deleting machine: {}", name))
+                .detail("Machine", &name)
+                .detail("Flake", &flake_str)
+                .effect("Remove the machine configuration from the flake");
+        if let ConfirmationOutcome::Declined(result) = confirmation
+            .confirm(&self.audit, &operation, confirmation_token.as_deref())
+            .await?
+        {
+            return Ok(result);
+        }
 
         // Execute with security features (audit logging + 60s timeout)
         audit_tool_execution(
@@ -323,30 +427,38 @@ impl MachineTools {
             "clan_machine_delete",
             Some(serde_json::json!({"name": &name, "flake": &flake_str})),
             || async {
-                with_timeout(&self.audit, "clan_machine_delete", 60, || async {
-                    let output = tokio::process::Command::new("clan")
-                        .args(["machines", "delete", &name, "--flake", &flake_str])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(format!("Failed to execute clan: {}", e), None)
-                        })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                with_timeout(
+                    &self.audit,
+                    "clan_machine_delete",
+                    self.config.timeout("clan_machine_delete", 60),
+                    || async {
+                        let output = tokio::process::Command::new("clan")
+                            .args(["machines", "delete", &name, "--flake", &flake_str])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute clan: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Failed to delete machine '{}':\n\n{}{}",
+                                name, stdout, stderr
+                            ))]));
+                        }
 
-                    if !output.status.success() {
-                        return Ok(CallToolResult::success(vec![Content::text(format!(
-                            "Failed to delete machine '{}':\n\n{}{}",
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Successfully deleted machine '{}'.\n\n{}{}",
                             name, stdout, stderr
-                        ))]));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Successfully deleted machine '{}'.\n\n{}{}",
-                        name, stdout, stderr
-                    ))]))
-                })
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -363,45 +475,53 @@ impl MachineTools {
             machine,
             target_host,
             flake,
-            confirm,
+            confirmation_token,
         }): Parameters<ClanMachineInstallArgs>,
+        progress: ProgressReporter,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate machine name
         validate_machine_name(&machine).map_err(validation_error_to_mcp)?;
 
         // Validate flake ref if provided
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
         validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         // Require user confirmation for this destructive operation
-        if !confirm.unwrap_or(false) {
-            return Ok(CallToolResult::success(vec![Content::text(format!(
-                "WARNING: Installing machine '{}' to '{}' will OVERWRITE THE DISK!\n\n\
-                    This is a destructive operation that will:\n\
-                    - Partition and format the target disk\n\
-                    - Install NixOS\n\
-                    - Deploy the Clan configuration\n\n\
-                    To proceed, call this function again with confirm=true",
-                machine, target_host
-            ))]));
-        }
-
-        // Log dangerous operation approval
-        self.audit.log_dangerous_operation(
+        let operation = DestructiveOperation::new(
             "clan_machine_install",
-            true,
-            &format!(
-                "Installing machine '{}' to '{}' (user confirmed)",
+            format!(
+                "Installing machine '{}' to '{}' (overwrites the target disk)",
                 machine, target_host
             ),
-        );
+        )
+        .detail("Machine", &machine)
+        .detail("Target host", &target_host)
+        .detail("Flake", &flake_str)
+        .effect("Partition and format the target disk")
+        .effect("Install NixOS")
+        .effect("Deploy the Clan configuration");
+        if let ConfirmationOutcome::Declined(result) = confirmation
+            .confirm(&self.audit, &operation, confirmation_token.as_deref())
+            .await?
+        {
+            return Ok(result);
+        }
 
         // Execute with security features (audit logging + 600s timeout for install)
         audit_tool_execution(&self.audit, "clan_machine_install", Some(serde_json::json!({"machine": &machine, "target_host": &target_host, "flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_machine_install", 600, || async {
-                let output = tokio::process::Command::new("clan")
-                    .args(["machines", "install", &machine, &target_host, "--flake", &flake_str])
-                    .output()
+            let _slot = scheduler()
+                .acquire(&self.audit, "clan_machine_install", CommandCategory::ClanDeploy)
+                .await;
+            with_timeout(&self.audit, "clan_machine_install", self.config.timeout("clan_machine_install", 600), || async {
+                let output = output_with_progress(
+                    tokio::process::Command::new("clan")
+                        .args(["machines", "install", &machine, &target_host, "--flake", &flake_str]),
+                    &progress,
+                )
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to execute clan: {}", e), None))?;
 
@@ -431,11 +551,19 @@ impl MachineTools {
             flake,
             use_nom,
         }): Parameters<ClanMachineBuildArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
+        validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
+
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
         audit_tool_execution(&self.audit, "clan_machine_build", Some(serde_json::json!({"machine": &machine, "flake": &flake_str})), || async {
-            with_timeout(&self.audit, "clan_machine_build", 300, || async {
+            let _slot = scheduler()
+                .acquire(&self.audit, "clan_machine_build", CommandCategory::Build)
+                .await;
+            with_timeout(&self.audit, "clan_machine_build", self.config.timeout("clan_machine_build", 300), || async {
                 let use_nom = use_nom.unwrap_or(false);
                 let build_target = format!(".#nixosConfigurations.{}.config.system.build.toplevel", machine);
 
@@ -443,7 +571,7 @@ impl MachineTools {
                     // Check if nom is available
                     let nom_check = tokio::process::Command::new("which")
                         .arg("nom")
-                        .output()
+                        .group_output()
                         .await;
 
                     if nom_check.is_ok() && nom_check.unwrap().status.success() {
@@ -463,7 +591,7 @@ impl MachineTools {
 
                 cmd.current_dir(&flake_str);
 
-                let output = cmd.output()
+                let output = cmd.group_output()
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to execute build command: {}", e), None))?;
 
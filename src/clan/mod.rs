@@ -33,11 +33,12 @@
 //!
 //! ```no_run
 //! use onix_mcp::clan::{MachineTools, ClanMachineListArgs};
+//! use onix_mcp::common::security::audit_logger;
 //! use std::sync::Arc;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Create machine tools
-//! let audit = Arc::new(/* audit logger */);
+//! let audit = audit_logger();
 //! let tools = MachineTools::new(audit);
 //!
 //! // List all machines in the current Clan flake
@@ -71,4 +72,5 @@ pub use types::{
     ClanBackupCreateArgs, ClanBackupListArgs, ClanBackupRestoreArgs, ClanFlakeCreateArgs,
     ClanMachineBuildArgs, ClanMachineCreateArgs, ClanMachineDeleteArgs, ClanMachineInstallArgs,
     ClanMachineListArgs, ClanMachineUpdateArgs, ClanSecretsListArgs, ClanVmCreateArgs,
+    MachineListResult,
 };
@@ -1,8 +1,8 @@
-//! Parameter types for Clan MCP tools.
+//! Parameter and result types for Clan MCP tools.
 //!
 //! This module defines all parameter types used by the Clan infrastructure management
 //! tools. Each type corresponds to a specific Clan operation and includes field-level
-//! documentation with examples.
+//! documentation with examples. Tools with an output schema also have a result type.
 
 use rmcp::schemars;
 
@@ -70,12 +70,14 @@ pub struct ClanMachineListArgs {
 /// let args = ClanMachineUpdateArgs {
 ///     machines: None,
 ///     flake: None,
+///     confirmation_token: None,
 /// };
 ///
 /// // Update specific machines
 /// let args = ClanMachineUpdateArgs {
 ///     machines: Some(vec!["web1".to_string(), "web2".to_string()]),
 ///     flake: Some(".".to_string()),
+///     confirmation_token: None,
 /// };
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -86,6 +88,9 @@ pub struct ClanMachineUpdateArgs {
     /// Optional flake directory path
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flake: Option<String>,
+    /// Token from an earlier call that asked for confirmation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation_token: Option<String>,
 }
 
 /// Parameters for deleting a Clan machine configuration.
@@ -100,6 +105,7 @@ pub struct ClanMachineUpdateArgs {
 /// let args = ClanMachineDeleteArgs {
 ///     name: "old-server".to_string(),
 ///     flake: None,
+///     confirmation_token: None,
 /// };
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -109,6 +115,9 @@ pub struct ClanMachineDeleteArgs {
     /// Optional flake directory path
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flake: Option<String>,
+    /// Token from an earlier call that asked for confirmation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation_token: Option<String>,
 }
 
 /// Parameters for installing a Clan machine to a target host.
@@ -126,7 +135,7 @@ pub struct ClanMachineDeleteArgs {
 ///     machine: "webserver".to_string(),
 ///     target_host: "root@192.168.1.10".to_string(),
 ///     flake: None,
-///     confirm: Some(true),
+///     confirmation_token: None,
 /// };
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -138,9 +147,9 @@ pub struct ClanMachineInstallArgs {
     /// Optional flake directory path
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flake: Option<String>,
-    /// Confirm destructive operations (overwrites disk)
+    /// Token from an earlier call that asked for confirmation
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub confirm: Option<bool>,
+    pub confirmation_token: Option<String>,
 }
 
 /// Parameters for building a Clan machine configuration locally.
@@ -241,6 +250,7 @@ pub struct ClanBackupListArgs {
 ///     name: "backup-2024-01-01".to_string(),
 ///     service: Some("nginx".to_string()),
 ///     flake: None,
+///     confirmation_token: None,
 /// };
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -257,6 +267,9 @@ pub struct ClanBackupRestoreArgs {
     /// Optional flake directory path
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flake: Option<String>,
+    /// Token from an earlier call that asked for confirmation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation_token: Option<String>,
 }
 
 /// Parameters for creating a new Clan flake from a template.
@@ -404,3 +417,14 @@ pub struct ClanAnalyzeRosterArgs {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub flake: Option<String>,
 }
+
+// ===== Result Types =====
+
+/// Structured result of [`MachineTools::clan_machine_list`](crate::clan::MachineTools::clan_machine_list).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct MachineListResult {
+    /// Flake the machines were listed from
+    pub flake: String,
+    /// Names of the configured machines
+    pub machines: Vec<String>,
+}
@@ -0,0 +1,310 @@
+//! Tools for reviewing what the server has run.
+//!
+//! [`AuditTools`] answers questions like "what did you run on this machine
+//! yesterday?" from the recorded audit events, through the `audit_query` tool
+//! and the `audit://recent` resource. Both read the persistent audit trail
+//! when `[audit] file` is configured and the in-memory history of this
+//! process otherwise.
+
+use crate::common::config::Config;
+use crate::common::security::audit_query::{
+    audit_history, parse_time, query, AuditFilter, AuditQueryResult, AuditSource,
+};
+use crate::common::security::AuditLogger;
+use crate::common::structured::ToolOutput;
+use crate::common::tool_module::ToolModule;
+use chrono::Utc;
+use rmcp::handler::server::wrapper::Parameters;
+use rmcp::model::{CallToolResult, ReadResourceResult, ResourceContents};
+use rmcp::{schemars, ErrorData as McpError};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// URI of the resource listing recent audit events.
+pub const RECENT_URI: &str = "audit://recent";
+
+/// Events returned when no limit is given.
+const DEFAULT_LIMIT: usize = 50;
+
+/// Upper bound for the number of events returned.
+const MAX_LIMIT: usize = 500;
+
+/// Parameters for querying recorded audit events.
+///
+/// # Examples
+///
+/// ```
+/// use onix_mcp::common::audit_tools::AuditQueryArgs;
+///
+/// // Failed shell commands of the last day
+/// let args = AuditQueryArgs {
+///     tool: Some("run_in_shell".to_string()),
+///     since: Some("24h".to_string()),
+///     success: Some(false),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct AuditQueryArgs {
+    /// Only events about this tool (e.g., "clan_machine_update", "run_in_shell")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Start of the time range: RFC 3339 timestamp or age (e.g., "30m", "24h", "7d")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    /// End of the time range: RFC 3339 timestamp or age (e.g., "1h")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    /// Only tool calls that succeeded (true) or failed (false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    /// Minimum security level: "info", "warning", "error" or "critical"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Maximum number of events, newest first (default: 50, max: 500)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl AuditQueryArgs {
+    /// Parse the query string of an `audit://recent?tool=...&since=...` URI.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `invalid_params` error for unknown keys or malformed values.
+    pub fn from_query(query: &str) -> Result<Self, McpError> {
+        let mut args = Self::default();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = crate::common::roots::percent_decode(value)
+                .ok_or_else(|| invalid(format!("invalid encoding in '{}'", pair)))?;
+            match key {
+                "tool" => args.tool = Some(value),
+                "since" => args.since = Some(value),
+                "until" => args.until = Some(value),
+                "level" => args.level = Some(value),
+                "success" => {
+                    args.success = Some(value.parse().map_err(|_| {
+                        invalid(format!("success must be true or false, got '{}'", value))
+                    })?)
+                }
+                "limit" => {
+                    args.limit =
+                        Some(value.parse().map_err(|_| {
+                            invalid(format!("limit must be a number, got '{}'", value))
+                        })?)
+                }
+                _ => return Err(invalid(format!("unknown query parameter '{}'", key))),
+            }
+        }
+        Ok(args)
+    }
+
+    fn filter(&self) -> Result<AuditFilter, McpError> {
+        let now = Utc::now();
+        let time = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|v| parse_time(v, now))
+                .transpose()
+                .map_err(invalid)
+        };
+        Ok(AuditFilter {
+            tool: self.tool.clone(),
+            since: time(&self.since)?,
+            until: time(&self.until)?,
+            success: self.success,
+            min_level: self
+                .level
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(invalid)?,
+        })
+    }
+}
+
+fn invalid(message: String) -> McpError {
+    McpError::invalid_params(message, None)
+}
+
+/// Audit review tools.
+pub struct AuditTools {
+    audit: Arc<AuditLogger>,
+    config: Arc<Config>,
+}
+
+impl AuditTools {
+    /// Creates a new `AuditTools` instance reading the audit trail configured in `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
+    }
+
+    fn source(&self) -> AuditSource {
+        match &self.config.audit.file {
+            Some(path) => AuditSource::File(path.clone()),
+            None => AuditSource::Memory(audit_history()),
+        }
+    }
+
+    fn run(&self, args: &AuditQueryArgs) -> Result<AuditQueryResult, McpError> {
+        let filter = args.filter()?;
+        let limit = args.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        query(&self.source(), &filter, limit)
+            .map_err(|e| McpError::internal_error(format!("Failed to read audit log: {}", e), None))
+    }
+
+    /// Query recorded audit events by tool, time range, outcome and security level.
+    pub fn audit_query(
+        &self,
+        Parameters(args): Parameters<AuditQueryArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let start = Instant::now();
+        let result = self.run(&args);
+
+        self.audit.log_tool_invocation(
+            "audit_query",
+            serde_json::to_value(&args).ok(),
+            result.is_ok(),
+            result.as_ref().err().map(|e| e.message.to_string()),
+            start.elapsed().as_millis() as u64,
+        );
+
+        Ok(result?.to_tool_result())
+    }
+
+    /// Read `audit://recent`, optionally filtered by a query string with the
+    /// [`AuditQueryArgs`] fields (`audit://recent?tool=run_in_shell&since=24h`).
+    pub fn read_recent(&self, uri: &str) -> Result<ReadResourceResult, McpError> {
+        let query = uri
+            .strip_prefix(RECENT_URI)
+            .and_then(|rest| match rest {
+                "" => Some(""),
+                _ => rest.strip_prefix('?'),
+            })
+            .ok_or_else(|| {
+                McpError::resource_not_found("resource_not_found", Some(json!({ "uri": uri })))
+            })?;
+
+        let result = self.run(&AuditQueryArgs::from_query(query)?)?;
+        let content = serde_json::to_string_pretty(&result).map_err(|e| {
+            McpError::internal_error(format!("Failed to serialize audit events: {}", e), None)
+        })?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text: content,
+                meta: None,
+            }],
+        })
+    }
+}
+
+impl ToolModule for AuditTools {
+    fn audit_logger(&self) -> &Arc<AuditLogger> {
+        &self.audit
+    }
+
+    fn name(&self) -> &'static str {
+        "AuditTools"
+    }
+}
+
+impl ToolOutput for AuditQueryResult {
+    fn to_text(&self) -> String {
+        if self.events.is_empty() {
+            return format!("No matching audit events (source: {})", self.source);
+        }
+
+        let mut text = format!(
+            "{} of {} matching audit events, newest first (source: {})\n\n",
+            self.events.len(),
+            self.matched,
+            self.source
+        );
+        for event in &self.events {
+            text.push_str(&format!(
+                "{} {:<8} {}",
+                event.timestamp, event.level, event.event_type
+            ));
+            if let Some(tool) = &event.tool {
+                text.push_str(&format!(" {}", tool));
+            }
+            match event.success {
+                Some(true) => text.push_str(" ok"),
+                Some(false) => text.push_str(" FAILED"),
+                None => {}
+            }
+            if let Some(ms) = event.duration_ms {
+                text.push_str(&format!(" {}ms", ms));
+            }
+            for field in ["parameters", "error", "reason"] {
+                if let Some(value) = event.event.get(field).filter(|v| !v.is_null()) {
+                    text.push_str(&format!("\n    {}: {}", field, value));
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_string() {
+        let args = AuditQueryArgs::from_query(
+            "tool=clan_machine_update&since=24h&success=false&level=warning&limit=5",
+        )
+        .unwrap();
+        assert_eq!(args.tool.as_deref(), Some("clan_machine_update"));
+        assert_eq!(args.success, Some(false));
+        assert_eq!(args.limit, Some(5));
+
+        let filter = args.filter().unwrap();
+        assert!(filter.since.is_some());
+        assert_eq!(
+            filter.min_level,
+            Some(crate::common::security::audit::SecurityLevel::Warning)
+        );
+
+        assert!(AuditQueryArgs::from_query("bogus=1").is_err());
+        assert!(AuditQueryArgs::from_query("success=maybe").is_err());
+        assert!(AuditQueryArgs::from_query("level=loud")
+            .unwrap()
+            .filter()
+            .is_err());
+    }
+
+    #[test]
+    fn test_recent_resource() {
+        let tools = AuditTools::with_config(Arc::new(AuditLogger::new()), Arc::default());
+        crate::common::security::audit_logger().log_tool_invocation(
+            "clan_machine_update",
+            Some(json!({"machine": "web01"})),
+            true,
+            None,
+            1234,
+        );
+
+        let result = tools
+            .read_recent("audit://recent?tool=clan_machine_update&since=1h")
+            .unwrap();
+        let ResourceContents::TextResourceContents { text, .. } = &result.contents[0] else {
+            panic!("expected text contents");
+        };
+        let parsed: AuditQueryResult = serde_json::from_str(text).unwrap();
+        assert_eq!(parsed.source, "memory");
+        // Other tests log through the global logger concurrently
+        assert!(parsed
+            .events
+            .iter()
+            .any(|e| e.duration_ms == Some(1234) && e.event["parameters"]["machine"] == "web01"));
+
+        assert!(tools.read_recent("audit://recently").is_err());
+    }
+}
@@ -1,19 +1,50 @@
 use std::collections::HashMap;
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-/// TTL cache with capacity limits for expensive operations.
+/// Second-level storage behind a [`TtlCache`], such as a
+/// [`DiskCache`](crate::common::disk_cache::DiskCache).
+///
+/// Entries are written through on insert and read back on a memory miss.
+/// Expiry is wall-clock time so that it survives restarts; `None` means the
+/// entry never expires.
+pub trait CacheBackend<K, V>: Send + Sync {
+    /// Unexpired value of `key` and when it expires.
+    fn load(&self, key: &K) -> Option<(V, Option<SystemTime>)>;
+
+    /// Store `value` for `key` until `expires_at`.
+    fn store(&self, key: &K, value: &V, expires_at: Option<SystemTime>);
+}
+
+/// Weight of an entry, compared against [`TtlCache::with_max_weight`].
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+/// TTL cache with LRU eviction for expensive operations.
 ///
 /// This cache combines time-based expiration (TTL) with capacity limits
 /// to prevent unbounded memory growth. When the cache reaches its maximum
-/// capacity, the oldest entry (by insertion time) is evicted.
+/// number of entries, or optionally its maximum total weight (e.g. bytes),
+/// the least recently used entries are evicted.
 ///
 /// # Features
 ///
-/// - **Time-based expiration**: Entries automatically expire after TTL
+/// - **Time-based expiration**: Entries automatically expire after TTL, except
+///   those inserted with [`insert_permanent`](Self::insert_permanent)
 /// - **Capacity limits**: Maximum number of entries enforced
-/// - **LRU-like eviction**: Oldest entries removed when at capacity
+/// - **Weight limits**: Optional maximum total weight, set with
+///   [`with_max_weight`](Self::with_max_weight)
+/// - **LRU eviction**: Lookups, inserts and evictions are O(1)
 /// - **Thread-safe**: Uses Mutex for concurrent access
+/// - **Statistics**: Hit, miss, eviction and expiration counters, see
+///   [`stats`](Self::stats)
+/// - **Optional persistence**: A [`CacheBackend`] set with
+///   [`with_backend`](Self::with_backend) keeps entries across restarts
+///
+/// Expired entries are dropped when looked up, and the others by
+/// [`cleanup`](Self::cleanup), which
+/// [`CacheRegistry::spawn_sweeper`](crate::common::cache_registry::CacheRegistry::spawn_sweeper)
+/// calls in the background.
 ///
 /// # Examples
 ///
@@ -29,15 +60,203 @@ use std::time::{Duration, Instant};
 /// }
 /// ```
 pub struct TtlCache<K, V> {
-    data: Mutex<HashMap<K, CacheEntry<V>>>,
+    data: Mutex<LruList<K, V>>,
     ttl: Duration,
     max_capacity: usize,
+    max_weight: usize,
+    weigher: Option<Weigher<K, V>>,
+    backend: Option<Arc<dyn CacheBackend<K, V>>>,
+    counters: Counters,
 }
 
 struct CacheEntry<V> {
     value: V,
-    expires_at: Instant,
-    inserted_at: Instant,
+    /// `None` for entries that never expire
+    expires_at: Option<Instant>,
+    weight: usize,
+}
+
+impl<V> CacheEntry<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+/// Counters behind [`CacheStats`].
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+/// Usage statistics of a [`TtlCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered from memory or the backend
+    pub hits: u64,
+    /// Lookups that found nothing
+    pub misses: u64,
+    /// Entries evicted to respect the capacity or weight limit
+    pub evictions: u64,
+    /// Expired entries removed by lookups or the sweeper
+    pub expirations: u64,
+    /// Entries currently held, including expired ones not yet removed
+    pub entries: usize,
+    /// Total weight of the entries currently held
+    pub weight: usize,
+}
+
+/// Index of no node.
+const NIL: usize = usize::MAX;
+
+struct Node<K, V> {
+    key: K,
+    entry: CacheEntry<V>,
+    /// Towards the most recently used node
+    prev: usize,
+    /// Towards the least recently used node
+    next: usize,
+}
+
+/// Entries in recency order: a hash map from key to slot, and a doubly
+/// linked list threaded through the slots.
+struct LruList<K, V> {
+    index: HashMap<K, usize>,
+    slots: Vec<Option<Node<K, V>>>,
+    /// Slots freed by removals, reused before growing `slots`
+    free: Vec<usize>,
+    /// Most recently used node
+    head: usize,
+    /// Least recently used node
+    tail: usize,
+    weight: usize,
+}
+
+impl<K: Eq + std::hash::Hash + Clone, V> LruList<K, V> {
+    fn new() -> Self {
+        Self {
+            index: HashMap::new(),
+            slots: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            weight: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn node(&self, slot: usize) -> &Node<K, V> {
+        self.slots[slot].as_ref().expect("linked slot is occupied")
+    }
+
+    fn node_mut(&mut self, slot: usize) -> &mut Node<K, V> {
+        self.slots[slot].as_mut().expect("linked slot is occupied")
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = {
+            let node = self.node(slot);
+            (node.prev, node.next)
+        };
+        match prev {
+            NIL => self.head = next,
+            prev => self.node_mut(prev).next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.node_mut(next).prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        let head = self.head;
+        {
+            let node = self.node_mut(slot);
+            node.prev = NIL;
+            node.next = head;
+        }
+        match head {
+            NIL => self.tail = slot,
+            head => self.node_mut(head).prev = slot,
+        }
+        self.head = slot;
+    }
+
+    /// Entry of `key`, marked as most recently used.
+    fn get(&mut self, key: &K) -> Option<&CacheEntry<V>> {
+        let slot = *self.index.get(key)?;
+        self.unlink(slot);
+        self.push_front(slot);
+        Some(&self.node(slot).entry)
+    }
+
+    /// Insert or replace the entry of `key` as most recently used.
+    fn insert(&mut self, key: K, entry: CacheEntry<V>) {
+        self.remove(&key);
+        self.weight += entry.weight;
+        let node = Node {
+            key: key.clone(),
+            entry,
+            prev: NIL,
+            next: NIL,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.slots.push(Some(node));
+                self.slots.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        self.push_front(slot);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<CacheEntry<V>> {
+        let slot = self.index.remove(key)?;
+        Some(self.take(slot))
+    }
+
+    /// Remove the least recently used entry.
+    fn pop_lru(&mut self) -> Option<CacheEntry<V>> {
+        let slot = self.tail;
+        if slot == NIL {
+            return None;
+        }
+        let key = self.node(slot).key.clone();
+        self.index.remove(&key);
+        Some(self.take(slot))
+    }
+
+    fn take(&mut self, slot: usize) -> CacheEntry<V> {
+        self.unlink(slot);
+        let node = self.slots[slot].take().expect("linked slot is occupied");
+        self.free.push(slot);
+        self.weight -= node.entry.weight;
+        node.entry
+    }
+
+    /// Remove entries matching `expired`, returning how many were removed.
+    fn remove_where(&mut self, expired: impl Fn(&CacheEntry<V>) -> bool) -> usize {
+        let keys: Vec<K> = self
+            .slots
+            .iter()
+            .flatten()
+            .filter(|node| expired(&node.entry))
+            .map(|node| node.key.clone())
+            .collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        keys.len()
+    }
 }
 
 impl<K: Eq + std::hash::Hash + Clone, V: Clone> TtlCache<K, V> {
@@ -55,97 +274,210 @@ impl<K: Eq + std::hash::Hash + Clone, V: Clone> TtlCache<K, V> {
     /// use onix_mcp::common::cache::TtlCache;
     ///
     /// // Cache with 10-minute TTL and max 1000 entries
-    /// let cache = TtlCache::new(Duration::from_secs(600), 1000);
+    /// let cache: TtlCache<String, String> = TtlCache::new(Duration::from_secs(600), 1000);
     /// ```
     pub fn new(ttl: Duration, max_capacity: usize) -> Self {
         Self {
-            data: Mutex::new(HashMap::new()),
+            data: Mutex::new(LruList::new()),
             ttl,
             max_capacity,
+            max_weight: 0,
+            weigher: None,
+            backend: None,
+            counters: Counters::default(),
+        }
+    }
+
+    /// Limit the total weight of the entries to `max_weight`, as measured by
+    /// `weigher` (e.g. the size of the value in bytes).
+    ///
+    /// Least recently used entries are evicted to make room; a single entry
+    /// heavier than the limit is not kept in memory.
+    pub fn with_max_weight(
+        self,
+        max_weight: usize,
+        weigher: impl Fn(&K, &V) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            max_weight,
+            weigher: Some(Box::new(weigher)),
+            ..self
+        }
+    }
+
+    /// Write entries through to `backend` and fall back to it on misses.
+    pub fn with_backend(self, backend: Arc<dyn CacheBackend<K, V>>) -> Self {
+        Self {
+            backend: Some(backend),
+            ..self
         }
     }
 
     /// Get a value from the cache if it exists and hasn't expired
+    ///
+    /// A hit marks the entry as most recently used. On a miss the backend,
+    /// if any, is consulted and a hit is kept in memory until it expires,
+    /// but no longer than this cache's TTL unless it never expires.
     pub fn get(&self, key: &K) -> Option<V> {
-        let mut data = self.data.lock().ok()?;
-
-        if let Some(entry) = data.get(key) {
-            if Instant::now() < entry.expires_at {
-                return Some(entry.value.clone());
-            } else {
-                // Remove expired entry
-                data.remove(key);
+        {
+            let mut data = self.data.lock().ok()?;
+
+            let now = Instant::now();
+            match data.get(key) {
+                Some(entry) if !entry.is_expired(now) => {
+                    self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                    return Some(entry.value.clone());
+                }
+                Some(_) => {
+                    // Remove expired entry
+                    data.remove(key);
+                    self.counters.expirations.fetch_add(1, Ordering::Relaxed);
+                }
+                None => {}
             }
         }
 
-        None
+        match self.load(key) {
+            Some(value) => {
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Some(value)
+            }
+            None => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Read `key` from the backend into memory.
+    fn load(&self, key: &K) -> Option<V> {
+        let (value, expires_at) = self.backend.as_ref()?.load(key)?;
+        let ttl = match expires_at {
+            Some(at) => Some(at.duration_since(SystemTime::now()).ok()?.min(self.ttl)),
+            None => None,
+        };
+        self.insert_entry(key.clone(), value.clone(), ttl);
+        Some(value)
     }
 
     /// Insert a value into the cache.
     ///
-    /// If the cache is at maximum capacity, the oldest entry (by insertion time)
-    /// will be evicted before inserting the new entry.
+    /// If the cache is at maximum capacity or weight, the least recently
+    /// used entries are evicted before inserting the new entry. With a
+    /// backend, the entry is also written through to it.
     ///
     /// # Arguments
     ///
     /// * `key` - The key to insert
     /// * `value` - The value to cache
     pub fn insert(&self, key: K, value: V) {
+        if let Some(backend) = &self.backend {
+            backend.store(&key, &value, Some(SystemTime::now() + self.ttl));
+        }
+        self.insert_entry(key, value, Some(self.ttl));
+    }
+
+    /// Insert a value that expires after `ttl` instead of the cache's TTL,
+    /// such as a short-lived negative result.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        if let Some(backend) = &self.backend {
+            backend.store(&key, &value, Some(SystemTime::now() + ttl));
+        }
+        self.insert_entry(key, value, Some(ttl));
+    }
+
+    /// Insert a value that never expires, such as a result computed from an
+    /// immutable input. It is still subject to capacity eviction.
+    pub fn insert_permanent(&self, key: K, value: V) {
+        if let Some(backend) = &self.backend {
+            backend.store(&key, &value, None);
+        }
+        self.insert_entry(key, value, None);
+    }
+
+    fn insert_entry(&self, key: K, value: V, ttl: Option<Duration>) {
+        let weight = self.weigher.as_ref().map_or(0, |weigh| weigh(&key, &value));
+
         if let Ok(mut data) = self.data.lock() {
-            let now = Instant::now();
+            if self.max_weight > 0 && weight > self.max_weight {
+                // Too heavy to keep; drop any older value rather than serve it
+                data.remove(&key);
+                return;
+            }
+            data.remove(&key);
 
-            // Evict oldest entry if at capacity (and max_capacity > 0)
-            if self.max_capacity > 0 && data.len() >= self.max_capacity && !data.contains_key(&key)
+            // Evict least recently used entries until the new one fits
+            let mut evicted = 0;
+            while (self.max_capacity > 0 && data.len() >= self.max_capacity)
+                || (self.max_weight > 0 && data.weight + weight > self.max_weight)
             {
-                // Find and remove the oldest entry by insertion time
-                if let Some(oldest_key) = data
-                    .iter()
-                    .min_by_key(|(_, entry)| entry.inserted_at)
-                    .map(|(k, _)| k.clone())
-                {
-                    data.remove(&oldest_key);
+                if data.pop_lru().is_none() {
+                    break;
                 }
+                evicted += 1;
             }
+            self.counters
+                .evictions
+                .fetch_add(evicted, Ordering::Relaxed);
 
             data.insert(
                 key,
                 CacheEntry {
                     value,
-                    expires_at: now + self.ttl,
-                    inserted_at: now,
+                    expires_at: ttl.map(|ttl| Instant::now() + ttl),
+                    weight,
                 },
             );
         }
     }
 
     /// Clear all entries from the cache
-    #[allow(dead_code)]
     pub fn clear(&self) {
         if let Ok(mut data) = self.data.lock() {
-            data.clear();
+            *data = LruList::new();
         }
     }
 
     /// Remove expired entries
-    #[allow(dead_code)]
+    ///
+    /// This is O(n) in the number of entries, so it runs periodically in the
+    /// background rather than on insert.
     pub fn cleanup(&self) {
         if let Ok(mut data) = self.data.lock() {
             let now = Instant::now();
-            data.retain(|_, entry| now < entry.expires_at);
+            let removed = data.remove_where(|entry| entry.is_expired(now));
+            self.counters
+                .expirations
+                .fetch_add(removed as u64, Ordering::Relaxed);
         }
     }
 
     /// Get the number of entries in the cache (including expired)
-    #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.data.lock().map(|d| d.len()).unwrap_or(0)
     }
 
     /// Check if the cache is empty
-    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Counters since the cache was created, and its current size.
+    pub fn stats(&self) -> CacheStats {
+        let (entries, weight) = self
+            .data
+            .lock()
+            .map(|d| (d.len(), d.weight))
+            .unwrap_or_default();
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            expirations: self.counters.expirations.load(Ordering::Relaxed),
+            entries,
+            weight,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -173,6 +505,22 @@ mod tests {
         assert_eq!(cache.get(&"key1".to_string()), None);
     }
 
+    #[test]
+    fn test_cache_custom_ttl() {
+        let cache = TtlCache::new(Duration::from_secs(3600), 1000);
+
+        cache.insert_with_ttl(
+            "short".to_string(),
+            "value".to_string(),
+            Duration::from_millis(100),
+        );
+        cache.insert("long".to_string(), "value".to_string());
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(cache.get(&"short".to_string()), None);
+        assert_eq!(cache.get(&"long".to_string()), Some("value".to_string()));
+    }
+
     #[test]
     fn test_cache_cleanup() {
         let cache = TtlCache::new(Duration::from_millis(100), 1000);
@@ -209,6 +557,75 @@ mod tests {
         assert_eq!(cache.get(&"key4".to_string()), Some("value4".to_string()));
     }
 
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let cache = TtlCache::new(Duration::from_secs(60), 3);
+        for key in ["key1", "key2", "key3"] {
+            cache.insert(key.to_string(), key.to_string());
+        }
+
+        // Reading key1 makes key2 the least recently used
+        assert!(cache.get(&"key1".to_string()).is_some());
+        cache.insert("key4".to_string(), "key4".to_string());
+        assert_eq!(cache.get(&"key2".to_string()), None);
+        assert!(cache.get(&"key1".to_string()).is_some());
+
+        // Replacing a value refreshes it as well
+        cache.insert("key3".to_string(), "key3b".to_string());
+        cache.insert("key5".to_string(), "key5".to_string());
+        assert_eq!(cache.get(&"key4".to_string()), None);
+        assert_eq!(cache.get(&"key3".to_string()), Some("key3b".to_string()));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_cache_weight_limit() {
+        let cache = TtlCache::new(Duration::from_secs(60), 100)
+            .with_max_weight(10, |_: &String, value: &String| value.len());
+
+        cache.insert("a".to_string(), "xxxx".to_string());
+        cache.insert("b".to_string(), "xxxx".to_string());
+        assert_eq!(cache.stats().weight, 8);
+
+        // Needs 4 more: evicts a, the least recently used
+        cache.insert("c".to_string(), "xxxx".to_string());
+        assert_eq!(cache.get(&"a".to_string()), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().weight, 8);
+
+        // Heavier than the whole cache: not kept, and the old value is gone
+        cache.insert("b".to_string(), "x".repeat(11));
+        assert_eq!(cache.get(&"b".to_string()), None);
+        assert_eq!(cache.get(&"c".to_string()), Some("xxxx".to_string()));
+        assert_eq!(cache.stats().weight, 4);
+    }
+
+    #[test]
+    fn test_cache_stats() {
+        let cache = TtlCache::new(Duration::from_millis(100), 2);
+        cache.insert("key1".to_string(), "value1".to_string());
+        cache.insert("key2".to_string(), "value2".to_string());
+        cache.insert("key3".to_string(), "value3".to_string());
+        assert!(cache.get(&"key3".to_string()).is_some());
+        assert!(cache.get(&"key1".to_string()).is_none());
+
+        thread::sleep(Duration::from_millis(150));
+        assert!(cache.get(&"key3".to_string()).is_none());
+        cache.cleanup();
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                evictions: 1,
+                expirations: 2,
+                entries: 0,
+                weight: 0,
+            }
+        );
+    }
+
     #[test]
     fn test_cache_unlimited_capacity() {
         // Cache with 0 capacity = unlimited
@@ -221,6 +638,56 @@ mod tests {
         assert_eq!(cache.len(), 100);
     }
 
+    #[derive(Default)]
+    struct MapBackend(Mutex<HashMap<String, (String, Option<SystemTime>)>>);
+
+    impl CacheBackend<String, String> for MapBackend {
+        fn load(&self, key: &String) -> Option<(String, Option<SystemTime>)> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        fn store(&self, key: &String, value: &String, expires_at: Option<SystemTime>) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.clone(), (value.clone(), expires_at));
+        }
+    }
+
+    #[test]
+    fn test_cache_backend() {
+        let backend = Arc::new(MapBackend::default());
+        let cache = TtlCache::new(Duration::from_secs(60), 10).with_backend(backend.clone());
+        cache.insert("key1".to_string(), "value1".to_string());
+        assert!(backend.load(&"key1".to_string()).is_some());
+
+        // A fresh cache over the same backend reads entries back
+        let cache = TtlCache::new(Duration::from_secs(60), 10).with_backend(backend.clone());
+        assert_eq!(cache.get(&"key1".to_string()), Some("value1".to_string()));
+        assert_eq!(cache.len(), 1);
+
+        backend.store(
+            &"stale".to_string(),
+            &"old".to_string(),
+            Some(SystemTime::now() - Duration::from_secs(1)),
+        );
+        assert_eq!(cache.get(&"stale".to_string()), None);
+    }
+
+    #[test]
+    fn test_cache_permanent_entries() {
+        let backend = Arc::new(MapBackend::default());
+        let cache = TtlCache::new(Duration::from_millis(100), 10).with_backend(backend.clone());
+        cache.insert_permanent("pinned".to_string(), "value".to_string());
+        cache.insert("floating".to_string(), "value".to_string());
+        assert_eq!(backend.load(&"pinned".to_string()).unwrap().1, None);
+
+        thread::sleep(Duration::from_millis(150));
+        cache.cleanup();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"pinned".to_string()), Some("value".to_string()));
+    }
+
     #[test]
     fn test_cache_update_existing_key() {
         let cache = TtlCache::new(Duration::from_secs(60), 3);
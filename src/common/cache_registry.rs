@@ -1,4 +1,6 @@
-use crate::common::cache::TtlCache;
+use crate::common::cache::{CacheStats, TtlCache};
+use crate::common::config::{CacheConfig, CacheSettings};
+use crate::common::disk_cache::DiskCache;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -17,17 +19,23 @@ use std::time::Duration;
 /// - `prefetch`: 24 hours - URL content hashes are immutable
 /// - `closure_size`: 30 minutes - Closure sizes are stable for given derivations
 /// - `derivation`: 30 minutes - Derivation info is immutable for a given hash
+/// - `completion`: 1 hour - Attribute, option and machine name lists for argument completion
+/// - `revision`: 1 minute - Lock revisions of floating flake references, see
+///   [`Revision`](crate::common::revision::Revision)
 ///
 /// # Example
 ///
 /// ```no_run
 /// use onix_mcp::common::cache_registry::CacheRegistry;
+/// use onix_mcp::common::security::audit_logger;
+/// use onix_mcp::nix::{BuildTools, PackageTools};
 /// use std::sync::Arc;
 ///
+/// let audit = audit_logger();
 /// let caches = Arc::new(CacheRegistry::new());
 ///
 /// // Use in tools
-/// let package_tools = PackageTools::new(audit, caches.clone());
+/// let package_tools = PackageTools::new(audit.clone(), caches.clone());
 /// let build_tools = BuildTools::new(audit, caches.clone());
 /// ```
 #[derive(Clone)]
@@ -52,6 +60,12 @@ pub struct CacheRegistry {
 
     /// Cache for derivation info (TTL: 30 minutes)
     pub derivation: Arc<TtlCache<String, String>>,
+
+    /// Cache for argument completion candidates (TTL: 1 hour)
+    pub completion: Arc<TtlCache<String, String>>,
+
+    /// Cache for resolved flake lock revisions (TTL: 1 minute)
+    pub revision: Arc<TtlCache<String, String>>,
 }
 
 impl CacheRegistry {
@@ -69,19 +83,121 @@ impl CacheRegistry {
     /// - `prefetch`: 1000 entries - URL hashes are immutable
     /// - `closure_size`: 200 entries - Expensive closure calculations
     /// - `derivation`: 200 entries - Derivation analysis
+    /// - `completion`: 200 entries - Candidate lists per flake and option path
+    /// - `revision`: 100 entries - One per floating flake reference
+    ///
+    /// Each cache also holds at most 32 MiB of keys and values, evicting the
+    /// least recently used entries beyond either limit.
     pub fn new() -> Self {
+        Self::from_config(&CacheConfig::default())
+    }
+
+    /// Create a cache registry with TTLs and capacities from configuration.
+    ///
+    /// The defaults listed on [`CacheRegistry::new`] apply to any cache not
+    /// overridden in the `[caches]` section of the config file.
+    pub fn from_config(config: &CacheConfig) -> Self {
+        Self::build(config, None)
+    }
+
+    /// Create a cache registry whose caches are persisted in `store`.
+    ///
+    /// Each cache keeps its configured TTL and capacity in memory; `store`
+    /// holds entries across restarts under the cache's configuration key.
+    pub fn persistent(config: &CacheConfig, store: Arc<DiskCache>) -> Self {
+        Self::build(config, Some(&store))
+    }
+
+    fn build(config: &CacheConfig, store: Option<&Arc<DiskCache>>) -> Self {
+        let cache = |name: &'static str, settings: &CacheSettings| {
+            let cache = TtlCache::new(settings.ttl(), settings.capacity)
+                .with_max_weight(settings.max_bytes, |key: &String, value: &String| {
+                    key.len() + value.len()
+                });
+            Arc::new(match store {
+                Some(store) => cache.with_backend(store.backend(name)),
+                None => cache,
+            })
+        };
+
         Self {
-            locate: Arc::new(TtlCache::new(Duration::from_secs(300), 200)), // 5 min, 200 entries
-            search: Arc::new(TtlCache::new(Duration::from_secs(600), 1000)), // 10 min, 1000 entries
-            package_info: Arc::new(TtlCache::new(Duration::from_secs(1800), 500)), // 30 min, 500 entries
-            eval: Arc::new(TtlCache::new(Duration::from_secs(300), 500)), // 5 min, 500 entries
-            prefetch: Arc::new(TtlCache::new(Duration::from_secs(86400), 1000)), // 24 hours, 1000 entries
-            closure_size: Arc::new(TtlCache::new(Duration::from_secs(1800), 200)), // 30 min, 200 entries
-            derivation: Arc::new(TtlCache::new(Duration::from_secs(1800), 200)), // 30 min, 200 entries
+            locate: cache("locate", &config.locate),
+            search: cache("search", &config.search),
+            package_info: cache("package_info", &config.package_info),
+            eval: cache("eval", &config.eval),
+            prefetch: cache("prefetch", &config.prefetch),
+            closure_size: cache("closure_size", &config.closure_size),
+            derivation: cache("derivation", &config.derivation),
+            completion: cache("completion", &config.completion),
+            revision: cache("revision", &config.revision),
         }
     }
 }
 
+impl CacheRegistry {
+    /// All caches with their configuration key.
+    pub fn entries(&self) -> [(&'static str, &Arc<TtlCache<String, String>>); 9] {
+        [
+            ("locate", &self.locate),
+            ("search", &self.search),
+            ("package_info", &self.package_info),
+            ("eval", &self.eval),
+            ("prefetch", &self.prefetch),
+            ("closure_size", &self.closure_size),
+            ("derivation", &self.derivation),
+            ("completion", &self.completion),
+            ("revision", &self.revision),
+        ]
+    }
+
+    /// Hit, miss and eviction counters of every cache.
+    pub fn stats(&self) -> Vec<(&'static str, CacheStats)> {
+        self.entries()
+            .into_iter()
+            .map(|(name, cache)| (name, cache.stats()))
+            .collect()
+    }
+
+    /// Remove expired entries from every cache each `interval` on a
+    /// background thread, logging cache statistics at debug level.
+    ///
+    /// The thread only holds weak references and exits once the caches
+    /// are dropped.
+    pub fn spawn_sweeper(&self, interval: Duration) {
+        let caches: Vec<_> = self
+            .entries()
+            .into_iter()
+            .map(|(name, cache)| (name, Arc::downgrade(cache)))
+            .collect();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let mut alive = false;
+            for (name, cache) in &caches {
+                let Some(cache) = cache.upgrade() else {
+                    continue;
+                };
+                alive = true;
+                cache.cleanup();
+                let stats = cache.stats();
+                tracing::debug!(
+                    cache = name,
+                    hits = stats.hits,
+                    misses = stats.misses,
+                    evictions = stats.evictions,
+                    expirations = stats.expirations,
+                    entries = stats.entries,
+                    bytes = stats.weight,
+                    "Cache statistics"
+                );
+            }
+            if !alive {
+                break;
+            }
+        });
+    }
+}
+
 impl Default for CacheRegistry {
     fn default() -> Self {
         Self::new()
@@ -1,17 +1,132 @@
 use crate::common::cache::TtlCache;
+use crate::common::revision::Revision;
+use crate::common::structured::ToolOutput;
+use once_cell::sync::Lazy;
 use rmcp::model::{CallToolResult, Content};
 use rmcp::ErrorData as McpError;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::OnceCell;
+
+/// Result of one execution, shared by every caller waiting for it.
+type Flight = Arc<OnceCell<Result<String, McpError>>>;
+
+/// Executions in progress, by cache (address) and key.
+///
+/// An entry only lives while a caller holding the cache waits on it, so
+/// cache addresses cannot be reused in the meantime.
+static FLIGHTS: Lazy<Mutex<HashMap<(usize, String), Flight>>> = Lazy::new(Default::default);
+
+/// Removes a flight from [`FLIGHTS`] once it is done, or once its last
+/// waiter has given up.
+struct FlightGuard {
+    key: (usize, String),
+    flight: Flight,
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        let mut flights = FLIGHTS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(current) = flights.get(&self.key) {
+            // Held by the map and this guard only: no one else is waiting
+            let abandoned = Arc::strong_count(&self.flight) == 2;
+            if Arc::ptr_eq(current, &self.flight) && (self.flight.initialized() || abandoned) {
+                flights.remove(&self.key);
+            }
+        }
+    }
+}
 
 /// Helper for executing operations with caching
+///
+/// Concurrent calls for the same key share one execution: the first caller
+/// runs its executor and the others wait for its result. Only successful
+/// results are cached; an error is returned to the callers waiting at the
+/// time and the next call executes again.
 pub struct CachedExecutor {
     cache: Arc<TtlCache<String, String>>,
+    revision: Option<Revision>,
 }
 
 impl CachedExecutor {
     pub fn new(cache: Arc<TtlCache<String, String>>) -> Self {
-        Self { cache }
+        Self {
+            cache,
+            revision: None,
+        }
+    }
+
+    /// Scope cache keys to `revision`, the inputs results are computed from.
+    ///
+    /// Results for an immutable revision are cached without expiry.
+    pub fn at_revision(self, revision: Revision) -> Self {
+        Self {
+            revision: Some(revision),
+            ..self
+        }
+    }
+
+    fn scoped(&self, key: &str) -> String {
+        match &self.revision {
+            Some(revision) => revision.scope(key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Cached value of `key` if `reuse` accepts it, otherwise the result of
+    /// the execution in progress for `key` or of `executor`.
+    async fn get_or_execute<F, Fut>(
+        &self,
+        key: &str,
+        reuse: impl Fn(&str) -> bool,
+        executor: F,
+    ) -> Result<String, McpError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, McpError>>,
+    {
+        let cached = || self.get(key).filter(|value| reuse(value));
+        if let Some(value) = cached() {
+            return Ok(value);
+        }
+
+        let flight_key = (Arc::as_ptr(&self.cache) as usize, self.scoped(key));
+        let flight = FLIGHTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(flight_key.clone())
+            .or_default()
+            .clone();
+        let guard = FlightGuard {
+            key: flight_key,
+            flight,
+        };
+
+        guard
+            .flight
+            .get_or_init(|| async {
+                // A flight that finished since the check above filled the cache
+                if let Some(value) = cached() {
+                    return Ok(value);
+                }
+                let result = executor().await;
+                if let Ok(value) = &result {
+                    self.store(key, value.clone());
+                }
+                result
+            })
+            .await
+            .clone()
+    }
+
+    fn store(&self, key: &str, value: String) {
+        let key = self.scoped(key);
+        match &self.revision {
+            Some(revision) if revision.is_immutable() => self.cache.insert_permanent(key, value),
+            _ => self.cache.insert(key, value),
+        }
     }
 
     /// Execute with cache-check-execute-cache pattern for string results
@@ -31,16 +146,9 @@ impl CachedExecutor {
         F: FnOnce() -> Fut,
         Fut: Future<Output = Result<String, McpError>>,
     {
-        // Check cache first
-        if let Some(cached_result) = self.cache.get(&cache_key) {
-            return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
-        }
-
-        // Execute the operation to get string result
-        let result_string = executor().await?;
-
-        // Cache the result
-        self.cache.insert(cache_key, result_string.clone());
+        // Check cache first, otherwise execute (or join an identical
+        // execution in progress) and cache the result
+        let result_string = self.get_or_execute(&cache_key, |_| true, executor).await?;
 
         Ok(CallToolResult::success(vec![Content::text(result_string)]))
     }
@@ -60,7 +168,7 @@ impl CachedExecutor {
         Fut: Future<Output = Result<CallToolResult, McpError>>,
     {
         // Check cache first
-        if let Some(cached_result) = self.cache.get(&cache_key) {
+        if let Some(cached_result) = self.get(&cache_key) {
             return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
         }
 
@@ -91,14 +199,49 @@ impl CachedExecutor {
         self.execute_with_string_cache(cache_key, executor).await
     }
 
+    /// Execute with cache-check-execute-cache pattern for structured results
+    ///
+    /// The value is cached as JSON and the text content is rendered from it,
+    /// so cached and fresh results carry the same structured content.
+    /// Entries that no longer deserialize (e.g. after a format change) are
+    /// treated as cache misses.
+    pub async fn execute_with_structured_cache<T, F, Fut>(
+        &self,
+        cache_key: String,
+        executor: F,
+    ) -> Result<CallToolResult, McpError>
+    where
+        T: ToolOutput + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, McpError>>,
+    {
+        let json = self
+            .get_or_execute(
+                &cache_key,
+                |cached| serde_json::from_str::<T>(cached).is_ok(),
+                || async {
+                    let value = executor().await?;
+                    serde_json::to_string(&value).map_err(|e| {
+                        McpError::internal_error(format!("Failed to serialize result: {}", e), None)
+                    })
+                },
+            )
+            .await?;
+
+        let value = serde_json::from_str::<T>(&json).map_err(|e| {
+            McpError::internal_error(format!("Failed to deserialize result: {}", e), None)
+        })?;
+        Ok(value.to_tool_result())
+    }
+
     /// Get a value from cache without execution
     pub fn get(&self, key: &str) -> Option<String> {
-        self.cache.get(&key.to_string())
+        self.cache.get(&self.scoped(key))
     }
 
     /// Insert a value into cache
     pub fn insert(&self, key: String, value: String) {
-        self.cache.insert(key, value);
+        self.store(&key, value);
     }
 
     /// Clear the cache
@@ -110,6 +253,7 @@ impl CachedExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::Duration;
 
     #[tokio::test]
@@ -156,6 +300,48 @@ mod tests {
         );
     }
 
+    use rmcp::schemars;
+
+    #[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    struct Count {
+        value: u32,
+    }
+
+    impl ToolOutput for Count {
+        fn to_text(&self) -> String {
+            format!("count: {}", self.value)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_structured_cache_round_trip() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60), 100));
+        let executor = CachedExecutor::new(cache.clone());
+
+        let fresh = executor
+            .execute_with_structured_cache("count".to_string(), || async { Ok(Count { value: 3 }) })
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.get(&"count".to_string()),
+            Some(r#"{"value":3}"#.to_string())
+        );
+
+        let cached = executor
+            .execute_with_structured_cache::<Count, _, _>("count".to_string(), || async {
+                panic!("Should not execute when cached");
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(cached.structured_content, fresh.structured_content);
+        assert_eq!(
+            cached.structured_content,
+            Some(serde_json::json!({"value": 3}))
+        );
+        assert_eq!(cached.content, fresh.content);
+    }
+
     #[tokio::test]
     async fn test_formatted_cache_key() {
         let cache = Arc::new(TtlCache::new(Duration::from_secs(60), 100));
@@ -178,4 +364,85 @@ mod tests {
             Some("value".to_string())
         );
     }
+
+    #[tokio::test]
+    async fn test_revision_scoped_keys() {
+        let cache = Arc::new(TtlCache::new(Duration::from_millis(100), 100));
+
+        for revision in [Revision::floating("r1"), Revision::immutable("r2")] {
+            CachedExecutor::new(cache.clone())
+                .at_revision(revision)
+                .execute_with_string_cache("hello".to_string(), || async {
+                    Ok("value".to_string())
+                })
+                .await
+                .unwrap();
+        }
+        assert!(cache.get(&"hello".to_string()).is_none());
+
+        // Only the result for the immutable revision outlives the TTL
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(cache.get(&"hello@r1".to_string()).is_none());
+        assert_eq!(
+            CachedExecutor::new(cache.clone())
+                .at_revision(Revision::immutable("r2"))
+                .get("hello"),
+            Some("value".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_calls_coalesced() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60), 100));
+        let runs = AtomicUsize::new(0);
+        let call = || async {
+            CachedExecutor::new(cache.clone())
+                .execute_with_string_cache("key".to_string(), || async {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok("value".to_string())
+                })
+                .await
+        };
+
+        let (a, b, c) = tokio::join!(call(), call(), call());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        for result in [a, b, c] {
+            assert_eq!(result.unwrap().content, call().await.unwrap().content);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        let flight_key = (Arc::as_ptr(&cache) as usize, "key".to_string());
+        assert!(!FLIGHTS.lock().unwrap().contains_key(&flight_key));
+    }
+
+    #[tokio::test]
+    async fn test_errors_shared_but_not_cached() {
+        let cache = Arc::new(TtlCache::new(Duration::from_secs(60), 100));
+        let runs = AtomicUsize::new(0);
+        let call = |fail: bool| {
+            let executor = CachedExecutor::new(cache.clone());
+            let runs = &runs;
+            async move {
+                executor
+                    .execute_with_string_cache("failing".to_string(), || async move {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        if fail {
+                            Err(McpError::internal_error("nix failed", None))
+                        } else {
+                            Ok("value".to_string())
+                        }
+                    })
+                    .await
+            }
+        };
+
+        let (a, b) = tokio::join!(call(true), call(true));
+        assert!(a.is_err() && b.is_err());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(cache.get(&"failing".to_string()).is_none());
+
+        assert!(call(false).await.is_ok());
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
 }
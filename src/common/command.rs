@@ -1,9 +1,37 @@
+//! Command execution utilities.
+//!
+//! Besides the [`CommandExecutor`] helpers, this module holds the process-wide
+//! [`Scheduler`] that bounds how many expensive commands run at once. Commands
+//! are grouped into [`CommandCategory`]s with separate limits
+//! (`[concurrency]` in the configuration); calls beyond a limit wait in a
+//! first-come, first-served queue, and every call that had to wait is recorded
+//! as a `CommandQueued` audit event with the queue depth it saw.
+//!
+//! ```no_run
+//! use onix_mcp::common::command::{scheduler, CommandCategory};
+//! use onix_mcp::common::security::audit_logger;
+//!
+//! # async fn example() {
+//! let audit = audit_logger();
+//! let output = scheduler()
+//!     .run(&audit, "nix_build", CommandCategory::Build, || async {
+//!         tokio::process::Command::new("nix").args(["build", "nixpkgs#hello"]).output().await
+//!     })
+//!     .await;
+//! # }
+//! ```
+
+use crate::common::config::ConcurrencyConfig;
+use crate::common::process_group::ProcessGroupExt;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::helpers::{audit_tool_execution, with_timeout};
 use rmcp::model::{CallToolResult, Content};
 use rmcp::ErrorData as McpError;
 use std::process::Output;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 /// Result of executing a command
 pub struct CommandResult {
@@ -78,7 +106,7 @@ impl CommandExecutor {
     ) -> Result<CommandResult, McpError> {
         let output = tokio::process::Command::new("nix")
             .args(args)
-            .output()
+            .group_output()
             .await
             .map_err(|e| McpError::internal_error(format!("{}: {}", context, e), None))?;
 
@@ -101,7 +129,7 @@ impl CommandExecutor {
                 let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
                 let output = tokio::process::Command::new("nix")
                     .args(&args_refs)
-                    .output()
+                    .group_output()
                     .await
                     .map_err(|e| {
                         McpError::internal_error(
@@ -136,7 +164,7 @@ impl CommandExecutor {
                 let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
                 let output = tokio::process::Command::new(&program)
                     .args(&args_refs)
-                    .output()
+                    .group_output()
                     .await
                     .map_err(|e| {
                         McpError::internal_error(
@@ -174,7 +202,7 @@ impl CommandExecutor {
                 let args_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
                 let output = tokio::process::Command::new("nix")
                     .args(&args_refs)
-                    .output()
+                    .group_output()
                     .await
                     .map_err(|e| {
                         McpError::internal_error(
@@ -191,3 +219,275 @@ impl CommandExecutor {
         .await
     }
 }
+
+/// Kinds of expensive commands, each with its own concurrency limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandCategory {
+    /// `nix eval`, `nix search`, `nix-locate` and other evaluations
+    Evaluation,
+    /// `nix build` and anything that may realise store paths
+    Build,
+    /// Downloads and flake input fetches
+    Network,
+    /// Clan deployments, installations and backups
+    ClanDeploy,
+}
+
+impl CommandCategory {
+    /// All categories, in configuration order.
+    pub const ALL: [CommandCategory; 4] = [
+        CommandCategory::Evaluation,
+        CommandCategory::Build,
+        CommandCategory::Network,
+        CommandCategory::ClanDeploy,
+    ];
+
+    /// Name used in configuration files and audit events.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommandCategory::Evaluation => "evaluation",
+            CommandCategory::Build => "build",
+            CommandCategory::Network => "network",
+            CommandCategory::ClanDeploy => "clan_deploy",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Slots and waiters of one category.
+struct Lane {
+    slots: Arc<Semaphore>,
+    limit: usize,
+    waiting: AtomicUsize,
+}
+
+/// Bounds the number of concurrently running commands per [`CommandCategory`].
+///
+/// Waiting calls are served in arrival order. Dropping a waiting future (for
+/// example when the client cancels the tool call) leaves the queue.
+pub struct Scheduler {
+    lanes: [Lane; 4],
+}
+
+/// A running slot; the next queued command starts when it is dropped.
+pub struct CommandPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Decrements a lane's waiter count, also when the wait is abandoned.
+struct WaitGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Scheduler {
+    /// Scheduler with the limits from `config`.
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        let lane = |limit: usize| Lane {
+            slots: Arc::new(Semaphore::new(limit)),
+            limit,
+            waiting: AtomicUsize::new(0),
+        };
+        Self {
+            lanes: [
+                lane(config.evaluation),
+                lane(config.build),
+                lane(config.network),
+                lane(config.clan_deploy),
+            ],
+        }
+    }
+
+    /// Maximum number of concurrently running commands in `category`.
+    pub fn limit(&self, category: CommandCategory) -> usize {
+        self.lanes[category.index()].limit
+    }
+
+    /// Number of commands in `category` currently running.
+    pub fn running(&self, category: CommandCategory) -> usize {
+        let lane = &self.lanes[category.index()];
+        lane.limit - lane.slots.available_permits()
+    }
+
+    /// Number of commands in `category` waiting for a slot.
+    pub fn queue_depth(&self, category: CommandCategory) -> usize {
+        self.lanes[category.index()].waiting.load(Ordering::SeqCst)
+    }
+
+    /// Wait for a slot in `category` on behalf of `tool_name`.
+    ///
+    /// Calls that cannot start immediately are audited with the queue depth
+    /// they joined and how long they waited.
+    pub async fn acquire(
+        &self,
+        audit: &AuditLogger,
+        tool_name: &str,
+        category: CommandCategory,
+    ) -> CommandPermit {
+        let lane = &self.lanes[category.index()];
+        if let Ok(permit) = lane.slots.clone().try_acquire_owned() {
+            return CommandPermit { _permit: permit };
+        }
+
+        let queue_depth = lane.waiting.fetch_add(1, Ordering::SeqCst) + 1;
+        let _waiting = WaitGuard(&lane.waiting);
+        let start = Instant::now();
+        let permit = lane
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("scheduler semaphores are never closed");
+
+        audit.log_command_queued(
+            tool_name,
+            category.as_str(),
+            queue_depth,
+            start.elapsed().as_millis() as u64,
+        );
+        CommandPermit { _permit: permit }
+    }
+
+    /// Run `f` once a slot in `category` is free.
+    pub async fn run<F, Fut, T>(
+        &self,
+        audit: &AuditLogger,
+        tool_name: &str,
+        category: CommandCategory,
+        f: F,
+    ) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        let _permit = self.acquire(audit, tool_name, category).await;
+        f().await
+    }
+}
+
+/// Process-wide scheduler, configured by [`init_scheduler`].
+static SCHEDULER: OnceLock<Arc<Scheduler>> = OnceLock::new();
+
+/// Configure the process-wide scheduler.
+///
+/// Limits protect the whole machine, so they are shared by every server and
+/// session in the process. Only the first call has an effect; returns whether
+/// `config` was applied.
+pub fn init_scheduler(config: &ConcurrencyConfig) -> bool {
+    SCHEDULER.set(Arc::new(Scheduler::new(config))).is_ok()
+}
+
+/// The process-wide scheduler (default limits if not configured).
+pub fn scheduler() -> Arc<Scheduler> {
+    SCHEDULER
+        .get_or_init(|| Arc::new(Scheduler::new(&ConcurrencyConfig::default())))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn scheduler_with_build_limit(build: usize) -> Arc<Scheduler> {
+        Arc::new(Scheduler::new(&ConcurrencyConfig {
+            build,
+            ..ConcurrencyConfig::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_limit_enforced() {
+        let scheduler = scheduler_with_build_limit(2);
+        let audit = AuditLogger::new();
+
+        let first = scheduler
+            .acquire(&audit, "nix_build", CommandCategory::Build)
+            .await;
+        let _second = scheduler
+            .acquire(&audit, "nix_build", CommandCategory::Build)
+            .await;
+        assert_eq!(scheduler.running(CommandCategory::Build), 2);
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                scheduler
+                    .acquire(&AuditLogger::new(), "nix_build", CommandCategory::Build)
+                    .await;
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(scheduler.queue_depth(CommandCategory::Build), 1);
+        assert!(!waiter.is_finished());
+
+        // Other categories are not affected
+        let _eval = scheduler
+            .acquire(&audit, "nix_eval", CommandCategory::Evaluation)
+            .await;
+
+        drop(first);
+        waiter.await.unwrap();
+        assert_eq!(scheduler.queue_depth(CommandCategory::Build), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fifo_order() {
+        let scheduler = scheduler_with_build_limit(1);
+        let running = scheduler
+            .acquire(&AuditLogger::new(), "nix_build", CommandCategory::Build)
+            .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for i in 0..3 {
+            let waiter = scheduler.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                waiter
+                    .run(
+                        &AuditLogger::new(),
+                        "nix_build",
+                        CommandCategory::Build,
+                        || async {
+                            tx.send(i).unwrap();
+                        },
+                    )
+                    .await;
+            });
+            // Make sure each waiter has queued before the next one
+            while scheduler.queue_depth(CommandCategory::Build) < i + 1 {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(running);
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            order.push(rx.recv().await.unwrap());
+        }
+        assert_eq!(order, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_wait_leaves_queue() {
+        let scheduler = scheduler_with_build_limit(1);
+        let audit = AuditLogger::new();
+        let _running = scheduler
+            .acquire(&audit, "nix_build", CommandCategory::Build)
+            .await;
+
+        let wait = tokio::time::timeout(
+            Duration::from_millis(10),
+            scheduler.acquire(&audit, "nix_build", CommandCategory::Build),
+        )
+        .await;
+        assert!(wait.is_err());
+        assert_eq!(scheduler.queue_depth(CommandCategory::Build), 0);
+    }
+}
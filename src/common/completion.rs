@@ -0,0 +1,624 @@
+//! Argument completion for prompts and resource templates (`completion/complete`).
+//!
+//! Candidates come from the Nix and Clan tooling itself and are cached in the
+//! `completion` cache of the [`CacheRegistry`]:
+//!
+//! - nixpkgs attribute names for `nix://package/{name}` and the `package`
+//!   argument of `troubleshoot_build` and `optimize_closure`
+//! - `flake#attribute` installables for `nix://derivation/{package}`
+//! - `flake#output` paths for `nix://flake/{ref}/show`
+//! - NixOS option paths, one level at a time, for `nix://option/{path}`
+//! - Clan machine names for any `machine` or `machines` argument, using the
+//!   `flake` argument from the request context if present
+//! - fixed project types for `project_type` prompt arguments
+//!
+//! Commands run like tool calls of the `completion` pseudo-tool: they take an
+//! evaluation slot of the [scheduler], a
+//! token from the `completion` rate limit (`[rate_limits.tools] completion`)
+//! and are recorded in the audit log.
+//!
+//! Completion never fails: if a command is missing, errors out, exceeds
+//! [`COMPLETION_TIMEOUT`] or is rate limited, no candidates are returned.
+//! Failures are cached for [`FAILURE_TTL`], so a broken or unreachable flake
+//! is not evaluated again on every keystroke.
+
+use crate::common::cache_registry::CacheRegistry;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
+use crate::common::security::helpers::{audit_tool_execution, with_timeout};
+use crate::common::security::{validate_flake_ref, AuditLogger, RateLimiter};
+use rmcp::model::{CompleteRequestParam, CompletionInfo, Reference};
+use rmcp::ErrorData as McpError;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Maximum time a command may take to produce completion candidates.
+pub const COMPLETION_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long a failed candidate lookup is remembered as having no candidates.
+pub const FAILURE_TTL: Duration = Duration::from_secs(60);
+
+/// Name completion commands are audited, scheduled and rate limited under.
+const COMPLETION_TOOL: &str = "completion";
+
+/// Project types offered for `project_type` prompt arguments.
+const PROJECT_TYPES: &[&str] = &[
+    "rust", "python", "nodejs", "go", "c", "c++", "java", "haskell", "generic",
+];
+
+/// Project types supported by the `generate_flake` prompt.
+const FLAKE_PROJECT_TYPES: &[&str] = &["rust", "python", "nodejs", "go", "c", "generic"];
+
+/// Resolves completion requests against nixpkgs, flakes, NixOS options and Clan.
+pub struct CompletionProvider {
+    caches: Arc<CacheRegistry>,
+    config: Arc<Config>,
+    audit: Arc<AuditLogger>,
+    rate_limits: Arc<RateLimiter>,
+}
+
+impl CompletionProvider {
+    /// Creates a provider using the completion cache and default flakes of
+    /// `config`, logging its commands to `audit` and limiting them with
+    /// `rate_limits`.
+    pub fn new(
+        caches: Arc<CacheRegistry>,
+        config: Arc<Config>,
+        audit: Arc<AuditLogger>,
+        rate_limits: Arc<RateLimiter>,
+    ) -> Self {
+        Self {
+            caches,
+            config,
+            audit,
+            rate_limits,
+        }
+    }
+
+    /// Complete the argument of a `completion/complete` request.
+    ///
+    /// Values starting with the typed text come first, followed by values
+    /// containing it; at most [`CompletionInfo::MAX_VALUES`] are returned.
+    ///
+    /// Machine names are listed for the context `flake`; it and the flakes
+    /// whose outputs are listed are resolved against the client's
+    /// `workspace` roots.
+    pub async fn complete(
+        &self,
+        request: &CompleteRequestParam,
+        workspace: &Workspace,
+    ) -> CompletionInfo {
+        let value = request.argument.value.as_str();
+        let context_flake = request
+            .context
+            .as_ref()
+            .and_then(|c| c.arguments.as_ref())
+            .and_then(|args| args.get("flake"))
+            .cloned();
+
+        let candidates = match (&request.r#ref, request.argument.name.as_str()) {
+            (_, "machine" | "machines") => {
+                let flake = context_flake.unwrap_or_else(|| self.config.flakes.default.clone());
+                self.machine_names(&flake, workspace).await
+            }
+            (Reference::Prompt(prompt), "project_type") => match prompt.name.as_str() {
+                "generate_flake" => to_strings(FLAKE_PROJECT_TYPES),
+                "setup_dev_environment" | "migrate_to_flakes" => to_strings(PROJECT_TYPES),
+                _ => Vec::new(),
+            },
+            (Reference::Prompt(prompt), "package")
+                if matches!(
+                    prompt.name.as_str(),
+                    "troubleshoot_build" | "optimize_closure"
+                ) =>
+            {
+                self.package_names().await
+            }
+            (Reference::Resource(resource), argument) => match (resource.uri.as_str(), argument) {
+                ("nix://package/{name}", "name") => self.package_names().await,
+                ("nix://derivation/{package}", "package") => {
+                    self.installables(value, workspace).await
+                }
+                ("nix://flake/{ref}/show", "ref") => self.flake_outputs(value, workspace).await,
+                ("nix://option/{path}", "path") => self.option_paths(value).await,
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+
+        rank(candidates, value)
+    }
+
+    /// Top-level attribute names of nixpkgs for the current system.
+    async fn package_names(&self) -> Vec<String> {
+        let Ok(nixpkgs) = validate_flake_ref(&self.config.flakes.nixpkgs) else {
+            return Vec::new();
+        };
+
+        let installable = format!("{}#legacyPackages", nixpkgs.without_fragment());
+        self.cached(format!("packages:{}", nixpkgs), || async {
+            self.run_json(
+                "nix",
+                &[
+                    "eval",
+                    "--impure",
+                    "--json",
+                    &installable,
+                    "--apply",
+                    "pkgs: builtins.attrNames pkgs.${builtins.currentSystem}",
+                ],
+            )
+            .await
+            .and_then(|json| serde_json::from_value(json).ok())
+        })
+        .await
+    }
+
+    /// `flake#attribute` installables: nixpkgs packages, or outputs of other flakes.
+    async fn installables(&self, value: &str, workspace: &Workspace) -> Vec<String> {
+        let nixpkgs = &self.config.flakes.nixpkgs;
+        match value.split_once('#') {
+            Some((flake, _)) if flake != nixpkgs.as_str() => {
+                self.flake_outputs(value, workspace).await
+            }
+            _ => self
+                .package_names()
+                .await
+                .into_iter()
+                .map(|name| format!("{}#{}", nixpkgs, name))
+                .collect(),
+        }
+    }
+
+    /// `flake#output` paths for the flake named before `#` in `value`.
+    ///
+    /// Local flakes outside the workspace roots get no completions.
+    async fn flake_outputs(&self, value: &str, workspace: &Workspace) -> Vec<String> {
+        let Some((flake, _)) = value.split_once('#') else {
+            return Vec::new();
+        };
+        let Ok(flake_ref) = validate_flake_ref(flake) else {
+            return Vec::new();
+        };
+        let Ok(flake_ref) = workspace.resolve_flake_ref(&flake_ref).await else {
+            return Vec::new();
+        };
+
+        let canonical = flake_ref.to_string();
+        let outputs = self
+            .cached(format!("outputs:{}", canonical), || async {
+                self.run_json("nix", &["flake", "show", "--json", &canonical])
+                    .await
+                    .map(|json| flatten_flake_outputs(&json))
+            })
+            .await;
+
+        outputs
+            .into_iter()
+            .map(|output| format!("{}#{}", flake, output))
+            .collect()
+    }
+
+    /// NixOS option paths one level below the last complete segment of `value`.
+    ///
+    /// Options are those of the NixOS in the configured nixpkgs flake.
+    async fn option_paths(&self, value: &str) -> Vec<String> {
+        // The path is spliced into a Nix expression, so only plain attribute names pass
+        if !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        {
+            return Vec::new();
+        }
+        let Ok(nixpkgs) = validate_flake_ref(&self.config.flakes.nixpkgs) else {
+            return Vec::new();
+        };
+
+        let parent = option_parent(value);
+        let installable = format!("{}#legacyPackages", nixpkgs.without_fragment());
+        let apply = format!(
+            "pkgs: let o = (import (pkgs.${{builtins.currentSystem}}.path + \"/nixos\") \
+             {{ configuration = {{}}; }}).options{}; \
+             in if o ? _type then [] else builtins.attrNames o",
+            if parent.is_empty() {
+                String::new()
+            } else {
+                format!(".{}", parent)
+            }
+        );
+
+        let children = self
+            .cached(format!("options:{}:{}", nixpkgs, parent), || async {
+                self.run_json(
+                    "nix",
+                    &[
+                        "eval",
+                        "--impure",
+                        "--json",
+                        &installable,
+                        "--apply",
+                        &apply,
+                    ],
+                )
+                .await
+                .and_then(|json| serde_json::from_value(json).ok())
+            })
+            .await;
+
+        children
+            .into_iter()
+            .map(|child| {
+                if parent.is_empty() {
+                    child
+                } else {
+                    format!("{}.{}", parent, child)
+                }
+            })
+            .collect()
+    }
+
+    /// Machine names of the Clan flake `flake`.
+    ///
+    /// Local flakes outside the workspace roots get no completions.
+    async fn machine_names(&self, flake: &str, workspace: &Workspace) -> Vec<String> {
+        let Ok(flake_ref) = validate_flake_ref(flake) else {
+            return Vec::new();
+        };
+        let Ok(flake_ref) = workspace.resolve_flake_ref(&flake_ref).await else {
+            return Vec::new();
+        };
+
+        let canonical = flake_ref.to_string();
+        self.cached(format!("machines:{}", canonical), || async {
+            let output = self
+                .run("clan", &["machines", "list", "--flake", &canonical])
+                .await?;
+            Some(
+                String::from_utf8_lossy(&output)
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            )
+        })
+        .await
+    }
+
+    /// Look up `key` in the completion cache, filling it with `fetch` on a miss.
+    ///
+    /// A failed `fetch` is cached as no candidates for [`FAILURE_TTL`].
+    async fn cached<F, Fut>(&self, key: String, fetch: F) -> Vec<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Option<Vec<String>>>,
+    {
+        if let Some(cached) = self.caches.completion.get(&key) {
+            if let Ok(values) = serde_json::from_str(&cached) {
+                return values;
+            }
+        }
+
+        let Some(values) = fetch().await else {
+            self.caches
+                .completion
+                .insert_with_ttl(key, "[]".to_string(), FAILURE_TTL);
+            return Vec::new();
+        };
+        if let Ok(json) = serde_json::to_string(&values) {
+            self.caches.completion.insert(key, json);
+        }
+        values
+    }
+
+    /// Run a command with [`COMPLETION_TIMEOUT`] and return its stdout if it succeeded.
+    ///
+    /// The command is rate limited, scheduled and audited as [`COMPLETION_TOOL`].
+    async fn run(&self, program: &str, args: &[&str]) -> Option<Vec<u8>> {
+        self.rate_limits.check(&self.audit, COMPLETION_TOOL).ok()?;
+
+        let command: Vec<&str> = std::iter::once(program)
+            .chain(args.iter().copied())
+            .collect();
+        audit_tool_execution(
+            &self.audit,
+            COMPLETION_TOOL,
+            Some(serde_json::json!({ "command": command })),
+            || async {
+                let _slot = scheduler()
+                    .acquire(&self.audit, COMPLETION_TOOL, CommandCategory::Evaluation)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    COMPLETION_TOOL,
+                    COMPLETION_TIMEOUT.as_secs(),
+                    || async {
+                        let output = tokio::process::Command::new(program)
+                            .args(args)
+                            .group_output()
+                            .await
+                            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            tracing::debug!(program, stderr = %stderr, "Completion command failed");
+                            return Err(McpError::internal_error(stderr.to_string(), None));
+                        }
+                        Ok(output.stdout)
+                    },
+                )
+                .await
+            },
+        )
+        .await
+        .ok()
+    }
+
+    /// Like [`run`](Self::run), parsing stdout as JSON.
+    async fn run_json(&self, program: &str, args: &[&str]) -> Option<serde_json::Value> {
+        serde_json::from_slice(&self.run(program, args).await?).ok()
+    }
+}
+
+/// Dotted paths of the leaf outputs in `nix flake show --json` output.
+fn flatten_flake_outputs(json: &serde_json::Value) -> Vec<String> {
+    fn walk(value: &serde_json::Value, prefix: &str, out: &mut Vec<String>) {
+        let Some(map) = value.as_object() else {
+            return;
+        };
+        for (key, child) in map {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            if child.get("type").is_some_and(|t| t.is_string()) {
+                out.push(path);
+            } else {
+                walk(child, &path, out);
+            }
+        }
+    }
+
+    let mut outputs = Vec::new();
+    walk(json, "", &mut outputs);
+    outputs
+}
+
+/// Option path whose children complete `value` (`"services.ngi"` -> `"services"`).
+fn option_parent(value: &str) -> &str {
+    value.rsplit_once('.').map_or("", |(parent, _)| parent)
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+/// Filter and order candidates for the typed `value` (case-insensitive).
+fn rank(candidates: Vec<String>, value: &str) -> CompletionInfo {
+    let query = value.to_lowercase();
+    let (mut prefixed, mut containing) = (Vec::new(), Vec::new());
+    for candidate in candidates {
+        let lower = candidate.to_lowercase();
+        if lower.starts_with(&query) {
+            prefixed.push(candidate);
+        } else if lower.contains(&query) {
+            containing.push(candidate);
+        }
+    }
+    prefixed.append(&mut containing);
+
+    let total = prefixed.len();
+    prefixed.truncate(CompletionInfo::MAX_VALUES);
+    CompletionInfo {
+        values: prefixed,
+        total: Some(total as u32),
+        has_more: Some(total > CompletionInfo::MAX_VALUES),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{ArgumentInfo, PromptReference, ResourceReference};
+    use std::path::PathBuf;
+
+    fn provider() -> CompletionProvider {
+        let config = Config::default();
+        CompletionProvider::new(
+            Arc::new(CacheRegistry::new()),
+            Arc::new(config.clone()),
+            Arc::new(AuditLogger::new()),
+            Arc::new(RateLimiter::new(&config.rate_limits)),
+        )
+    }
+
+    fn request(r#ref: Reference, name: &str, value: &str) -> CompleteRequestParam {
+        CompleteRequestParam {
+            r#ref,
+            argument: ArgumentInfo {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+            context: None,
+        }
+    }
+
+    #[test]
+    fn test_rank_prefers_prefix_matches() {
+        let candidates = to_strings(&["python3", "cpython", "hello", "Python2"]);
+        let info = rank(candidates, "py");
+
+        assert_eq!(info.values, vec!["python3", "Python2", "cpython"]);
+        assert_eq!(info.total, Some(3));
+        assert_eq!(info.has_more, Some(false));
+
+        let many: Vec<String> = (0..150).map(|i| format!("pkg{}", i)).collect();
+        let info = rank(many, "pkg");
+        assert_eq!(info.values.len(), CompletionInfo::MAX_VALUES);
+        assert_eq!(info.total, Some(150));
+        assert_eq!(info.has_more, Some(true));
+    }
+
+    #[test]
+    fn test_flatten_flake_outputs() {
+        let show = serde_json::json!({
+            "packages": {
+                "x86_64-linux": {
+                    "default": {"type": "derivation", "name": "hello-2.12"},
+                    "hello": {"type": "derivation", "name": "hello-2.12"}
+                }
+            },
+            "nixosModules": {"default": {"type": "nixos-module"}},
+            "legacyPackages": {"x86_64-linux": {}}
+        });
+
+        let mut outputs = flatten_flake_outputs(&show);
+        outputs.sort();
+        assert_eq!(
+            outputs,
+            vec![
+                "nixosModules.default",
+                "packages.x86_64-linux.default",
+                "packages.x86_64-linux.hello",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_option_parent() {
+        assert_eq!(option_parent("services.nginx.ena"), "services.nginx");
+        assert_eq!(option_parent("services."), "services");
+        assert_eq!(option_parent("serv"), "");
+    }
+
+    #[tokio::test]
+    async fn test_complete_project_type() {
+        let info = provider()
+            .complete(
+                &request(
+                    Reference::Prompt(PromptReference {
+                        name: "generate_flake".to_string(),
+                        title: None,
+                    }),
+                    "project_type",
+                    "r",
+                ),
+                &Workspace::default(),
+            )
+            .await;
+
+        assert_eq!(info.values, vec!["rust", "generic"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_uses_cache() {
+        let provider = provider();
+        provider.caches.completion.insert(
+            format!("options:{}:services", provider.config.flakes.nixpkgs),
+            r#"["nginx","openssh"]"#.to_string(),
+        );
+
+        let info = provider
+            .complete(
+                &request(
+                    Reference::Resource(ResourceReference {
+                        uri: "nix://option/{path}".to_string(),
+                    }),
+                    "path",
+                    "services.ng",
+                ),
+                &Workspace::default(),
+            )
+            .await;
+        assert_eq!(info.values, vec!["services.nginx"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_flake_outputs_within_roots() {
+        let provider = provider();
+        for flake in ["/work/app", "/etc/nixos"] {
+            provider.caches.completion.insert(
+                format!("outputs:{}", flake),
+                r#"["packages.x86_64-linux.hello"]"#.to_string(),
+            );
+        }
+        let workspace = Workspace::with_roots(vec![PathBuf::from("/work/app")]);
+        let show = |value: &str| {
+            request(
+                Reference::Resource(ResourceReference {
+                    uri: "nix://flake/{ref}/show".to_string(),
+                }),
+                "ref",
+                value,
+            )
+        };
+
+        let info = provider.complete(&show(".#pack"), &workspace).await;
+        assert_eq!(info.values, vec![".#packages.x86_64-linux.hello"]);
+
+        let info = provider
+            .complete(&show("/etc/nixos#pack"), &workspace)
+            .await;
+        assert!(info.values.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_machine_completion_failures_cached() {
+        let provider = provider();
+        let machines = |flake: &str| {
+            let mut request = request(
+                Reference::Prompt(PromptReference {
+                    name: "deploy_machine".to_string(),
+                    title: None,
+                }),
+                "machine",
+                "",
+            );
+            request.context = Some(rmcp::model::CompletionContext::with_arguments(
+                [("flake".to_string(), flake.to_string())].into(),
+            ));
+            request
+        };
+
+        // Outside the roots: nothing runs, so nothing is cached
+        let workspace = Workspace::with_roots(vec![PathBuf::from("/work/app")]);
+        let info = provider.complete(&machines("/etc/nixos"), &workspace).await;
+        assert!(info.values.is_empty());
+        assert_eq!(provider.caches.completion.len(), 0);
+
+        // A failing lookup is remembered as having no candidates
+        let flake = "path:/nonexistent-onix-mcp-clan";
+        let info = provider
+            .complete(&machines(flake), &Workspace::default())
+            .await;
+        assert!(info.values.is_empty());
+        assert_eq!(
+            provider
+                .caches
+                .completion
+                .get(&format!("machines:{}", flake)),
+            Some("[]".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_rejects_unsafe_option_paths() {
+        let info = provider()
+            .complete(
+                &request(
+                    Reference::Resource(ResourceReference {
+                        uri: "nix://option/{path}".to_string(),
+                    }),
+                    "path",
+                    "services; builtins.exec",
+                ),
+                &Workspace::default(),
+            )
+            .await;
+        assert!(info.values.is_empty());
+    }
+}
@@ -0,0 +1,926 @@
+//! Layered server configuration.
+//!
+//! Configuration is assembled from several layers, each overriding the
+//! previous one:
+//!
+//! 1. Built-in defaults (the values the tools used before configuration existed)
+//! 2. `$XDG_CONFIG_DIRS/onix-mcp/config.toml` (default: `/etc/xdg/onix-mcp/config.toml`)
+//! 3. `$XDG_CONFIG_HOME/onix-mcp/config.toml` (default: `~/.config/onix-mcp/config.toml`)
+//! 4. The file passed via `--config` / `ONIX_MCP_CONFIG`, if set
+//! 5. `ONIX_MCP__<SECTION>__<KEY>` environment variables
+//!
+//! Tables are merged key by key, so a layer only needs to contain the values
+//! it changes. Environment values are parsed as TOML (numbers, booleans,
+//! arrays) and fall back to plain strings, e.g.
+//! `ONIX_MCP__TIMEOUTS__NIX_BUILD=900` or
+//! `ONIX_MCP__TOOLS__ENABLED_GROUPS='["packages", "build"]'`.
+//!
+//! # Example
+//!
+//! ```toml
+//! # Per-tool timeouts in seconds (tools not listed keep their defaults)
+//! [timeouts]
+//! nix_build = 900
+//! clan_machine_install = 1800
+//!
+//! # Per-cache TTL, capacity and memory limit (default 32 MiB)
+//! [caches.search]
+//! ttl_secs = 120
+//! capacity = 500
+//! max_bytes = 67108864
+//!
+//! # Keep cache entries across restarts (off by default)
+//! [cache_store]
+//! enabled = true
+//! file = "/var/cache/onix-mcp/cache.jsonl"   # default: $XDG_CACHE_HOME/onix-mcp/cache.jsonl
+//! max_bytes = 67108864
+//!
+//! # Flakes used when a tool argument is omitted
+//! [flakes]
+//! default = "/srv/infra"
+//! nixpkgs = "github:NixOS/nixpkgs/nixos-unstable"
+//!
+//! [tools]
+//! enabled_groups = ["packages", "build", "flakes", "quality", "info"]
+//! profile = "readonly"   # full | readonly | dev | ops
+//!
+//! # Concurrently running commands per category (others queue)
+//! [concurrency]
+//! build = 1
+//! evaluation = 8
+//!
+//! # Token-bucket rate limits per tool and per tool group (none by default)
+//! [rate_limits.tools.nix_build]
+//! burst = 3
+//! per_minute = 6
+//!
+//! [rate_limits.groups.clan_machines]
+//! burst = 5
+//! per_minute = 10
+//!
+//! # Hash-chained JSONL audit trail (off by default)
+//! [audit]
+//! file = "/var/log/onix-mcp/audit.jsonl"
+//! max_bytes = 10485760
+//! max_age_secs = 86400
+//! keep_files = 30
+//! # Values of keys containing these names are redacted from audit events
+//! redact_keys = ["password", "secret", "token", "api_key", "private_key", "otp"]
+//!
+//! # Allow/deny/confirm rules for tool calls (see `security::policy`)
+//! [policy]
+//! file = "/etc/onix-mcp/policy.toml"
+//! ```
+//!
+//! The merged configuration is validated by [`Config::load`] so that typos
+//! (unknown tools, unknown keys, zero TTLs) fail at startup instead of being
+//! silently ignored.
+
+use crate::common::security::audit::redact::{Redactor, DEFAULT_SENSITIVE_KEYS};
+use crate::common::security::audit_file::Rotation;
+use crate::common::security::validate_flake_ref;
+use crate::common::tool_registry::{Profile, ToolGroup};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Prefix for environment variable overrides (`ONIX_MCP__TIMEOUTS__NIX_BUILD=900`).
+pub const ENV_PREFIX: &str = "ONIX_MCP__";
+
+/// Upper bound for configured timeouts (24 hours).
+const MAX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Errors raised while loading or validating configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// A configuration file could not be read
+    Read { path: PathBuf, reason: String },
+    /// A configuration layer is not valid TOML or does not match the schema
+    Parse { source: String, reason: String },
+    /// A value is syntactically valid but not allowed
+    Invalid { field: String, reason: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, reason } => {
+                write!(
+                    f,
+                    "Failed to read config file {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            ConfigError::Parse { source, reason } => {
+                write!(f, "Invalid configuration in {}: {}", source, reason)
+            }
+            ConfigError::Invalid { field, reason } => {
+                write!(f, "Invalid configuration value '{}': {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Top-level server configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Per-tool timeout overrides in seconds, keyed by tool name
+    pub timeouts: BTreeMap<String, u64>,
+    /// TTL and capacity for each cache in the [`CacheRegistry`](crate::common::cache_registry::CacheRegistry)
+    pub caches: CacheConfig,
+    /// On-disk persistence for the caches
+    pub cache_store: CacheStoreConfig,
+    /// Default flake references
+    pub flakes: FlakeDefaults,
+    /// Tool enablement
+    pub tools: ToolsConfig,
+    /// Concurrent command limits per category
+    pub concurrency: ConcurrencyConfig,
+    /// Call rate limits per tool and per tool group
+    pub rate_limits: RateLimitConfig,
+    /// Persistent audit trail
+    pub audit: AuditConfig,
+    /// Authorization policy for tool calls
+    pub policy: PolicyConfig,
+}
+
+/// Default memory limit of each cache: 32 MiB.
+pub const DEFAULT_CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// TTL and capacity of a single cache.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheSettings {
+    /// Time-to-live for entries in seconds
+    pub ttl_secs: u64,
+    /// Maximum number of entries
+    pub capacity: usize,
+    /// Maximum total size of keys and values in bytes
+    pub max_bytes: usize,
+}
+
+impl CacheSettings {
+    pub const fn new(ttl_secs: u64, capacity: usize) -> Self {
+        Self {
+            ttl_secs,
+            capacity,
+            max_bytes: DEFAULT_CACHE_MAX_BYTES,
+        }
+    }
+
+    /// Entry time-to-live as a [`Duration`].
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+}
+
+/// Settings for every cache in the cache registry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub locate: CacheSettings,
+    pub search: CacheSettings,
+    pub package_info: CacheSettings,
+    pub eval: CacheSettings,
+    pub prefetch: CacheSettings,
+    pub closure_size: CacheSettings,
+    pub derivation: CacheSettings,
+    pub completion: CacheSettings,
+    /// Resolved lock revisions of floating flake references; the TTL is how
+    /// often they are revalidated
+    pub revision: CacheSettings,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            locate: CacheSettings::new(300, 200),
+            search: CacheSettings::new(600, 1000),
+            package_info: CacheSettings::new(1800, 500),
+            eval: CacheSettings::new(300, 500),
+            prefetch: CacheSettings::new(86400, 1000),
+            closure_size: CacheSettings::new(1800, 200),
+            derivation: CacheSettings::new(1800, 200),
+            completion: CacheSettings::new(3600, 200),
+            revision: CacheSettings::new(60, 100),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// All caches with their configuration key.
+    pub fn entries(&self) -> [(&'static str, &CacheSettings); 9] {
+        [
+            ("locate", &self.locate),
+            ("search", &self.search),
+            ("package_info", &self.package_info),
+            ("eval", &self.eval),
+            ("prefetch", &self.prefetch),
+            ("closure_size", &self.closure_size),
+            ("derivation", &self.derivation),
+            ("completion", &self.completion),
+            ("revision", &self.revision),
+        ]
+    }
+}
+
+/// Flake references used when a tool is called without one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlakeDefaults {
+    /// Flake for Clan tools, `nixos_build` and `flake_show` (default: `.`)
+    ///
+    /// Local paths are resolved against the client's workspace roots.
+    pub default: String,
+    /// Flake providing packages for search and package info (default: `nixpkgs`)
+    pub nixpkgs: String,
+}
+
+impl Default for FlakeDefaults {
+    fn default() -> Self {
+        Self {
+            default: ".".to_string(),
+            nixpkgs: "nixpkgs".to_string(),
+        }
+    }
+}
+
+/// Maximum number of concurrently running commands in each
+/// [`CommandCategory`](crate::common::command::CommandCategory).
+///
+/// Further commands wait in a first-come, first-served queue.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Evaluations, searches and index lookups (default: 4)
+    pub evaluation: usize,
+    /// Builds and realisations (default: 2)
+    pub build: usize,
+    /// Downloads and flake input fetches (default: 4)
+    pub network: usize,
+    /// Clan deployments, installations and backups (default: 1)
+    pub clan_deploy: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            evaluation: 4,
+            build: 2,
+            network: 4,
+            clan_deploy: 1,
+        }
+    }
+}
+
+impl ConcurrencyConfig {
+    /// All limits with their configuration key.
+    pub fn entries(&self) -> [(&'static str, usize); 4] {
+        [
+            ("evaluation", self.evaluation),
+            ("build", self.build),
+            ("network", self.network),
+            ("clan_deploy", self.clan_deploy),
+        ]
+    }
+}
+
+/// Token bucket for calls to a tool or tool group.
+///
+/// A bucket holds up to `burst` calls and refills at `per_minute` calls per
+/// minute; a call is rejected when its bucket is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSettings {
+    /// Calls allowed in quick succession
+    pub burst: u32,
+    /// Sustained calls per minute
+    pub per_minute: u32,
+}
+
+impl RateLimitSettings {
+    pub const fn new(burst: u32, per_minute: u32) -> Self {
+        Self { burst, per_minute }
+    }
+}
+
+/// Rate limits enforced by the [`RateLimiter`](crate::common::security::RateLimiter).
+///
+/// A call must pass both the limit of its tool and the limit of its group.
+/// Tools and groups without an entry are not limited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Limits keyed by tool name
+    pub tools: BTreeMap<String, RateLimitSettings>,
+    /// Limits keyed by tool group
+    pub groups: BTreeMap<ToolGroup, RateLimitSettings>,
+}
+
+/// Cache entries kept on disk across restarts by a [`DiskCache`](crate::common::disk_cache::DiskCache).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheStoreConfig {
+    /// Persist cache entries (default: false)
+    pub enabled: bool,
+    /// Cache file (default: `$XDG_CACHE_HOME/onix-mcp/cache.jsonl`)
+    pub file: Option<PathBuf>,
+    /// Compact the file once it exceeds this many bytes (default: 64 MiB)
+    pub max_bytes: u64,
+}
+
+impl Default for CacheStoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: None,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl CacheStoreConfig {
+    /// The configured file, or the default under `$XDG_CACHE_HOME`
+    /// (`~/.cache`). `None` if neither variable is set.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.file.clone().or_else(|| {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .map(|dir| dir.join("onix-mcp").join("cache.jsonl"))
+        })
+    }
+}
+
+/// Persistent audit trail written by a [`FileAuditSink`](crate::common::security::FileAuditSink).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// JSONL file receiving every audit event (default: none)
+    pub file: Option<PathBuf>,
+    /// Rotate before the file exceeds this many bytes (default: 10 MiB)
+    pub max_bytes: u64,
+    /// Rotate once the file is this many seconds old (default: 1 day)
+    pub max_age_secs: u64,
+    /// Rotated files to keep, 0 keeps all (default: 0)
+    pub keep_files: usize,
+    /// Key names whose values are redacted from audit events, matched
+    /// case-insensitively within longer names like `GITHUB_TOKEN`
+    /// (default: [`DEFAULT_SENSITIVE_KEYS`])
+    pub redact_keys: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        let rotation = Rotation::default();
+        Self {
+            file: None,
+            max_bytes: rotation.max_bytes,
+            max_age_secs: rotation.max_age.as_secs(),
+            keep_files: rotation.keep_files,
+            redact_keys: DEFAULT_SENSITIVE_KEYS
+                .iter()
+                .map(|k| k.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Rotation policy for the audit file.
+    pub fn rotation(&self) -> Rotation {
+        Rotation {
+            max_bytes: self.max_bytes,
+            max_age: Duration::from_secs(self.max_age_secs),
+            keep_files: self.keep_files,
+        }
+    }
+
+    /// Redactor removing secrets from audit events.
+    pub fn redactor(&self) -> Redactor {
+        Redactor::new(&self.redact_keys)
+    }
+}
+
+/// Authorization rules applied to every tool call.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// TOML file with the [`Policy`](crate::common::security::Policy) rules (default: none, allow all)
+    pub file: Option<PathBuf>,
+}
+
+/// Which tools are exposed to clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolsConfig {
+    /// Enabled tool groups (default: all)
+    pub enabled_groups: Vec<ToolGroup>,
+    /// Profile filtering tools by their annotations (default: `full`)
+    pub profile: Profile,
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            enabled_groups: ToolGroup::ALL.to_vec(),
+            profile: Profile::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from the XDG config paths and the process environment.
+    ///
+    /// `explicit` is an additional config file with the highest file
+    /// precedence (the `--config` flag or `ONIX_MCP_CONFIG`); unlike the XDG
+    /// files it must exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `explicit` does not exist, if any layer fails to
+    /// parse, or if the merged configuration is invalid.
+    pub fn load(explicit: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut files = Self::xdg_paths();
+
+        if let Some(explicit) = explicit {
+            if !explicit.is_file() {
+                return Err(ConfigError::Read {
+                    path: explicit.to_path_buf(),
+                    reason: "file does not exist".to_string(),
+                });
+            }
+            files.push(explicit.to_path_buf());
+        }
+
+        let config = Self::load_from(&files, std::env::vars())?;
+        for file in files.iter().filter(|f| f.is_file()) {
+            tracing::info!(path = %file.display(), "Loaded configuration file");
+        }
+        Ok(config)
+    }
+
+    /// Load configuration from `files` (lowest precedence first, missing files
+    /// are skipped) and `env` overrides, then validate the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a layer fails to parse or the result is invalid.
+    pub fn load_from<I>(files: &[PathBuf], env: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut merged = Self::default_value();
+
+        for path in files {
+            if !path.is_file() {
+                continue;
+            }
+            let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Read {
+                path: path.clone(),
+                reason: e.to_string(),
+            })?;
+            let layer = parse_layer(&path.display().to_string(), &content)?;
+            merge(&mut merged, layer);
+        }
+
+        for (key, value) in env {
+            if let Some(path) = key.strip_prefix(ENV_PREFIX) {
+                let segments: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+                set_path(&mut merged, &segments, parse_env_value(&value));
+            }
+        }
+
+        let config = Self::deserialize(merged).map_err(|e| ConfigError::Parse {
+            source: "merged configuration".to_string(),
+            reason: e.to_string(),
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse a single TOML document layered over the defaults and validate it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document fails to parse or is invalid.
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        let mut merged = Self::default_value();
+        merge(&mut merged, parse_layer("<string>", content)?);
+
+        let config = Self::deserialize(merged).map_err(|e| ConfigError::Parse {
+            source: "<string>".to_string(),
+            reason: e.to_string(),
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check value ranges and references that serde cannot express.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first invalid value found.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (tool, &secs) in &self.timeouts {
+            if ToolGroup::of(tool).is_none() {
+                return Err(ConfigError::Invalid {
+                    field: format!("timeouts.{}", tool),
+                    reason: "unknown tool".to_string(),
+                });
+            }
+            if secs == 0 || secs > MAX_TIMEOUT_SECS {
+                return Err(ConfigError::Invalid {
+                    field: format!("timeouts.{}", tool),
+                    reason: format!("must be between 1 and {} seconds", MAX_TIMEOUT_SECS),
+                });
+            }
+        }
+
+        for (name, settings) in self.caches.entries() {
+            if settings.ttl_secs == 0 {
+                return Err(ConfigError::Invalid {
+                    field: format!("caches.{}.ttl_secs", name),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+            if settings.capacity == 0 {
+                return Err(ConfigError::Invalid {
+                    field: format!("caches.{}.capacity", name),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+            if settings.max_bytes == 0 {
+                return Err(ConfigError::Invalid {
+                    field: format!("caches.{}.max_bytes", name),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+        }
+
+        if self.cache_store.max_bytes == 0 {
+            return Err(ConfigError::Invalid {
+                field: "cache_store.max_bytes".to_string(),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        for (name, limit) in self.concurrency.entries() {
+            if limit == 0 {
+                return Err(ConfigError::Invalid {
+                    field: format!("concurrency.{}", name),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+        }
+
+        for tool in self.rate_limits.tools.keys() {
+            if ToolGroup::of(tool).is_none() {
+                return Err(ConfigError::Invalid {
+                    field: format!("rate_limits.tools.{}", tool),
+                    reason: "unknown tool".to_string(),
+                });
+            }
+        }
+        let rate_limits = self
+            .rate_limits
+            .tools
+            .iter()
+            .map(|(tool, limit)| (format!("tools.{}", tool), limit))
+            .chain(
+                self.rate_limits
+                    .groups
+                    .iter()
+                    .map(|(group, limit)| (format!("groups.{}", group), limit)),
+            );
+        for (key, limit) in rate_limits {
+            for (name, value) in [("burst", limit.burst), ("per_minute", limit.per_minute)] {
+                if value == 0 {
+                    return Err(ConfigError::Invalid {
+                        field: format!("rate_limits.{}.{}", key, name),
+                        reason: "must be greater than 0".to_string(),
+                    });
+                }
+            }
+        }
+
+        for (name, value) in [
+            ("max_bytes", self.audit.max_bytes),
+            ("max_age_secs", self.audit.max_age_secs),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::Invalid {
+                    field: format!("audit.{}", name),
+                    reason: "must be greater than 0".to_string(),
+                });
+            }
+        }
+        if self.audit.redact_keys.iter().any(|k| k.trim().is_empty()) {
+            return Err(ConfigError::Invalid {
+                field: "audit.redact_keys".to_string(),
+                reason: "key names must not be empty".to_string(),
+            });
+        }
+
+        for (field, value) in [
+            ("flakes.default", &self.flakes.default),
+            ("flakes.nixpkgs", &self.flakes.nixpkgs),
+        ] {
+            validate_flake_ref(value).map_err(|e| ConfigError::Invalid {
+                field: field.to_string(),
+                reason: e.to_string(),
+            })?;
+        }
+
+        if self.tools.enabled_groups.is_empty() {
+            return Err(ConfigError::Invalid {
+                field: "tools.enabled_groups".to_string(),
+                reason: "at least one tool group must be enabled".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Timeout for `tool` in seconds, falling back to the tool's built-in `default`.
+    pub fn timeout(&self, tool: &str, default: u64) -> u64 {
+        self.timeouts.get(tool).copied().unwrap_or(default)
+    }
+
+    /// Whether tools in `group` should be exposed.
+    pub fn is_group_enabled(&self, group: ToolGroup) -> bool {
+        self.tools.enabled_groups.contains(&group)
+    }
+
+    /// Whether `tool` should be exposed: its group is enabled and the profile allows it.
+    pub fn is_tool_exposed(&self, tool: &rmcp::model::Tool) -> bool {
+        ToolGroup::of(&tool.name).is_some_and(|g| self.is_group_enabled(g))
+            && self.tools.profile.allows(tool)
+    }
+
+    /// Config files in XDG precedence order (lowest first).
+    fn xdg_paths() -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+
+        // XDG_CONFIG_DIRS is ordered most-important first, so apply it in reverse
+        let system_dirs =
+            std::env::var("XDG_CONFIG_DIRS").unwrap_or_else(|_| "/etc/xdg".to_string());
+        for dir in system_dirs.split(':').filter(|d| !d.is_empty()).rev() {
+            paths.push(config_file_in(Path::new(dir)));
+        }
+
+        let user_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        if let Some(dir) = user_dir {
+            paths.push(config_file_in(&dir));
+        }
+
+        paths
+    }
+
+    fn default_value() -> toml::Value {
+        toml::Value::try_from(Self::default()).expect("default config serializes to TOML")
+    }
+}
+
+fn config_file_in(dir: &Path) -> PathBuf {
+    dir.join("onix-mcp").join("config.toml")
+}
+
+fn parse_layer(source: &str, content: &str) -> Result<toml::Value, ConfigError> {
+    content
+        .parse::<toml::Table>()
+        .map(toml::Value::Table)
+        .map_err(|e| ConfigError::Parse {
+            source: source.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Recursively merge `overlay` into `base`; non-table values are replaced.
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Set `value` at the dotted `path`, creating intermediate tables.
+fn set_path(root: &mut toml::Value, path: &[String], value: toml::Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut current = root;
+    for segment in parents {
+        let toml::Value::Table(table) = current else {
+            return;
+        };
+        current = table
+            .entry(segment.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    }
+
+    if let toml::Value::Table(table) = current {
+        table.insert(last.clone(), value);
+    }
+}
+
+/// Interpret an environment value as a TOML value, falling back to a string.
+fn parse_env_value(raw: &str) -> toml::Value {
+    format!("value = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.timeout("nix_build", 300), 300);
+        assert_eq!(config.flakes.default, ".");
+        assert!(ToolGroup::ALL.iter().all(|g| config.is_group_enabled(*g)));
+    }
+
+    #[test]
+    fn test_partial_cache_override_keeps_other_fields() {
+        let config = Config::from_toml_str(
+            r#"
+            [caches.search]
+            ttl_secs = 60
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.caches.search.ttl_secs, 60);
+        assert_eq!(config.caches.search.capacity, 1000);
+        assert_eq!(config.caches.locate, CacheConfig::default().locate);
+    }
+
+    #[test]
+    fn test_timeout_override() {
+        let config = Config::from_toml_str("[timeouts]\nnix_build = 900\n").unwrap();
+        assert_eq!(config.timeout("nix_build", 300), 900);
+        assert_eq!(config.timeout("lint_nix", 30), 30);
+    }
+
+    #[test]
+    fn test_unknown_tool_timeout_rejected() {
+        let err = Config::from_toml_str("[timeouts]\nnix_bild = 900\n").unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Invalid { ref field, .. } if field == "timeouts.nix_bild")
+        );
+    }
+
+    #[test]
+    fn test_zero_values_rejected() {
+        assert!(Config::from_toml_str("[timeouts]\nnix_build = 0\n").is_err());
+        assert!(Config::from_toml_str("[caches.eval]\nttl_secs = 0\n").is_err());
+        assert!(Config::from_toml_str("[caches.eval]\ncapacity = 0\n").is_err());
+        assert!(Config::from_toml_str("[caches.eval]\nmax_bytes = 0\n").is_err());
+        assert!(Config::from_toml_str("[cache_store]\nmax_bytes = 0\n").is_err());
+        assert!(Config::from_toml_str("[concurrency]\nbuild = 0\n").is_err());
+        assert!(Config::from_toml_str("[audit]\nmax_bytes = 0\n").is_err());
+        assert!(Config::from_toml_str("[audit]\nredact_keys = [\"\"]\n").is_err());
+        assert!(Config::from_toml_str(
+            "[rate_limits.tools.nix_build]\nburst = 0\nper_minute = 1\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let config = Config::from_toml_str(
+            r#"
+            [rate_limits.tools.nix_build]
+            burst = 2
+            per_minute = 6
+
+            [rate_limits.groups.clan_machines]
+            burst = 5
+            per_minute = 10
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.rate_limits.tools["nix_build"],
+            RateLimitSettings::new(2, 6)
+        );
+        assert_eq!(
+            config.rate_limits.groups[&ToolGroup::ClanMachines],
+            RateLimitSettings::new(5, 10)
+        );
+
+        let err =
+            Config::from_toml_str("[rate_limits.tools.nix_bild]\nburst = 1\nper_minute = 1\n")
+                .unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Invalid { ref field, .. } if field == "rate_limits.tools.nix_bild")
+        );
+        assert!(
+            Config::from_toml_str("[rate_limits.groups.warp]\nburst = 1\nper_minute = 1\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        assert!(Config::from_toml_str("[caches.bogus]\nttl_secs = 1\ncapacity = 1\n").is_err());
+        assert!(Config::from_toml_str("[nope]\nx = 1\n").is_err());
+        assert!(Config::from_toml_str("[tools]\nenabled_groups = [\"warp\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_invalid_flake_rejected() {
+        assert!(Config::from_toml_str("[flakes]\ndefault = \"; rm -rf /\"\n").is_err());
+    }
+
+    #[test]
+    fn test_enabled_groups() {
+        let config =
+            Config::from_toml_str("[tools]\nenabled_groups = [\"packages\", \"clan_machines\"]\n")
+                .unwrap();
+        assert!(config.is_group_enabled(ToolGroup::Packages));
+        assert!(config.is_group_enabled(ToolGroup::ClanMachines));
+        assert!(!config.is_group_enabled(ToolGroup::Pexpect));
+
+        assert!(Config::from_toml_str("[tools]\nenabled_groups = []\n").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = Config::load_from(
+            &[],
+            env(&[
+                ("ONIX_MCP__TIMEOUTS__NIX_BUILD", "1200"),
+                ("ONIX_MCP__CACHES__SEARCH__CAPACITY", "5"),
+                ("ONIX_MCP__FLAKES__DEFAULT", "/srv/infra"),
+                ("ONIX_MCP__TOOLS__ENABLED_GROUPS", r#"["build"]"#),
+                ("ONIX_MCP__AUDIT__FILE", "/var/log/onix-mcp/audit.jsonl"),
+                ("UNRELATED", "1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.timeout("nix_build", 300), 1200);
+        assert_eq!(config.caches.search.capacity, 5);
+        assert_eq!(config.flakes.default, "/srv/infra");
+        assert_eq!(config.tools.enabled_groups, vec![ToolGroup::Build]);
+        assert_eq!(
+            config.audit.file.as_deref(),
+            Some(Path::new("/var/log/onix-mcp/audit.jsonl"))
+        );
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        std::fs::write(&system, "[timeouts]\nnix_build = 100\nlint_nix = 10\n").unwrap();
+        std::fs::write(&user, "[timeouts]\nnix_build = 200\n").unwrap();
+
+        let files = vec![system, user, dir.path().join("missing.toml")];
+        let config = Config::load_from(&files, Vec::new()).unwrap();
+        assert_eq!(config.timeout("nix_build", 300), 200);
+        assert_eq!(config.timeout("lint_nix", 30), 10);
+
+        let config =
+            Config::load_from(&files, env(&[("ONIX_MCP__TIMEOUTS__NIX_BUILD", "300")])).unwrap();
+        assert_eq!(config.timeout("nix_build", 1), 300);
+    }
+
+    #[test]
+    fn test_parse_error_reports_source() {
+        let err = Config::from_toml_str("[timeouts\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+    }
+}
@@ -0,0 +1,427 @@
+//! Human confirmation for destructive operations.
+//!
+//! Tools such as `clan_machine_install` wipe disks or overwrite data. Before
+//! running them, the server asks the human behind the client to approve the
+//! operation:
+//!
+//! - Clients that support elicitation show a confirmation prompt listing the
+//!   machine, target host and flake. Declining or dismissing it cancels the
+//!   operation.
+//! - Other clients receive a warning and a single-use confirmation token. The
+//!   operation only runs when the tool is called again with that token and the
+//!   same arguments, within [`TOKEN_TTL`].
+//!
+//! Every approval and denial is recorded with
+//! [`AuditLogger::log_dangerous_operation`].
+
+use crate::common::security::AuditLogger;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use rmcp::handler::server::common::{AsRequestContext, FromContextPart};
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::{ElicitationError, RequestContext};
+use rmcp::{schemars, ErrorData as McpError, Peer, RoleServer};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tools that ask for confirmation of every call themselves.
+pub const CONFIRMED_TOOLS: &[&str] = &[
+    "clan_machine_update",
+    "clan_machine_delete",
+    "clan_machine_install",
+    "clan_backup_restore",
+];
+
+/// How long an issued confirmation token stays valid.
+pub const TOKEN_TTL: Duration = Duration::from_secs(300);
+
+/// How long to wait for the user to answer a confirmation prompt.
+const ELICITATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tokens issued to clients without elicitation support.
+static TOKENS: Lazy<TokenStore> = Lazy::new(TokenStore::default);
+
+/// Answer to the confirmation prompt.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+struct ConfirmationResponse {
+    /// Proceed with this destructive operation
+    confirm: bool,
+}
+
+rmcp::elicit_safe!(ConfirmationResponse);
+
+/// Description of a destructive operation shown to the user.
+///
+/// ```
+/// use onix_mcp::common::confirmation::DestructiveOperation;
+///
+/// let op = DestructiveOperation::new("clan_machine_delete", "Delete machine 'old-server'")
+///     .detail("Machine", "old-server")
+///     .detail("Flake", ".")
+///     .effect("Remove the machine configuration from the flake");
+/// assert!(op.prompt().contains("Machine: old-server"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DestructiveOperation {
+    tool: &'static str,
+    summary: String,
+    details: Vec<(&'static str, String)>,
+    effects: Vec<&'static str>,
+}
+
+impl DestructiveOperation {
+    /// Operation performed by `tool`, summarized in one line.
+    pub fn new(tool: &'static str, summary: impl Into<String>) -> Self {
+        Self {
+            tool,
+            summary: summary.into(),
+            details: Vec::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    /// Add a labelled argument (machine, target host, flake, ...).
+    ///
+    /// Details identify the operation: a confirmation token is only accepted
+    /// for an operation with the same tool and details.
+    pub fn detail(mut self, label: &'static str, value: impl Into<String>) -> Self {
+        self.details.push((label, value.into()));
+        self
+    }
+
+    /// Add a consequence of the operation to the warning.
+    pub fn effect(mut self, effect: &'static str) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    /// Confirmation prompt text.
+    pub fn prompt(&self) -> String {
+        let mut text = format!("WARNING: {}\n", self.summary);
+        for (label, value) in &self.details {
+            text.push_str(&format!("\n  {}: {}", label, value));
+        }
+        if !self.effects.is_empty() {
+            text.push_str("\n\nThis is a destructive operation that will:");
+            for effect in &self.effects {
+                text.push_str(&format!("\n- {}", effect));
+            }
+        }
+        text
+    }
+
+    /// Key binding a confirmation token to this exact operation.
+    fn fingerprint(&self) -> String {
+        serde_json::json!([self.tool, self.details]).to_string()
+    }
+}
+
+/// Result of asking for confirmation.
+#[derive(Debug)]
+pub enum ConfirmationOutcome {
+    /// The user approved; run the operation.
+    Approved,
+    /// The operation must not run; return this result to the client instead.
+    Declined(CallToolResult),
+}
+
+/// Asks the user to confirm destructive operations.
+///
+/// Can be used directly as a tool handler argument.
+#[derive(Clone, Default)]
+pub struct Confirmation {
+    peer: Option<Peer<RoleServer>>,
+}
+
+impl Confirmation {
+    /// Confirmation through the client that sent the request.
+    pub fn from_request(context: &RequestContext<RoleServer>) -> Self {
+        Self {
+            peer: Some(context.peer.clone()),
+        }
+    }
+
+    /// Confirmation through a client connection.
+    pub fn from_peer(peer: Peer<RoleServer>) -> Self {
+        Self { peer: Some(peer) }
+    }
+
+    /// Confirmation that can only use tokens (no client to prompt).
+    pub fn tokens_only() -> Self {
+        Self::default()
+    }
+
+    /// Ask the user to confirm `operation`.
+    ///
+    /// A `token` from an earlier call is redeemed instead of prompting. An
+    /// unknown, expired or mismatched token is an invalid-params error.
+    pub async fn confirm(
+        &self,
+        audit: &AuditLogger,
+        operation: &DestructiveOperation,
+        token: Option<&str>,
+    ) -> Result<ConfirmationOutcome, McpError> {
+        let fingerprint = operation.fingerprint();
+
+        if let Some(token) = token {
+            if TOKENS.redeem(token, &fingerprint) {
+                audit.log_dangerous_operation(
+                    operation.tool,
+                    true,
+                    &format!("{} (confirmed with token)", operation.summary),
+                );
+                return Ok(ConfirmationOutcome::Approved);
+            }
+
+            audit.log_dangerous_operation(
+                operation.tool,
+                false,
+                &format!("{} (invalid confirmation token)", operation.summary),
+            );
+            return Err(McpError::invalid_params(
+                "Invalid confirmation token: it has expired, was already used, \
+                 or was issued for different arguments",
+                None,
+            ));
+        }
+
+        match &self.peer {
+            Some(peer) if peer.supports_elicitation() => self.elicit(peer, audit, operation).await,
+            _ => {
+                let token = TOKENS.issue(fingerprint);
+                Ok(ConfirmationOutcome::Declined(CallToolResult::success(
+                    vec![Content::text(format!(
+                        "{}\n\nTo proceed, call this tool again with the same arguments and \
+                         confirmation_token=\"{}\". The token can be used once and expires in \
+                         {} minutes.",
+                        operation.prompt(),
+                        token,
+                        TOKEN_TTL.as_secs() / 60
+                    ))],
+                )))
+            }
+        }
+    }
+
+    async fn elicit(
+        &self,
+        peer: &Peer<RoleServer>,
+        audit: &AuditLogger,
+        operation: &DestructiveOperation,
+    ) -> Result<ConfirmationOutcome, McpError> {
+        let message = format!("{}\n\nProceed?", operation.prompt());
+        let reason = match peer
+            .elicit_with_timeout::<ConfirmationResponse>(message, Some(ELICITATION_TIMEOUT))
+            .await
+        {
+            Ok(Some(ConfirmationResponse { confirm: true })) => {
+                audit.log_dangerous_operation(
+                    operation.tool,
+                    true,
+                    &format!("{} (confirmed by user)", operation.summary),
+                );
+                return Ok(ConfirmationOutcome::Approved);
+            }
+            Ok(_) | Err(ElicitationError::UserDeclined) => "declined by user".to_string(),
+            Err(ElicitationError::UserCancelled) => "cancelled by user".to_string(),
+            Err(e) => format!("confirmation failed: {}", e),
+        };
+
+        audit.log_dangerous_operation(
+            operation.tool,
+            false,
+            &format!("{} ({})", operation.summary, reason),
+        );
+        Ok(ConfirmationOutcome::Declined(CallToolResult::error(vec![
+            Content::text(format!(
+                "Operation not performed ({}): {}",
+                reason, operation.summary
+            )),
+        ])))
+    }
+}
+
+impl<C: AsRequestContext> FromContextPart<C> for Confirmation {
+    fn from_context_part(context: &mut C) -> Result<Self, rmcp::ErrorData> {
+        Ok(Self::from_request(context.as_request_context()))
+    }
+}
+
+/// Single-use tokens bound to an operation fingerprint.
+#[derive(Default)]
+struct TokenStore {
+    tokens: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl TokenStore {
+    /// Issue a token for the operation with `fingerprint`.
+    fn issue(&self, fingerprint: String) -> String {
+        let token = format!("{:032x}", rand::rng().random::<u128>());
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        tokens.retain(|_, (_, expires)| *expires > now);
+        tokens.insert(token.clone(), (fingerprint, now + TOKEN_TTL));
+        token
+    }
+
+    /// Consume `token`; true if it was issued for `fingerprint` and has not expired.
+    ///
+    /// A token is consumed even when it does not match, so it cannot be probed.
+    fn redeem(&self, token: &str, fingerprint: &str) -> bool {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        match tokens.remove(token) {
+            Some((issued_for, expires)) => issued_for == fingerprint && expires > Instant::now(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::nix_server::NixServer;
+    use rmcp::model::{
+        ClientCapabilities, ClientInfo, CreateElicitationRequestParam, CreateElicitationResult,
+        ElicitationAction, ElicitationCapability,
+    };
+    use rmcp::service::RequestContext;
+    use rmcp::{ClientHandler, RoleClient, ServiceExt};
+
+    fn install() -> DestructiveOperation {
+        DestructiveOperation::new("clan_machine_install", "Install machine 'web1' to 'host'")
+            .detail("Machine", "web1")
+            .detail("Target host", "root@host")
+            .detail("Flake", ".")
+            .effect("Partition and format the target disk")
+    }
+
+    /// Client that answers every confirmation prompt the same way.
+    struct Responder(ElicitationAction, Option<serde_json::Value>);
+
+    impl ClientHandler for Responder {
+        async fn create_elicitation(
+            &self,
+            request: CreateElicitationRequestParam,
+            _context: RequestContext<RoleClient>,
+        ) -> Result<CreateElicitationResult, McpError> {
+            assert!(request.message.contains("Target host: root@host"));
+            Ok(CreateElicitationResult {
+                action: self.0.clone(),
+                content: self.1.clone(),
+            })
+        }
+
+        fn get_info(&self) -> ClientInfo {
+            ClientInfo {
+                capabilities: ClientCapabilities {
+                    elicitation: Some(ElicitationCapability::default()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    async fn confirm_with(client: Responder) -> ConfirmationOutcome {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(NixServer::new().serve(server_io), async {
+            client.serve(client_io).await
+        });
+        let (server, client) = (server.unwrap(), client.unwrap());
+
+        let outcome = Confirmation::from_peer(server.peer().clone())
+            .confirm(&AuditLogger::new(), &install(), None)
+            .await
+            .unwrap();
+
+        client.cancel().await.unwrap();
+        outcome
+    }
+
+    #[test]
+    fn test_token_single_use() {
+        let store = TokenStore::default();
+        let token = store.issue("op".to_string());
+
+        assert!(store.redeem(&token, "op"));
+        assert!(!store.redeem(&token, "op"));
+    }
+
+    #[test]
+    fn test_token_bound_to_operation() {
+        let store = TokenStore::default();
+        let token = store.issue("op".to_string());
+
+        assert!(!store.redeem(&token, "other"));
+        // Consumed by the failed attempt
+        assert!(!store.redeem(&token, "op"));
+        assert!(!store.redeem("unknown", "op"));
+    }
+
+    #[test]
+    fn test_fingerprint_covers_details() {
+        let other_host = DestructiveOperation::new("clan_machine_install", "Install machine")
+            .detail("Machine", "web1")
+            .detail("Target host", "root@elsewhere")
+            .detail("Flake", ".");
+
+        assert_ne!(install().fingerprint(), other_host.fingerprint());
+    }
+
+    #[tokio::test]
+    async fn test_token_fallback() {
+        let audit = AuditLogger::new();
+        let confirmation = Confirmation::tokens_only();
+
+        let ConfirmationOutcome::Declined(result) = confirmation
+            .confirm(&audit, &install(), None)
+            .await
+            .unwrap()
+        else {
+            panic!("operation approved without confirmation");
+        };
+        let text = result.content[0].as_text().unwrap().text.clone();
+        assert!(text.contains("Target host: root@host"));
+        let token = text
+            .split("confirmation_token=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+
+        assert!(matches!(
+            confirmation.confirm(&audit, &install(), Some(token)).await,
+            Ok(ConfirmationOutcome::Approved)
+        ));
+        assert!(confirmation
+            .confirm(&audit, &install(), Some(token))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_elicitation_accepted() {
+        let outcome = confirm_with(Responder(
+            ElicitationAction::Accept,
+            Some(serde_json::json!({"confirm": true})),
+        ))
+        .await;
+        assert!(matches!(outcome, ConfirmationOutcome::Approved));
+    }
+
+    #[tokio::test]
+    async fn test_elicitation_declined() {
+        let outcome = confirm_with(Responder(ElicitationAction::Decline, None)).await;
+        let ConfirmationOutcome::Declined(result) = outcome else {
+            panic!("declined operation was approved");
+        };
+        assert_eq!(result.is_error, Some(true));
+
+        let outcome = confirm_with(Responder(
+            ElicitationAction::Accept,
+            Some(serde_json::json!({"confirm": false})),
+        ))
+        .await;
+        assert!(matches!(outcome, ConfirmationOutcome::Declined(_)));
+    }
+}
@@ -0,0 +1,710 @@
+//! Persistent cache store shared by every cache in the registry.
+//!
+//! [`DiskCache`] keeps cached tool results in a single JSON Lines file
+//! (default: `$XDG_CACHE_HOME/onix-mcp/cache.jsonl`), so expensive
+//! `nix-locate`, `nix search` and closure-size results survive server
+//! restarts. Each line is a record holding the cache name, key, value, the
+//! wall-clock expiry computed from that cache's TTL, and a SHA-256 checksum.
+//!
+//! - **Write-through**: [`TtlCache::insert`](crate::common::cache::TtlCache::insert)
+//!   queues a record that a writer thread appends; later records for the
+//!   same key supersede earlier ones. Writes and compaction never block the
+//!   caller.
+//! - **Lazy warm load**: [`DiskCache::warm`] starts the writer thread, which
+//!   indexes the file first, so startup does not wait for it. Lookups made
+//!   before indexing finishes are misses. Values are read from disk only
+//!   when a key misses in memory.
+//! - **Size cap**: once the file exceeds `max_bytes` it is compacted to the
+//!   newest live records filling three quarters of the cap.
+//! - **Corruption**: lines that do not parse or fail their checksum (a crash
+//!   mid-write, a truncated file) are skipped and dropped by the next
+//!   compaction. If the file cannot be opened at all, caching stays in memory.
+//! - **One owner**: the store holds an exclusive `flock` on `<file>.lock`
+//!   while open. Other servers sharing the file (one per editor with stdio)
+//!   cache in memory only instead of interleaving their writes.
+//!
+//! ```no_run
+//! use onix_mcp::common::cache_registry::CacheRegistry;
+//! use onix_mcp::common::config::CacheConfig;
+//! use onix_mcp::common::disk_cache::DiskCache;
+//! use std::sync::Arc;
+//!
+//! let store = Arc::new(DiskCache::open("/var/cache/onix-mcp/cache.jsonl", 64 << 20));
+//! store.warm();
+//! let caches = CacheRegistry::persistent(&CacheConfig::default(), store);
+//! ```
+
+use crate::common::cache::CacheBackend;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::RuntimeFlavor;
+
+/// One line of the cache file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    cache: String,
+    key: String,
+    value: String,
+    /// Unix time in seconds after which the record is stale, or
+    /// [`NEVER`] for records that do not expire
+    expires_at: u64,
+    /// SHA-256 over the other fields, hex encoded
+    sum: String,
+}
+
+/// The fields covered by [`Record::sum`], in record order.
+#[derive(Serialize)]
+struct SummedFields<'a> {
+    cache: &'a str,
+    key: &'a str,
+    value: &'a str,
+    expires_at: u64,
+}
+
+impl Record {
+    fn new(cache: &str, key: &str, value: &str, expires_at: u64) -> Self {
+        Self {
+            cache: cache.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            expires_at,
+            sum: checksum(cache, key, value, expires_at),
+        }
+    }
+
+    /// Parse a line, rejecting it if the checksum does not match.
+    fn parse(line: &[u8]) -> Option<Self> {
+        let record: Self = serde_json::from_slice(line).ok()?;
+        (record.sum == checksum(&record.cache, &record.key, &record.value, record.expires_at))
+            .then_some(record)
+    }
+}
+
+fn checksum(cache: &str, key: &str, value: &str, expires_at: u64) -> String {
+    let fields = SummedFields {
+        cache,
+        key,
+        value,
+        expires_at,
+    };
+    let bytes = serde_json::to_vec(&fields).expect("cache record serializes");
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// [`Record::expires_at`] of records that never expire.
+const NEVER: u64 = u64::MAX;
+
+fn expiry_secs(expires_at: Option<SystemTime>) -> u64 {
+    expires_at.map_or(NEVER, unix_secs)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Location of a record in the file.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    offset: u64,
+    /// Length without the trailing newline
+    len: u64,
+    expires_at: u64,
+}
+
+/// Cache name -> key -> newest record.
+type Index = HashMap<String, HashMap<String, Slot>>;
+
+struct State {
+    file: File,
+    size: u64,
+    index: Index,
+    /// Exclusive lock held while the store is open
+    _lock: File,
+}
+
+/// Work queued for the writer thread.
+enum Op {
+    Put(Record),
+    /// Answered once every earlier operation is done
+    Flush(mpsc::Sender<()>),
+}
+
+/// File state shared by a [`DiskCache`] and its writer thread.
+struct Inner {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Set by the writer thread once the file is indexed; `None` if unusable
+    state: OnceLock<Option<Mutex<State>>>,
+}
+
+/// Single-file persistent store backing the caches of a
+/// [`CacheRegistry`](crate::common::cache_registry::CacheRegistry).
+///
+/// Opening, indexing, writing and compacting the file happen on a writer
+/// thread started by [`warm`](Self::warm), never on the caller's thread.
+pub struct DiskCache {
+    inner: Arc<Inner>,
+    /// Queue of the writer thread, closed when the store is dropped
+    ops: Mutex<Option<mpsc::Sender<Op>>>,
+    /// Receiving end of `ops` until the writer thread takes it
+    pending: Mutex<Option<mpsc::Receiver<Op>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DiskCache {
+    /// Store backed by `path`, compacted when it grows beyond `max_bytes`.
+    ///
+    /// The file is not touched until [`warm`](Self::warm).
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        let (ops, pending) = mpsc::channel();
+        Self {
+            inner: Arc::new(Inner {
+                path: path.into(),
+                max_bytes,
+                state: OnceLock::new(),
+            }),
+            ops: Mutex::new(Some(ops)),
+            pending: Mutex::new(Some(pending)),
+            writer: Mutex::new(None),
+        }
+    }
+
+    /// Start the writer thread, which indexes the file and then applies
+    /// writes in the order they were made.
+    ///
+    /// Lookups made before indexing finishes are misses; writes made before
+    /// are queued. Later calls have no effect.
+    pub fn warm(&self) {
+        let Some(ops) = lock(&self.pending).take() else {
+            return;
+        };
+        let inner = self.inner.clone();
+        let writer = std::thread::Builder::new()
+            .name("onix-mcp-cache-store".to_string())
+            .spawn(move || inner.run(ops));
+        match writer {
+            Ok(writer) => *lock(&self.writer) = Some(writer),
+            Err(e) => tracing::warn!(error = %e, "Failed to start cache store writer"),
+        }
+    }
+
+    /// Wait until every write made so far is on disk (or has failed).
+    ///
+    /// Returns at once if the store was never [warmed](Self::warm).
+    pub fn flush(&self) {
+        if lock(&self.writer).is_none() {
+            return;
+        }
+        let (done, wait) = mpsc::channel();
+        if let Some(ops) = lock(&self.ops).as_ref() {
+            if ops.send(Op::Flush(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+    }
+
+    /// [`CacheBackend`] storing the entries of the cache called `cache`.
+    pub fn backend(self: &Arc<Self>, cache: &'static str) -> Arc<dyn CacheBackend<String, String>> {
+        Arc::new(DiskCacheBackend {
+            store: self.clone(),
+            cache,
+        })
+    }
+
+    /// Unexpired value of `key` in `cache`, with its expiry time (`None` if
+    /// it never expires).
+    ///
+    /// Lookups while the file is still being indexed or compacted miss
+    /// instead of waiting for it.
+    pub fn get(&self, cache: &str, key: &str) -> Option<(String, Option<SystemTime>)> {
+        let mut state = self.inner.state.get()?.as_ref()?.try_lock().ok()?;
+        let slot = *state.index.get(cache)?.get(key)?;
+
+        let record = if slot.expires_at > unix_secs(SystemTime::now()) {
+            blocking_io(|| read_record(&state.file, slot))
+                .filter(|r| r.cache == cache && r.key == key)
+        } else {
+            None
+        };
+        match record {
+            Some(record) => Some((
+                record.value,
+                (record.expires_at != NEVER)
+                    .then(|| UNIX_EPOCH + Duration::from_secs(record.expires_at)),
+            )),
+            None => {
+                // Expired or unreadable: forget it, compaction drops the line
+                if let Some(keys) = state.index.get_mut(cache) {
+                    keys.remove(key);
+                }
+                None
+            }
+        }
+    }
+
+    /// Store `value` for `key` in `cache` until `expires_at`, or for good if
+    /// it is `None`.
+    ///
+    /// The record is written by the writer thread. Write errors are logged
+    /// and otherwise ignored; the entry then only lives in memory.
+    pub fn put(&self, cache: &str, key: &str, value: &str, expires_at: Option<SystemTime>) {
+        let record = Record::new(cache, key, value, expiry_secs(expires_at));
+        if let Some(ops) = lock(&self.ops).as_ref() {
+            let _ = ops.send(Op::Put(record));
+        }
+    }
+
+    /// Size of the cache file in bytes, or 0 if it is unusable or not yet
+    /// indexed.
+    pub fn size(&self) -> u64 {
+        self.inner
+            .state
+            .get()
+            .and_then(Option::as_ref)
+            .map_or(0, |state| lock(state).size)
+    }
+}
+
+impl Drop for DiskCache {
+    /// Finish the queued writes before the file lock is released.
+    fn drop(&mut self) {
+        lock(&self.ops).take();
+        if let Some(writer) = lock(&self.writer).take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Inner {
+    /// Body of the writer thread: index the file, then apply `ops` until
+    /// the store is dropped.
+    fn run(&self, ops: mpsc::Receiver<Op>) {
+        let state = self.state();
+        for op in ops {
+            match op {
+                Op::Put(record) => {
+                    if let Some(state) = state {
+                        self.append(&mut lock(state), record);
+                    }
+                }
+                Op::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    fn append(&self, state: &mut State, record: Record) {
+        let mut line = serde_json::to_vec(&record).expect("cache record serializes");
+        line.push(b'\n');
+        // A record that cannot fit after compaction is not worth writing
+        if line.len() as u64 > self.max_bytes / 2 {
+            return;
+        }
+
+        if let Err(e) = state.file.write_all(&line) {
+            tracing::warn!(path = %self.path.display(), error = %e, "Failed to write cache entry");
+            // A partial write still moved the end of the file
+            state.size = state.file.metadata().map_or(state.size, |m| m.len());
+            return;
+        }
+        let slot = Slot {
+            offset: state.size,
+            len: line.len() as u64 - 1,
+            expires_at: record.expires_at,
+        };
+        state.size += line.len() as u64;
+        state
+            .index
+            .entry(record.cache)
+            .or_default()
+            .insert(record.key, slot);
+
+        if state.size > self.max_bytes {
+            self.compact(state);
+        }
+    }
+
+    fn state(&self) -> Option<&Mutex<State>> {
+        self.state
+            .get_or_init(|| match self.load() {
+                Ok(state) => Some(Mutex::new(state)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    tracing::info!(
+                        path = %self.path.display(),
+                        "Persistent cache in use by another server, caching in memory only"
+                    );
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        path = %self.path.display(),
+                        error = %e,
+                        "Persistent cache unavailable, caching in memory only"
+                    );
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Lock the file, open it and index its valid, unexpired records.
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if another store holds the lock.
+    fn load(&self) -> io::Result<State> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Compaction replaces the cache file, so the lock lives on a file of its own
+        let lock = open_file(&lock_path(&self.path))?;
+        try_lock_exclusive(&lock)?;
+        let mut file = open_file(&self.path)?;
+
+        let now = unix_secs(SystemTime::now());
+        let mut index: Index = HashMap::new();
+        let mut corrupted = 0usize;
+        let mut offset = 0u64;
+        let mut torn = false;
+        let mut reader = BufReader::new(&file);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            let complete = line.last() == Some(&b'\n');
+            torn = !complete;
+            let body = if complete {
+                &line[..line.len() - 1]
+            } else {
+                &line[..]
+            };
+            match Record::parse(body).filter(|_| complete) {
+                Some(record) if record.expires_at > now => {
+                    let slot = Slot {
+                        offset,
+                        len: body.len() as u64,
+                        expires_at: record.expires_at,
+                    };
+                    index
+                        .entry(record.cache)
+                        .or_default()
+                        .insert(record.key, slot);
+                }
+                Some(_) => {}
+                None if body.iter().all(u8::is_ascii_whitespace) => {}
+                None => corrupted += 1,
+            }
+            offset += read;
+        }
+
+        drop(reader);
+
+        // A torn final line would swallow the next record
+        if torn {
+            file.write_all(b"\n")?;
+            offset += 1;
+        }
+
+        let entries: usize = index.values().map(HashMap::len).sum();
+        if corrupted > 0 {
+            tracing::warn!(
+                path = %self.path.display(),
+                corrupted,
+                "Skipped corrupted cache records"
+            );
+        }
+        tracing::debug!(path = %self.path.display(), entries, "Loaded persistent cache");
+
+        let mut state = State {
+            file,
+            size: offset,
+            index,
+            _lock: lock,
+        };
+        if corrupted > 0 || state.size > self.max_bytes {
+            self.compact(&mut state);
+        }
+        Ok(state)
+    }
+
+    /// Rewrite the file with the newest live records that fit in three
+    /// quarters of `max_bytes`.
+    fn compact(&self, state: &mut State) {
+        let now = unix_secs(SystemTime::now());
+        let mut live: Vec<Slot> = state
+            .index
+            .values()
+            .flat_map(HashMap::values)
+            .filter(|slot| slot.expires_at > now)
+            .copied()
+            .collect();
+        live.sort_by_key(|slot| std::cmp::Reverse(slot.offset));
+
+        let budget = self.max_bytes / 4 * 3;
+        let mut used = 0u64;
+        let mut kept = Vec::new();
+        for slot in live {
+            if used + slot.len + 1 > budget {
+                break;
+            }
+            if let Some(record) = read_record(&state.file, slot) {
+                used += slot.len + 1;
+                kept.push(record);
+            }
+        }
+        kept.reverse();
+
+        match self.rewrite(&kept) {
+            Ok((file, index)) => {
+                state.file = file;
+                state.size = used;
+                state.index = index;
+            }
+            Err(e) => {
+                tracing::warn!(path = %self.path.display(), error = %e, "Failed to compact cache file")
+            }
+        }
+    }
+
+    /// Replace the file with `records`, returning the reopened file and its index.
+    fn rewrite(&self, records: &[Record]) -> io::Result<(File, Index)> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut index: Index = HashMap::new();
+        {
+            let mut out = io::BufWriter::new(File::create(&tmp)?);
+            let mut offset = 0u64;
+            for record in records {
+                let line = serde_json::to_vec(record).expect("cache record serializes");
+                out.write_all(&line)?;
+                out.write_all(b"\n")?;
+                index.entry(record.cache.clone()).or_default().insert(
+                    record.key.clone(),
+                    Slot {
+                        offset,
+                        len: line.len() as u64,
+                        expires_at: record.expires_at,
+                    },
+                );
+                offset += line.len() as u64 + 1;
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)?;
+        Ok((open_file(&self.path)?, index))
+    }
+}
+
+fn open_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Take an exclusive `flock` on `file` without waiting.
+fn try_lock_exclusive(file: &File) -> io::Result<()> {
+    // SAFETY: flock only operates on the descriptor, which `file` keeps open
+    // for the duration of the call.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run blocking file I/O, letting a multi-threaded tokio runtime move its
+/// other tasks off this worker meanwhile.
+fn blocking_io<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) if runtime.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+fn read_record(file: &File, slot: Slot) -> Option<Record> {
+    let mut buf = vec![0; slot.len as usize];
+    file.read_exact_at(&mut buf, slot.offset).ok()?;
+    Record::parse(&buf)
+}
+
+/// One named cache inside a [`DiskCache`].
+struct DiskCacheBackend {
+    store: Arc<DiskCache>,
+    cache: &'static str,
+}
+
+impl CacheBackend<String, String> for DiskCacheBackend {
+    fn load(&self, key: &String) -> Option<(String, Option<SystemTime>)> {
+        self.store.get(self.cache, key)
+    }
+
+    fn store(&self, key: &String, value: &String, expires_at: Option<SystemTime>) {
+        self.store.put(self.cache, key, value, expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cache file in a fresh directory, removed when the guard drops.
+    fn temp_store() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        (dir, path)
+    }
+
+    fn in_an_hour() -> Option<SystemTime> {
+        Some(SystemTime::now() + Duration::from_secs(3600))
+    }
+
+    /// Store on `path` with its file indexed.
+    fn warmed(path: &Path, max_bytes: u64) -> DiskCache {
+        let store = DiskCache::open(path, max_bytes);
+        store.warm();
+        store.flush();
+        store
+    }
+
+    #[test]
+    fn test_entries_survive_reopen() {
+        let (_dir, path) = temp_store();
+        let store = warmed(&path, 1 << 20);
+        store.put("search", "hello", "v1", in_an_hour());
+        store.put("search", "hello", "v2", in_an_hour());
+        store.put("locate", "hello", "bin/hello", in_an_hour());
+        store.put(
+            "search",
+            "gone",
+            "x",
+            Some(SystemTime::now() - Duration::from_secs(1)),
+        );
+        store.put("search", "pinned", "p", None);
+        drop(store);
+
+        let store = warmed(&path, 1 << 20);
+        assert_eq!(store.get("search", "hello").unwrap().0, "v2");
+        assert_eq!(store.get("locate", "hello").unwrap().0, "bin/hello");
+        assert!(store.get("search", "gone").is_none());
+        assert_eq!(store.get("search", "pinned"), Some(("p".to_string(), None)));
+        assert!(store.get("eval", "hello").is_none());
+    }
+
+    #[test]
+    fn test_lookups_miss_until_warm() {
+        let (_dir, path) = temp_store();
+        let store = warmed(&path, 1 << 20);
+        store.put("search", "a", "1", in_an_hour());
+        drop(store);
+
+        let store = DiskCache::open(&path, 1 << 20);
+        assert!(store.get("search", "a").is_none());
+        // Queued until the writer thread runs
+        store.put("search", "b", "2", in_an_hour());
+        assert_eq!(store.size(), 0);
+
+        store.warm();
+        store.flush();
+        assert_eq!(store.get("search", "a").unwrap().0, "1");
+        assert_eq!(store.get("search", "b").unwrap().0, "2");
+    }
+
+    #[test]
+    fn test_corrupted_records_skipped() {
+        let (_dir, path) = temp_store();
+        let store = warmed(&path, 1 << 20);
+        store.put("search", "a", "1", in_an_hour());
+        store.put("search", "b", "2", in_an_hour());
+        drop(store);
+
+        // Tamper with one record and tear the end of the file
+        let content = std::fs::read_to_string(&path).unwrap();
+        let tampered = content.replacen("\"value\":\"1\"", "\"value\":\"9\"", 1);
+        std::fs::write(&path, format!("not json\n{}{{\"cache\":\"sea", tampered)).unwrap();
+
+        let store = warmed(&path, 1 << 20);
+        assert!(store.get("search", "a").is_none());
+        assert_eq!(store.get("search", "b").unwrap().0, "2");
+        store.put("search", "c", "3", in_an_hour());
+        drop(store);
+
+        // Compaction on load dropped the bad lines
+        let store = warmed(&path, 1 << 20);
+        assert_eq!(store.get("search", "c").unwrap().0, "3");
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_size_cap_keeps_newest() {
+        let (_dir, path) = temp_store();
+        let store = warmed(&path, 4096);
+        let value = "x".repeat(200);
+        for i in 0..100 {
+            store.put("search", &format!("key{}", i), &value, in_an_hour());
+            store.flush();
+            assert!(store.size() <= 4096);
+        }
+        assert!(store.get("search", "key99").is_some());
+        assert!(store.get("search", "key0").is_none());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), store.size());
+    }
+
+    #[test]
+    fn test_second_store_on_same_file_stays_in_memory() {
+        let (_dir, path) = temp_store();
+        let owner = warmed(&path, 1 << 20);
+        owner.put("search", "a", "1", in_an_hour());
+        owner.flush();
+
+        let other = warmed(&path, 1 << 20);
+        other.put("search", "b", "2", in_an_hour());
+        other.flush();
+        assert!(other.get("search", "a").is_none());
+        assert_eq!(other.size(), 0);
+        drop(other);
+        drop(owner);
+
+        // The lock is released with the owning store
+        let store = warmed(&path, 1 << 20);
+        assert_eq!(store.get("search", "a").unwrap().0, "1");
+        assert!(store.get("search", "b").is_none());
+    }
+
+    #[test]
+    fn test_unusable_path_disables_store() {
+        let (_dir, path) = temp_store();
+        std::fs::create_dir_all(&path).unwrap();
+        let store = warmed(&path, 1 << 20);
+        store.put("search", "a", "1", in_an_hour());
+        store.flush();
+        assert!(store.get("search", "a").is_none());
+        assert_eq!(store.size(), 0);
+    }
+}
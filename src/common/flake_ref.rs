@@ -0,0 +1,897 @@
+//! Parsed and normalized flake references.
+//!
+//! [`FlakeRef`] understands the flake reference forms documented for
+//! `nix flake`:
+//!
+//! | Form | Example |
+//! |------|---------|
+//! | Indirect (registry) | `nixpkgs`, `flake:nixpkgs/nixos-24.05` |
+//! | Local path | `.`, `../infra`, `/etc/nixos`, `path:./sub` |
+//! | Git | `git+https://host/repo`, `git+ssh://git@host/repo`, `git+file:///src` |
+//! | Forges | `github:owner/repo/ref`, `gitlab:owner/repo`, `gitlab:group%2Fsub/repo`, `sourcehut:~user/repo` |
+//! | Tarball | `https://host/src.tar.gz`, `tarball+https://host/archive` |
+//! | File | `https://host/flake.nix`, `file+https://host/src.tar.gz` |
+//!
+//! Every form accepts `?key=value` parameters (`ref`, `rev`, `dir`, ...) and
+//! an `#attr.path` fragment. Parsing checks each component against what Nix
+//! itself accepts, so anything that parses is safe to pass to `nix` as a
+//! single argument.
+//!
+//! [`Display`](std::fmt::Display) prints a canonical spelling, so references
+//! that differ only in how they are written compare equal and make the same
+//! cache key:
+//!
+//! ```
+//! use onix_mcp::common::flake_ref::FlakeRef;
+//!
+//! let a: FlakeRef = "github:NixOS/nixpkgs?ref=nixos-unstable".parse().unwrap();
+//! let b: FlakeRef = "github:nixos/nixpkgs/nixos-unstable".parse().unwrap();
+//! assert_eq!(a, b);
+//! assert_eq!(a.to_string(), "github:nixos/nixpkgs/nixos-unstable");
+//!
+//! let c: FlakeRef = "flake:nixpkgs?dir=./&ref=nixos-unstable#hello".parse().unwrap();
+//! assert_eq!(c.to_string(), "nixpkgs/nixos-unstable#hello");
+//! ```
+//!
+//! Normalization never changes what Nix fetches. Bare paths stay distinct
+//! from `path:` references because Nix fetches a bare path inside a Git
+//! repository with Git, which ignores untracked files.
+
+use crate::common::security::ValidationError;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Maximum accepted length of a flake reference.
+pub const MAX_FLAKE_REF_LEN: usize = 1000;
+
+/// Extensions that make a plain URL a tarball rather than a file.
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    ".zip", ".tar", ".tgz", ".tar.gz", ".tar.xz", ".tar.bz2", ".tar.zst",
+];
+
+/// Parameters accepted by every form; `dir` selects a subdirectory flake.
+const COMMON_PARAMS: &[&str] = &["dir", "narHash"];
+const INDIRECT_PARAMS: &[&str] = &["ref", "rev"];
+const PATH_PARAMS: &[&str] = &["rev", "revCount", "lastModified"];
+const GIT_PARAMS: &[&str] = &[
+    "ref",
+    "rev",
+    "shallow",
+    "submodules",
+    "lfs",
+    "exportIgnore",
+    "allRefs",
+    "revCount",
+    "lastModified",
+    "name",
+    "verifyCommit",
+    "keytype",
+    "publicKey",
+    "publicKeys",
+];
+const FORGE_PARAMS: &[&str] = &["ref", "rev", "host", "lastModified", "treeHash"];
+
+/// Git parameters Nix reads as booleans; only `0` and `1` are meaningful.
+const BOOL_PARAMS: &[&str] = &[
+    "shallow",
+    "submodules",
+    "lfs",
+    "exportIgnore",
+    "allRefs",
+    "verifyCommit",
+];
+const NUMERIC_PARAMS: &[&str] = &["revCount", "lastModified"];
+
+static FLAKE_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9_-]*$").unwrap());
+static REV: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:[0-9a-f]{40}|[0-9a-f]{64})$").unwrap());
+static GIT_REF: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[a-zA-Z0-9@][a-zA-Z0-9_./@+-]*$").unwrap());
+static FORGE_NAME: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^~?[a-zA-Z0-9_][a-zA-Z0-9_.-]*$").unwrap());
+/// GitLab group path with subgroups separated by `%2F` (`veloren%2Fdev`).
+static GITLAB_OWNER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9_.-]*(?:%2[fF][a-zA-Z0-9_][a-zA-Z0-9_.-]*)*$").unwrap()
+});
+static AUTHORITY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:[a-zA-Z0-9_.-]+@)?[a-zA-Z0-9](?:[a-zA-Z0-9.-]*[a-zA-Z0-9])?(?::[0-9]{1,5})?$")
+        .unwrap()
+});
+static ATTR_PATH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_'+-]+(?:\.[a-zA-Z0-9_'+-]+)*$").unwrap());
+static PARAM_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9]*$").unwrap());
+static PARAM_VALUE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9._~/+=:@%,-]+$").unwrap());
+static PATH: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9._~/+=:@%,-]*$").unwrap());
+
+/// Code forges with a dedicated flake reference scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+    SourceHut,
+}
+
+impl Forge {
+    /// URL scheme of the forge, without the colon.
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Forge::GitHub => "github",
+            Forge::GitLab => "gitlab",
+            Forge::SourceHut => "sourcehut",
+        }
+    }
+}
+
+/// Transport of a `git+` reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GitTransport {
+    Http,
+    Https,
+    Ssh,
+    File,
+}
+
+impl GitTransport {
+    fn scheme(&self) -> &'static str {
+        match self {
+            GitTransport::Http => "http",
+            GitTransport::Https => "https",
+            GitTransport::Ssh => "ssh",
+            GitTransport::File => "file",
+        }
+    }
+}
+
+/// Where a flake comes from, with parameters and fragment stripped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FlakeSource {
+    /// Registry entry such as `nixpkgs`.
+    Indirect { id: String },
+    /// Local directory. `explicit` is true for `path:` references; bare
+    /// paths may be fetched as Git repositories instead.
+    Path { path: String, explicit: bool },
+    /// `git+<transport>://<location>`; for `file` the location is the path.
+    Git {
+        transport: GitTransport,
+        location: String,
+    },
+    /// `github:`, `gitlab:` or `sourcehut:` repository.
+    Forge {
+        forge: Forge,
+        owner: String,
+        repo: String,
+    },
+    /// Archive unpacked as the flake source.
+    Tarball { url: String },
+    /// Single file used as the flake source.
+    File { url: String },
+}
+
+/// A validated flake reference in canonical form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlakeRef {
+    source: FlakeSource,
+    params: BTreeMap<String, String>,
+    fragment: Option<String>,
+}
+
+impl FlakeRef {
+    /// Parse and normalize a flake reference.
+    pub fn parse(input: &str) -> Result<Self, ValidationError> {
+        if input.is_empty() {
+            return Err(ValidationError::Empty {
+                field: "flake_ref".to_string(),
+            });
+        }
+        if input.len() > MAX_FLAKE_REF_LEN {
+            return Err(ValidationError::TooLong {
+                field: "flake_ref".to_string(),
+                max_length: MAX_FLAKE_REF_LEN,
+                actual: input.len(),
+            });
+        }
+        if input.contains('\0') {
+            return Err(ValidationError::Suspicious {
+                field: "flake_ref".to_string(),
+                reason: "contains null byte".to_string(),
+            });
+        }
+
+        let (rest, fragment) = match input.split_once('#') {
+            Some((rest, "")) => (rest, None),
+            Some((rest, fragment)) => {
+                if !ATTR_PATH.is_match(fragment) {
+                    return Err(invalid("attribute path after '#'", input));
+                }
+                (rest, Some(fragment.to_string()))
+            }
+            None => (input, None),
+        };
+        let (base, query) = match rest.split_once('?') {
+            Some((base, query)) => (base, Some(query)),
+            None => (rest, None),
+        };
+        let mut params = parse_query(query, input)?;
+
+        let source = parse_source(base, &mut params, input)?;
+        check_params(&source, &mut params, input)?;
+
+        Ok(Self {
+            source,
+            params,
+            fragment,
+        })
+    }
+
+    /// Where the flake comes from.
+    pub fn source(&self) -> &FlakeSource {
+        &self.source
+    }
+
+    /// Value of a `?name=value` parameter, including `ref` and `rev`
+    /// given in the path of indirect and forge references.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// Branch or tag name, if pinned to one.
+    pub fn git_ref(&self) -> Option<&str> {
+        self.param("ref")
+    }
+
+    /// Commit hash, if pinned to one.
+    pub fn rev(&self) -> Option<&str> {
+        self.param("rev")
+    }
+
+    /// Subdirectory containing `flake.nix`, if not the root.
+    pub fn dir(&self) -> Option<&str> {
+        self.param("dir")
+    }
+
+    /// Attribute path after `#`, if any.
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// The same reference without its `#` fragment.
+    pub fn without_fragment(&self) -> Self {
+        Self {
+            fragment: None,
+            ..self.clone()
+        }
+    }
+
+    /// Local directory of `path:`, bare path and `git+file` references.
+    pub fn local_path(&self) -> Option<&str> {
+        match &self.source {
+            FlakeSource::Path { path, .. } => Some(path),
+            FlakeSource::Git {
+                transport: GitTransport::File,
+                location,
+            } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The same local reference pointing at `path` instead.
+    ///
+    /// References without a [local path](Self::local_path) are returned
+    /// unchanged.
+    pub fn with_local_path(&self, path: &str) -> Self {
+        let source = match &self.source {
+            FlakeSource::Path { explicit, .. } => FlakeSource::Path {
+                path: path.to_string(),
+                explicit: *explicit,
+            },
+            FlakeSource::Git {
+                transport: GitTransport::File,
+                ..
+            } => FlakeSource::Git {
+                transport: GitTransport::File,
+                location: path.to_string(),
+            },
+            source => source.clone(),
+        };
+        Self {
+            source,
+            ..self.clone()
+        }
+    }
+
+    /// Names of the `ref` and `rev` parameters printed in the path of
+    /// indirect and forge references rather than after `?`.
+    fn path_params(&self) -> &'static [&'static str] {
+        let short_ref = self.git_ref().map(|r| !r.contains('/'));
+        match (&self.source, short_ref, self.rev()) {
+            // `ref` must precede `rev` in the path, so neither moves
+            (FlakeSource::Indirect { .. }, Some(false), _) => &[],
+            (FlakeSource::Indirect { .. }, Some(true), Some(_)) => &["ref", "rev"],
+            (FlakeSource::Indirect { .. } | FlakeSource::Forge { .. }, Some(true), _) => &["ref"],
+            (FlakeSource::Indirect { .. } | FlakeSource::Forge { .. }, None, Some(_)) => &["rev"],
+            _ => &[],
+        }
+    }
+}
+
+impl FromStr for FlakeRef {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for FlakeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            FlakeSource::Indirect { id } => write!(f, "{}", id)?,
+            FlakeSource::Path { path, explicit } => {
+                write!(f, "{}{}", if *explicit { "path:" } else { "" }, path)?
+            }
+            FlakeSource::Git {
+                transport,
+                location,
+            } => write!(f, "git+{}://{}", transport.scheme(), location)?,
+            FlakeSource::Forge { forge, owner, repo } => {
+                write!(f, "{}:{}/{}", forge.scheme(), owner, repo)?
+            }
+            FlakeSource::Tarball { url } => {
+                let prefix = if is_archive(url) { "" } else { "tarball+" };
+                write!(f, "{}{}", prefix, url)?
+            }
+            FlakeSource::File { url } => {
+                let prefix = if is_archive(url) { "file+" } else { "" };
+                write!(f, "{}{}", prefix, url)?
+            }
+        }
+
+        let path_params = self.path_params();
+        for name in path_params {
+            write!(f, "/{}", self.params[*name])?;
+        }
+
+        let mut separator = '?';
+        for (name, value) in &self.params {
+            if path_params.contains(&name.as_str()) {
+                continue;
+            }
+            write!(f, "{}{}={}", separator, name, value)?;
+            separator = '&';
+        }
+
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(expected: &str, got: &str) -> ValidationError {
+    ValidationError::InvalidFormat {
+        field: "flake_ref".to_string(),
+        expected: expected.to_string(),
+        got: got.to_string(),
+    }
+}
+
+/// Split `a=1&b=2` into parameters; duplicate names are rejected.
+fn parse_query(
+    query: Option<&str>,
+    input: &str,
+) -> Result<BTreeMap<String, String>, ValidationError> {
+    let mut params = BTreeMap::new();
+    let Some(query) = query else {
+        return Ok(params);
+    };
+
+    for pair in query.split('&') {
+        let Some((name, value)) = pair.split_once('=') else {
+            return Err(invalid("'name=value' parameters after '?'", input));
+        };
+        if !PARAM_NAME.is_match(name) || !PARAM_VALUE.is_match(value) {
+            return Err(invalid("'name=value' parameters after '?'", input));
+        }
+        if params.insert(name.to_string(), value.to_string()).is_some() {
+            return Err(invalid(
+                &format!("parameter '{}' at most once", name),
+                input,
+            ));
+        }
+    }
+    Ok(params)
+}
+
+fn parse_source(
+    base: &str,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<FlakeSource, ValidationError> {
+    if base.starts_with('.') || base.starts_with('/') {
+        return Ok(FlakeSource::Path {
+            path: normalize_path(base, input)?,
+            explicit: false,
+        });
+    }
+
+    let Some((scheme, rest)) = base.split_once(':') else {
+        return parse_indirect(base, params, input);
+    };
+
+    match scheme.to_ascii_lowercase().as_str() {
+        "flake" => parse_indirect(rest, params, input),
+        "path" => {
+            if rest.is_empty() {
+                return Err(invalid("path after 'path:'", input));
+            }
+            Ok(FlakeSource::Path {
+                path: normalize_path(rest, input)?,
+                explicit: true,
+            })
+        }
+        "github" => parse_forge(Forge::GitHub, rest, params, input),
+        "gitlab" => parse_forge(Forge::GitLab, rest, params, input),
+        "sourcehut" => parse_forge(Forge::SourceHut, rest, params, input),
+        "git+http" => parse_git(GitTransport::Http, rest, input),
+        "git+https" => parse_git(GitTransport::Https, rest, input),
+        "git+ssh" => parse_git(GitTransport::Ssh, rest, input),
+        "git+file" => parse_git(GitTransport::File, rest, input),
+        "tarball+http" | "tarball+https" | "tarball+file" => Ok(FlakeSource::Tarball {
+            url: parse_url(&scheme[8..], rest, input)?,
+        }),
+        "file+http" | "file+https" | "file+file" => Ok(FlakeSource::File {
+            url: parse_url(&scheme[5..], rest, input)?,
+        }),
+        "http" | "https" | "file" => {
+            let url = parse_url(scheme, rest, input)?;
+            if is_archive(&url) {
+                Ok(FlakeSource::Tarball { url })
+            } else {
+                Ok(FlakeSource::File { url })
+            }
+        }
+        _ => Err(invalid(
+            "flake reference scheme (flake, path, git+http(s), git+ssh, git+file, \
+             github, gitlab, sourcehut, tarball+, file+, http(s), file)",
+            input,
+        )),
+    }
+}
+
+/// `id[/ref-or-rev[/rev]]`
+fn parse_indirect(
+    rest: &str,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<FlakeSource, ValidationError> {
+    let mut segments = rest.split('/');
+    let id = segments.next().unwrap_or_default();
+    if !FLAKE_ID.is_match(id) {
+        return Err(invalid("registry name such as 'nixpkgs'", input));
+    }
+
+    match (segments.next(), segments.next(), segments.next()) {
+        (None, _, _) => {}
+        (Some(second), None, _) => fold_ref_or_rev(second, params, input)?,
+        (Some(git_ref), Some(rev), None) => {
+            fold_param("ref", git_ref, params, input)?;
+            fold_param("rev", rev, params, input)?;
+        }
+        _ => return Err(invalid("registry reference 'ID[/REF][/REV]'", input)),
+    }
+
+    Ok(FlakeSource::Indirect { id: id.to_string() })
+}
+
+/// `owner/repo[/ref-or-rev]`
+fn parse_forge(
+    forge: Forge,
+    rest: &str,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<FlakeSource, ValidationError> {
+    let expected = format!("{}:OWNER/REPO[/REF-OR-REV]", forge.scheme());
+    let segments: Vec<&str> = rest.split('/').collect();
+    let (owner, repo) = match segments.as_slice() {
+        [owner, repo] | [owner, repo, _] => (*owner, *repo),
+        _ => return Err(invalid(&expected, input)),
+    };
+    let owner_valid = match forge {
+        Forge::GitLab => GITLAB_OWNER.is_match(owner),
+        _ => FORGE_NAME.is_match(owner),
+    };
+    if !owner_valid || !FORGE_NAME.is_match(repo) || repo.starts_with('~') {
+        return Err(invalid(&expected, input));
+    }
+    if let [_, _, ref_or_rev] = segments.as_slice() {
+        fold_ref_or_rev(ref_or_rev, params, input)?;
+    }
+
+    // GitHub owner and repository names are case-insensitive; GitLab subgroup
+    // separators are spelled `%2F`
+    let (owner, repo) = match forge {
+        Forge::GitHub => (owner.to_ascii_lowercase(), repo.to_ascii_lowercase()),
+        Forge::GitLab => (owner.replace("%2f", "%2F"), repo.to_string()),
+        Forge::SourceHut => (owner.to_string(), repo.to_string()),
+    };
+    Ok(FlakeSource::Forge { forge, owner, repo })
+}
+
+fn parse_git(
+    transport: GitTransport,
+    rest: &str,
+    input: &str,
+) -> Result<FlakeSource, ValidationError> {
+    let url = parse_url(transport.scheme(), rest, input)?;
+    let location = url
+        .split_once("://")
+        .map(|(_, location)| location.to_string())
+        .unwrap_or_default();
+    Ok(FlakeSource::Git {
+        transport,
+        location,
+    })
+}
+
+/// Validate `//authority/path` and return the whole URL with its scheme and
+/// host lower-cased and any trailing slash removed.
+fn parse_url(scheme: &str, rest: &str, input: &str) -> Result<String, ValidationError> {
+    let scheme = scheme.to_ascii_lowercase();
+    let expected = format!("URL of the form '{}://HOST/PATH'", scheme);
+    let Some(rest) = rest.strip_prefix("//") else {
+        return Err(invalid(&expected, input));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    if !PATH.is_match(path) {
+        return Err(invalid(&expected, input));
+    }
+
+    let authority = if scheme == "file" {
+        if !matches!(authority, "" | "localhost") || path.is_empty() {
+            return Err(invalid("absolute path after 'file://'", input));
+        }
+        ""
+    } else {
+        if !AUTHORITY.is_match(authority) {
+            return Err(invalid(&expected, input));
+        }
+        authority
+    };
+    let authority = match authority.rsplit_once('@') {
+        Some((user, host)) => format!("{}@{}", user, host.to_ascii_lowercase()),
+        None => authority.to_ascii_lowercase(),
+    };
+
+    let path = match path.trim_end_matches('/') {
+        "" if scheme == "file" => "/",
+        path => path,
+    };
+    Ok(format!("{}://{}{}", scheme, authority, path))
+}
+
+/// Lexically clean a local path: no empty or `.` segments, no trailing
+/// slash, and relative paths start with `./` or `../`.
+fn normalize_path(path: &str, input: &str) -> Result<String, ValidationError> {
+    if !PATH.is_match(path) {
+        return Err(invalid("local path", input));
+    }
+
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect();
+    let joined = segments.join("/");
+    Ok(if path.starts_with('/') {
+        format!("/{}", joined)
+    } else if segments.is_empty() {
+        ".".to_string()
+    } else if segments[0] == ".." {
+        joined
+    } else {
+        format!("./{}", joined)
+    })
+}
+
+/// A path segment that is a commit hash if it looks like one, else a ref.
+fn fold_ref_or_rev(
+    value: &str,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<(), ValidationError> {
+    let name = if REV.is_match(&value.to_ascii_lowercase()) {
+        "rev"
+    } else {
+        "ref"
+    };
+    fold_param(name, value, params, input)
+}
+
+/// Move `ref` or `rev` from the path into the parameters.
+fn fold_param(
+    name: &str,
+    value: &str,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<(), ValidationError> {
+    if params.contains_key(name) {
+        return Err(invalid(
+            &format!("'{}' in either the path or the parameters, not both", name),
+            input,
+        ));
+    }
+    params.insert(name.to_string(), value.to_string());
+    Ok(())
+}
+
+/// Check parameters against the source type and normalize their values.
+fn check_params(
+    source: &FlakeSource,
+    params: &mut BTreeMap<String, String>,
+    input: &str,
+) -> Result<(), ValidationError> {
+    let specific = match source {
+        FlakeSource::Indirect { .. } => INDIRECT_PARAMS,
+        FlakeSource::Path { .. } => PATH_PARAMS,
+        FlakeSource::Git { .. } => GIT_PARAMS,
+        FlakeSource::Forge { .. } => FORGE_PARAMS,
+        // Other parameters are part of the URL
+        FlakeSource::Tarball { .. } | FlakeSource::File { .. } => &[],
+    };
+    let open = matches!(
+        source,
+        FlakeSource::Tarball { .. } | FlakeSource::File { .. }
+    );
+
+    for name in params.keys() {
+        if !open && !COMMON_PARAMS.contains(&name.as_str()) && !specific.contains(&name.as_str()) {
+            return Err(invalid(
+                &format!(
+                    "one of the parameters {}",
+                    COMMON_PARAMS
+                        .iter()
+                        .chain(specific)
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                input,
+            ));
+        }
+    }
+
+    if let Some(rev) = params.get_mut("rev") {
+        *rev = rev.to_ascii_lowercase();
+        if !REV.is_match(rev) {
+            return Err(invalid(
+                "'rev' to be a full 40 or 64 digit commit hash",
+                input,
+            ));
+        }
+    }
+    if let Some(git_ref) = params.get("ref") {
+        if !GIT_REF.is_match(git_ref)
+            || git_ref.contains("..")
+            || git_ref.contains("//")
+            || git_ref.ends_with('/')
+            || git_ref.ends_with(".lock")
+        {
+            return Err(invalid("'ref' to be a valid Git branch or tag name", input));
+        }
+    }
+    if matches!(source, FlakeSource::Forge { .. })
+        && params.contains_key("ref")
+        && params.contains_key("rev")
+    {
+        return Err(invalid("either 'ref' or 'rev', not both", input));
+    }
+    if let Some(host) = params.get_mut("host") {
+        *host = host.to_ascii_lowercase();
+    }
+
+    if !open {
+        for name in BOOL_PARAMS {
+            if params.get(*name).is_some_and(|v| v != "0" && v != "1") {
+                return Err(invalid(&format!("'{}' to be 0 or 1", name), input));
+            }
+        }
+        for name in NUMERIC_PARAMS {
+            if params
+                .get(*name)
+                .is_some_and(|v| !v.bytes().all(|b| b.is_ascii_digit()))
+            {
+                return Err(invalid(&format!("'{}' to be a number", name), input));
+            }
+        }
+    }
+
+    if let Some(dir) = params.get("dir") {
+        let dir = normalize_path(dir, input)?;
+        if dir.starts_with('/') || dir.split('/').any(|s| s == "..") {
+            return Err(invalid("'dir' to be a subdirectory of the flake", input));
+        }
+        match dir.strip_prefix("./") {
+            Some(dir) => params.insert("dir".to_string(), dir.to_string()),
+            None => params.remove("dir"),
+        };
+    }
+
+    Ok(())
+}
+
+fn is_archive(url: &str) -> bool {
+    ARCHIVE_EXTENSIONS.iter().any(|ext| url.ends_with(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(input: &str) -> String {
+        FlakeRef::parse(input)
+            .unwrap_or_else(|e| panic!("{}: {}", input, e))
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_documented_forms() {
+        let cases = [
+            ("nixpkgs", "nixpkgs"),
+            ("flake:nixpkgs/nixos-24.05", "nixpkgs/nixos-24.05"),
+            (".", "."),
+            ("./sub/", "./sub"),
+            ("/etc/nixos", "/etc/nixos"),
+            ("path:/srv/flake", "path:/srv/flake"),
+            (
+                "git+https://example.org/repo.git",
+                "git+https://example.org/repo.git",
+            ),
+            (
+                "git+ssh://git@example.org/repo",
+                "git+ssh://git@example.org/repo",
+            ),
+            ("git+file:///src/app", "git+file:///src/app"),
+            ("github:NixOS/nixpkgs", "github:nixos/nixpkgs"),
+            ("gitlab:group/project/v1.0", "gitlab:group/project/v1.0"),
+            ("gitlab:veloren%2Fdev/rfcs", "gitlab:veloren%2Fdev/rfcs"),
+            ("gitlab:veloren%2fdev/rfcs", "gitlab:veloren%2Fdev/rfcs"),
+            ("sourcehut:~user/repo", "sourcehut:~user/repo"),
+            (
+                "https://example.org/src.tar.gz",
+                "https://example.org/src.tar.gz",
+            ),
+            (
+                "tarball+https://example.org/archive",
+                "tarball+https://example.org/archive",
+            ),
+            (
+                "https://example.org/flake.nix",
+                "https://example.org/flake.nix",
+            ),
+            (
+                "file+https://example.org/src.zip",
+                "file+https://example.org/src.zip",
+            ),
+            ("nixpkgs#hello", "nixpkgs#hello"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(canonical(input), expected, "{}", input);
+        }
+
+        let flake = FlakeRef::parse("github:owner/repo?dir=nix&ref=main#packages.default").unwrap();
+        assert_eq!(
+            flake.source(),
+            &FlakeSource::Forge {
+                forge: Forge::GitHub,
+                owner: "owner".to_string(),
+                repo: "repo".to_string(),
+            }
+        );
+        assert_eq!(flake.git_ref(), Some("main"));
+        assert_eq!(flake.dir(), Some("nix"));
+        assert_eq!(flake.fragment(), Some("packages.default"));
+        assert_eq!(
+            flake.without_fragment().to_string(),
+            "github:owner/repo/main?dir=nix"
+        );
+    }
+
+    #[test]
+    fn test_equivalent_spellings_normalize() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        let groups: &[&[&str]] = &[
+            &[
+                "github:NixOS/nixpkgs/nixos-unstable",
+                "github:nixos/nixpkgs?ref=nixos-unstable",
+                "GitHub:nixos/NixPkgs?ref=nixos-unstable#",
+            ],
+            &[
+                &format!("github:nixos/nixpkgs/{}", rev),
+                &format!("github:nixos/nixpkgs?rev={}", rev.to_uppercase()),
+            ],
+            &["nixpkgs/nixos-unstable", "flake:nixpkgs?ref=nixos-unstable"],
+            &[
+                "path:./infra/?narHash=x&dir=./sub/",
+                "path:infra//.?dir=sub&narHash=x",
+            ],
+            &[
+                "git+HTTPS://Example.org/repo/",
+                "git+https://example.org/repo",
+            ],
+            &[
+                "https://example.org/a.tar.gz",
+                "tarball+https://example.org/a.tar.gz",
+            ],
+            &["github:a/b?dir=.", "github:a/b"],
+        ];
+        for group in groups {
+            let first = canonical(group[0]);
+            for input in &group[1..] {
+                assert_eq!(canonical(input), first, "{} vs {}", input, group[0]);
+            }
+        }
+
+        // Bare paths may be fetched with Git, so they stay distinct
+        assert_ne!(canonical("."), canonical("path:."));
+        // Branch names are case-sensitive
+        assert_ne!(canonical("github:a/b/Main"), canonical("github:a/b/main"));
+    }
+
+    #[test]
+    fn test_canonical_form_reparses() {
+        for input in [
+            "nixpkgs/release-24.05/0123456789abcdef0123456789abcdef01234567",
+            "nixpkgs?ref=feature/x",
+            "github:a/b?ref=feature/x",
+            "git+https://example.org/r?ref=main&rev=0123456789abcdef0123456789abcdef01234567&shallow=1",
+            "https://example.org/download?file=src.tar.gz",
+            "../up?dir=nix#checks",
+        ] {
+            let parsed = FlakeRef::parse(input).unwrap();
+            assert_eq!(FlakeRef::parse(&parsed.to_string()).unwrap(), parsed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_refs_rejected() {
+        for input in [
+            "",
+            "nixpkgs?",
+            "nixpkgs?ref",
+            "nixpkgs?ref=a&ref=b",
+            "nixpkgs?bogus=1",
+            "nixpkgs;rm -rf /",
+            "nixpkgs$(whoami)",
+            "nixpkgs`id`",
+            "nixpkgs#a..b",
+            "9nixpkgs",
+            "github:owner",
+            "github:owner/repo/ref/extra",
+            "github:owner/repo/main?ref=dev",
+            "github:owner/repo?ref=main&rev=0123456789abcdef0123456789abcdef01234567",
+            "github:owner/repo?rev=abc123",
+            "github:owner/repo?ref=../main",
+            "git+https:example.org/repo",
+            "git+https://bad host/repo",
+            "git+file://host/src",
+            "git+https://example.org/r?shallow=true",
+            "path:",
+            "path:./x?dir=../escape",
+            "path:./x?dir=a/../..",
+            "hg+https://example.org/repo",
+            "./a b",
+        ] {
+            assert!(FlakeRef::parse(input).is_err(), "accepted: {:?}", input);
+        }
+
+        let too_long = "a".repeat(MAX_FLAKE_REF_LEN + 1);
+        assert!(matches!(
+            FlakeRef::parse(&too_long),
+            Err(ValidationError::TooLong { .. })
+        ));
+    }
+}
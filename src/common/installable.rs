@@ -0,0 +1,392 @@
+//! Installables: what build and analysis tools operate on.
+//!
+//! An [`Installable`] is one of the argument forms `nix build` accepts:
+//!
+//! - A flake output: `nixpkgs#hello`, `.#packages.x86_64-linux.default`,
+//!   optionally with an output selector (`nixpkgs#openssl^dev,out`)
+//! - A store path: `/nix/store/<hash>-hello-2.12.1`
+//! - Outputs of a derivation: `/nix/store/<hash>-hello-2.12.1.drv^out` or `^*`
+//!
+//! A bare `.drv` path without `^` is the derivation file itself.
+//! [`Installable::realise`] builds or substitutes any of these and returns
+//! the resulting store paths, so tools need not parse `nix build --json`
+//! themselves.
+//!
+//! ```
+//! use onix_mcp::common::installable::Installable;
+//!
+//! let installable: Installable = "github:NixOS/nixpkgs#openssl^out,dev".parse().unwrap();
+//! assert_eq!(installable.to_string(), "github:nixos/nixpkgs#openssl^dev,out");
+//! assert!("nixpkgs#hello^".parse::<Installable>().is_err());
+//! ```
+
+use crate::common::flake_ref::FlakeRef;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::security::ValidationError;
+use crate::common::store_path::{is_valid_name, StorePath, STORE_DIR};
+use rmcp::ErrorData as McpError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Outputs selected with `^`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OutputsSpec {
+    /// `^*`: every output.
+    All,
+    /// `^out,dev`: the named outputs.
+    Names(BTreeSet<String>),
+}
+
+impl OutputsSpec {
+    fn parse(input: &str, spec: &str) -> Result<Self, ValidationError> {
+        if spec == "*" {
+            return Ok(OutputsSpec::All);
+        }
+        let names: BTreeSet<String> = spec.split(',').map(str::to_string).collect();
+        if names.iter().any(|name| !is_valid_name(name)) {
+            return Err(invalid(
+                "output names after '^' (e.g. ^out,dev or ^*)",
+                input,
+            ));
+        }
+        Ok(OutputsSpec::Names(names))
+    }
+}
+
+impl fmt::Display for OutputsSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputsSpec::All => write!(f, "*"),
+            OutputsSpec::Names(names) => {
+                write!(f, "{}", names.iter().cloned().collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+}
+
+/// A validated `nix build` argument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Installable {
+    /// Flake output; the attribute path is the reference's fragment.
+    Flake {
+        flake_ref: FlakeRef,
+        outputs: Option<OutputsSpec>,
+    },
+    /// A store path used as is.
+    StorePath(StorePath),
+    /// Outputs of a `.drv` file.
+    Derivation {
+        drv: StorePath,
+        outputs: OutputsSpec,
+    },
+}
+
+/// Store paths an installable resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Realised {
+    /// A store path not addressed through a derivation.
+    Opaque(StorePath),
+    /// Built outputs of a derivation, by output name.
+    Built {
+        drv_path: StorePath,
+        outputs: BTreeMap<String, StorePath>,
+    },
+}
+
+impl Realised {
+    /// The path tools report for a package: the `out` output if built,
+    /// otherwise the first output.
+    pub fn primary(&self) -> Option<&StorePath> {
+        match self {
+            Realised::Opaque(path) => Some(path),
+            Realised::Built { outputs, .. } => {
+                outputs.get("out").or_else(|| outputs.values().next())
+            }
+        }
+    }
+}
+
+impl Installable {
+    /// Parse and normalize an installable.
+    pub fn parse(input: &str) -> Result<Self, ValidationError> {
+        let (base, outputs) = match input.split_once('^') {
+            Some((base, spec)) => (base, Some(OutputsSpec::parse(input, spec)?)),
+            None => (input, None),
+        };
+
+        if base.starts_with(STORE_DIR) {
+            let path = StorePath::parse(base)?;
+            return match outputs {
+                None => Ok(Installable::StorePath(path)),
+                Some(outputs) if path.is_derivation() => {
+                    Ok(Installable::Derivation { drv: path, outputs })
+                }
+                Some(_) => Err(invalid("'^' outputs only after a .drv store path", input)),
+            };
+        }
+
+        let flake_ref = FlakeRef::parse(base)?;
+        Ok(Installable::Flake { flake_ref, outputs })
+    }
+
+    /// Build or substitute the installable and return its store paths.
+    pub async fn realise(&self) -> Result<Vec<Realised>, McpError> {
+        let json = self.build_json(false).await?;
+        parse_realised(&json).ok_or_else(|| {
+            McpError::internal_error(
+                format!("Unexpected `nix build --json` output for {}", self),
+                Some(json),
+            )
+        })
+    }
+
+    /// The primary output path of the installable, built if necessary.
+    ///
+    /// See [`Realised::primary`].
+    pub async fn output_path(&self) -> Result<StorePath, McpError> {
+        self.realise()
+            .await?
+            .first()
+            .and_then(Realised::primary)
+            .cloned()
+            .ok_or_else(|| {
+                McpError::internal_error(format!("{} produced no output path", self), None)
+            })
+    }
+
+    /// Derivations the installable evaluates to, without building anything.
+    ///
+    /// `.drv` paths are their own derivation and output paths resolve to
+    /// their deriver; flake outputs are evaluated.
+    pub async fn derivations(&self) -> Result<Vec<StorePath>, McpError> {
+        match self {
+            Installable::Derivation { drv, .. } => Ok(vec![drv.clone()]),
+            Installable::StorePath(path) if path.is_derivation() => Ok(vec![path.clone()]),
+            Installable::StorePath(path) => {
+                let path_str = path.to_string();
+                let json =
+                    nix_json(&["path-info", "--json", &path_str], "query", &path_str).await?;
+                let deriver = parse_deriver(&json).ok_or_else(|| {
+                    McpError::invalid_params(format!("{} has no known deriver", path), None)
+                })?;
+                Ok(vec![deriver])
+            }
+            Installable::Flake { .. } => Ok(parse_drv_paths(&self.build_json(true).await?)),
+        }
+    }
+
+    async fn build_json(&self, dry_run: bool) -> Result<serde_json::Value, McpError> {
+        let installable = self.to_string();
+        let mut args = vec!["build", installable.as_str(), "--json", "--no-link"];
+        if dry_run {
+            args.push("--dry-run");
+        }
+        nix_json(&args, "build", &installable).await
+    }
+}
+
+/// Run `nix args` and parse its JSON output; errors read "Failed to
+/// `action` `target`".
+async fn nix_json(
+    args: &[&str],
+    action: &str,
+    target: &str,
+) -> Result<serde_json::Value, McpError> {
+    let output = tokio::process::Command::new("nix")
+        .args(args)
+        .group_output()
+        .await
+        .map_err(|e| {
+            McpError::internal_error(format!("Failed to {} {}: {}", action, target, e), None)
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(McpError::internal_error(
+            format!("Failed to {} {}: {}", action, target, stderr),
+            None,
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| {
+        McpError::internal_error(format!("Failed to parse {} output: {}", action, e), None)
+    })
+}
+
+fn invalid(expected: &str, got: &str) -> ValidationError {
+    ValidationError::InvalidFormat {
+        field: "installable".to_string(),
+        expected: expected.to_string(),
+        got: got.to_string(),
+    }
+}
+
+/// `drvPath`s of `nix build --dry-run --json` output.
+fn parse_drv_paths(json: &serde_json::Value) -> Vec<StorePath> {
+    json.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.get("drvPath")?.as_str()?.parse().ok())
+        .collect()
+}
+
+/// Deriver of the single path in `nix path-info --json` output.
+///
+/// Accepts both the list of `{"path", "deriver"}` objects of older Nix and
+/// the object keyed by path of Nix 2.19 and later.
+fn parse_deriver(json: &serde_json::Value) -> Option<StorePath> {
+    let info = match json {
+        serde_json::Value::Array(items) => items.first()?,
+        serde_json::Value::Object(map) => map.values().next()?,
+        _ => return None,
+    };
+    info.get("deriver")?.as_str()?.parse().ok()
+}
+
+/// Parse `nix build --json`: `{"drvPath", "outputs"}` entries for built
+/// derivations and `{"path"}` entries for opaque store paths.
+fn parse_realised(json: &serde_json::Value) -> Option<Vec<Realised>> {
+    json.as_array()?
+        .iter()
+        .map(|item| {
+            if let Some(path) = item.get("path") {
+                return Some(Realised::Opaque(path.as_str()?.parse().ok()?));
+            }
+            let drv_path = item.get("drvPath")?.as_str()?.parse().ok()?;
+            let outputs = item
+                .get("outputs")?
+                .as_object()?
+                .iter()
+                .map(|(name, path)| Some((name.clone(), path.as_str()?.parse().ok()?)))
+                .collect::<Option<_>>()?;
+            Some(Realised::Built { drv_path, outputs })
+        })
+        .collect()
+}
+
+impl FromStr for Installable {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Installable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Installable::Flake { flake_ref, outputs } => {
+                write!(f, "{}", flake_ref)?;
+                if let Some(outputs) = outputs {
+                    write!(f, "^{}", outputs)?;
+                }
+                Ok(())
+            }
+            Installable::StorePath(path) => write!(f, "{}", path),
+            Installable::Derivation { drv, outputs } => write!(f, "{}^{}", drv, outputs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const OUT: &str = "/nix/store/0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5-hello-2.12.1";
+    const DRV: &str = "/nix/store/1d6wxrknl2x6ip8yx8b9kd56ghmy0wz6-hello-2.12.1.drv";
+
+    #[test]
+    fn test_parse_installables() {
+        assert!(matches!(
+            Installable::parse("nixpkgs#hello").unwrap(),
+            Installable::Flake { outputs: None, .. }
+        ));
+        assert_eq!(
+            Installable::parse(".#default^*").unwrap().to_string(),
+            ".#default^*"
+        );
+        assert!(matches!(
+            Installable::parse(OUT).unwrap(),
+            Installable::StorePath(_)
+        ));
+        // Without a selector a .drv path is the file itself
+        assert!(matches!(
+            Installable::parse(DRV).unwrap(),
+            Installable::StorePath(_)
+        ));
+        assert_eq!(
+            Installable::parse(&format!("{}^out,dev,out", DRV))
+                .unwrap()
+                .to_string(),
+            format!("{}^dev,out", DRV)
+        );
+
+        for input in [
+            "nixpkgs#hello^",
+            "nixpkgs#hello^out,",
+            "nixpkgs#hello^out^dev",
+            "nixpkgs#hello; rm -rf /",
+            "/nix/store/hello",
+            &format!("{}^out", OUT),
+            &format!("{}/bin/hello", OUT),
+        ] {
+            assert!(Installable::parse(input).is_err(), "accepted: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_realised() {
+        let json = json!([
+            {"drvPath": DRV, "outputs": {"dev": OUT, "out": OUT}},
+            {"path": OUT},
+        ]);
+        let realised = parse_realised(&json).unwrap();
+        assert_eq!(realised.len(), 2);
+        assert_eq!(realised[0].primary().unwrap().to_string(), OUT);
+        assert_eq!(realised[1], Realised::Opaque(OUT.parse().unwrap()));
+
+        assert!(parse_realised(&json!([{"drvPath": DRV}])).is_none());
+        assert!(parse_realised(&json!([{"path": "/tmp/x"}])).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_derivation_forms_need_no_evaluation() {
+        let drv: StorePath = DRV.parse().unwrap();
+        for input in [
+            DRV.to_string(),
+            format!("{}^out", DRV),
+            format!("{}^*", DRV),
+        ] {
+            let installable = Installable::parse(&input).unwrap();
+            assert_eq!(installable.derivations().await.unwrap(), vec![drv.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_output_path_deriver() {
+        let drv: StorePath = DRV.parse().unwrap();
+        let legacy = json!([{"path": OUT, "deriver": DRV, "narSize": 1}]);
+        assert_eq!(parse_deriver(&legacy), Some(drv.clone()));
+        let keyed = json!({OUT: {"deriver": DRV, "narSize": 1}});
+        assert_eq!(parse_deriver(&keyed), Some(drv));
+
+        assert_eq!(parse_deriver(&json!({OUT: {"deriver": null}})), None);
+        assert_eq!(parse_deriver(&json!([{"path": OUT}])), None);
+        assert_eq!(parse_deriver(&json!({OUT: null})), None);
+    }
+
+    #[test]
+    fn test_flake_output_drv_paths() {
+        let json = json!([
+            {"drvPath": DRV, "outputs": {"out": OUT}},
+            {"path": OUT},
+        ]);
+        assert_eq!(
+            parse_drv_paths(&json),
+            vec![DRV.parse::<StorePath>().unwrap()]
+        );
+        assert!(parse_drv_paths(&json!({})).is_empty());
+    }
+}
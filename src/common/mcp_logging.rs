@@ -0,0 +1,434 @@
+//! Forwarding of server diagnostics to MCP clients.
+//!
+//! Tracing output normally only reaches stderr, which MCP clients never show.
+//! [`layer`] returns a `tracing_subscriber` layer that also sends this crate's
+//! events, including [`AuditEvent`](crate::common::security::audit::AuditEvent)s, to
+//! the client whose request caused them as `notifications/message`.
+//!
+//! Each MCP session owns a [`LogSession`]. The session is attached to its
+//! client once initialization completes and starts out forwarding warnings and
+//! above; clients choose a different minimum with `logging/setLevel`.
+//! Events are routed by the [session span](LogSession::span) they are emitted
+//! in; events outside any session span (startup, background tasks) only go to
+//! stderr, so one client never sees another client's activity.
+//!
+//! ```no_run
+//! use tracing_subscriber::prelude::*;
+//!
+//! tracing_subscriber::registry()
+//!     .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+//!     .with(onix_mcp::common::mcp_logging::layer())
+//!     .init();
+//! ```
+
+use once_cell::sync::Lazy;
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::{Peer, RoleServer};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Span, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Minimum level forwarded to a client that has not sent `logging/setLevel`.
+pub const DEFAULT_LEVEL: LoggingLevel = LoggingLevel::Warning;
+
+/// Target prefix of events that are forwarded.
+///
+/// Restricting forwarding to this crate keeps rmcp's own transport logging
+/// (including the log notifications we send) out of the stream.
+const FORWARDED_TARGET: &str = "onix_mcp";
+
+/// Target of the events emitted by [`AuditLogger`](crate::common::security::AuditLogger).
+const AUDIT_TARGET: &str = "onix_mcp::common::security::audit";
+
+/// Name of the span returned by [`LogSession::span`].
+const SESSION_SPAN: &str = "mcp_session";
+
+/// Live sessions that receive forwarded events, by id.
+static SESSIONS: Lazy<Mutex<HashMap<u64, Weak<LogSession>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Source of [`LogSession`] ids.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Log forwarding state of a single MCP session.
+pub struct LogSession {
+    id: u64,
+    peer: OnceLock<Peer<RoleServer>>,
+    level: Mutex<LoggingLevel>,
+}
+
+impl LogSession {
+    /// Create a session and register it for forwarded events.
+    ///
+    /// Events are only sent once a client is [attached](Self::attach). The
+    /// session is unregistered when the last reference is dropped.
+    pub fn register() -> Arc<Self> {
+        let session = Arc::new(Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            peer: OnceLock::new(),
+            level: Mutex::new(DEFAULT_LEVEL),
+        });
+
+        SESSIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(session.id, Arc::downgrade(&session));
+        session
+    }
+
+    /// Span routing the events emitted inside it to this session's client.
+    ///
+    /// Tasks spawned while handling a request must be
+    /// [instrumented](tracing::Instrument) with the span for their events to
+    /// be forwarded too.
+    pub fn span(&self) -> Span {
+        tracing::info_span!(SESSION_SPAN, session = self.id)
+    }
+
+    /// Attach the client that forwarded events are sent to.
+    ///
+    /// Only the first call has an effect; a session belongs to one client.
+    pub fn attach(&self, peer: Peer<RoleServer>) {
+        let _ = self.peer.set(peer);
+    }
+
+    /// Set the minimum level forwarded to the client (`logging/setLevel`).
+    pub fn set_level(&self, level: LoggingLevel) {
+        *self.level.lock().unwrap_or_else(|e| e.into_inner()) = level;
+    }
+
+    /// Current minimum forwarded level.
+    pub fn level(&self) -> LoggingLevel {
+        *self.level.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn forward(&self, message: &LoggingMessageNotificationParam) {
+        let Some(peer) = self.peer.get() else {
+            return;
+        };
+        if severity(message.level) < severity(self.level()) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let peer = peer.clone();
+        let message = message.clone();
+        runtime.spawn(async move {
+            // The client may have disconnected; there is nowhere to report that
+            let _ = peer.notify_logging_message(message).await;
+        });
+    }
+}
+
+impl Drop for LogSession {
+    fn drop(&mut self) {
+        SESSIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+/// Session id stored in the extensions of a [`SESSION_SPAN`].
+struct SessionId(u64);
+
+/// Tracing layer that forwards this crate's events to the MCP client of the
+/// session they were emitted in.
+pub struct McpLogLayer;
+
+impl<S> Layer<S> for McpLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != SESSION_SPAN {
+            return;
+        }
+        let mut visitor = JsonVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(session), Some(span)) = (visitor.0.get("session"), ctx.span(id)) {
+            if let Some(session) = session.as_u64() {
+                span.extensions_mut().insert(SessionId(session));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(id) = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .into_iter()
+                .find_map(|span| span.extensions().get::<SessionId>().map(|id| id.0))
+        }) else {
+            return;
+        };
+        let session = SESSIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .and_then(Weak::upgrade);
+
+        if let Some(session) = session {
+            session.forward(&to_log_message(event));
+        }
+    }
+}
+
+/// [`McpLogLayer`] filtered to this crate's events at debug level and above.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    McpLogLayer.with_filter(Targets::new().with_target(FORWARDED_TARGET, Level::DEBUG))
+}
+
+/// Numeric severity for comparing [`LoggingLevel`]s (higher is more severe).
+fn severity(level: LoggingLevel) -> u8 {
+    level as u8
+}
+
+/// Build the notification for a tracing event.
+///
+/// The message and all fields end up in `data`. Audit events are reported
+/// under the `audit` logger with their serialized `event` parsed back into
+/// JSON, and critical audit events keep their critical level.
+fn to_log_message(event: &Event<'_>) -> LoggingMessageNotificationParam {
+    let metadata = event.metadata();
+    let mut visitor = JsonVisitor::default();
+    event.record(&mut visitor);
+    let mut data = visitor.0;
+
+    let is_audit = metadata.target() == AUDIT_TARGET;
+    if is_audit {
+        if let Some(serde_json::Value::String(raw)) = data.get("event") {
+            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(raw) {
+                data.insert("event".to_string(), parsed);
+            }
+        }
+    }
+
+    let level = match *metadata.level() {
+        Level::ERROR if data.get("security_level").and_then(|v| v.as_str()) == Some("critical") => {
+            LoggingLevel::Critical
+        }
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        Level::DEBUG | Level::TRACE => LoggingLevel::Debug,
+    };
+
+    LoggingMessageNotificationParam {
+        level,
+        logger: Some(if is_audit {
+            "audit".to_string()
+        } else {
+            metadata.target().to_string()
+        }),
+        data: serde_json::Value::Object(data),
+    }
+}
+
+/// Collects event fields into a JSON object.
+#[derive(Default)]
+struct JsonVisitor(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for JsonVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::nix_server::NixServer;
+    use crate::common::security::audit::{AuditEvent, AuditLogger, SecurityLevel};
+    use rmcp::model::{CallToolRequestParam, SetLevelRequestParam};
+    use rmcp::service::{NotificationContext, RunningService};
+    use rmcp::{ClientHandler, RoleClient, ServiceExt};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::prelude::*;
+
+    struct Collector(mpsc::UnboundedSender<LoggingMessageNotificationParam>);
+
+    impl ClientHandler for Collector {
+        async fn on_logging_message(
+            &self,
+            params: LoggingMessageNotificationParam,
+            _context: NotificationContext<RoleClient>,
+        ) {
+            let _ = self.0.send(params);
+        }
+    }
+
+    /// Next audit notification, skipping warnings still in flight from earlier steps.
+    async fn next_audit_message(
+        rx: &mut mpsc::UnboundedReceiver<LoggingMessageNotificationParam>,
+    ) -> LoggingMessageNotificationParam {
+        loop {
+            let message = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+                .await
+                .expect("no log notification received")
+                .unwrap();
+            if message.logger.as_deref() == Some("audit") {
+                return message;
+            }
+        }
+    }
+
+    /// Serve `server` to a new client collecting its log notifications.
+    async fn connect(
+        server: NixServer,
+    ) -> (
+        RunningService<RoleClient, Collector>,
+        mpsc::UnboundedReceiver<LoggingMessageNotificationParam>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(service) = server.serve(server_io).await {
+                let _ = service.waiting().await;
+            }
+        });
+        (Collector(tx).serve(client_io).await.unwrap(), rx)
+    }
+
+    #[tokio::test]
+    async fn test_events_forwarded_to_client() {
+        let _guard = tracing_subscriber::registry().with(layer()).set_default();
+
+        let server = NixServer::new();
+        let span = server.log_session().span();
+        let (client, mut rx) = connect(server).await;
+
+        // The session is attached once the server has handled `initialized`
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                span.in_scope(|| {
+                    // Info events are below the default level
+                    tracing::info!("not forwarded");
+                    tracing::warn!(flake = ".", "falling back");
+                });
+                tokio::select! {
+                    message = rx.recv() => return message.unwrap(),
+                    _ = tokio::time::sleep(std::time::Duration::from_millis(20)) => {}
+                }
+            }
+        })
+        .await
+        .expect("no log notification received");
+        assert_eq!(message.level, LoggingLevel::Warning);
+        assert_eq!(message.data["message"], "falling back");
+        assert_eq!(message.data["flake"], ".");
+
+        client
+            .set_level(SetLevelRequestParam {
+                level: LoggingLevel::Info,
+            })
+            .await
+            .unwrap();
+        span.in_scope(|| AuditLogger::new().log_tool_invocation("nix_eval", None, true, None, 5));
+        let message = next_audit_message(&mut rx).await;
+        assert_eq!(message.level, LoggingLevel::Info);
+        assert_eq!(message.logger.as_deref(), Some("audit"));
+        assert_eq!(message.data["event"]["event_type"], "ToolInvoked");
+        assert_eq!(message.data["event"]["tool_name"], "nix_eval");
+
+        span.in_scope(|| {
+            AuditLogger::new().log(
+                SecurityLevel::Critical,
+                AuditEvent::AuthEvent {
+                    success: false,
+                    reason: "test".to_string(),
+                },
+            )
+        });
+        assert_eq!(
+            next_audit_message(&mut rx).await.level,
+            LoggingLevel::Critical
+        );
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_stay_in_their_session() {
+        let _guard = tracing_subscriber::registry().with(layer()).set_default();
+
+        let server = NixServer::new();
+        let mut clients = Vec::new();
+        for tool in ["alpha", "beta"] {
+            let (client, rx) = connect(server.for_session()).await;
+            client
+                .set_level(SetLevelRequestParam {
+                    level: LoggingLevel::Info,
+                })
+                .await
+                .unwrap();
+            clients.push((tool, client, rx));
+        }
+
+        for (tool, client, _) in &clients {
+            client
+                .call_tool(CallToolRequestParam {
+                    name: "audit_query".into(),
+                    arguments: json!({ "tool": tool }).as_object().cloned(),
+                })
+                .await
+                .unwrap();
+        }
+        // Events without a session reach nobody
+        AuditLogger::new().log_tool_invocation("unrelated", None, true, None, 5);
+
+        for (tool, client, mut rx) in clients {
+            let message = next_audit_message(&mut rx).await;
+            assert_eq!(message.data["event"]["tool_name"], "audit_query");
+            assert_eq!(message.data["event"]["parameters"]["tool"], tool);
+
+            let other =
+                tokio::time::timeout(std::time::Duration::from_millis(200), rx.recv()).await;
+            assert!(
+                other.is_err(),
+                "{} received another event: {:?}",
+                tool,
+                other
+            );
+            client.cancel().await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_session_level() {
+        let session = LogSession::register();
+        assert_eq!(session.level(), DEFAULT_LEVEL);
+
+        session.set_level(LoggingLevel::Debug);
+        assert_eq!(session.level(), LoggingLevel::Debug);
+        assert!(severity(LoggingLevel::Emergency) > severity(LoggingLevel::Warning));
+    }
+}
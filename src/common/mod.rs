@@ -4,15 +4,29 @@
 //!
 //! # Modules
 //!
+//! - [`audit_tools`] - Querying recorded audit events (`audit_query`, `audit://recent`)
 //! - [`cache`] - TTL-based cache implementation for expensive operations
 //! - [`cache_registry`] - Centralized cache management across all tools
+//! - [`completion`] - Argument completion for prompts and resource templates
+//! - [`confirmation`] - User confirmation for destructive operations
+//! - [`config`] - Layered TOML configuration (timeouts, cache TTLs, tool groups)
+//! - [`disk_cache`] - Optional on-disk store keeping cache entries across restarts
+//! - [`flake_ref`] - Parsed flake references with a canonical spelling
+//! - [`installable`] - Build arguments (flake outputs, store paths, derivations) and their realisation
+//! - [`mcp_logging`] - Tracing events forwarded to MCP clients as log notifications
+//! - [`process_group`] - Child processes killed with their process group when a call is abandoned
+//! - [`progress`] - MCP progress notifications for long-running builds
+//! - [`revision`] - Lock revisions that key and expire cached results
+//! - [`roots`] - Client workspace roots for resolving flake and project paths
+//! - [`structured`] - Typed tool results with output schemas
 //! - [`tool_registry`] - Central registry for all tool module instances
 //! - [`tool_module`] - Common trait for all MCP tool modules
 //! - [`security`] - Input validation, audit logging, and security utilities
+//! - [`store_path`] - Validated `/nix/store` paths
 //! - [`nix_server`] - Main MCP server implementation
 //! - [`nix_tools_helpers`] - Helper functions for Nix tool implementations
-//! - [`command`] - Command execution utilities (currently unused)
-//! - [`caching`] - Advanced caching strategies (currently unused)
+//! - [`command`] - Command execution utilities and the concurrency scheduler
+//! - [`caching`] - Cache-check-execute-cache helpers for tool results
 //!
 //! # Architecture
 //!
@@ -22,17 +36,31 @@
 //! NixServer
 //!   ├── ToolRegistry (manages all tool instances)
 //!   │   ├── PackageTools, BuildTools, etc.
-//!   │   └── Each tool has Arc<AuditLogger> and Arc<CacheRegistry>
-//!   ├── CacheRegistry (7 specialized caches)
+//!   │   └── Each tool has Arc<AuditLogger>, Arc<Config> and Arc<CacheRegistry>
+//!   ├── CacheRegistry (9 specialized caches)
 //!   └── AuditLogger (security event logging)
 //! ```
 
+pub mod audit_tools;
 pub mod cache;
 pub mod cache_registry;
 pub mod caching;
 pub mod command;
+pub mod completion;
+pub mod config;
+pub mod confirmation;
+pub mod disk_cache;
+pub mod flake_ref;
+pub mod installable;
+pub mod mcp_logging;
 pub mod nix_server;
 pub mod nix_tools_helpers;
+pub mod process_group;
+pub mod progress;
+pub mod revision;
+pub mod roots;
 pub mod security;
+pub mod store_path;
+pub mod structured;
 pub mod tool_module;
 pub mod tool_registry;
@@ -1,6 +1,21 @@
+use crate::common::audit_tools::{AuditQueryArgs, RECENT_URI};
 use crate::common::cache_registry::CacheRegistry;
-use crate::common::security::{audit_logger, AuditLogger};
+use crate::common::command::init_scheduler;
+use crate::common::completion::CompletionProvider;
+use crate::common::config::Config;
+use crate::common::confirmation::Confirmation;
+use crate::common::disk_cache::DiskCache;
+use crate::common::mcp_logging::LogSession;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::progress::ProgressReporter;
+use crate::common::roots::{ClientRoots, Workspace};
+use crate::common::security::audit_query::AuditQueryResult;
+use crate::common::security::helpers::with_cancellation;
+use crate::common::security::policy::ClanMachineTags;
+use crate::common::security::{audit_logger, AuditLogger, Policy, RateLimiter};
+use crate::common::structured::output_schema;
 use crate::common::tool_registry::ToolRegistry;
+use crate::nix::{ClosureSizeResult, FlakeMetadataResult, NixBuildResult, PackageSearchResult};
 use crate::nix::{
     CommaArgs, DiffDerivationsArgs, EcosystemToolArgs, ExplainPackageArgs, FindCommandArgs,
     FlakeMetadataArgs, FlakeShowArgs, FormatNixArgs, GetBuildLogArgs, GetClosureSizeArgs,
@@ -12,15 +27,18 @@ use crate::nix::{
 use rmcp::{
     handler::server::{
         router::{prompt::PromptRouter, tool::ToolRouter},
+        tool::ToolCallContext,
         wrapper::Parameters,
     },
     model::*,
     prompt, prompt_handler, prompt_router,
-    service::RequestContext,
-    tool, tool_handler, tool_router, ErrorData as McpError, RoleServer, ServerHandler,
+    service::{NotificationContext, RequestContext},
+    tool, tool_router, ErrorData as McpError, RoleServer, ServerHandler,
 };
 use serde_json::json;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
 
 // Import pre-commit types from dev module
 use crate::dev::{CheckPreCommitStatusArgs, PreCommitRunArgs, SetupPreCommitArgs};
@@ -28,7 +46,8 @@ use crate::dev::{CheckPreCommitStatusArgs, PreCommitRunArgs, SetupPreCommitArgs}
 // Import pexpect and pueue types from process module
 use crate::process::{
     PexpectCloseArgs, PexpectSendArgs, PexpectStartArgs, PueueAddArgs, PueueCleanArgs,
-    PueueLogArgs, PueuePauseArgs, PueueRemoveArgs, PueueStartArgs, PueueStatusArgs, PueueWaitArgs,
+    PueueLogArgs, PueuePauseArgs, PueueRemoveArgs, PueueStartArgs, PueueStatusArgs,
+    PueueStatusResult, PueueWaitArgs,
 };
 
 // Import clan types from clan module
@@ -37,6 +56,7 @@ use crate::clan::{
     ClanBackupCreateArgs, ClanBackupListArgs, ClanBackupRestoreArgs, ClanFlakeCreateArgs,
     ClanMachineBuildArgs, ClanMachineCreateArgs, ClanMachineDeleteArgs, ClanMachineInstallArgs,
     ClanMachineListArgs, ClanMachineUpdateArgs, ClanSecretsListArgs, ClanVmCreateArgs,
+    MachineListResult,
 };
 
 // Import prompt types from prompts module
@@ -44,6 +64,9 @@ use crate::prompts::{
     MigrateToFlakesArgs, OptimizeClosureArgs, SetupDevEnvironmentArgs, TroubleshootBuildArgs,
 };
 
+/// How often expired cache entries are removed.
+const CACHE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct NixServer {
     tool_router: ToolRouter<NixServer>,
@@ -53,31 +76,174 @@ pub struct NixServer {
     tools: Arc<ToolRegistry>,
     // Centralized cache registry for all caching needs
     caches: Arc<CacheRegistry>,
+    // Validated server configuration
+    config: Arc<Config>,
+    // Log forwarding to the connected client (per session)
+    logging: Arc<LogSession>,
+    // Workspace roots declared by the connected client (per session)
+    roots: Arc<ClientRoots>,
+    // Call rate limits shared by all sessions
+    rate_limits: Arc<RateLimiter>,
+    // Authorization rules checked before each call
+    policy: Arc<Policy>,
+}
+
+impl Default for NixServer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[tool_router]
 impl NixServer {
     pub fn new() -> Self {
+        Self::with_config(Arc::new(Config::default()))
+    }
+
+    /// Create a server from a loaded configuration.
+    ///
+    /// Cache TTLs and capacities, tool timeouts and default flakes come from
+    /// `config`. Expired cache entries are swept every minute. With
+    /// `[cache_store] enabled`, caches are persisted on disk and the store is
+    /// indexed in the background. Rate limits apply to all sessions of this
+    /// server; the concurrency limits are process-wide and taken from the
+    /// first server created. Tools in disabled groups or not allowed by the configured
+    /// profile are removed from the router, so they are neither listed nor
+    /// callable.
+    pub fn with_config(config: Arc<Config>) -> Self {
+        // Concurrency limits are process-wide; the first server configures them
+        init_scheduler(&config.concurrency);
         let audit = audit_logger();
-        let caches = Arc::new(CacheRegistry::new());
-        let tools = Arc::new(ToolRegistry::new(audit.clone(), caches.clone()));
+        let caches = Arc::new(match config.cache_store.path() {
+            Some(path) if config.cache_store.enabled => {
+                let store = Arc::new(DiskCache::open(path, config.cache_store.max_bytes));
+                store.warm();
+                CacheRegistry::persistent(&config.caches, store)
+            }
+            None if config.cache_store.enabled => {
+                tracing::warn!(
+                    "No cache_store file and no $XDG_CACHE_HOME or $HOME, caching in memory only"
+                );
+                CacheRegistry::from_config(&config.caches)
+            }
+            _ => CacheRegistry::from_config(&config.caches),
+        });
+        caches.spawn_sweeper(CACHE_SWEEP_INTERVAL);
+        let rate_limits = Arc::new(RateLimiter::new(&config.rate_limits));
+        let tools = Arc::new(ToolRegistry::new(
+            audit.clone(),
+            caches.clone(),
+            config.clone(),
+        ));
+
+        let mut tool_router = Self::tool_router();
+        for tool in tool_router.list_all() {
+            if !config.is_tool_exposed(&tool) {
+                tool_router.remove_route(&tool.name);
+            }
+        }
 
         Self {
-            tool_router: Self::tool_router(),
+            tool_router,
             prompt_router: Self::prompt_router(),
             audit,
             tools,
             caches,
+            config,
+            logging: LogSession::register(),
+            roots: ClientRoots::new(),
+            rate_limits,
+            policy: Arc::new(Policy::default()),
+        }
+    }
+
+    /// Check every tool call against `policy` (loaded from `[policy] file`).
+    pub fn with_policy(self, policy: Policy) -> Self {
+        Self {
+            policy: Arc::new(policy),
+            ..self
+        }
+    }
+
+    /// Clone of this server for a new MCP session.
+    ///
+    /// Tools, caches, rate limits, the policy and the audit logger are shared with
+    /// `self`; log forwarding state (the client and its `logging/setLevel`
+    /// level) and the client's workspace roots are not.
+    /// Transports serving several clients call this once per connection.
+    pub fn for_session(&self) -> Self {
+        Self {
+            logging: LogSession::register(),
+            roots: ClientRoots::new(),
+            ..self.clone()
         }
     }
 
+    /// Configuration this server was created with.
+    pub fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    /// Rate limit, authorize and route a tool call (see `call_tool`).
+    async fn dispatch_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        mut context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        // Rejected calls never reach the tool or its audit trail
+        self.rate_limits.check(&self.audit, &request.name)?;
+
+        let tags = ClanMachineTags::new(
+            Workspace::for_peer(self.roots.clone(), context.peer.clone()),
+            &self.config.flakes.default,
+        );
+        if let Some(result) = self
+            .policy
+            .authorize(
+                &self.audit,
+                &request.name,
+                &mut request.arguments,
+                &Confirmation::from_request(&context),
+                &tags,
+            )
+            .await?
+        {
+            return Ok(result);
+        }
+
+        let ct = context.ct.clone();
+        // Lets the `Workspace` extractor find this session's roots
+        context.extensions.insert(self.roots.clone());
+        let tool_name = request.name.clone();
+        let tcc = ToolCallContext::new(self, request, context);
+
+        with_cancellation(&self.audit, &tool_name, &ct, || self.tool_router.call(tcc)).await
+    }
+
+    /// Log forwarding state of this session.
+    #[cfg(test)]
+    pub(crate) fn log_session(&self) -> &Arc<LogSession> {
+        &self.logging
+    }
+
+    /// Audit logger shared by every session cloned from this server.
+    pub fn audit(&self) -> &Arc<AuditLogger> {
+        &self.audit
+    }
+
+    /// Cache registry shared by every session cloned from this server.
+    pub fn caches(&self) -> &Arc<CacheRegistry> {
+        &self.caches
+    }
+
     fn _create_resource_text(&self, uri: &str, name: &str) -> Resource {
         RawResource::new(uri, name.to_string()).no_annotation()
     }
 
     #[tool(
         description = "Search for packages in nixpkgs by name or description",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false),
+        output_schema = output_schema::<PackageSearchResult>()
     )]
     async fn search_packages(
         &self,
@@ -88,18 +254,19 @@ impl NixServer {
 
     #[tool(
         description = "Get detailed information about a specific package",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn get_package_info(
         &self,
         args: Parameters<GetPackageInfoArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.package.get_package_info(args).await
+        self.tools.package.get_package_info(args, workspace).await
     }
 
     #[tool(
         description = "Search NixOS configuration options",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn search_options(
         &self,
@@ -108,14 +275,21 @@ impl NixServer {
         self.tools.develop.search_options(args).await
     }
 
-    #[tool(description = "Evaluate a Nix expression")]
+    #[tool(
+        description = "Evaluate a Nix expression",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn nix_eval(&self, args: Parameters<NixEvalArgs>) -> Result<CallToolResult, McpError> {
         self.tools.develop.nix_eval(args).await
     }
 
     #[tool(
         description = "Format Nix code using nixpkgs-fmt",
-        annotations(idempotent_hint = true)
+        annotations(
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true
+        )
     )]
     async fn format_nix(
         &self,
@@ -126,7 +300,7 @@ impl NixServer {
 
     #[tool(
         description = "Get help with common Nix commands and patterns",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     fn nix_command_help(
         &self,
@@ -138,7 +312,7 @@ impl NixServer {
 
     #[tool(
         description = "Get information about useful Nix ecosystem tools and utilities",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     fn ecosystem_tools(
         &self,
@@ -148,9 +322,22 @@ impl NixServer {
         self.tools.info.ecosystem_tools(args)
     }
 
+    #[tool(
+        description = "Query the audit log of tool calls and security events run by this server. Filter by tool name, time range (RFC 3339 or ages like '24h'), success and minimum security level; results include parameters and durations, newest first.",
+        annotations(read_only_hint = true, destructive_hint = false),
+        output_schema = output_schema::<AuditQueryResult>()
+    )]
+    fn audit_query(&self, args: Parameters<AuditQueryArgs>) -> Result<CallToolResult, McpError> {
+        self.tools.audit_log.audit_query(args)
+    }
+
     #[tool(
         description = "Validate Nix code syntax and check for parse errors",
-        annotations(idempotent_hint = true)
+        annotations(
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true
+        )
     )]
     async fn validate_nix(
         &self,
@@ -161,7 +348,11 @@ impl NixServer {
 
     #[tool(
         description = "Lint Nix code with statix and/or deadnix to find issues and anti-patterns",
-        annotations(idempotent_hint = true)
+        annotations(
+            read_only_hint = true,
+            destructive_hint = false,
+            idempotent_hint = true
+        )
     )]
     async fn lint_nix(&self, args: Parameters<LintNixArgs>) -> Result<CallToolResult, McpError> {
         self.tools.quality.lint_nix(args).await
@@ -169,7 +360,7 @@ impl NixServer {
 
     #[tool(
         description = "Get detailed information about a package (version, description, homepage, license, etc.)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn explain_package(
         &self,
@@ -178,7 +369,10 @@ impl NixServer {
         self.tools.package.explain_package(args).await
     }
 
-    #[tool(description = "Prefetch a URL and get its hash for use in Nix expressions")]
+    #[tool(
+        description = "Prefetch a URL and get its hash for use in Nix expressions",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn prefetch_url(
         &self,
         args: Parameters<PrefetchUrlArgs>,
@@ -188,18 +382,20 @@ impl NixServer {
 
     #[tool(
         description = "Get metadata about a flake (inputs, outputs, description)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false),
+        output_schema = output_schema::<FlakeMetadataResult>()
     )]
     async fn flake_metadata(
         &self,
         args: Parameters<FlakeMetadataArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.flake.flake_metadata(args).await
+        self.tools.flake.flake_metadata(args, workspace).await
     }
 
     #[tool(
         description = "Find which package provides a command using nix-locate",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn find_command(
         &self,
@@ -209,51 +405,68 @@ impl NixServer {
     }
 
     #[tool(
-        description = "Run a command without installing it using comma (automatically finds and runs commands from nixpkgs)"
+        description = "Run a command without installing it using comma (automatically finds and runs commands from nixpkgs)",
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn comma(&self, args: Parameters<CommaArgs>) -> Result<CallToolResult, McpError> {
         self.tools.package.comma(args).await
     }
 
-    #[tool(description = "Build a Nix package and show what will be built or the build output")]
-    async fn nix_build(&self, args: Parameters<NixBuildArgs>) -> Result<CallToolResult, McpError> {
-        self.tools.build.nix_build(args).await
+    #[tool(
+        description = "Build a Nix package and show what will be built or the build output",
+        annotations(read_only_hint = false, destructive_hint = false),
+        output_schema = output_schema::<NixBuildResult>()
+    )]
+    async fn nix_build(
+        &self,
+        args: Parameters<NixBuildArgs>,
+        progress: ProgressReporter,
+        workspace: Workspace,
+    ) -> Result<CallToolResult, McpError> {
+        self.tools.build.nix_build(args, progress, workspace).await
     }
 
     #[tool(
         description = "Explain why one package depends on another (show dependency chain)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn why_depends(
         &self,
         args: Parameters<WhyDependsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.why_depends(args).await
+        self.tools.build.why_depends(args, workspace).await
     }
 
     #[tool(
         description = "Show the derivation details of a package (build inputs, environment, etc.)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn show_derivation(
         &self,
         args: Parameters<ShowDerivationArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.show_derivation(args).await
+        self.tools.build.show_derivation(args, workspace).await
     }
 
     #[tool(
         description = "Get the closure size of a package (total size including all dependencies)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = false, destructive_hint = false),
+        output_schema = output_schema::<ClosureSizeResult>()
     )]
     async fn get_closure_size(
         &self,
         args: Parameters<GetClosureSizeArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.get_closure_size(args).await
+        self.tools.build.get_closure_size(args, workspace).await
     }
 
-    #[tool(description = "Run a command in a Nix shell with specified packages available")]
+    #[tool(
+        description = "Run a command in a Nix shell with specified packages available",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn run_in_shell(
         &self,
         args: Parameters<RunInShellArgs>,
@@ -263,29 +476,31 @@ impl NixServer {
 
     #[tool(
         description = "Show the outputs available in a flake (packages, apps, devShells, etc.)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn flake_show(
         &self,
         args: Parameters<FlakeShowArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.flake.flake_show(args).await
+        self.tools.flake.flake_show(args, workspace).await
     }
 
     #[tool(
         description = "Get the build log for a package (useful for debugging build failures)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn get_build_log(
         &self,
         args: Parameters<GetBuildLogArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.get_build_log(args).await
+        self.tools.build.get_build_log(args, workspace).await
     }
 
     #[tool(
         description = "Get Nix build logs directly from store path, optionally filtered with grep pattern",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn nix_log(&self, args: Parameters<NixLogArgs>) -> Result<CallToolResult, McpError> {
         self.tools.develop.nix_log(args).await
@@ -293,178 +508,257 @@ impl NixServer {
 
     #[tool(
         description = "Compare two derivations to understand what differs between packages (uses nix-diff)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn diff_derivations(
         &self,
         args: Parameters<DiffDerivationsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.diff_derivations(args).await
+        self.tools.build.diff_derivations(args, workspace).await
     }
 
     // Clan integration tools
 
-    #[tool(description = "Create a new Clan machine configuration")]
+    #[tool(
+        description = "Create a new Clan machine configuration",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn clan_machine_create(
         &self,
         args: Parameters<ClanMachineCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_create(args).await
+        self.tools
+            .machine
+            .clan_machine_create(args, workspace)
+            .await
     }
 
     #[tool(
         description = "List all Clan machines in the flake",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false),
+        output_schema = output_schema::<MachineListResult>()
     )]
     async fn clan_machine_list(
         &self,
         args: Parameters<ClanMachineListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_list(args).await
+        self.tools.machine.clan_machine_list(args, workspace).await
     }
 
     #[tool(
         description = "Update Clan machine(s) - rebuilds and deploys configuration",
-        annotations(destructive_hint = true)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn clan_machine_update(
         &self,
         args: Parameters<ClanMachineUpdateArgs>,
+        progress: ProgressReporter,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_update(args).await
+        self.tools
+            .machine
+            .clan_machine_update(args, progress, confirmation, workspace)
+            .await
     }
 
     #[tool(
         description = "Delete a Clan machine configuration",
-        annotations(destructive_hint = true)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn clan_machine_delete(
         &self,
         args: Parameters<ClanMachineDeleteArgs>,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_delete(args).await
+        self.tools
+            .machine
+            .clan_machine_delete(args, confirmation, workspace)
+            .await
     }
 
     #[tool(
         description = "Install Clan machine to a target host via SSH (WARNING: Destructive - overwrites disk)",
-        annotations(destructive_hint = true)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn clan_machine_install(
         &self,
         args: Parameters<ClanMachineInstallArgs>,
+        progress: ProgressReporter,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_install(args).await
+        self.tools
+            .machine
+            .clan_machine_install(args, progress, confirmation, workspace)
+            .await
     }
 
-    #[tool(description = "Create a backup for a Clan machine")]
+    #[tool(
+        description = "Create a backup for a Clan machine",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn clan_backup_create(
         &self,
         args: Parameters<ClanBackupCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.backup.clan_backup_create(args).await
+        self.tools.backup.clan_backup_create(args, workspace).await
     }
 
     #[tool(
         description = "List backups for a Clan machine",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn clan_backup_list(
         &self,
         args: Parameters<ClanBackupListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.backup.clan_backup_list(args).await
+        self.tools.backup.clan_backup_list(args, workspace).await
     }
 
     #[tool(
         description = "Restore a backup for a Clan machine",
-        annotations(destructive_hint = true)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn clan_backup_restore(
         &self,
         args: Parameters<ClanBackupRestoreArgs>,
+        confirmation: Confirmation,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.backup.clan_backup_restore(args).await
+        self.tools
+            .backup
+            .clan_backup_restore(args, confirmation, workspace)
+            .await
     }
 
-    #[tool(description = "Create a new Clan flake from a template")]
+    #[tool(
+        description = "Create a new Clan flake from a template",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn clan_flake_create(
         &self,
         args: Parameters<ClanFlakeCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_flake_create(args).await
+        self.tools.analysis.clan_flake_create(args, workspace).await
     }
 
     #[tool(
         description = "List secrets in a Clan flake",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn clan_secrets_list(
         &self,
         args: Parameters<ClanSecretsListArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_secrets_list(args).await
+        self.tools.analysis.clan_secrets_list(args, workspace).await
     }
 
-    #[tool(description = "Create and run a VM for a Clan machine (useful for testing)")]
+    #[tool(
+        description = "Create and run a VM for a Clan machine (useful for testing)",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn clan_vm_create(
         &self,
         args: Parameters<ClanVmCreateArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_vm_create(args).await
+        self.tools.analysis.clan_vm_create(args, workspace).await
     }
 
     #[tool(
-        description = "Build a Clan machine configuration locally for testing without deployment"
+        description = "Build a Clan machine configuration locally for testing without deployment",
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn clan_machine_build(
         &self,
         args: Parameters<ClanMachineBuildArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.machine.clan_machine_build(args).await
+        self.tools.machine.clan_machine_build(args, workspace).await
     }
 
-    #[tool(description = "Build a NixOS machine configuration from a flake")]
+    #[tool(
+        description = "Build a NixOS machine configuration from a flake",
+        annotations(read_only_hint = false, destructive_hint = false)
+    )]
     async fn nixos_build(
         &self,
         args: Parameters<NixosBuildArgs>,
+        progress: ProgressReporter,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.build.nixos_build(args).await
+        self.tools
+            .build
+            .nixos_build(args, progress, workspace)
+            .await
     }
 
-    #[tool(description = "Analyze Clan secret (ACL) ownership across machines")]
+    #[tool(
+        description = "Analyze Clan secret (ACL) ownership across machines",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
     async fn clan_analyze_secrets(
         &self,
         args: Parameters<ClanAnalyzeSecretsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_analyze_secrets(args).await
+        self.tools
+            .analysis
+            .clan_analyze_secrets(args, workspace)
+            .await
     }
 
-    #[tool(description = "Analyze Clan vars ownership across machines")]
+    #[tool(
+        description = "Analyze Clan vars ownership across machines",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
     async fn clan_analyze_vars(
         &self,
         args: Parameters<ClanAnalyzeVarsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_analyze_vars(args).await
+        self.tools.analysis.clan_analyze_vars(args, workspace).await
     }
 
-    #[tool(description = "Analyze Clan machine tags across the infrastructure")]
+    #[tool(
+        description = "Analyze Clan machine tags across the infrastructure",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
     async fn clan_analyze_tags(
         &self,
         args: Parameters<ClanAnalyzeTagsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_analyze_tags(args).await
+        self.tools.analysis.clan_analyze_tags(args, workspace).await
     }
 
-    #[tool(description = "Analyze Clan user roster configurations")]
+    #[tool(
+        description = "Analyze Clan user roster configurations",
+        annotations(read_only_hint = true, destructive_hint = false)
+    )]
     async fn clan_analyze_roster(
         &self,
         args: Parameters<ClanAnalyzeRosterArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.analysis.clan_analyze_roster(args).await
+        self.tools
+            .analysis
+            .clan_analyze_roster(args, workspace)
+            .await
     }
 
     #[tool(
-        description = "Get help and information about Clan - the peer-to-peer NixOS management framework"
+        description = "Get help and information about Clan - the peer-to-peer NixOS management framework",
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     fn clan_help(
         &self,
@@ -475,7 +769,7 @@ impl NixServer {
 
     #[tool(
         description = "Find which package provides a specific file path using nix-locate",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn nix_locate(
         &self,
@@ -486,26 +780,31 @@ impl NixServer {
 
     #[tool(
         description = "Run an application from nixpkgs without installing it",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
-    async fn nix_run(&self, args: Parameters<NixRunArgs>) -> Result<CallToolResult, McpError> {
-        self.tools.develop.nix_run(args).await
+    async fn nix_run(
+        &self,
+        args: Parameters<NixRunArgs>,
+        workspace: Workspace,
+    ) -> Result<CallToolResult, McpError> {
+        self.tools.develop.nix_run(args, workspace).await
     }
 
     #[tool(
         description = "Run a command in a Nix development environment (from flake.nix devShell)",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn nix_develop(
         &self,
         args: Parameters<NixDevelopArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        self.tools.develop.nix_develop(args).await
+        self.tools.develop.nix_develop(args, workspace).await
     }
 
     #[tool(
         description = "Format Nix code using the project's formatter (typically nix fmt)",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn nix_fmt(&self, args: Parameters<NixFmtArgs>) -> Result<CallToolResult, McpError> {
         self.tools.quality.nix_fmt(args).await
@@ -513,7 +812,7 @@ impl NixServer {
 
     #[tool(
         description = "Add a command to the pueue task queue for async execution. Returns task ID.",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pueue_add(&self, args: Parameters<PueueAddArgs>) -> Result<CallToolResult, McpError> {
         // Delegate to modular implementation
@@ -522,7 +821,8 @@ impl NixServer {
 
     #[tool(
         description = "Get the status of pueue tasks (all or specific task IDs)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false),
+        output_schema = output_schema::<PueueStatusResult>()
     )]
     async fn pueue_status(
         &self,
@@ -534,7 +834,7 @@ impl NixServer {
 
     #[tool(
         description = "Get logs for a specific pueue task",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn pueue_log(&self, args: Parameters<PueueLogArgs>) -> Result<CallToolResult, McpError> {
         // Delegate to modular implementation
@@ -543,7 +843,7 @@ impl NixServer {
 
     #[tool(
         description = "Wait for specific pueue tasks to complete",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn pueue_wait(
         &self,
@@ -555,7 +855,7 @@ impl NixServer {
 
     #[tool(
         description = "Remove/kill specific pueue tasks",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn pueue_remove(
         &self,
@@ -567,7 +867,7 @@ impl NixServer {
 
     #[tool(
         description = "Clean up finished pueue tasks from the queue",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = true)
     )]
     async fn pueue_clean(
         &self,
@@ -579,7 +879,7 @@ impl NixServer {
 
     #[tool(
         description = "Pause specific pueue tasks or all tasks",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pueue_pause(
         &self,
@@ -591,7 +891,7 @@ impl NixServer {
 
     #[tool(
         description = "Start/resume specific pueue tasks or all tasks",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pueue_start(
         &self,
@@ -603,7 +903,7 @@ impl NixServer {
 
     #[tool(
         description = "Start a new pexpect-cli interactive session. Returns session ID.",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pexpect_start(
         &self,
@@ -615,7 +915,7 @@ impl NixServer {
 
     #[tool(
         description = "Send Python pexpect code to an active session",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pexpect_send(
         &self,
@@ -627,7 +927,7 @@ impl NixServer {
 
     #[tool(
         description = "Close an active pexpect-cli session",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pexpect_close(
         &self,
@@ -639,38 +939,44 @@ impl NixServer {
 
     #[tool(
         description = "Run pre-commit hooks to check code quality (formatting, linting, etc.)",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn pre_commit_run(
         &self,
         args: Parameters<PreCommitRunArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Delegate to modular implementation
-        self.tools.precommit.pre_commit_run(args).await
+        self.tools.precommit.pre_commit_run(args, workspace).await
     }
 
     #[tool(
         description = "Check if pre-commit hooks are installed and configured in the current repository",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true, destructive_hint = false)
     )]
     async fn check_pre_commit_status(
         &self,
         args: Parameters<CheckPreCommitStatusArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Delegate to modular implementation
-        self.tools.precommit.check_pre_commit_status(args).await
+        self.tools
+            .precommit
+            .check_pre_commit_status(args, workspace)
+            .await
     }
 
     #[tool(
         description = "Set up pre-commit hooks for a project (creates config and installs hooks)",
-        annotations(read_only_hint = false)
+        annotations(read_only_hint = false, destructive_hint = false)
     )]
     async fn setup_pre_commit(
         &self,
         args: Parameters<SetupPreCommitArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Delegate to modular implementation
-        self.tools.precommit.setup_pre_commit(args).await
+        self.tools.precommit.setup_pre_commit(args, workspace).await
     }
 }
 
@@ -727,9 +1033,33 @@ impl NixServer {
     }
 }
 
-#[tool_handler]
 #[prompt_handler]
 impl ServerHandler for NixServer {
+    /// Dispatch a tool call, honouring client cancellation.
+    ///
+    /// Calls exceeding a configured rate limit are rejected before routing.
+    /// When the client sends `notifications/cancelled` (or the session ends),
+    /// the in-flight tool future is dropped, which terminates the process
+    /// groups of any `nix`/`clan` children it spawned. Events logged while
+    /// handling the call are forwarded to this session's client only.
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.dispatch_tool(request, context)
+            .instrument(self.logging.span())
+            .await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
@@ -738,6 +1068,7 @@ impl ServerHandler for NixServer {
                 .enable_resources()
                 .enable_tools()
                 .enable_completions()
+                .enable_logging()
                 .build(),
             server_info: Implementation::from_build_env(),
             instructions: Some(
@@ -749,6 +1080,7 @@ impl ServerHandler for NixServer {
                 \n\nFlake Management: flake_metadata, flake_show \
                 \n\nCode Quality: validate_nix, lint_nix, format_nix, pre_commit_run, check_pre_commit_status, setup_pre_commit \
                 \n\nUtilities: nix_eval, prefetch_url, search_options, nix_command_help, ecosystem_tools \
+                \n\nAudit: audit_query answers what was run when (also readable as the audit://recent resource) \
                 \n\n=== PROACTIVE CODE QUALITY CHECKS === \
                 \n\nWhen working with a git repository, PROACTIVELY check if pre-commit hooks are set up using check_pre_commit_status. \
                 If they are not configured, suggest setting them up with setup_pre_commit or by adding pre-commit-hooks.nix to the flake. \
@@ -800,6 +1132,7 @@ impl ServerHandler for NixServer {
                 self._create_resource_text("nix://commands/common", "Common Nix Commands"),
                 self._create_resource_text("nix://ecosystem/tools", "Ecosystem Tools"),
                 self._create_resource_text("nix://flake/template", "Flake Template"),
+                self._create_resource_text(RECENT_URI, "Recent Audit Events"),
             ],
             next_cursor: None,
         })
@@ -989,13 +1322,19 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
                     contents: vec![ResourceContents::text(content, uri)],
                 })
             }
+            _ if uri.starts_with(RECENT_URI) => self.tools.audit_log.read_recent(&uri),
             _ => {
                 // Handle dynamic resource templates
                 if let Some(package_name) = uri.strip_prefix("nix://package/") {
                     // Get package information
                     let output = tokio::process::Command::new("nix")
-                        .args(["search", "nixpkgs", package_name, "--json"])
-                        .output()
+                        .args([
+                            "search",
+                            &self.config.flakes.nixpkgs,
+                            package_name,
+                            "--json",
+                        ])
+                        .group_output()
                         .await
                         .map_err(|e| {
                             McpError::internal_error(
@@ -1046,7 +1385,7 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
                         // Show flake outputs
                         let output = tokio::process::Command::new("nix")
                             .args(["flake", "show", flake_ref, "--json"])
-                            .output()
+                            .group_output()
                             .await
                             .map_err(|e| {
                                 McpError::internal_error(
@@ -1080,7 +1419,7 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
                             "--expr",
                             &format!("(import <nixpkgs/nixos> {{}}).options.{}.description or \"Option not found\"", option_path)
                         ])
-                        .output()
+                        .group_output()
                         .await
                         .map_err(|e| McpError::internal_error(format!("Failed to query option: {}", e), None))?;
 
@@ -1103,7 +1442,7 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
                     // Show derivation details
                     let output = tokio::process::Command::new("nix")
                         .args(["show-derivation", package])
-                        .output()
+                        .group_output()
                         .await
                         .map_err(|e| {
                             McpError::internal_error(
@@ -1168,6 +1507,13 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
                 description: Some("Show derivation details for a package (e.g., nix://derivation/nixpkgs#hello)".to_string()),
                 mime_type: Some("application/json".to_string()),
             }.no_annotation(),
+            RawResourceTemplate {
+                uri_template: "audit://recent{?tool,since,until,success,level,limit}".to_string(),
+                name: "audit-events".to_string(),
+                title: Some("Audit Events".to_string()),
+                description: Some("Recorded audit events, newest first, filtered like the audit_query tool (e.g., audit://recent?tool=run_in_shell&since=24h)".to_string()),
+                mime_type: Some("application/json".to_string()),
+            }.no_annotation(),
         ];
 
         Ok(ListResourceTemplatesResult {
@@ -1179,51 +1525,38 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
     async fn complete(
         &self,
         request: CompleteRequestParam,
-        _context: RequestContext<RoleServer>,
+        context: RequestContext<RoleServer>,
     ) -> Result<CompleteResult, McpError> {
-        let candidates = match &request.r#ref {
-            Reference::Prompt(prompt_ref) => {
-                // Handle prompt argument completion
-                match (prompt_ref.name.as_str(), request.argument.name.as_str()) {
-                    ("setup_dev_environment", "project_type") => {
-                        vec![
-                            "rust", "python", "nodejs", "go", "c", "c++", "java", "haskell",
-                            "generic",
-                        ]
-                    }
-                    ("generate_flake", "project_type") => {
-                        vec!["rust", "python", "nodejs", "go", "c", "generic"]
-                    }
-                    _ => vec![],
-                }
-            }
-            _ => {
-                // Could also handle tool or resource template argument completion here
-                vec![]
-            }
-        };
-
-        // Filter candidates based on the current input value
-        let filtered: Vec<String> = if request.argument.value.is_empty() {
-            candidates.into_iter().map(String::from).collect()
-        } else {
-            let query_lower = request.argument.value.to_lowercase();
-            candidates
-                .into_iter()
-                .filter(|c| c.to_lowercase().contains(&query_lower))
-                .map(String::from)
-                .collect()
-        };
-
+        let provider = CompletionProvider::new(
+            self.caches.clone(),
+            self.config.clone(),
+            self.audit.clone(),
+            self.rate_limits.clone(),
+        );
+        let workspace = Workspace::for_peer(self.roots.clone(), context.peer);
         Ok(CompleteResult {
-            completion: CompletionInfo {
-                values: filtered,
-                total: None,
-                has_more: Some(false),
-            },
+            completion: provider.complete(&request, &workspace).await,
         })
     }
 
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.logging.set_level(request.level);
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        tracing::info!("client initialized");
+        self.logging.attach(context.peer);
+    }
+
+    async fn on_roots_list_changed(&self, _context: NotificationContext<RoleServer>) {
+        self.roots.invalidate();
+    }
+
     async fn initialize(
         &self,
         _request: InitializeRequestParam,
@@ -1237,3 +1570,148 @@ Use the 'ecosystem_tools' tool to get detailed information about any of these to
         Ok(self.get_info())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tool_registry::{Profile, ToolGroup};
+
+    fn server_with_profile(profile: Profile) -> NixServer {
+        let mut config = Config::default();
+        config.tools.profile = profile;
+        NixServer::with_config(Arc::new(config))
+    }
+
+    #[test]
+    fn test_every_tool_has_a_group() {
+        let server = NixServer::new();
+        let routed: Vec<String> = server
+            .tool_router
+            .list_all()
+            .into_iter()
+            .map(|t| t.name.to_string())
+            .collect();
+
+        for name in &routed {
+            assert!(ToolGroup::of(name).is_some(), "{} has no tool group", name);
+        }
+        for group in ToolGroup::ALL {
+            for tool in group.tools() {
+                assert!(routed.iter().any(|r| r == tool), "{} is not routed", tool);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_tool_has_explicit_hints() {
+        // Profiles rely on these; the MCP defaults would hide unannotated tools
+        for tool in NixServer::new().tool_router.list_all() {
+            let annotations = tool.annotations.clone().unwrap_or_default();
+            assert!(
+                annotations.read_only_hint.is_some() && annotations.destructive_hint.is_some(),
+                "{} lacks read_only_hint or destructive_hint",
+                tool.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_disabled_groups_are_not_routed() {
+        let config = Config::from_toml_str("[tools]\nenabled_groups = [\"packages\"]\n").unwrap();
+        let server = NixServer::with_config(Arc::new(config));
+
+        assert!(server.tool_router.has_route("search_packages"));
+        assert!(!server.tool_router.has_route("nix_build"));
+        assert!(!server.tool_router.has_route("clan_machine_install"));
+        assert_eq!(
+            server.tool_router.list_all().len(),
+            ToolGroup::Packages.tools().len()
+        );
+    }
+
+    #[test]
+    fn test_readonly_profile_hides_mutating_tools() {
+        let server = server_with_profile(Profile::Readonly);
+
+        for tool in [
+            "clan_machine_install",
+            "clan_machine_delete",
+            "clan_backup_restore",
+            "run_in_shell",
+            "pueue_add",
+            "pexpect_start",
+            "nix_build",
+        ] {
+            assert!(!server.tool_router.has_route(tool), "{} is routed", tool);
+        }
+        for tool in [
+            "search_packages",
+            "clan_machine_list",
+            "clan_analyze_tags",
+            "show_derivation",
+        ] {
+            assert!(server.tool_router.has_route(tool), "{} is not routed", tool);
+        }
+    }
+
+    #[test]
+    fn test_readonly_profile_exposes_no_building_tools() {
+        let server = server_with_profile(Profile::Readonly);
+        let full = server_with_profile(Profile::Full);
+
+        // Tools that build, realise or run store paths
+        for tool in [
+            "nix_build",
+            "nixos_build",
+            "why_depends",
+            "get_closure_size",
+            "nix_run",
+            "nix_develop",
+            "run_in_shell",
+            "comma",
+            "nix_fmt",
+            "clan_vm_create",
+        ] {
+            assert!(full.tool_router.has_route(tool), "{} does not exist", tool);
+            assert!(!server.tool_router.has_route(tool), "{} is routed", tool);
+        }
+    }
+
+    #[test]
+    fn test_dev_profile_hides_destructive_tools() {
+        let server = server_with_profile(Profile::Dev);
+
+        assert!(server.tool_router.has_route("nix_build"));
+        assert!(server.tool_router.has_route("run_in_shell"));
+        assert!(!server.tool_router.has_route("clan_machine_install"));
+        assert!(!server.tool_router.has_route("clan_machine_update"));
+        assert!(!server.tool_router.has_route("clan_backup_restore"));
+        assert!(!server.tool_router.has_route("pueue_remove"));
+        assert!(server.tool_router.has_route("clan_machine_create"));
+
+        // Tools that are not read-only are destructive unless annotated otherwise
+        let unannotated = rmcp::model::Tool::new("x", "", Arc::new(Default::default()));
+        assert!(!Profile::Dev.allows(&unannotated));
+    }
+
+    #[test]
+    fn test_ops_profile_hides_command_execution() {
+        let server = server_with_profile(Profile::Ops);
+
+        assert!(server.tool_router.has_route("clan_machine_update"));
+        assert!(server.tool_router.has_route("nix_build"));
+        assert!(server.tool_router.has_route("pueue_status"));
+        assert!(!server.tool_router.has_route("run_in_shell"));
+        assert!(!server.tool_router.has_route("pueue_add"));
+        assert!(!server.tool_router.has_route("pexpect_send"));
+    }
+
+    #[test]
+    fn test_profile_from_config() {
+        let config = Config::from_toml_str("[tools]\nprofile = \"readonly\"\n").unwrap();
+        assert_eq!(config.tools.profile, Profile::Readonly);
+        assert!(Config::from_toml_str("[tools]\nprofile = \"root\"\n").is_err());
+        assert_eq!("ops".parse::<Profile>(), Ok(Profile::Ops));
+        assert!("admin".parse::<Profile>().is_err());
+    }
+}
@@ -72,6 +72,58 @@ pub fn format_option_search_response(query: &str) -> String {
     )
 }
 
+/// Parse the build plan Nix prints to stderr for `nix build --dry-run`.
+///
+/// Returns `(will_build, will_fetch)`: the store paths listed under
+/// "these N derivations will be built:" and "these N paths will be fetched ...:".
+pub fn parse_build_plan(stderr: &str) -> (Vec<String>, Vec<String>) {
+    let mut will_build = Vec::new();
+    let mut will_fetch = Vec::new();
+    let mut current: Option<&mut Vec<String>> = None;
+
+    for line in stderr.lines() {
+        if line.contains("will be built:") {
+            current = Some(&mut will_build);
+        } else if line.contains("will be fetched") {
+            current = Some(&mut will_fetch);
+        } else if let (Some(list), true) = (current.as_mut(), line.starts_with(' ')) {
+            let path = line.trim();
+            if path.starts_with("/nix/store/") {
+                list.push(path.to_string());
+            }
+        } else {
+            current = None;
+        }
+    }
+
+    (will_build, will_fetch)
+}
+
+/// Extract the closure size in bytes from `nix path-info -S --json` output.
+///
+/// Handles both the object form keyed by store path (Nix >= 2.19) and the
+/// older array form.
+pub fn parse_closure_size(path_info_json: &serde_json::Value) -> Option<u64> {
+    let entry = match path_info_json {
+        serde_json::Value::Array(items) => items.first()?,
+        serde_json::Value::Object(map) => map.values().next()?,
+        _ => return None,
+    };
+    entry.get("closureSize")?.as_u64()
+}
+
+/// Format a byte count as MB or GB with two decimals.
+pub fn format_size(bytes: u64) -> String {
+    let size_gb = bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+    let size_mb = bytes as f64 / (1024.0 * 1024.0);
+
+    if size_gb >= 1.0 {
+        format!("{:.2} GB", size_gb)
+    } else {
+        format!("{:.2} MB", size_mb)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,4 +205,33 @@ mod tests {
         assert!(response.contains("nixos-option networking.hostName"));
         assert!(response.contains("man configuration.nix"));
     }
+
+    #[test]
+    fn test_parse_build_plan() {
+        let stderr = "these 2 derivations will be built:\n  /nix/store/aaa-hello.drv\n  /nix/store/bbb-world.drv\nthese 1 paths will be fetched (0.10 MiB download, 0.50 MiB unpacked):\n  /nix/store/ccc-glibc\nwarning: something else\n";
+        let (build, fetch) = parse_build_plan(stderr);
+
+        assert_eq!(
+            build,
+            vec!["/nix/store/aaa-hello.drv", "/nix/store/bbb-world.drv"]
+        );
+        assert_eq!(fetch, vec!["/nix/store/ccc-glibc"]);
+        assert_eq!(parse_build_plan(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_parse_closure_size() {
+        let object = serde_json::json!({"/nix/store/aaa-hello": {"closureSize": 1234}});
+        let array = serde_json::json!([{"path": "/nix/store/aaa-hello", "closureSize": 5678}]);
+
+        assert_eq!(parse_closure_size(&object), Some(1234));
+        assert_eq!(parse_closure_size(&array), Some(5678));
+        assert_eq!(parse_closure_size(&serde_json::json!([])), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(5 * 1024 * 1024), "5.00 MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 / 2), "1.50 GB");
+    }
 }
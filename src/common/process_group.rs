@@ -0,0 +1,226 @@
+//! Child processes that are cleaned up when their tool call ends.
+//!
+//! Dropping a `tokio::process::Command::output()` future (on timeout or client
+//! cancellation) leaves the child running. `nix build` and `clan machines
+//! update` fork many helpers, so killing only the direct child is not enough
+//! either. [`ProcessGroupExt`] spawns each command as the leader of its own
+//! process group and terminates the whole group if the call is abandoned
+//! before the child exits: `SIGTERM` first, then `SIGKILL` after
+//! [`KILL_GRACE_PERIOD`].
+
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::process::{Output, Stdio};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+
+/// Time between `SIGTERM` and `SIGKILL` when an abandoned process group is terminated.
+pub const KILL_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// Run commands in their own process group, terminated when abandoned.
+pub trait ProcessGroupExt {
+    /// Spawn the command as the leader of a new process group.
+    ///
+    /// The group is terminated when the returned [`GroupChild`] is dropped
+    /// before the child has been waited for.
+    fn spawn_group(&mut self) -> io::Result<GroupChild>;
+
+    /// Like [`Command::output`], but terminates the child's process group if
+    /// the future is dropped before the child exits.
+    ///
+    /// As with `output`, stdout and stderr are captured and stdin is closed.
+    fn group_output(&mut self) -> impl Future<Output = io::Result<Output>> + Send;
+}
+
+impl ProcessGroupExt for Command {
+    fn spawn_group(&mut self) -> io::Result<GroupChild> {
+        #[cfg(unix)]
+        self.process_group(0);
+        #[cfg(not(unix))]
+        self.kill_on_drop(true);
+
+        let child = self.spawn()?;
+        Ok(GroupChild {
+            pgid: child.id(),
+            child,
+            finished: false,
+        })
+    }
+
+    fn group_output(&mut self) -> impl Future<Output = io::Result<Output>> + Send {
+        let child = self
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn_group();
+        async move { child?.wait_with_output().await }
+    }
+}
+
+/// A child process leading its own process group.
+///
+/// Dereferences to the underlying [`Child`] for access to its pipes.
+pub struct GroupChild {
+    child: Child,
+    pgid: Option<u32>,
+    finished: bool,
+}
+
+impl GroupChild {
+    /// Wait for the child to exit, collecting stdout and stderr.
+    ///
+    /// Like [`Child::wait_with_output`], stdin is closed before waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting on the child or reading its output fails.
+    pub async fn wait_with_output(mut self) -> io::Result<Output> {
+        // Close stdin so children reading it until EOF can finish
+        drop(self.child.stdin.take());
+        let stdout = self.child.stdout.take();
+        let stderr = self.child.stderr.take();
+
+        let (stdout, stderr) = tokio::try_join!(read_all(stdout), read_all(stderr))?;
+        let status = self.wait().await?;
+
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+
+    /// Wait for the child to exit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if waiting on the child fails.
+    pub async fn wait(&mut self) -> io::Result<std::process::ExitStatus> {
+        let status = self.child.wait().await?;
+        self.finished = true;
+        Ok(status)
+    }
+}
+
+impl Deref for GroupChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for GroupChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+impl Drop for GroupChild {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(pgid) = self.pgid {
+            terminate_group(pgid);
+        }
+    }
+}
+
+async fn read_all<R: tokio::io::AsyncRead + Unpin>(reader: Option<R>) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if let Some(mut reader) = reader {
+        reader.read_to_end(&mut buf).await?;
+    }
+    Ok(buf)
+}
+
+/// Send `SIGTERM` to the group now and `SIGKILL` after the grace period.
+#[cfg(unix)]
+fn terminate_group(pgid: u32) {
+    let Ok(pgid) = libc::pid_t::try_from(pgid) else {
+        return;
+    };
+
+    tracing::debug!(pgid, "Terminating abandoned process group");
+    // SAFETY: killpg has no memory-safety preconditions; a stale group id
+    // only results in ESRCH.
+    unsafe {
+        libc::killpg(pgid, libc::SIGTERM);
+    }
+
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        runtime.spawn(async move {
+            tokio::time::sleep(KILL_GRACE_PERIOD).await;
+            // SAFETY: see above
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate_group(_pgid: u32) {
+    // kill_on_drop(true) terminates the child when it is dropped
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// Whether `pid` is running (zombies awaiting reaping count as dead).
+    fn is_alive(pid: u32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|stat| {
+                let state = stat.rsplit_once(") ")?.1.chars().next()?;
+                Some(state != 'Z' && state != 'X')
+            })
+            .unwrap_or(false)
+    }
+
+    #[tokio::test]
+    async fn test_group_output_collects_output() {
+        let output = Command::new("sh")
+            .args(["-c", "echo out; echo err >&2; exit 3"])
+            .group_output()
+            .await
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_output_kills_grandchildren() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("grandchild.pid");
+
+        // The grandchild writes its pid and sleeps; the direct child waits on it
+        let script = format!("sleep 30 & echo $! > {}; wait", pid_file.display());
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", &script]);
+        let result = tokio::time::timeout(Duration::from_millis(500), cmd.group_output()).await;
+        assert!(result.is_err(), "command should still be running");
+
+        let pid: u32 = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        let mut alive = true;
+        for _ in 0..50 {
+            if !is_alive(pid) {
+                alive = false;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!alive, "grandchild {} survived the dropped call", pid);
+    }
+}
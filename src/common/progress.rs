@@ -0,0 +1,378 @@
+//! MCP progress notifications for long-running builds and deployments.
+//!
+//! Tools such as `nix_build` and `clan_machine_update` can run for many
+//! minutes. When the client sends a `progressToken` with the request, these
+//! tools stream `notifications/progress` while the command runs, based on the
+//! messages Nix prints to stderr:
+//!
+//! ```text
+//! these 17 derivations will be built:
+//! these 42 paths will be fetched (120.3 MiB download, 512.0 MiB unpacked):
+//! copying path '/nix/store/...-glibc-2.39' from 'https://cache.nixos.org'...
+//! building '/nix/store/...-hello-2.12.1.drv'...
+//! ```
+//!
+//! which becomes a progress message like
+//! `building 3/17 derivations, fetching 12/42 paths (120.3 MiB download)`.
+
+use crate::common::process_group::ProcessGroupExt;
+use rmcp::handler::server::common::{AsRequestContext, FromContextPart};
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::service::RequestContext;
+use rmcp::{Peer, RoleServer};
+use std::process::Output;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+/// Sends progress notifications for a single request.
+///
+/// Reporting is a no-op when the client did not request progress, so tools can
+/// report unconditionally. Can be used directly as a tool handler argument.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    target: Option<(Peer<RoleServer>, ProgressToken)>,
+}
+
+impl ProgressReporter {
+    /// Reporter for a request; disabled if the request has no progress token.
+    pub fn from_request(context: &RequestContext<RoleServer>) -> Self {
+        Self {
+            target: context
+                .meta
+                .get_progress_token()
+                .map(|token| (context.peer.clone(), token)),
+        }
+    }
+
+    /// Reporter that never sends anything.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Whether the client asked for progress notifications.
+    pub fn is_enabled(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Send a progress notification.
+    ///
+    /// Delivery failures are logged and otherwise ignored: a client that went
+    /// away should not fail the build it started.
+    pub async fn report(&self, progress: f64, total: Option<f64>, message: impl Into<String>) {
+        let Some((peer, token)) = &self.target else {
+            return;
+        };
+
+        let param = ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress,
+            total,
+            message: Some(message.into()),
+        };
+        if let Err(e) = peer.notify_progress(param).await {
+            tracing::debug!("Failed to send progress notification: {}", e);
+        }
+    }
+
+    /// Send the current state of a build.
+    pub async fn report_build(&self, build: &BuildProgress) {
+        self.report(build.completed() as f64, build.total(), build.message())
+            .await;
+    }
+}
+
+impl<C: AsRequestContext> FromContextPart<C> for ProgressReporter {
+    fn from_context_part(context: &mut C) -> Result<Self, rmcp::ErrorData> {
+        Ok(Self::from_request(context.as_request_context()))
+    }
+}
+
+/// Build and download counts parsed from Nix log output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildProgress {
+    /// Derivations Nix announced it will build
+    pub builds_expected: u64,
+    /// Derivations started so far
+    pub builds_started: u64,
+    /// Store paths Nix announced it will fetch
+    pub fetches_expected: u64,
+    /// Store paths copied from a substituter so far
+    pub fetches_started: u64,
+    /// Announced download size (e.g. "120.3 MiB")
+    pub download_size: Option<String>,
+    /// Name of the most recently started derivation or path
+    pub current: Option<String>,
+}
+
+impl BuildProgress {
+    /// Update the counts from one line of Nix output.
+    ///
+    /// Returns `true` if the line changed the progress. Lines may carry a
+    /// prefix (Clan prefixes output with the machine name).
+    pub fn observe(&mut self, line: &str) -> bool {
+        let line = line.trim();
+
+        if let Some(count) = announced_count(line, "derivation", "will be built") {
+            self.builds_expected += count;
+            return true;
+        }
+
+        if let Some(count) = announced_count(line, "path", "will be fetched") {
+            self.fetches_expected += count;
+            if let Some(size) = download_size(line) {
+                self.download_size = Some(size);
+            }
+            return true;
+        }
+
+        if let Some(path) = quoted_after(line, "building '") {
+            if path.ends_with(".drv") {
+                self.builds_started += 1;
+                self.current = Some(store_path_name(path).trim_end_matches(".drv").to_string());
+                return true;
+            }
+        }
+
+        if let Some(path) = quoted_after(line, "copying path '") {
+            self.fetches_started += 1;
+            self.current = Some(store_path_name(path).to_string());
+            return true;
+        }
+
+        false
+    }
+
+    /// Builds and fetches started so far.
+    pub fn completed(&self) -> u64 {
+        self.builds_started + self.fetches_started
+    }
+
+    /// Total announced work, if Nix printed a build plan.
+    pub fn total(&self) -> Option<f64> {
+        let total = self.builds_expected + self.fetches_expected;
+        (total > 0 && total >= self.completed()).then_some(total as f64)
+    }
+
+    /// Human-readable summary, e.g. `building 3/17 derivations, fetching 2/5 paths (120.3 MiB download)`.
+    pub fn message(&self) -> String {
+        let mut parts = Vec::new();
+
+        if self.builds_expected > 0 || self.builds_started > 0 {
+            parts.push(format!(
+                "building {}/{} derivations",
+                self.builds_started,
+                self.builds_expected.max(self.builds_started)
+            ));
+        }
+        if self.fetches_expected > 0 || self.fetches_started > 0 {
+            let mut part = format!(
+                "fetching {}/{} paths",
+                self.fetches_started,
+                self.fetches_expected.max(self.fetches_started)
+            );
+            if let Some(size) = &self.download_size {
+                part.push_str(&format!(" ({} download)", size));
+            }
+            parts.push(part);
+        }
+
+        let mut message = if parts.is_empty() {
+            "evaluating".to_string()
+        } else {
+            parts.join(", ")
+        };
+        if let Some(current) = &self.current {
+            message.push_str(&format!(" - {}", current));
+        }
+        message
+    }
+}
+
+/// Parse "these N <noun>s <verb>" or "this <noun> <verb>".
+fn announced_count(line: &str, noun: &str, verb: &str) -> Option<u64> {
+    if line.contains(&format!("this {} {}", noun, verb)) {
+        return Some(1);
+    }
+
+    let marker = format!(" {}s {}", noun, verb);
+    let end = line.find(&marker)?;
+    let before = &line[..end];
+    let digits_start = before
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let count = before[digits_start..].parse().ok()?;
+    line[..digits_start]
+        .trim_end()
+        .ends_with("these")
+        .then_some(count)
+}
+
+/// Extract "120.3 MiB" from "(120.3 MiB download, 512.0 MiB unpacked)".
+fn download_size(line: &str) -> Option<String> {
+    let end = line.find(" download")?;
+    let start = line[..end].rfind('(')? + 1;
+    Some(line[start..end].trim().to_string())
+}
+
+/// Text between `prefix` and the next single quote.
+fn quoted_after<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = line.find(prefix)? + prefix.len();
+    let len = line[start..].find('\'')?;
+    Some(&line[start..start + len])
+}
+
+/// "/nix/store/<hash>-hello-2.12.1" -> "hello-2.12.1"
+fn store_path_name(path: &str) -> &str {
+    let base = path.rsplit('/').next().unwrap_or(path);
+    match base.split_once('-') {
+        Some((hash, name)) if hash.len() == 32 => name,
+        _ => base,
+    }
+}
+
+/// Run `cmd` to completion like [`ProcessGroupExt::group_output`], reporting
+/// build progress parsed from its stderr as it is produced.
+///
+/// When progress is disabled this is exactly `cmd.group_output()`.
+///
+/// # Errors
+///
+/// Returns an error if the command cannot be spawned or its output cannot be read.
+pub async fn output_with_progress(
+    cmd: &mut tokio::process::Command,
+    progress: &ProgressReporter,
+) -> std::io::Result<Output> {
+    if !progress.is_enabled() {
+        return cmd.group_output().await;
+    }
+
+    let mut child = cmd
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn_group()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let read_stdout = async {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).await.map(|_| buf)
+    };
+
+    let read_stderr = async {
+        let mut reader = BufReader::new(stderr);
+        let mut buf = Vec::new();
+        let mut line = Vec::new();
+        let mut build = BuildProgress::default();
+
+        progress.report_build(&build).await;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+            buf.extend_from_slice(&line);
+            if build.observe(&String::from_utf8_lossy(&line)) {
+                progress.report_build(&build).await;
+            }
+        }
+        Ok::<_, std::io::Error>(buf)
+    };
+
+    let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+    let status = child.wait().await?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe_all(lines: &[&str]) -> BuildProgress {
+        let mut build = BuildProgress::default();
+        for line in lines {
+            build.observe(line);
+        }
+        build
+    }
+
+    #[test]
+    fn test_parses_build_plan() {
+        let build = observe_all(&[
+            "these 17 derivations will be built:",
+            "  /nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.1.drv",
+            "these 42 paths will be fetched (120.3 MiB download, 512.0 MiB unpacked):",
+        ]);
+
+        assert_eq!(build.builds_expected, 17);
+        assert_eq!(build.fetches_expected, 42);
+        assert_eq!(build.download_size.as_deref(), Some("120.3 MiB"));
+        assert_eq!(build.total(), Some(59.0));
+    }
+
+    #[test]
+    fn test_singular_announcements() {
+        let build = observe_all(&[
+            "this derivation will be built:",
+            "this path will be fetched (0.5 MiB download, 2.1 MiB unpacked):",
+        ]);
+        assert_eq!(build.builds_expected, 1);
+        assert_eq!(build.fetches_expected, 1);
+    }
+
+    #[test]
+    fn test_counts_builds_and_fetches() {
+        let build = observe_all(&[
+            "these 2 derivations will be built:",
+            "these 3 paths will be fetched (1.0 MiB download, 4.0 MiB unpacked):",
+            "copying path '/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-glibc-2.39' from 'https://cache.nixos.org'...",
+            "building '/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.1.drv'...",
+        ]);
+
+        assert_eq!(build.completed(), 2);
+        assert_eq!(build.current.as_deref(), Some("hello-2.12.1"));
+        assert_eq!(
+            build.message(),
+            "building 1/2 derivations, fetching 1/3 paths (1.0 MiB download) - hello-2.12.1"
+        );
+    }
+
+    #[test]
+    fn test_prefixed_clan_output() {
+        let build = observe_all(&[
+            "[jon] these 5 derivations will be built:",
+            "[jon] building '/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-etc.drv'...",
+        ]);
+        assert_eq!(build.builds_expected, 5);
+        assert_eq!(build.builds_started, 1);
+    }
+
+    #[test]
+    fn test_ignores_unrelated_lines() {
+        let mut build = BuildProgress::default();
+        assert!(!build.observe("warning: Git tree '/src' is dirty"));
+        assert!(!build.observe("building the system configuration..."));
+        assert!(!build.observe("error: 12 derivations will be built is not a real message"));
+        assert_eq!(build, BuildProgress::default());
+        assert_eq!(build.message(), "evaluating");
+        assert_eq!(build.total(), None);
+    }
+
+    #[tokio::test]
+    async fn test_output_with_progress_disabled_matches_output() {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.args(["-c", "echo out; echo err >&2"]);
+
+        let output = output_with_progress(&mut cmd, &ProgressReporter::disabled())
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+}
@@ -0,0 +1,266 @@
+//! Lock revisions that scope cached results.
+//!
+//! A result evaluated from a flake is only valid for the inputs it was
+//! evaluated against. A [`Revision`] identifies those inputs, and
+//! [`CachedExecutor::at_revision`](crate::common::caching::CachedExecutor::at_revision)
+//! keys cache entries on it:
+//!
+//! - References pinned with `rev` or `narHash` (`github:NixOS/nixpkgs/<commit>`)
+//!   and store paths are immutable; their results never expire.
+//! - Local flakes are identified by a hash of their `flake.lock`, found
+//!   through the client's workspace roots, so `nix flake update`
+//!   invalidates their results.
+//! - Other references (`nixpkgs`, `github:NixOS/nixpkgs/nixos-unstable`)
+//!   float. They resolve to the `narHash` of what they currently lock to,
+//!   and the resolution is revalidated after the `[caches.revision]` TTL.
+//!
+//! [`RevisionResolver`] computes revisions on behalf of a tool.
+
+use crate::common::cache::TtlCache;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::flake_ref::FlakeRef;
+use crate::common::installable::Installable;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
+use crate::common::security::audit::AuditLogger;
+use crate::common::security::helpers::with_timeout;
+use rmcp::ErrorData as McpError;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Identifier for the inputs a cached result was computed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    id: String,
+    immutable: bool,
+}
+
+impl Revision {
+    /// Revision whose content can never change.
+    pub fn immutable(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            immutable: true,
+        }
+    }
+
+    /// Revision that may move, so results for it keep their TTL.
+    pub fn floating(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            immutable: false,
+        }
+    }
+
+    /// Identifier included in cache keys.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether results for this revision never expire.
+    pub fn is_immutable(&self) -> bool {
+        self.immutable
+    }
+
+    /// `key` scoped to this revision.
+    pub fn scope(&self, key: &str) -> String {
+        format!("{}@{}", key, self.id)
+    }
+}
+
+/// Computes [`Revision`]s on behalf of one tool.
+///
+/// `nix flake metadata` for floating references runs in an evaluation slot
+/// of the [scheduler], under the tool's
+/// timeout and audit trail, and its results are memoized in `resolved`
+/// (`caches.revision`).
+pub struct RevisionResolver<'a> {
+    tool: &'a str,
+    audit: &'a AuditLogger,
+    timeout_secs: u64,
+    resolved: &'a TtlCache<String, String>,
+}
+
+impl<'a> RevisionResolver<'a> {
+    /// Resolver for `tool`, giving up on `nix flake metadata` after `timeout_secs`.
+    pub fn new(
+        tool: &'a str,
+        audit: &'a AuditLogger,
+        timeout_secs: u64,
+        resolved: &'a TtlCache<String, String>,
+    ) -> Self {
+        Self {
+            tool,
+            audit,
+            timeout_secs,
+            resolved,
+        }
+    }
+
+    /// Revision of the flake `flake_ref` points to.
+    ///
+    /// Local paths are resolved against the roots of `workspace`. A reference
+    /// that cannot be resolved (outside the roots, offline, no lock file)
+    /// gets a fixed floating revision, so its results simply expire after
+    /// the TTL.
+    pub async fn of_flake(&self, flake_ref: &FlakeRef, workspace: &Workspace) -> Revision {
+        let flake_ref = flake_ref.without_fragment();
+        if flake_ref.rev().is_some() || flake_ref.param("narHash").is_some() {
+            return Revision::immutable(flake_ref.to_string());
+        }
+
+        if flake_ref.local_path().is_some() {
+            let Ok(flake_ref) = workspace.resolve_flake_ref(&flake_ref).await else {
+                return Revision::floating("unresolved");
+            };
+            let path = flake_ref.local_path().unwrap_or(".");
+            let dir = Path::new(path).join(flake_ref.dir().unwrap_or(""));
+            return match tokio::fs::read(dir.join("flake.lock")).await {
+                Ok(lock) => Revision::floating(format!("lock:{:x}", Sha256::digest(lock))),
+                Err(_) => Revision::floating("unlocked"),
+            };
+        }
+
+        let key = flake_ref.to_string();
+        if let Some(nar_hash) = self.resolved.get(&key) {
+            return Revision::floating(nar_hash);
+        }
+        match self.locked_nar_hash(&key).await {
+            Some(nar_hash) => {
+                self.resolved.insert(key, nar_hash.clone());
+                Revision::floating(nar_hash)
+            }
+            None => Revision::floating("unresolved"),
+        }
+    }
+
+    /// Revision of the inputs of `installable`.
+    ///
+    /// Store paths and derivation outputs are immutable.
+    pub async fn of_installable(
+        &self,
+        installable: &Installable,
+        workspace: &Workspace,
+    ) -> Revision {
+        match installable {
+            Installable::Flake { flake_ref, .. } => self.of_flake(flake_ref, workspace).await,
+            Installable::StorePath(_) | Installable::Derivation { .. } => {
+                Revision::immutable("store")
+            }
+        }
+    }
+
+    /// `narHash` of what `flake_ref` currently locks to.
+    async fn locked_nar_hash(&self, flake_ref: &str) -> Option<String> {
+        let _slot = scheduler()
+            .acquire(self.audit, self.tool, CommandCategory::Evaluation)
+            .await;
+        let output = with_timeout(self.audit, self.tool, self.timeout_secs, || async {
+            tokio::process::Command::new("nix")
+                .args(["flake", "metadata", "--json", flake_ref])
+                .group_output()
+                .await
+                .map_err(|e| McpError::internal_error(e.to_string(), None))
+        })
+        .await
+        .ok()?;
+
+        if !output.status.success() {
+            tracing::debug!(
+                flake_ref,
+                stderr = %String::from_utf8_lossy(&output.stderr),
+                "Failed to resolve flake revision"
+            );
+            return None;
+        }
+        parse_nar_hash(&serde_json::from_slice(&output.stdout).ok()?)
+    }
+}
+
+/// `locked.narHash` from `nix flake metadata --json` output.
+fn parse_nar_hash(metadata: &serde_json::Value) -> Option<String> {
+    metadata["locked"]["narHash"].as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn resolved() -> TtlCache<String, String> {
+        TtlCache::new(Duration::from_secs(60), 10)
+    }
+
+    #[tokio::test]
+    async fn test_pinned_and_store_revisions_immutable() {
+        let audit = AuditLogger::new();
+        let resolved = resolved();
+        let resolver = RevisionResolver::new("test", &audit, 15, &resolved);
+        let workspace = Workspace::default();
+
+        let pinned: FlakeRef =
+            "github:NixOS/nixpkgs/0123456789abcdef0123456789abcdef01234567#hello"
+                .parse()
+                .unwrap();
+        let revision = resolver.of_flake(&pinned, &workspace).await;
+        assert!(revision.is_immutable());
+        assert!(!revision.id().contains('#'));
+
+        let store_path: Installable = "/nix/store/0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5-hello-2.12.1"
+            .parse()
+            .unwrap();
+        assert!(resolver
+            .of_installable(&store_path, &workspace)
+            .await
+            .is_immutable());
+    }
+
+    #[tokio::test]
+    async fn test_local_revision_follows_lock_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let audit = AuditLogger::new();
+        let resolved = resolved();
+        let resolver = RevisionResolver::new("test", &audit, 15, &resolved);
+        // "." names the client's root, not the server's working directory.
+        let workspace = Workspace::with_roots(vec![dir.to_path_buf()]);
+        let flake_ref: FlakeRef = ".#hello".parse().unwrap();
+
+        let unlocked = resolver.of_flake(&flake_ref, &workspace).await;
+        assert_eq!(unlocked, Revision::floating("unlocked"));
+
+        std::fs::write(dir.join("flake.lock"), r#"{"version": 7}"#).unwrap();
+        let locked = resolver.of_flake(&flake_ref, &workspace).await;
+        std::fs::write(dir.join("flake.lock"), r#"{"version": 7, "nodes": {}}"#).unwrap();
+        let updated = resolver.of_flake(&flake_ref, &workspace).await;
+        assert!(locked.id().starts_with("lock:"));
+        assert!(!updated.is_immutable());
+        assert_ne!(locked, updated);
+
+        let outside: FlakeRef = "path:/nonexistent-onix-mcp-flake".parse().unwrap();
+        assert_eq!(
+            resolver.of_flake(&outside, &workspace).await,
+            Revision::floating("unresolved")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_floating_revision_resolved_once() {
+        let flake_ref: FlakeRef = "github:NixOS/nixpkgs/nixos-unstable".parse().unwrap();
+        let audit = AuditLogger::new();
+        let resolved = resolved();
+        resolved.insert(flake_ref.to_string(), "sha256-abc".to_string());
+        let resolver = RevisionResolver::new("test", &audit, 15, &resolved);
+
+        let revision = resolver.of_flake(&flake_ref, &Workspace::default()).await;
+        assert_eq!(revision, Revision::floating("sha256-abc"));
+        assert_eq!(revision.scope("hello:10"), "hello:10@sha256-abc");
+
+        assert_eq!(
+            parse_nar_hash(&json!({"locked": {"narHash": "sha256-xyz", "rev": "abc"}})),
+            Some("sha256-xyz".to_string())
+        );
+        assert_eq!(parse_nar_hash(&json!({"locked": {}})), None);
+    }
+}
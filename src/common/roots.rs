@@ -0,0 +1,624 @@
+//! Client workspace roots.
+//!
+//! A single server may serve several editors or projects at once, so the
+//! server's own working directory says nothing about which project a tool call
+//! is about. MCP clients declare the directories they have open as `roots`;
+//! flake-relative tools resolve their paths against them:
+//!
+//! - Relative paths and local flake references (`.`, `./sub`, `path:..`,
+//!   `git+file:///src`) are resolved against the first declared root, the
+//!   active workspace.
+//! - Absolute paths must lie inside one of the declared roots, and so must
+//!   the `?dir=` subdirectory of a local flake.
+//! - Remote flake references (`github:...`, `nixpkgs`) and store paths are
+//!   left untouched.
+//!
+//! Containment is checked on canonical paths, so symlinks inside a root cannot
+//! lead outside of it.
+//!
+//! Roots are requested from the client on first use and re-requested after
+//! `notifications/roots/list_changed`. Clients that do not support roots keep
+//! the previous behaviour: paths are relative to the server's working
+//! directory and are not restricted. Clients that support roots but whose
+//! roots cannot be obtained, or that declare no `file://` roots, get an error
+//! for every local path rather than unrestricted access.
+
+use crate::common::flake_ref::FlakeRef;
+use crate::common::installable::Installable;
+use rmcp::handler::server::common::{AsRequestContext, FromContextPart};
+use rmcp::model::Root;
+use rmcp::service::RequestContext;
+use rmcp::{ErrorData as McpError, Peer, RoleServer};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long to wait for the client to answer `roots/list`.
+const LIST_ROOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Roots declared by the client of one MCP session.
+#[derive(Debug, Default)]
+pub struct ClientRoots {
+    /// `None` until the roots have been requested (again)
+    roots: Mutex<Option<Vec<PathBuf>>>,
+}
+
+impl ClientRoots {
+    /// Roots for a new session.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Forget the known roots so they are requested again on next use.
+    pub fn invalidate(&self) {
+        *self.roots.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Set the roots directly (for clients configured out of band and tests).
+    pub fn set(&self, roots: Vec<PathBuf>) {
+        *self.roots.lock().unwrap_or_else(|e| e.into_inner()) = Some(roots);
+    }
+
+    /// Known roots, requesting them from `peer` if needed.
+    ///
+    /// Returns no roots for clients without the roots capability. A failed
+    /// request, or one that yields no `file://` roots, is an error and is not
+    /// cached, so the next call retries.
+    async fn get(&self, peer: Option<&Peer<RoleServer>>) -> Result<Vec<PathBuf>, McpError> {
+        if let Some(roots) = self
+            .roots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            return Ok(roots.clone());
+        }
+
+        let Some(peer) = peer.filter(|p| supports_roots(p)) else {
+            return Ok(Vec::new());
+        };
+
+        let result = match tokio::time::timeout(LIST_ROOTS_TIMEOUT, peer.list_roots()).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to list client roots: {}", e);
+                return Err(McpError::internal_error(
+                    format!("Failed to list the client's workspace roots: {}", e),
+                    None,
+                ));
+            }
+            Err(_) => {
+                tracing::warn!("Timed out listing client roots");
+                return Err(McpError::internal_error(
+                    "Timed out listing the client's workspace roots",
+                    None,
+                ));
+            }
+        };
+
+        let roots = root_paths(&result.roots);
+        if roots.is_empty() {
+            return Err(McpError::invalid_params(
+                "The client declares no file:// workspace roots",
+                None,
+            ));
+        }
+        tracing::debug!(?roots, "client roots updated");
+        self.set(roots.clone());
+        Ok(roots)
+    }
+}
+
+fn supports_roots(peer: &Peer<RoleServer>) -> bool {
+    peer.peer_info()
+        .is_some_and(|info| info.capabilities.roots.is_some())
+}
+
+/// Resolves paths and flake references against the client's roots.
+///
+/// Can be used directly as a tool handler argument; tool calls through
+/// [`NixServer`](crate::common::nix_server::NixServer) see the roots of their
+/// session. [`Workspace::default`] applies no roots.
+#[derive(Clone, Default)]
+pub struct Workspace {
+    roots: Option<Arc<ClientRoots>>,
+    peer: Option<Peer<RoleServer>>,
+}
+
+impl Workspace {
+    /// Workspace of the session a request belongs to.
+    ///
+    /// The session's [`ClientRoots`] are taken from the request extensions.
+    pub fn from_request(context: &RequestContext<RoleServer>) -> Self {
+        Self {
+            roots: context.extensions.get::<Arc<ClientRoots>>().cloned(),
+            peer: Some(context.peer.clone()),
+        }
+    }
+
+    /// Workspace restricted to fixed roots.
+    pub fn with_roots(roots: Vec<PathBuf>) -> Self {
+        let client_roots = ClientRoots::new();
+        client_roots.set(roots);
+        Self {
+            roots: Some(client_roots),
+            peer: None,
+        }
+    }
+
+    /// Workspace of a session whose roots are requested from `peer`.
+    pub fn for_peer(roots: Arc<ClientRoots>, peer: Peer<RoleServer>) -> Self {
+        Self {
+            roots: Some(roots),
+            peer: Some(peer),
+        }
+    }
+
+    /// Declared roots; empty if the client does not support roots.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the client supports roots but they could not be obtained.
+    pub async fn roots(&self) -> Result<Vec<PathBuf>, McpError> {
+        match &self.roots {
+            Some(roots) => roots.get(self.peer.as_ref()).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Resolve a flake reference, rejecting local paths outside the roots.
+    pub async fn resolve_flake(&self, flake_ref: &str) -> Result<String, McpError> {
+        // Remote references never need the roots
+        if FlakeRef::parse(flake_ref).is_ok_and(|parsed| parsed.local_path().is_none()) {
+            return Ok(flake_ref.to_string());
+        }
+        resolve_flake_ref(&self.roots().await?, flake_ref)
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
+
+    /// Resolve a parsed flake reference, rejecting local paths outside the roots.
+    pub async fn resolve_flake_ref(&self, flake_ref: &FlakeRef) -> Result<FlakeRef, McpError> {
+        if flake_ref.local_path().is_none() {
+            return Ok(flake_ref.clone());
+        }
+        resolve_local_flake(&self.roots().await?, flake_ref)
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
+
+    /// Resolve the flake reference of an installable; store paths and
+    /// derivations are returned unchanged.
+    pub async fn resolve_installable(
+        &self,
+        installable: Installable,
+    ) -> Result<Installable, McpError> {
+        match installable {
+            Installable::Flake { flake_ref, outputs } => Ok(Installable::Flake {
+                flake_ref: self.resolve_flake_ref(&flake_ref).await?,
+                outputs,
+            }),
+            other => Ok(other),
+        }
+    }
+
+    /// Resolve a directory path, rejecting paths outside the roots.
+    ///
+    /// `None` is the active root, or the server's working directory if the
+    /// client declares no roots.
+    pub async fn resolve_dir(&self, path: Option<&str>) -> Result<PathBuf, McpError> {
+        resolve_path(&self.roots().await?, Path::new(path.unwrap_or(".")))
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
+}
+
+impl<C: AsRequestContext> FromContextPart<C> for Workspace {
+    fn from_context_part(context: &mut C) -> Result<Self, rmcp::ErrorData> {
+        Ok(Self::from_request(context.as_request_context()))
+    }
+}
+
+/// Resolve `path` against the first root and check it lies inside a root.
+///
+/// The path and the roots are compared in [canonical](canonicalize) form, so
+/// a symlink inside a root that points elsewhere is outside the roots.
+/// Without roots paths are returned unchanged.
+fn resolve_path(roots: &[PathBuf], path: &Path) -> Result<PathBuf, String> {
+    let Some(active) = roots.first() else {
+        return Ok(path.to_path_buf());
+    };
+
+    let resolved = canonicalize(&active.join(path));
+    if roots
+        .iter()
+        .any(|root| resolved.starts_with(canonicalize(root)))
+    {
+        Ok(resolved)
+    } else {
+        Err(format!(
+            "Path '{}' is outside the client's workspace roots",
+            path.display()
+        ))
+    }
+}
+
+/// Resolve the local path of a flake reference.
+///
+/// Local references are returned in their canonical spelling, keeping the
+/// `path:` or `git+file` scheme, `?query` and `#fragment`. References that
+/// are not local paths are returned unchanged.
+fn resolve_flake_ref(roots: &[PathBuf], flake_ref: &str) -> Result<String, String> {
+    if roots.is_empty() {
+        return Ok(flake_ref.to_string());
+    }
+
+    let parsed = FlakeRef::parse(flake_ref).map_err(|e| e.to_string())?;
+    if parsed.local_path().is_none() {
+        return Ok(flake_ref.to_string());
+    }
+    Ok(resolve_local_flake(roots, &parsed)?.to_string())
+}
+
+/// Point a local flake reference at its resolved directory, checking that
+/// the directory and its `?dir=` subdirectory lie inside a root.
+fn resolve_local_flake(roots: &[PathBuf], flake_ref: &FlakeRef) -> Result<FlakeRef, String> {
+    let Some(path) = flake_ref.local_path().filter(|_| !roots.is_empty()) else {
+        return Ok(flake_ref.clone());
+    };
+
+    let resolved = resolve_path(roots, Path::new(path))?;
+    if let Some(dir) = flake_ref.dir() {
+        resolve_path(roots, &resolved.join(dir))?;
+    }
+    Ok(flake_ref.with_local_path(&resolved.display().to_string()))
+}
+
+/// Local paths of `file://` roots; other URIs are ignored.
+fn root_paths(roots: &[Root]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .filter_map(|root| file_uri_to_path(&root.uri))
+        .collect()
+}
+
+/// Path of a `file://` URI with an empty or `localhost` host.
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    percent_decode(path).map(PathBuf::from)
+}
+
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Resolve symlinks in the longest existing prefix of `path` and normalize
+/// the rest lexically.
+///
+/// Paths that do not exist yet (or roots on another machine) keep their
+/// lexical form below the deepest existing ancestor.
+fn canonicalize(path: &Path) -> PathBuf {
+    let path = normalize(path);
+    let mut existing = path.as_path();
+    loop {
+        if let Ok(canonical) = std::fs::canonicalize(existing) {
+            let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+            return if rest.as_os_str().is_empty() {
+                canonical
+            } else {
+                canonical.join(rest)
+            };
+        }
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return path,
+        }
+    }
+}
+
+/// Remove `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::nix_server::NixServer;
+    use rmcp::model::{ClientCapabilities, ClientInfo, ListRootsResult, RootsCapabilities};
+    use rmcp::{ClientHandler, RoleClient, ServiceExt};
+
+    fn roots() -> Vec<PathBuf> {
+        vec![PathBuf::from("/work/app"), PathBuf::from("/work/infra")]
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let roots = roots();
+        assert_eq!(
+            resolve_path(&roots, Path::new(".")).unwrap(),
+            PathBuf::from("/work/app")
+        );
+        assert_eq!(
+            resolve_path(&roots, Path::new("sub/../nix")).unwrap(),
+            PathBuf::from("/work/app/nix")
+        );
+        assert_eq!(
+            resolve_path(&roots, Path::new("../infra")).unwrap(),
+            PathBuf::from("/work/infra")
+        );
+        assert_eq!(
+            resolve_path(&roots, Path::new("/work/infra/machines")).unwrap(),
+            PathBuf::from("/work/infra/machines")
+        );
+        assert!(resolve_path(&roots, Path::new("../../etc")).is_err());
+        assert!(resolve_path(&roots, Path::new("/work/application")).is_err());
+        assert!(resolve_path(&roots, Path::new("/etc/nixos")).is_err());
+    }
+
+    #[test]
+    fn test_no_roots_unrestricted() {
+        assert_eq!(
+            resolve_path(&[], Path::new("../x")).unwrap(),
+            PathBuf::from("../x")
+        );
+        assert_eq!(resolve_flake_ref(&[], "/etc/nixos").unwrap(), "/etc/nixos");
+    }
+
+    #[test]
+    fn test_resolve_flake_ref() {
+        let roots = roots();
+        assert_eq!(resolve_flake_ref(&roots, ".").unwrap(), "/work/app");
+        assert_eq!(
+            resolve_flake_ref(&roots, ".#nixosConfigurations.web").unwrap(),
+            "/work/app#nixosConfigurations.web"
+        );
+        assert_eq!(
+            resolve_flake_ref(&roots, "path:../infra?dir=sub").unwrap(),
+            "path:/work/infra?dir=sub"
+        );
+        assert_eq!(
+            resolve_flake_ref(&roots, "github:NixOS/nixpkgs").unwrap(),
+            "github:NixOS/nixpkgs"
+        );
+        assert_eq!(resolve_flake_ref(&roots, "nixpkgs").unwrap(), "nixpkgs");
+        assert!(resolve_flake_ref(&roots, "/etc/nixos#host").is_err());
+    }
+
+    #[test]
+    fn test_resolve_git_file_and_dir() {
+        let roots = roots();
+        assert_eq!(
+            resolve_flake_ref(&roots, "git+file:///work/infra?ref=main#web").unwrap(),
+            "git+file:///work/infra?ref=main#web"
+        );
+        assert!(resolve_flake_ref(&roots, "git+file:///etc/nixos").is_err());
+        assert!(resolve_flake_ref(&roots, "git+file:///work/app/../../etc").is_err());
+        assert!(resolve_flake_ref(&roots, ".?dir=../../etc").is_err());
+        assert_eq!(
+            resolve_flake_ref(&roots, "path:.?dir=nix").unwrap(),
+            "path:/work/app?dir=nix"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_installable() {
+        let workspace = Workspace::with_roots(roots());
+        let installable: Installable = ".#hello^out".parse().unwrap();
+        assert_eq!(
+            workspace
+                .resolve_installable(installable)
+                .await
+                .unwrap()
+                .to_string(),
+            "/work/app#hello^out"
+        );
+
+        let store_path: Installable = "/nix/store/0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5-hello-2.12.1"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            workspace
+                .resolve_installable(store_path.clone())
+                .await
+                .unwrap(),
+            store_path
+        );
+        assert!(workspace
+            .resolve_installable("/etc/nixos#host".parse().unwrap())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_file_uri_to_path() {
+        assert_eq!(
+            file_uri_to_path("file:///home/me/My%20Project"),
+            Some(PathBuf::from("/home/me/My Project"))
+        );
+        assert_eq!(
+            file_uri_to_path("file://localhost/srv"),
+            Some(PathBuf::from("/srv"))
+        );
+        assert_eq!(file_uri_to_path("file://remote/srv"), None);
+        assert_eq!(file_uri_to_path("https://example.com"), None);
+        assert_eq!(file_uri_to_path("file:///bad%zz"), None);
+    }
+
+    /// Client that declares a single workspace root.
+    struct RootsClient;
+
+    impl ClientHandler for RootsClient {
+        async fn list_roots(
+            &self,
+            _context: RequestContext<RoleClient>,
+        ) -> Result<ListRootsResult, McpError> {
+            Ok(ListRootsResult {
+                roots: vec![Root {
+                    uri: "file:///work/app".to_string(),
+                    name: Some("app".to_string()),
+                }],
+            })
+        }
+
+        fn get_info(&self) -> ClientInfo {
+            ClientInfo {
+                capabilities: ClientCapabilities {
+                    roots: Some(RootsCapabilities {
+                        list_changed: Some(true),
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_roots_requested_from_client() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(NixServer::new().serve(server_io), async {
+            RootsClient.serve(client_io).await
+        });
+        let (server, client) = (server.unwrap(), client.unwrap());
+
+        let workspace = Workspace::for_peer(ClientRoots::new(), server.peer().clone());
+        assert_eq!(
+            workspace.roots().await.unwrap(),
+            vec![PathBuf::from("/work/app")]
+        );
+        assert_eq!(workspace.resolve_flake(".").await.unwrap(), "/work/app");
+        assert!(workspace.resolve_dir(Some("/tmp")).await.is_err());
+
+        client.cancel().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escape_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("app");
+        std::fs::create_dir_all(root.join("nix")).unwrap();
+        std::os::unix::fs::symlink("/", root.join("escape")).unwrap();
+        let roots = vec![root.clone()];
+
+        assert_eq!(
+            resolve_path(&roots, Path::new("nix")).unwrap(),
+            std::fs::canonicalize(root.join("nix")).unwrap()
+        );
+        assert!(resolve_path(&roots, Path::new("escape/etc")).is_err());
+        assert!(resolve_path(&roots, Path::new("escape/does/not/exist")).is_err());
+        assert!(resolve_flake_ref(&roots, "path:./escape/etc/nixos#host").is_err());
+        assert!(resolve_flake_ref(&roots, ".?dir=escape/etc").is_err());
+    }
+
+    /// Client that supports roots but fails to list them.
+    struct FailingRootsClient;
+
+    impl ClientHandler for FailingRootsClient {
+        async fn list_roots(
+            &self,
+            _context: RequestContext<RoleClient>,
+        ) -> Result<ListRootsResult, McpError> {
+            Err(McpError::internal_error("no workspace open", None))
+        }
+
+        fn get_info(&self) -> ClientInfo {
+            RootsClient.get_info()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_roots_request_denies_local_paths() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(NixServer::new().serve(server_io), async {
+            FailingRootsClient.serve(client_io).await
+        });
+        let (server, client) = (server.unwrap(), client.unwrap());
+
+        let workspace = Workspace::for_peer(ClientRoots::new(), server.peer().clone());
+        assert!(workspace.roots().await.is_err());
+        assert!(workspace.resolve_flake("/etc/nixos").await.is_err());
+        assert!(workspace.resolve_dir(None).await.is_err());
+        assert_eq!(
+            workspace
+                .resolve_flake("github:NixOS/nixpkgs")
+                .await
+                .unwrap(),
+            "github:NixOS/nixpkgs"
+        );
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_restricted_to_roots() {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(service) = NixServer::new().serve(server_io).await {
+                let _ = service.waiting().await;
+            }
+        });
+        let client = RootsClient.serve(client_io).await.unwrap();
+
+        let calls = [
+            ("flake_show", serde_json::json!({"flake_ref": "/etc/nixos"})),
+            (
+                "get_build_log",
+                serde_json::json!({"package": "path:/etc#x"}),
+            ),
+            (
+                "diff_derivations",
+                serde_json::json!({"package_a": ".#a", "package_b": "git+file:///etc#b"}),
+            ),
+            ("nix_run", serde_json::json!({"package": "/etc/nixos#app"})),
+            (
+                "nix_develop",
+                serde_json::json!({"flake_ref": "/etc/nixos", "command": "ls"}),
+            ),
+        ];
+        for (tool, arguments) in calls {
+            let error = client
+                .call_tool(rmcp::model::CallToolRequestParam {
+                    name: tool.into(),
+                    arguments: arguments.as_object().cloned(),
+                })
+                .await
+                .unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("outside the client's workspace roots"),
+                "{}: {}",
+                tool,
+                error
+            );
+        }
+
+        client.cancel().await.unwrap();
+    }
+}
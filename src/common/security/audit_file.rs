@@ -0,0 +1,692 @@
+//! Persistent, tamper-evident audit trail.
+//!
+//! [`FileAuditSink`] appends every [`AuditEvent`] to a JSON Lines file. Each
+//! line is an [`AuditRecord`] holding a sequence number, a timestamp, the
+//! event and the SHA-256 hash of the previous record, and is itself hashed.
+//! Editing, reordering or removing a record breaks the chain, which
+//! [`verify`] detects.
+//!
+//! The active file is rotated to `<file>.<first seq>` once it exceeds
+//! [`Rotation::max_bytes`] or gets older than [`Rotation::max_age`]. The
+//! chain continues across rotated files, so they are verified together.
+//!
+//! ```no_run
+//! use onix_mcp::common::security::audit_file::{verify, FileAuditSink, Rotation};
+//! use onix_mcp::common::security::audit_logger;
+//! use std::sync::Arc;
+//!
+//! let sink = FileAuditSink::open("/var/log/onix-mcp/audit.jsonl", Rotation::default())?;
+//! audit_logger().add_sink(Arc::new(sink));
+//!
+//! let report = verify("/var/log/onix-mcp/audit.jsonl".as_ref())?;
+//! println!("{} records intact", report.records);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use super::audit::{AuditEvent, AuditSink, SecurityLevel};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// `prev_hash` of the first record ever written.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the chain, starting at 0
+    pub seq: u64,
+    /// RFC 3339 time the event was recorded
+    pub timestamp: String,
+    pub level: SecurityLevel,
+    /// The serialized [`AuditEvent`]
+    pub event: serde_json::Value,
+    /// `hash` of the previous record ([`GENESIS_HASH`] for the first)
+    pub prev_hash: String,
+    /// SHA-256 over all other fields, hex encoded
+    pub hash: String,
+}
+
+/// The fields covered by [`AuditRecord::hash`], in record order.
+#[derive(Serialize)]
+struct HashedFields<'a> {
+    seq: u64,
+    timestamp: &'a str,
+    level: SecurityLevel,
+    event: &'a serde_json::Value,
+    prev_hash: &'a str,
+}
+
+impl AuditRecord {
+    fn new(
+        seq: u64,
+        timestamp: DateTime<Utc>,
+        level: SecurityLevel,
+        event: serde_json::Value,
+        prev_hash: String,
+    ) -> Self {
+        let mut record = Self {
+            seq,
+            timestamp: timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            level,
+            event,
+            prev_hash,
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+        record
+    }
+
+    /// Hash of this record's contents, to compare with [`hash`](Self::hash).
+    pub fn compute_hash(&self) -> String {
+        let fields = HashedFields {
+            seq: self.seq,
+            timestamp: &self.timestamp,
+            level: self.level,
+            event: &self.event,
+            prev_hash: &self.prev_hash,
+        };
+        let bytes = serde_json::to_vec(&fields).expect("audit record serializes");
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    fn recorded_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// When the active audit file is rotated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    /// Rotate before the file would exceed this size
+    pub max_bytes: u64,
+    /// Rotate once the first record in the file is this old
+    pub max_age: Duration,
+    /// Rotated files to keep, deleting the oldest; 0 keeps all
+    pub keep_files: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            keep_files: 0,
+        }
+    }
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+    first_seq: u64,
+    opened: DateTime<Utc>,
+    next_seq: u64,
+    last_hash: String,
+}
+
+/// [`AuditSink`] appending hash-chained records to a rotating JSONL file.
+pub struct FileAuditSink {
+    path: PathBuf,
+    rotation: Rotation,
+    active: Mutex<ActiveFile>,
+}
+
+impl FileAuditSink {
+    /// Open `path` for appending, continuing the chain of any existing records.
+    ///
+    /// The parent directory is created if missing. New files are only
+    /// readable by the current user. A final record torn by a crash is
+    /// dropped, or completed if it is intact up to its newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or file cannot be created or the
+    /// existing log cannot be read.
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        repair_tail(&path)?;
+        let now = Utc::now();
+        let records = read_records(&path)?;
+        let (first_seq, opened, next_seq, last_hash) = match (records.first(), records.last()) {
+            (Some(first), Some(last)) => (
+                first.seq,
+                first.recorded_at().unwrap_or(now),
+                last.seq + 1,
+                last.hash.clone(),
+            ),
+            _ => {
+                // An empty active file continues from the newest rotated one
+                let previous = match rotated_files(&path)?.last() {
+                    Some((_, rotated)) => read_records(rotated)?.pop(),
+                    None => None,
+                };
+                let (next_seq, last_hash) = previous
+                    .map(|r| (r.seq + 1, r.hash))
+                    .unwrap_or_else(|| (0, GENESIS_HASH.to_string()));
+                (next_seq, now, next_seq, last_hash)
+            }
+        };
+
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            rotation,
+            active: Mutex::new(ActiveFile {
+                file,
+                size,
+                first_seq,
+                opened,
+                next_seq,
+                last_hash,
+            }),
+        })
+    }
+
+    /// Path of the active file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, level: SecurityLevel, event: &AuditEvent) -> io::Result<()> {
+        let event = serde_json::to_value(event).map_err(io::Error::other)?;
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+
+        let now = Utc::now();
+        let record = AuditRecord::new(active.next_seq, now, level, event, active.last_hash.clone());
+        let mut line = serde_json::to_vec(&record).map_err(io::Error::other)?;
+        line.push(b'\n');
+
+        let too_big = active.size + line.len() as u64 > self.rotation.max_bytes;
+        let too_old = (now - active.opened)
+            .to_std()
+            .is_ok_and(|age| age >= self.rotation.max_age);
+        if active.size > 0 && (too_big || too_old) {
+            self.rotate(&mut active, now)?;
+        }
+
+        active.file.write_all(&line)?;
+        active.file.flush()?;
+        active.size += line.len() as u64;
+        active.next_seq = record.seq + 1;
+        active.last_hash = record.hash;
+        Ok(())
+    }
+
+    /// Move the active file aside and start a new one at the next record.
+    fn rotate(&self, active: &mut ActiveFile, now: DateTime<Utc>) -> io::Result<()> {
+        active.file.sync_all()?;
+        std::fs::rename(&self.path, rotated_path(&self.path, active.first_seq))?;
+
+        active.file = open_append(&self.path)?;
+        active.size = 0;
+        active.first_seq = active.next_seq;
+        active.opened = now;
+
+        if self.rotation.keep_files > 0 {
+            let rotated = rotated_files(&self.path)?;
+            let excess = rotated.len().saturating_sub(self.rotation.keep_files);
+            for (_, old) in &rotated[..excess] {
+                std::fs::remove_file(old)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn write(&self, level: SecurityLevel, event: &AuditEvent) {
+        if let Err(e) = self.append(level, event) {
+            tracing::error!(
+                path = %self.path.display(),
+                error = %e,
+                "Failed to write audit record"
+            );
+        }
+    }
+}
+
+/// Result of a successful [`verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyReport {
+    /// Files checked, rotated ones first
+    pub files: Vec<PathBuf>,
+    /// Records checked
+    pub records: u64,
+    /// Sequence number of the first record, if any
+    pub first_seq: Option<u64>,
+    /// Hash of the last record, to compare with an externally kept copy
+    pub last_hash: Option<String>,
+    /// Whether the chain starts at the first record ever written
+    ///
+    /// `false` when older rotated files have been deleted.
+    pub complete: bool,
+}
+
+/// Ways an audit log can fail [`verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    /// A file could not be read
+    Read { path: PathBuf, reason: String },
+    /// A line is not a valid record
+    Malformed {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// A record's contents do not match its hash
+    HashMismatch {
+        path: PathBuf,
+        line: usize,
+        seq: u64,
+    },
+    /// A record does not follow the one before it
+    BrokenChain {
+        path: PathBuf,
+        line: usize,
+        seq: u64,
+        expected_seq: u64,
+    },
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Read { path, reason } => {
+                write!(f, "Failed to read {}: {}", path.display(), reason)
+            }
+            VerifyError::Malformed { path, line, reason } => {
+                write!(
+                    f,
+                    "{}:{}: malformed audit record: {}",
+                    path.display(),
+                    line,
+                    reason
+                )
+            }
+            VerifyError::HashMismatch { path, line, seq } => {
+                write!(
+                    f,
+                    "{}:{}: record {} does not match its hash (modified)",
+                    path.display(),
+                    line,
+                    seq
+                )
+            }
+            VerifyError::BrokenChain {
+                path,
+                line,
+                seq,
+                expected_seq,
+            } => {
+                write!(
+                    f,
+                    "{}:{}: record {} does not follow record {} (inserted, removed or reordered)",
+                    path.display(),
+                    line,
+                    seq,
+                    expected_seq.saturating_sub(1)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Check the hash chain of the audit log at `path` and its rotated files.
+///
+/// Every record must match its own hash and reference the hash and
+/// sequence number of the record before it. Deleting the newest records
+/// cannot be detected from the log alone; compare
+/// [`VerifyReport::last_hash`] with a copy kept elsewhere for that.
+///
+/// # Errors
+///
+/// Returns the first problem found.
+pub fn verify(path: &Path) -> Result<VerifyReport, VerifyError> {
+    let read_error = |path: &Path, e: io::Error| VerifyError::Read {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+
+    let files = log_files(path).map_err(|e| read_error(path, e))?;
+
+    let mut report = VerifyReport {
+        files: files.clone(),
+        records: 0,
+        first_seq: None,
+        last_hash: None,
+        complete: true,
+    };
+    let mut previous: Option<(u64, String)> = None;
+
+    for file in &files {
+        let content = std::fs::read_to_string(file).map_err(|e| read_error(file, e))?;
+        for (index, raw) in content.lines().enumerate() {
+            let line = index + 1;
+            let record: AuditRecord =
+                serde_json::from_str(raw).map_err(|e| VerifyError::Malformed {
+                    path: file.clone(),
+                    line,
+                    reason: e.to_string(),
+                })?;
+
+            if record.compute_hash() != record.hash {
+                return Err(VerifyError::HashMismatch {
+                    path: file.clone(),
+                    line,
+                    seq: record.seq,
+                });
+            }
+
+            let (expected_seq, expected_hash) = match &previous {
+                Some((seq, hash)) => (seq + 1, hash.as_str()),
+                None if record.seq == 0 => (0, GENESIS_HASH),
+                None => {
+                    // Older files were pruned; the chain is anchored here
+                    report.complete = false;
+                    (record.seq, record.prev_hash.as_str())
+                }
+            };
+            if record.seq != expected_seq || record.prev_hash != expected_hash {
+                return Err(VerifyError::BrokenChain {
+                    path: file.clone(),
+                    line,
+                    seq: record.seq,
+                    expected_seq,
+                });
+            }
+
+            report.first_seq.get_or_insert(record.seq);
+            report.records += 1;
+            previous = Some((record.seq, record.hash));
+        }
+    }
+
+    report.last_hash = previous.map(|(_, hash)| hash);
+    Ok(report)
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Make the file at `path` end with a complete line.
+///
+/// Records are appended with their newline in one write, so a final line
+/// without one was interrupted; appending after it would corrupt the next
+/// record too. The line is completed if it parses as a record and cut off
+/// otherwise.
+fn repair_tail(path: &Path) -> io::Result<()> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if content.last().is_none_or(|&b| b == b'\n') {
+        return Ok(());
+    }
+
+    let start = content
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let file = OpenOptions::new().append(true).open(path)?;
+    if serde_json::from_slice::<AuditRecord>(&content[start..]).is_ok() {
+        tracing::warn!(path = %path.display(), "Completing audit record missing its newline");
+        (&file).write_all(b"\n")?;
+    } else {
+        tracing::warn!(
+            path = %path.display(),
+            bytes = content.len() - start,
+            "Dropping torn audit record"
+        );
+        file.set_len(start as u64)?;
+    }
+    file.sync_all()
+}
+
+/// The rotated files of the audit log at `path`, oldest first, followed by
+/// `path` itself if it exists.
+///
+/// # Errors
+///
+/// Returns an error if the log directory cannot be listed.
+pub fn log_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = rotated_files(path)?.into_iter().map(|(_, p)| p).collect();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    Ok(files)
+}
+
+/// Records in `path`, skipping lines that do not parse (such as a torn last
+/// line); a missing file has none.
+///
+/// Hashes are not checked; use [`verify`] for that.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but cannot be read.
+pub fn read_records(path: &Path) -> io::Result<Vec<AuditRecord>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn rotated_path(path: &Path, first_seq: u64) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", first_seq));
+    path.with_file_name(name)
+}
+
+/// Rotated files of `path`, ordered by their first sequence number.
+fn rotated_files(path: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let prefix = format!("{}.", name);
+
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut rotated = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let seq = file_name
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|suffix| suffix.parse::<u64>().ok());
+        if let Some(seq) = seq {
+            rotated.push((seq, entry.path()));
+        }
+    }
+    rotated.sort();
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::security::AuditLogger;
+    use std::sync::Arc;
+
+    /// Log file in a fresh directory, removed when the guard drops.
+    fn temp_log() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        (dir, path)
+    }
+
+    fn event(tool: &str) -> AuditEvent {
+        AuditEvent::ToolInvoked {
+            tool_name: tool.to_string(),
+            parameters: Some(serde_json::json!({"machine": "web01"})),
+            success: true,
+            error: None,
+            duration_ms: 5,
+        }
+    }
+
+    #[test]
+    fn test_logger_writes_chain() {
+        let (_dir, path) = temp_log();
+        let logger = AuditLogger::new();
+        logger.add_sink(Arc::new(
+            FileAuditSink::open(&path, Rotation::default()).unwrap(),
+        ));
+
+        logger.log_tool_invocation("clan_machine_install", None, true, None, 10);
+        logger.log_dangerous_operation("clan_machine_install", true, "confirmed");
+
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].prev_hash, GENESIS_HASH);
+        assert_eq!(records[1].prev_hash, records[0].hash);
+        assert_eq!(records[0].event["tool_name"], "clan_machine_install");
+
+        let report = verify(&path).unwrap();
+        assert_eq!(report.records, 2);
+        assert!(report.complete);
+        assert_eq!(report.last_hash.as_deref(), Some(records[1].hash.as_str()));
+    }
+
+    #[test]
+    fn test_tampering_detected() {
+        let (_dir, path) = temp_log();
+        let sink = FileAuditSink::open(&path, Rotation::default()).unwrap();
+        for tool in ["nix_build", "clan_machine_install", "nix_eval"] {
+            sink.write(SecurityLevel::Info, &event(tool));
+        }
+        let original = std::fs::read_to_string(&path).unwrap();
+
+        // Edited record
+        std::fs::write(&path, original.replace("web01", "web02")).unwrap();
+        assert!(matches!(
+            verify(&path),
+            Err(VerifyError::HashMismatch { line: 1, .. })
+        ));
+
+        // Removed record
+        let lines: Vec<&str> = original.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(matches!(
+            verify(&path),
+            Err(VerifyError::BrokenChain {
+                line: 2,
+                seq: 2,
+                expected_seq: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_torn_tail_repaired_on_open() {
+        let (_dir, path) = temp_log();
+        let sink = FileAuditSink::open(&path, Rotation::default()).unwrap();
+        for tool in ["nix_build", "nix_eval"] {
+            sink.write(SecurityLevel::Info, &event(tool));
+        }
+        drop(sink);
+        let complete = std::fs::read_to_string(&path).unwrap();
+
+        // Crash halfway through a record: it is dropped
+        let (_, last) = complete.trim_end().rsplit_once('\n').unwrap();
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&last.as_bytes()[..last.len() / 2]).unwrap();
+        drop(torn);
+        let sink = FileAuditSink::open(&path, Rotation::default()).unwrap();
+        sink.write(SecurityLevel::Info, &event("clan_machine_install"));
+        drop(sink);
+        let report = verify(&path).unwrap();
+        assert_eq!(report.records, 3);
+
+        // Crash right before the newline: the record is kept
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.trim_end()).unwrap();
+        let sink = FileAuditSink::open(&path, Rotation::default()).unwrap();
+        sink.write(SecurityLevel::Info, &event("nix_eval"));
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[2].event["tool_name"], "clan_machine_install");
+        assert_eq!(verify(&path).unwrap().records, 4);
+    }
+
+    #[test]
+    fn test_rotation_and_reopen_continue_chain() {
+        let (_dir, path) = temp_log();
+        let rotation = Rotation {
+            max_bytes: 600,
+            ..Rotation::default()
+        };
+
+        let sink = FileAuditSink::open(&path, rotation).unwrap();
+        for _ in 0..5 {
+            sink.write(SecurityLevel::Info, &event("nix_build"));
+        }
+        drop(sink);
+
+        // A restarted server picks up where the previous one stopped
+        let sink = FileAuditSink::open(&path, rotation).unwrap();
+        sink.write(SecurityLevel::Warning, &event("clan_machine_install"));
+
+        let rotated = rotated_files(&path).unwrap();
+        assert!(rotated.len() >= 2);
+        assert_eq!(rotated[0].0, 0);
+
+        let report = verify(&path).unwrap();
+        assert_eq!(report.records, 6);
+        assert_eq!(report.files.len(), rotated.len() + 1);
+        assert!(report.complete);
+
+        // Pruned history still verifies, but is reported as incomplete
+        let pruned = FileAuditSink::open(
+            &path,
+            Rotation {
+                keep_files: 1,
+                ..rotation
+            },
+        )
+        .unwrap();
+        for _ in 0..3 {
+            pruned.write(SecurityLevel::Info, &event("nix_eval"));
+        }
+        assert_eq!(rotated_files(&path).unwrap().len(), 1);
+        let report = verify(&path).unwrap();
+        assert!(!report.complete);
+        assert!(report.first_seq.unwrap() > 0);
+    }
+}
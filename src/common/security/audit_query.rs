@@ -0,0 +1,377 @@
+//! Querying recorded audit events.
+//!
+//! Events are read from the persistent audit trail when `[audit] file` is
+//! configured, and otherwise from [`audit_history`], an in-memory buffer of
+//! the most recent events logged through the global
+//! [`audit_logger`](super::audit_logger).
+//!
+//! ```
+//! use onix_mcp::common::security::audit_query::{audit_history, query, AuditFilter, AuditSource};
+//! use onix_mcp::common::security::audit_logger;
+//!
+//! audit_logger().log_tool_invocation("run_in_shell", None, false, Some("exit 1".into()), 120);
+//!
+//! let filter = AuditFilter {
+//!     tool: Some("run_in_shell".to_string()),
+//!     success: Some(false),
+//!     ..Default::default()
+//! };
+//! let result = query(&AuditSource::Memory(audit_history()), &filter, 10).unwrap();
+//! assert_eq!(result.events[0].duration_ms, Some(120));
+//! ```
+
+use super::audit::{AuditEvent, AuditSink, SecurityLevel};
+use super::audit_file::{self, AuditRecord};
+use chrono::{DateTime, SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use rmcp::schemars;
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Events kept by [`audit_history`].
+pub const HISTORY_CAPACITY: usize = 1000;
+
+static HISTORY: Lazy<Arc<AuditHistory>> =
+    Lazy::new(|| Arc::new(AuditHistory::new(HISTORY_CAPACITY)));
+
+/// In-memory history of the events logged through the global audit logger.
+pub fn audit_history() -> Arc<AuditHistory> {
+    Arc::clone(&HISTORY)
+}
+
+/// A recorded audit event.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub level: SecurityLevel,
+    pub event: AuditEvent,
+}
+
+impl AuditEntry {
+    /// Entry for a record of the audit trail, if its event is still understood.
+    pub fn from_record(record: AuditRecord) -> Option<Self> {
+        Some(Self {
+            timestamp: DateTime::parse_from_rfc3339(&record.timestamp)
+                .ok()?
+                .with_timezone(&Utc),
+            level: record.level,
+            event: serde_json::from_value(record.event).ok()?,
+        })
+    }
+}
+
+/// [`AuditSink`] keeping the most recent events in memory.
+pub struct AuditHistory {
+    capacity: usize,
+    entries: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditHistory {
+    /// Create a history holding up to `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Recorded events, oldest first.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl AuditSink for AuditHistory {
+    fn write(&self, level: SecurityLevel, event: &AuditEvent) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(AuditEntry {
+            timestamp: Utc::now(),
+            level,
+            event: event.clone(),
+        });
+    }
+}
+
+/// Where [`query`] reads events from.
+pub enum AuditSource {
+    /// The audit trail at this path, including rotated files
+    File(PathBuf),
+    /// An in-memory history
+    Memory(Arc<AuditHistory>),
+}
+
+impl AuditSource {
+    fn name(&self) -> &'static str {
+        match self {
+            AuditSource::File(_) => "file",
+            AuditSource::Memory(_) => "memory",
+        }
+    }
+}
+
+/// Criteria an event must meet; unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    /// Tool the event is about (see [`AuditEvent::tool_name`])
+    pub tool: Option<String>,
+    /// Earliest time, inclusive
+    pub since: Option<DateTime<Utc>>,
+    /// Latest time, inclusive
+    pub until: Option<DateTime<Utc>>,
+    /// Outcome; events without one (see [`AuditEvent::success`]) never match
+    pub success: Option<bool>,
+    /// Minimum security level
+    pub min_level: Option<SecurityLevel>,
+}
+
+impl AuditFilter {
+    /// Whether `entry` meets every criterion.
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.tool
+            .as_deref()
+            .is_none_or(|tool| entry.event.tool_name() == Some(tool))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self
+                .success
+                .is_none_or(|success| entry.event.success() == Some(success))
+            && self.min_level.is_none_or(|level| entry.level >= level)
+    }
+}
+
+/// Parse a point in time: an RFC 3339 timestamp, or an age relative to `now`
+/// such as `"90s"`, `"30m"`, `"24h"` or `"7d"`.
+///
+/// # Errors
+///
+/// Returns a message describing the expected formats.
+pub fn parse_time(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let invalid = || {
+        format!(
+            "invalid time '{}' (expected an RFC 3339 timestamp or an age such as 30m, 24h, 7d)",
+            value
+        )
+    };
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let age = match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    now.checked_sub_signed(age).ok_or_else(invalid)
+}
+
+/// An event returned by [`query`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct AuditEventSummary {
+    /// RFC 3339 time the event was recorded
+    pub timestamp: String,
+    /// Security level (info, warning, error, critical)
+    pub level: String,
+    /// Event type (e.g., "ToolInvoked", "DangerousOperation")
+    pub event_type: String,
+    /// Tool the event is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Whether the call succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    /// How long the call ran in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// The full event, including parameters and errors
+    pub event: serde_json::Value,
+}
+
+impl From<&AuditEntry> for AuditEventSummary {
+    fn from(entry: &AuditEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            level: entry.level.as_str().to_string(),
+            event_type: entry.event.event_type().to_string(),
+            tool: entry.event.tool_name().map(str::to_string),
+            success: entry.event.success(),
+            duration_ms: entry.event.duration_ms(),
+            event: serde_json::to_value(&entry.event).unwrap_or_default(),
+        }
+    }
+}
+
+/// Structured result of an audit query.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct AuditQueryResult {
+    /// Where events were read from: "file" (the audit trail) or "memory"
+    pub source: String,
+    /// Number of matching events before applying the limit
+    pub matched: usize,
+    /// Matching events, newest first
+    pub events: Vec<AuditEventSummary>,
+}
+
+/// Events from `source` matching `filter`, newest first, at most `limit`.
+///
+/// # Errors
+///
+/// Returns an error if the audit trail cannot be read.
+pub fn query(
+    source: &AuditSource,
+    filter: &AuditFilter,
+    limit: usize,
+) -> io::Result<AuditQueryResult> {
+    let mut matching: Vec<AuditEntry> = match source {
+        AuditSource::Memory(history) => history
+            .entries()
+            .into_iter()
+            .filter(|entry| filter.matches(entry))
+            .collect(),
+        AuditSource::File(path) => {
+            let mut matching = Vec::new();
+            for file in audit_file::log_files(path)? {
+                matching.extend(
+                    audit_file::read_records(&file)?
+                        .into_iter()
+                        .filter_map(AuditEntry::from_record)
+                        .filter(|entry| filter.matches(entry)),
+                );
+            }
+            matching
+        }
+    };
+
+    let matched = matching.len();
+    matching.reverse();
+    matching.truncate(limit);
+
+    Ok(AuditQueryResult {
+        source: source.name().to_string(),
+        matched,
+        events: matching.iter().map(AuditEventSummary::from).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::security::audit_file::{FileAuditSink, Rotation};
+    use crate::common::security::AuditLogger;
+
+    fn logger_with_history() -> (AuditLogger, Arc<AuditHistory>) {
+        let logger = AuditLogger::new();
+        let history = Arc::new(AuditHistory::new(3));
+        logger.add_sink(history.clone());
+        (logger, history)
+    }
+
+    #[test]
+    fn test_filter_by_tool_success_and_level() {
+        let (logger, history) = logger_with_history();
+        logger.log_tool_invocation("run_in_shell", None, true, None, 10);
+        logger.log_tool_invocation("run_in_shell", None, false, Some("exit 1".into()), 20);
+        logger.log_timeout("clan_machine_update", 600);
+
+        let source = AuditSource::Memory(history);
+        let failed = AuditFilter {
+            tool: Some("run_in_shell".to_string()),
+            success: Some(false),
+            ..Default::default()
+        };
+        let result = query(&source, &failed, 10).unwrap();
+        assert_eq!(result.matched, 1);
+        assert_eq!(result.events[0].duration_ms, Some(20));
+        assert_eq!(result.events[0].level, "warning");
+
+        let warnings = AuditFilter {
+            min_level: Some(SecurityLevel::Warning),
+            ..Default::default()
+        };
+        let result = query(&source, &warnings, 10).unwrap();
+        assert_eq!(result.matched, 2);
+        // Newest first
+        assert_eq!(result.events[0].event_type, "OperationTimeout");
+        assert_eq!(
+            result.events[0].tool.as_deref(),
+            Some("clan_machine_update")
+        );
+
+        let limited = query(&source, &AuditFilter::default(), 1).unwrap();
+        assert_eq!(limited.matched, 3);
+        assert_eq!(limited.events.len(), 1);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let (logger, history) = logger_with_history();
+        for duration in 0..5 {
+            logger.log_tool_invocation("nix_eval", None, true, None, duration);
+        }
+        let entries = history.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].event.duration_ms(), Some(2));
+    }
+
+    #[test]
+    fn test_time_range_and_file_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+
+        let logger = AuditLogger::new();
+        logger.add_sink(Arc::new(
+            FileAuditSink::open(&path, Rotation::default()).unwrap(),
+        ));
+        logger.log_tool_invocation("clan_machine_install", None, true, None, 90_000);
+
+        let now = Utc::now();
+        let source = AuditSource::File(path);
+        let recent = AuditFilter {
+            since: Some(parse_time("1h", now).unwrap()),
+            ..Default::default()
+        };
+        let result = query(&source, &recent, 10).unwrap();
+        assert_eq!(result.source, "file");
+        assert_eq!(result.matched, 1);
+        assert_eq!(result.events[0].duration_ms, Some(90_000));
+
+        let future = AuditFilter {
+            since: Some(now + chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(query(&source, &future, 10).unwrap().matched, 0);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = Utc::now();
+        assert_eq!(
+            parse_time("24h", now).unwrap(),
+            now - chrono::Duration::hours(24)
+        );
+        assert_eq!(
+            parse_time("2025-12-02T10:30:00Z", now)
+                .unwrap()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2025-12-02T10:30:00Z"
+        );
+        assert!(parse_time("yesterday", now).is_err());
+        assert!(parse_time("", now).is_err());
+        assert!(parse_time("5w", now).is_err());
+    }
+}
@@ -6,12 +6,13 @@ use std::time::Instant;
 
 /// Convert ValidationError to McpError
 pub fn validation_error_to_mcp(err: ValidationError) -> McpError {
-    McpError::invalid_params(
-        err.to_string(),
-        Some(json!({
-            "validation_error": format!("{:?}", err),
-        })),
-    )
+    let mut data = json!({
+        "validation_error": format!("{:?}", err),
+    });
+    if let ValidationError::UnsafeNix { diagnostics, .. } = &err {
+        data["diagnostics"] = json!(diagnostics);
+    }
+    McpError::invalid_params(err.to_string(), Some(data))
 }
 
 /// Audit tool execution with timing
@@ -74,8 +75,14 @@ where
 }
 
 /// Execute with cancellation support
-#[allow(dead_code)]
+///
+/// When `ct` is cancelled the future returned by `f` is dropped, which
+/// terminates any child process groups it spawned (see
+/// [`process_group`](crate::common::process_group)), and a `ToolCancelled`
+/// audit event is recorded.
 pub async fn with_cancellation<F, Fut, T>(
+    audit: &AuditLogger,
+    operation_name: &str,
     ct: &tokio_util::sync::CancellationToken,
     f: F,
 ) -> Result<T, McpError>
@@ -83,12 +90,16 @@ where
     F: FnOnce() -> Fut,
     Fut: std::future::Future<Output = Result<T, McpError>>,
 {
+    let start = Instant::now();
     tokio::select! {
         result = f() => result,
         _ = ct.cancelled() => {
+            audit.log_tool_cancelled(operation_name, start.elapsed().as_millis() as u64);
             Err(McpError::internal_error(
                 "Operation cancelled by client".to_string(),
-                None,
+                Some(json!({
+                    "operation": operation_name,
+                })),
             ))
         }
     }
@@ -110,7 +121,7 @@ macro_rules! secure_tool {
         };
 
         audit_tool_execution($audit, $tool_name, Some($params), || async {
-            with_cancellation($ct, || async {
+            with_cancellation($audit, $tool_name, $ct, || async {
                 with_timeout($audit, $tool_name, $timeout, || async { $body }).await
             })
             .await
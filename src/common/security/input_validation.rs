@@ -1,6 +1,9 @@
-use once_cell::sync::Lazy;
 /// Input validation for Nix MCP server
 /// Prevents command injection, path traversal, and other security vulnerabilities
+use super::nix_analysis::{analyze_nix, NixDiagnostic};
+use crate::common::flake_ref::FlakeRef;
+use crate::common::installable::Installable;
+use once_cell::sync::Lazy;
 use regex::Regex;
 use std::path::PathBuf;
 
@@ -32,6 +35,10 @@ pub enum ValidationError {
         field: String,
         reason: String,
     },
+    UnsafeNix {
+        field: String,
+        diagnostics: Vec<NixDiagnostic>,
+    },
 }
 
 impl std::fmt::Display for ValidationError {
@@ -75,6 +82,13 @@ impl std::fmt::Display for ValidationError {
             ValidationError::Suspicious { field, reason } => {
                 write!(f, "Field '{}' is suspicious: {}", field, reason)
             }
+            ValidationError::UnsafeNix { field, diagnostics } => {
+                write!(f, "Field '{}' contains unsafe Nix code", field)?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -83,7 +97,6 @@ impl std::error::Error for ValidationError {}
 
 /// Maximum lengths for various input types
 const MAX_PACKAGE_NAME_LEN: usize = 255;
-const MAX_FLAKE_REF_LEN: usize = 1000;
 const MAX_PATH_LEN: usize = 4096;
 const MAX_EXPRESSION_LEN: usize = 10000;
 const MAX_COMMAND_LEN: usize = 1000;
@@ -92,36 +105,8 @@ const MAX_COMMAND_LEN: usize = 1000;
 static PACKAGE_NAME_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9_\-\.]*$").unwrap());
 
-static FLAKE_REF_PATTERN: Lazy<Regex> = Lazy::new(|| {
-    // Matches:
-    // - Simple registry refs: nixpkgs
-    // - Registry refs with fragments: nixpkgs#hello, github:owner/repo
-    // - URLs: https://..., git+https://...
-    // - Paths: ., ./, ../, /absolute/path
-    // Note: This is a permissive regex - shell metacharacters are blocked separately
-    Regex::new(r"^[a-zA-Z0-9_\-\.\+/:@#]+$").unwrap()
-});
-
 static MACHINE_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_\-]+$").unwrap());
 
-/// Dangerous patterns that should never appear in Nix expressions
-static DANGEROUS_PATTERNS: &[&str] = &[
-    "__noChroot",
-    "allowSubstitutes = false",
-    "trustedUsers",
-    "allowed-users",
-    "builders",
-    "substituters",
-    "trusted-substituters",
-    "system-features",
-    "builtins.exec",
-];
-
-/// Shell metacharacters that indicate potential command injection
-static SHELL_METACHARACTERS: &[char] = &[
-    ';', '|', '&', '$', '`', '\n', '\r', '>', '<', '(', ')', '{', '}', '[', ']', '!', '*', '?',
-];
-
 /// Validate package name for nixpkgs
 ///
 /// Ensures package names:
@@ -176,56 +161,21 @@ pub fn validate_package_name(name: &str) -> Result<(), ValidationError> {
 
 /// Validate flake reference
 ///
-/// Supports formats:
-/// - Relative paths: ".", "..", "./path"
-/// - Absolute paths: "/path/to/flake"
-/// - Git URLs: "github:owner/repo", "git+https://..."
-/// - Flake registry: "nixpkgs", "nixpkgs/nixos-unstable"
-pub fn validate_flake_ref(flake_ref: &str) -> Result<(), ValidationError> {
-    // Check empty
-    if flake_ref.is_empty() {
-        return Err(ValidationError::Empty {
-            field: "flake_ref".to_string(),
-        });
-    }
-
-    // Check length
-    if flake_ref.len() > MAX_FLAKE_REF_LEN {
-        return Err(ValidationError::TooLong {
-            field: "flake_ref".to_string(),
-            max_length: MAX_FLAKE_REF_LEN,
-            actual: flake_ref.len(),
-        });
-    }
-
-    // Check pattern
-    if !FLAKE_REF_PATTERN.is_match(flake_ref) {
-        return Err(ValidationError::InvalidFormat {
-            field: "flake_ref".to_string(),
-            expected: "valid flake reference (path, URL, or registry)".to_string(),
-            got: flake_ref.to_string(),
-        });
-    }
-
-    // Check for null bytes (command injection via C strings)
-    if flake_ref.contains('\0') {
-        return Err(ValidationError::Suspicious {
-            field: "flake_ref".to_string(),
-            reason: "contains null byte".to_string(),
-        });
-    }
-
-    // Check for shell metacharacters
-    for &metachar in SHELL_METACHARACTERS {
-        if flake_ref.contains(metachar) {
-            return Err(ValidationError::Suspicious {
-                field: "flake_ref".to_string(),
-                reason: format!("contains shell metacharacter: '{}'", metachar),
-            });
-        }
-    }
+/// Parses every documented flake reference form (registry names, paths,
+/// `git+*`, `github:`/`gitlab:`/`sourcehut:`, tarballs and files) with
+/// `?ref=`/`?rev=`/`?dir=` parameters and `#attr` fragments; see
+/// [`FlakeRef`]. Returns the parsed reference, whose `Display` is canonical.
+pub fn validate_flake_ref(flake_ref: &str) -> Result<FlakeRef, ValidationError> {
+    FlakeRef::parse(flake_ref)
+}
 
-    Ok(())
+/// Validate an installable
+///
+/// Accepts flake outputs (`nixpkgs#hello`, optionally `^out,dev`), store
+/// paths, and `.drv` paths with an output selector (`^out`, `^*`); see
+/// [`Installable`].
+pub fn validate_installable(installable: &str) -> Result<Installable, ValidationError> {
+    Installable::parse(installable)
 }
 
 /// Validate filesystem path
@@ -298,9 +248,10 @@ pub fn validate_path(path: &str) -> Result<PathBuf, ValidationError> {
 /// Validate Nix expression for evaluation
 ///
 /// Checks for:
-/// - Dangerous patterns (builtins that bypass sandboxing)
-/// - Excessive length
-/// - Shell injection attempts
+/// - Excessive length and null bytes
+/// - Dangerous constructs found by [`analyze_nix`]: builtins running
+///   external code, reads of absolute paths, import from derivation, sandbox
+///   settings and shell command substitution, with their positions
 pub fn validate_nix_expression(expr: &str) -> Result<(), ValidationError> {
     // Check empty
     if expr.is_empty() {
@@ -318,29 +269,19 @@ pub fn validate_nix_expression(expr: &str) -> Result<(), ValidationError> {
         });
     }
 
-    // Check for dangerous patterns
-    for &pattern in DANGEROUS_PATTERNS {
-        if expr.contains(pattern) {
-            return Err(ValidationError::Suspicious {
-                field: "expression".to_string(),
-                reason: format!("contains dangerous pattern: {}", pattern),
-            });
-        }
-    }
-
-    // Check for shell command injection attempts
-    if expr.contains("$(") || expr.contains("`") {
+    // Check for null bytes
+    if expr.contains('\0') {
         return Err(ValidationError::Suspicious {
             field: "expression".to_string(),
-            reason: "contains shell command substitution".to_string(),
+            reason: "contains null byte".to_string(),
         });
     }
 
-    // Check for null bytes
-    if expr.contains('\0') {
-        return Err(ValidationError::Suspicious {
+    let diagnostics = analyze_nix(expr);
+    if !diagnostics.is_empty() {
+        return Err(ValidationError::UnsafeNix {
             field: "expression".to_string(),
-            reason: "contains null byte".to_string(),
+            diagnostics,
         });
     }
 
@@ -529,6 +470,11 @@ mod tests {
         assert!(validate_nix_expression("").is_err());
         assert!(validate_nix_expression("builtins.exec [\"rm\" \"-rf\" \"/\"]").is_err());
         assert!(validate_nix_expression("$(rm -rf /)").is_err());
+
+        // Diagnostics carry positions
+        let err = validate_nix_expression("let b = builtins;\nin b.\"exec\" [ ]").unwrap_err();
+        assert!(err.to_string().contains("line 2, column 4"), "{}", err);
+        assert!(validate_nix_expression("\"`whoami` is quoted\"").is_ok());
     }
 
     #[test]
@@ -682,24 +628,38 @@ mod proptests {
     // ========== validate_nix_expression property tests ==========
 
     proptest! {
-        /// Test that dangerous patterns in Nix expressions are rejected
+        /// Test that dangerous settings and builtins in Nix expressions are rejected
         #[test]
         fn prop_nix_expression_dangerous_patterns_reject(
-            pattern in prop::sample::select(vec![
-                "__noChroot",
+            binding in prop::sample::select(vec![
+                "__noChroot = true",
                 "allowSubstitutes = false",
-                "trustedUsers",
-                "allowed-users",
-                "builders",
-                "substituters",
-                "trusted-substituters",
-                "system-features",
-                "builtins.exec",
+                "trustedUsers = [ \"me\" ]",
+                "allowed-users = [ \"*\" ]",
+                "builders = \"ssh://host\"",
+                "substituters = [ ]",
+                "trusted-substituters = [ ]",
+                "system-features = [ ]",
+                "run = builtins.exec [ \"id\" ]",
             ])
         ) {
-            let expr = format!("{{ {} = true; }}", pattern);
+            let expr = format!("{{ {}; }}", binding);
             prop_assert!(validate_nix_expression(&expr).is_err(),
-                "Dangerous pattern not rejected: {}", pattern);
+                "Dangerous binding not rejected: {}", binding);
+        }
+
+        /// Test that backticks and `$(` are allowed inside strings
+        #[test]
+        fn prop_nix_expression_shell_syntax_in_strings_accept(
+            base in "[a-zA-Z0-9_ ]*",
+            cmd in "[a-z]{1,10}"
+        ) {
+            let expr1 = format!("\"{} $({}) end\"", base, cmd);
+            let expr2 = format!("''{} `{}` end''", base, cmd);
+            prop_assert!(validate_nix_expression(&expr1).is_ok(),
+                "String containing $() rejected: {}", expr1);
+            prop_assert!(validate_nix_expression(&expr2).is_ok(),
+                "String containing backticks rejected: {}", expr2);
         }
 
         /// Test that shell command substitution is rejected
@@ -5,9 +5,15 @@
 //!
 //! # Modules
 //!
-//! - [`audit`] - Security event logging and audit trail management
+//! - [`audit`] - Security event logging and audit trail management, with
+//!   [`audit::redact`] removing credentials from events
+//! - [`audit_file`] - Persistent, hash-chained JSONL audit trail with rotation
+//! - [`audit_query`] - Filtering recorded audit events by tool, time, outcome and level
 //! - [`helpers`] - Security helper functions (timeouts, validation wrappers)
 //! - [`input_validation`] - Input validation functions to prevent injection attacks
+//! - [`nix_analysis`] - Syntax-tree analysis of Nix code for dangerous builtins, paths and IFD
+//! - [`policy`] - Declarative allow/deny/confirm rules evaluated before each tool call
+//! - [`rate_limit`] - Token-bucket rate limits per tool and tool group
 //!
 //! # Security Features
 //!
@@ -30,6 +36,25 @@
 //! - Timeout events
 //! - Success/failure status
 //!
+//! Secrets in parameters and messages (URL passwords, API tokens,
+//! `GITHUB_TOKEN=...` assignments, random-looking strings) are replaced by
+//! `[REDACTED]` before an event is written anywhere.
+//!
+//! With `[audit] file` configured, every event is also appended to a rotating
+//! JSONL file whose records are hash-chained, so later edits are detectable
+//! with `onix-mcp verify-audit`.
+//!
+//! ## Rate Limiting
+//!
+//! Configured per-tool and per-group token buckets reject calls that arrive
+//! too quickly, before the tool runs, with the time to wait in the error data.
+//!
+//! ## Authorization Policy
+//!
+//! An optional policy file restricts what individual calls may do (only
+//! allowlisted shell packages, builds from known flakes, deployments to
+//! staging machines) and denies calls or asks the user to confirm them.
+//!
 //! ## Timeout Protection
 //!
 //! All external command executions have configurable timeouts to prevent:
@@ -58,26 +83,35 @@
 //!
 //! - **Command Injection** (OWASP A03:2021): Shell metacharacter filtering
 //! - **Path Traversal** (OWASP A01:2021): Directory traversal prevention
-//! - **Denial of Service**: Timeouts, length limits, resource controls
+//! - **Denial of Service**: Timeouts, rate limits, length limits, resource controls
 //! - **Information Disclosure**: Audit logging of security events
 //!
 //! # Validation Functions
 //!
 //! - [`validate_package_name`] - Nix package names (alphanumeric, -, _, .)
-//! - [`validate_flake_ref`] - Flake references (paths, URLs, identifiers)
-//! - [`validate_nix_expression`] - Nix expressions (dangerous patterns blocked)
+//! - [`validate_flake_ref`] - Flake references, parsed into a [`FlakeRef`](crate::common::flake_ref::FlakeRef)
+//! - [`validate_installable`] - Build arguments (flake outputs, store paths, `.drv^out`), parsed into an [`Installable`](crate::common::installable::Installable)
+//! - [`validate_nix_expression`] - Nix expressions (dangerous builtins, absolute imports and IFD blocked)
 //! - [`validate_command`] - Shell commands (null bytes, length checks)
 //! - [`validate_machine_name`] - Clan machine names (RFC 1123 compliant)
 //! - [`validate_url`] - HTTP(S)/FTP URLs (protocol whitelist)
 //! - [`validate_path`] - File paths (traversal prevention, dangerous paths)
 
 pub mod audit;
+pub mod audit_file;
+pub mod audit_query;
 pub mod helpers;
 pub mod input_validation;
+pub mod nix_analysis;
+pub mod policy;
+pub mod rate_limit;
 
-pub use audit::{audit_logger, AuditLogger};
+pub use audit::{audit_logger, AuditLogger, AuditSink};
+pub use audit_file::FileAuditSink;
 pub use helpers::validation_error_to_mcp;
 pub use input_validation::{
-    validate_command, validate_flake_ref, validate_machine_name, validate_nix_expression,
-    validate_package_name, validate_path, validate_url, ValidationError,
+    validate_command, validate_flake_ref, validate_installable, validate_machine_name,
+    validate_nix_expression, validate_package_name, validate_path, validate_url, ValidationError,
 };
+pub use policy::{Policy, PolicyAction, POLICY_DENIED};
+pub use rate_limit::{RateLimited, RateLimiter, RATE_LIMITED};
@@ -0,0 +1,753 @@
+//! Static analysis of Nix code before it is evaluated.
+//!
+//! [`analyze_nix`] parses code with rnix and walks the syntax tree, so
+//! dangerous constructs are found however they are spelled, and harmless text
+//! (a backtick inside a string, a comment mentioning `builtins.exec`) is not
+//! mistaken for them. It reports:
+//!
+//! - dangerous builtins (`exec`, `importNative`), including obfuscated
+//!   references such as `builtins."exec"`, `builtins.${"exec"}`,
+//!   `let b = builtins; in b.exec`, `with builtins; exec`,
+//!   `inherit (builtins) exec`, `builtins.getAttr "exec" builtins` and
+//!   `let g = builtins.getAttr; in g "exec" builtins`
+//! - attributes of `builtins` selected by a computed name, and `builtins`
+//!   passed to a function (`intersectAttrs`, `mapAttrs`, ...), which can
+//!   select any of its attributes
+//! - `import`, `readFile` and similar reading absolute or home-relative paths
+//!   (store paths are allowed)
+//! - import from derivation: reading the output of a derivation while
+//!   evaluating
+//! - derivation attributes weakening the build sandbox (`__noChroot`,
+//!   `allowSubstitutes = false`) and Nix daemon settings
+//! - shell command substitution (`` `...` ``, `$(...)`) outside strings
+//!
+//! Syntax errors are not reported; the partial tree of invalid code is still
+//! analyzed.
+//!
+//! ```
+//! use onix_mcp::common::security::nix_analysis::{analyze_nix, NixRisk};
+//!
+//! let diagnostics = analyze_nix("let b = builtins; in\n  b.\"exec\" [ \"id\" ]");
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].risk, NixRisk::DangerousBuiltin);
+//! assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 3));
+//!
+//! assert!(analyze_nix("''echo `date`''").is_empty());
+//! ```
+
+use rnix::ast;
+use rnix::{NodeOrToken, SyntaxKind, SyntaxNode};
+use rowan::ast::AstNode;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Builtins that run code outside the evaluator.
+const DANGEROUS_BUILTINS: &[&str] = &["exec", "importNative"];
+
+/// Builtins reading a path, with the index of the path argument.
+const PATH_BUILTINS: &[(&str, usize)] = &[
+    ("import", 0),
+    ("scopedImport", 1),
+    ("readFile", 0),
+    ("readDir", 0),
+    ("readFileType", 0),
+    ("hashFile", 1),
+];
+
+/// Builtins that can be called without the `builtins.` prefix.
+const GLOBAL_BUILTINS: &[&str] = &["import", "scopedImport", "derivation"];
+
+/// Functions whose result is a derivation.
+const DERIVATION_FUNCTIONS: &[&str] = &[
+    "derivation",
+    "mkDerivation",
+    "runCommand",
+    "runCommandLocal",
+    "runCommandCC",
+    "runCommandNoCC",
+    "writeText",
+    "writeTextFile",
+    "writeTextDir",
+    "writeScript",
+    "writeShellScript",
+    "symlinkJoin",
+];
+
+/// Attributes giving the store path of a derivation.
+const DERIVATION_PATHS: &[&str] = &["outPath", "drvPath"];
+
+/// Attributes that weaken the build sandbox or reconfigure the Nix daemon.
+const SANDBOX_SETTINGS: &[&str] = &[
+    "__noChroot",
+    "trustedUsers",
+    "trusted-users",
+    "allowed-users",
+    "builders",
+    "substituters",
+    "trusted-substituters",
+    "system-features",
+];
+
+/// Store paths are immutable, so reading them is allowed.
+const STORE_DIR: &str = "/nix/store/";
+
+/// Kind of dangerous construct found in Nix code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NixRisk {
+    /// Reference to a builtin that runs external code
+    DangerousBuiltin,
+    /// Attribute of `builtins` selected by a computed name, or `builtins`
+    /// passed to a function
+    DynamicBuiltin,
+    /// Import or read of an absolute or home-relative path
+    AbsolutePath,
+    /// Import or read of a derivation output during evaluation
+    ImportFromDerivation,
+    /// Attribute weakening the sandbox or configuring the daemon
+    SandboxSetting,
+    /// Shell command substitution outside a string
+    ShellSubstitution,
+}
+
+/// A dangerous construct and where it starts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct NixDiagnostic {
+    /// What was found
+    pub risk: NixRisk,
+    /// Description of the construct
+    pub message: String,
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+}
+
+impl std::fmt::Display for NixDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Find dangerous constructs in `source`, in order of position.
+pub fn analyze_nix(source: &str) -> Vec<NixDiagnostic> {
+    let root = rnix::Root::parse(source).syntax();
+    let mut analyzer = Analyzer {
+        source,
+        aliases: HashSet::from(["builtins".to_string()]),
+        functions: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    analyzer.collect_aliases(&root);
+    analyzer.check(&root);
+
+    let mut seen = HashSet::new();
+    let mut diagnostics = analyzer.diagnostics;
+    diagnostics.retain(|d| seen.insert(d.clone()));
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/// A builtin an expression refers to.
+enum Builtin {
+    Named(String),
+    /// Selected by a name only known at evaluation time
+    Computed,
+}
+
+struct Analyzer<'a> {
+    source: &'a str,
+    /// Names bound to the `builtins` set
+    aliases: HashSet<String>,
+    /// Names bound to a builtin (`inherit (builtins) getAttr`,
+    /// `g = builtins.getAttr`), with the builtin's name
+    functions: HashMap<String, String>,
+    diagnostics: Vec<NixDiagnostic>,
+}
+
+impl Analyzer<'_> {
+    /// Find every name `builtins` or one of its functions is bound to, until
+    /// no new ones appear.
+    ///
+    /// Scoping is ignored: a name bound to `builtins` anywhere is treated as
+    /// `builtins` everywhere, which can only report more.
+    fn collect_aliases(&mut self, root: &SyntaxNode) {
+        loop {
+            let known = self.aliases.len() + self.functions.len();
+            for node in root.descendants() {
+                if let Some(binding) = ast::AttrpathValue::cast(node.clone()) {
+                    let name = binding.attrpath().and_then(|path| single_name(&path));
+                    if let (Some(name), Some(value)) = (name, binding.value()) {
+                        self.bind(name, &value);
+                    }
+                } else if let Some(inherit) = ast::Inherit::cast(node.clone()) {
+                    let from_builtins = inherit
+                        .from()
+                        .and_then(|from| from.expr())
+                        .is_some_and(|expr| self.is_builtins(&expr));
+                    if from_builtins {
+                        for name in inherit.attrs().filter_map(|attr| static_name(&attr)) {
+                            self.functions.insert(name.clone(), name);
+                        }
+                    }
+                } else if let Some(entry) = ast::PatEntry::cast(node.clone()) {
+                    if let (Some(name), Some(default)) =
+                        (entry.ident().and_then(|i| ident_name(&i)), entry.default())
+                    {
+                        self.bind(name, &default);
+                    }
+                } else if let Some(apply) = ast::Apply::cast(node.clone()) {
+                    // (b: b.exec [ ... ]) builtins
+                    let param = apply.lambda().map(strip_parens).and_then(|f| match f {
+                        ast::Expr::Lambda(lambda) => match lambda.param() {
+                            Some(ast::Param::IdentParam(param)) => param.ident(),
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                    if let (Some(name), Some(arg)) =
+                        (param.and_then(|p| ident_name(&p)), apply.argument())
+                    {
+                        self.bind(name, &arg);
+                    }
+                }
+            }
+            if self.aliases.len() + self.functions.len() == known {
+                break;
+            }
+        }
+    }
+
+    /// Record `name` as an alias if `value` is `builtins` or a builtin.
+    fn bind(&mut self, name: String, value: &ast::Expr) {
+        if self.is_builtins(value) {
+            self.aliases.insert(name);
+        } else if let Some(Builtin::Named(builtin)) = self.builtin(value) {
+            self.functions.insert(name, builtin);
+        }
+    }
+
+    fn check(&mut self, root: &SyntaxNode) {
+        for element in root.descendants_with_tokens() {
+            let node = match element {
+                NodeOrToken::Token(token) => {
+                    if token.kind() == SyntaxKind::TOKEN_ERROR && token.text().contains(['`', '$'])
+                    {
+                        self.report(
+                            token.text_range().start(),
+                            NixRisk::ShellSubstitution,
+                            "shell command substitution outside a string".to_string(),
+                        );
+                    }
+                    continue;
+                }
+                NodeOrToken::Node(node) => node,
+            };
+
+            if let Some(inherit) = ast::Inherit::cast(node.clone()) {
+                self.check_inherit(&inherit);
+            } else if let Some(binding) = ast::AttrpathValue::cast(node.clone()) {
+                self.check_binding(&binding);
+            } else if let Some(expr) = ast::Expr::cast(node.clone()) {
+                if is_reference(&expr) {
+                    self.check_reference(&expr);
+                }
+                if let ast::Expr::Apply(apply) = &expr {
+                    self.check_path_read(apply);
+                    self.check_builtins_argument(apply);
+                }
+            }
+        }
+    }
+
+    fn check_reference(&mut self, expr: &ast::Expr) {
+        match self.builtin(expr) {
+            Some(Builtin::Named(name)) if DANGEROUS_BUILTINS.contains(&name.as_str()) => {
+                self.report(
+                    expr.syntax().text_range().start(),
+                    NixRisk::DangerousBuiltin,
+                    format!("builtins.{} runs code outside the evaluator", name),
+                );
+            }
+            Some(Builtin::Computed) => {
+                self.report(
+                    expr.syntax().text_range().start(),
+                    NixRisk::DynamicBuiltin,
+                    "attribute of builtins selected by a computed name".to_string(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Report `builtins` passed to a function other than `getAttr`, which
+    /// [`builtin`](Self::builtin) resolves. Even a lambda can return it, as
+    /// in `((x: x) builtins).exec`.
+    fn check_builtins_argument(&mut self, apply: &ast::Apply) {
+        let Some(arg) = apply.argument().filter(|arg| self.is_builtins(arg)) else {
+            return;
+        };
+        let (head, args) = apply_chain(apply);
+        let head = head.as_ref().and_then(|h| self.builtin(h));
+        if matches!(head, Some(Builtin::Named(name)) if name == "getAttr" && args.len() == 2) {
+            return;
+        }
+        self.report(
+            arg.syntax().text_range().start(),
+            NixRisk::DynamicBuiltin,
+            "builtins passed to a function, which can select any of its attributes".to_string(),
+        );
+    }
+
+    fn check_inherit(&mut self, inherit: &ast::Inherit) {
+        let from_builtins = inherit
+            .from()
+            .and_then(|from| from.expr())
+            .is_some_and(|expr| self.is_builtins(&expr));
+        if !from_builtins {
+            return;
+        }
+        for attr in inherit.attrs() {
+            match static_name(&attr) {
+                Some(name) if DANGEROUS_BUILTINS.contains(&name.as_str()) => self.report(
+                    attr.syntax().text_range().start(),
+                    NixRisk::DangerousBuiltin,
+                    format!("builtins.{} runs code outside the evaluator", name),
+                ),
+                None => self.report(
+                    attr.syntax().text_range().start(),
+                    NixRisk::DynamicBuiltin,
+                    "attribute of builtins selected by a computed name".to_string(),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_binding(&mut self, binding: &ast::AttrpathValue) {
+        let Some(name) = binding
+            .attrpath()
+            .and_then(|path| path.attrs().next())
+            .and_then(|attr| static_name(&attr))
+        else {
+            return;
+        };
+        let disables_substitutes = name == "allowSubstitutes"
+            && binding
+                .value()
+                .map(strip_parens)
+                .is_some_and(|value| value.syntax().text() == "false");
+        if SANDBOX_SETTINGS.contains(&name.as_str()) || disables_substitutes {
+            self.report(
+                binding.syntax().text_range().start(),
+                NixRisk::SandboxSetting,
+                format!("'{}' weakens the build sandbox or daemon settings", name),
+            );
+        }
+    }
+
+    /// Check the path read by `import`, `readFile` and friends.
+    fn check_path_read(&mut self, apply: &ast::Apply) {
+        let (Some(head), args) = apply_chain(apply) else {
+            return;
+        };
+        let Some(Builtin::Named(function)) = self.builtin(&head) else {
+            return;
+        };
+        let Some(path) = PATH_BUILTINS
+            .iter()
+            .find(|(name, _)| *name == function)
+            .and_then(|(_, index)| args.get(*index))
+        else {
+            return;
+        };
+        let function = if GLOBAL_BUILTINS.contains(&function.as_str()) {
+            function
+        } else {
+            format!("builtins.{}", function)
+        };
+        let start = path.syntax().text_range().start();
+
+        if let Some(absolute) = absolute_path(path) {
+            self.report(
+                start,
+                NixRisk::AbsolutePath,
+                format!("{} of absolute path '{}'", function, absolute),
+            );
+        }
+        if self.reads_derivation(path) {
+            self.report(
+                start,
+                NixRisk::ImportFromDerivation,
+                format!(
+                    "{} of a derivation output (import from derivation)",
+                    function
+                ),
+            );
+        }
+    }
+
+    /// Whether `expr` builds a derivation or takes its store path.
+    fn reads_derivation(&self, expr: &ast::Expr) -> bool {
+        expr.syntax().descendants().any(|node| {
+            if let Some(apply) = ast::Apply::cast(node.clone()) {
+                let head = apply_chain(&apply).0;
+                let builtin = head.as_ref().and_then(|h| self.builtin(h));
+                matches!(builtin, Some(Builtin::Named(name)) if name == "derivation")
+                    || head
+                        .and_then(|h| last_name(&h))
+                        .is_some_and(|name| DERIVATION_FUNCTIONS.contains(&name.as_str()))
+            } else if let Some(select) = ast::Select::cast(node) {
+                select
+                    .attrpath()
+                    .and_then(|path| path.attrs().last())
+                    .and_then(|attr| static_name(&attr))
+                    .is_some_and(|name| DERIVATION_PATHS.contains(&name.as_str()))
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Whether `expr` evaluates to the `builtins` set.
+    fn is_builtins(&self, expr: &ast::Expr) -> bool {
+        match strip_parens(expr.clone()) {
+            ast::Expr::Ident(ident) => {
+                ident_name(&ident).is_some_and(|name| self.aliases.contains(&name))
+            }
+            ast::Expr::Select(select) => select
+                .attrpath()
+                .and_then(|path| path.attrs().last())
+                .and_then(|attr| static_name(&attr))
+                .is_some_and(|name| self.aliases.contains(&name)),
+            _ => false,
+        }
+    }
+
+    /// The builtin `expr` refers to, if any.
+    fn builtin(&self, expr: &ast::Expr) -> Option<Builtin> {
+        match strip_parens(expr.clone()) {
+            ast::Expr::Select(select) => {
+                let attrs: Vec<ast::Attr> = select.attrpath()?.attrs().collect();
+                let selected = if select.expr().is_some_and(|e| self.is_builtins(&e)) {
+                    attrs.first()
+                } else {
+                    // pkgs.builtins.exec, { b = builtins; }.b.exec
+                    attrs
+                        .windows(2)
+                        .find(|pair| {
+                            static_name(&pair[0]).is_some_and(|name| self.aliases.contains(&name))
+                        })
+                        .map(|pair| &pair[1])
+                }?;
+                Some(match static_name(selected) {
+                    Some(name) => Builtin::Named(name),
+                    None => Builtin::Computed,
+                })
+            }
+            ast::Expr::Ident(ident) => {
+                let name = ident_name(&ident)?;
+                if let Some(primop) = name.strip_prefix("__").filter(|n| !n.is_empty()) {
+                    return Some(Builtin::Named(primop.to_string()));
+                }
+                if let Some(builtin) = self.functions.get(&name) {
+                    return Some(Builtin::Named(builtin.clone()));
+                }
+                let in_scope = GLOBAL_BUILTINS.contains(&name.as_str())
+                    || self.within_builtins_scope(ident.syntax());
+                in_scope.then_some(Builtin::Named(name))
+            }
+            ast::Expr::Apply(apply) => {
+                // builtins.getAttr "exec" builtins
+                let (head, args) = apply_chain(&apply);
+                match self.builtin(&head?)? {
+                    Builtin::Named(name) if name == "getAttr" && args.len() >= 2 => {
+                        if !self.is_builtins(&args[1]) {
+                            return None;
+                        }
+                        Some(match static_string(&args[0]) {
+                            Some(name) => Builtin::Named(name),
+                            None => Builtin::Computed,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `node` is in the body of a `with builtins;`.
+    fn within_builtins_scope(&self, node: &SyntaxNode) -> bool {
+        node.ancestors().filter_map(ast::With::cast).any(|with| {
+            with.namespace().is_some_and(|ns| self.is_builtins(&ns))
+                && with.body().is_some_and(|body| {
+                    body.syntax().text_range().contains_range(node.text_range())
+                })
+        })
+    }
+
+    fn report(&mut self, offset: rnix::TextSize, risk: NixRisk, message: String) {
+        let before = &self.source[..usize::from(offset)];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        self.diagnostics.push(NixDiagnostic {
+            risk,
+            message,
+            line,
+            column,
+        });
+    }
+}
+
+/// Whether `expr` is a variable or attribute reference, or a `getAttr` call,
+/// as opposed to an identifier naming an attribute or parameter.
+fn is_reference(expr: &ast::Expr) -> bool {
+    match expr {
+        ast::Expr::Select(_) | ast::Expr::Apply(_) => true,
+        ast::Expr::Ident(ident) => !ident.syntax().parent().is_some_and(|parent| {
+            matches!(
+                parent.kind(),
+                SyntaxKind::NODE_ATTRPATH
+                    | SyntaxKind::NODE_INHERIT
+                    | SyntaxKind::NODE_PAT_ENTRY
+                    | SyntaxKind::NODE_PAT_BIND
+                    | SyntaxKind::NODE_IDENT_PARAM
+            )
+        }),
+        _ => false,
+    }
+}
+
+fn strip_parens(mut expr: ast::Expr) -> ast::Expr {
+    while let ast::Expr::Paren(paren) = &expr {
+        match paren.expr() {
+            Some(inner) => expr = inner,
+            None => break,
+        }
+    }
+    expr
+}
+
+/// Function and arguments of `f a b c`.
+fn apply_chain(apply: &ast::Apply) -> (Option<ast::Expr>, Vec<ast::Expr>) {
+    let mut args = Vec::new();
+    let mut current = ast::Expr::Apply(apply.clone());
+    let head = loop {
+        match strip_parens(current) {
+            ast::Expr::Apply(apply) => {
+                args.extend(apply.argument());
+                match apply.lambda() {
+                    Some(lambda) => current = lambda,
+                    None => break None,
+                }
+            }
+            other => break Some(other),
+        }
+    };
+    args.reverse();
+    (head, args)
+}
+
+fn ident_name(ident: &ast::Ident) -> Option<String> {
+    Some(ident.ident_token()?.text().to_string())
+}
+
+/// Name of an attribute known without evaluating anything.
+fn static_name(attr: &ast::Attr) -> Option<String> {
+    match attr {
+        ast::Attr::Ident(ident) => ident_name(ident),
+        ast::Attr::Str(s) => static_string(&ast::Expr::Str(s.clone())),
+        ast::Attr::Dynamic(dynamic) => static_string(&dynamic.expr()?),
+    }
+}
+
+/// Content of a string literal without interpolations.
+fn static_string(expr: &ast::Expr) -> Option<String> {
+    let ast::Expr::Str(s) = strip_parens(expr.clone()) else {
+        return None;
+    };
+    s.normalized_parts()
+        .into_iter()
+        .map(|part| match part {
+            ast::InterpolPart::Literal(text) => Some(text),
+            ast::InterpolPart::Interpolation(_) => None,
+        })
+        .collect()
+}
+
+/// Name of the variable or last selected attribute of `expr`.
+fn last_name(expr: &ast::Expr) -> Option<String> {
+    match strip_parens(expr.clone()) {
+        ast::Expr::Ident(ident) => ident_name(&ident),
+        ast::Expr::Select(select) => static_name(&select.attrpath()?.attrs().last()?),
+        _ => None,
+    }
+}
+
+fn single_name(path: &ast::Attrpath) -> Option<String> {
+    let mut attrs = path.attrs();
+    let name = static_name(&attrs.next()?)?;
+    attrs.next().is_none().then_some(name)
+}
+
+/// The path if `expr` is an absolute or home-relative path outside the store.
+fn absolute_path(expr: &ast::Expr) -> Option<String> {
+    let path = match strip_parens(expr.clone()) {
+        ast::Expr::PathAbs(path) => path.syntax().text().to_string(),
+        ast::Expr::PathHome(path) => path.syntax().text().to_string(),
+        other => static_string(&other).filter(|s| s.starts_with(['/', '~']))?,
+    };
+    (!path.starts_with(STORE_DIR)).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn risks(source: &str) -> Vec<NixRisk> {
+        analyze_nix(source).into_iter().map(|d| d.risk).collect()
+    }
+
+    #[test]
+    fn test_dangerous_builtins_however_spelled() {
+        for source in [
+            "builtins.exec [ \"id\" ]",
+            "builtins.\"exec\" [ \"id\" ]",
+            "builtins.${\"exec\"} [ \"id\" ]",
+            "(builtins).exec [ \"id\" ]",
+            "let b = builtins; in b.exec [ \"id\" ]",
+            "let b = builtins; c = b; in c.exec [ \"id\" ]",
+            "let s = { b = builtins; }; in s.b.exec [ \"id\" ]",
+            "with builtins; exec [ \"id\" ]",
+            "let inherit (builtins) exec; in exec [ \"id\" ]",
+            "(b: b.exec [ \"id\" ]) builtins",
+            "({ b ? builtins }: b.exec [ \"id\" ]) { }",
+            "builtins.getAttr \"exec\" builtins [ \"id\" ]",
+            "let g = builtins.getAttr; in g \"exec\" builtins [ \"id\" ]",
+            "let inherit (builtins) getAttr; in getAttr \"exec\" builtins [ \"id\" ]",
+            "__exec [ \"id\" ]",
+            "builtins.importNative ./lib.so \"main\"",
+        ] {
+            assert!(
+                risks(source).contains(&NixRisk::DangerousBuiltin),
+                "not flagged: {}",
+                source
+            );
+        }
+
+        assert_eq!(risks("builtins.${name} 1"), vec![NixRisk::DynamicBuiltin]);
+        assert_eq!(
+            risks("builtins.getAttr (\"ex\" + \"ec\") builtins [ ]"),
+            vec![NixRisk::DynamicBuiltin]
+        );
+        for source in [
+            "(builtins.intersectAttrs { exec = null; } builtins).exec [ \"id\" ]",
+            "let b = builtins; in (b.mapAttrs (n: v: v) b).exec [ ]",
+            "((x: x) builtins).exec [ ]",
+            "let f = s: s.exec; in f builtins",
+        ] {
+            assert!(
+                risks(source).contains(&NixRisk::DynamicBuiltin),
+                "not flagged: {}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_harmless_code_accepted() {
+        for source in [
+            "1 + 1",
+            "builtins.toString 42",
+            "# calls builtins.exec\n{ a = 1; }",
+            "\"run `date` and $(id) in a string\"",
+            "''echo `date` $(id)''",
+            "{ exec = 1; }.exec",
+            "with builtins; map toString [ 1 2 ]",
+            "let exec = x: x; in exec 1",
+            "import <nixpkgs> { }",
+            "import ./default.nix { }",
+            "import /nix/store/0c0b6adq3k7lzmkw3x5h8j2d2sm7v1a9-source/lib",
+            "import ./${name}.nix",
+            "{ allowSubstitutes = true; }",
+        ] {
+            assert_eq!(analyze_nix(source), vec![], "flagged: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_paths_and_derivations() {
+        assert_eq!(
+            risks("import /etc/nixos/secrets.nix"),
+            vec![NixRisk::AbsolutePath]
+        );
+        assert_eq!(
+            risks("builtins.readFile ~/.ssh/id_ed25519"),
+            vec![NixRisk::AbsolutePath]
+        );
+        assert_eq!(
+            risks("builtins.readFile \"/etc/shadow\""),
+            vec![NixRisk::AbsolutePath]
+        );
+        assert_eq!(
+            risks("with builtins; hashFile \"sha256\" /etc/passwd"),
+            vec![NixRisk::AbsolutePath]
+        );
+
+        assert_eq!(
+            risks("import (pkgs.runCommand \"gen\" { } \"echo 1 > $out\")"),
+            vec![NixRisk::ImportFromDerivation]
+        );
+        assert_eq!(
+            risks("builtins.readFile \"${pkgs.hello.outPath}/share/doc\""),
+            vec![NixRisk::ImportFromDerivation]
+        );
+        assert_eq!(
+            risks("import (derivation { name = \"x\"; builder = \"/bin/sh\"; system = \"x86_64-linux\"; })"),
+            vec![NixRisk::ImportFromDerivation]
+        );
+    }
+
+    #[test]
+    fn test_sandbox_settings_and_shell_syntax() {
+        assert_eq!(
+            risks("derivation { __noChroot = true; }"),
+            vec![NixRisk::SandboxSetting]
+        );
+        assert_eq!(
+            risks("{ allowSubstitutes = (false); }"),
+            vec![NixRisk::SandboxSetting]
+        );
+        assert_eq!(
+            risks("{ nix.settings.trusted-users = [ \"me\" ]; }"),
+            vec![]
+        );
+        assert_eq!(
+            risks("{ trusted-users = [ \"me\" ]; }"),
+            vec![NixRisk::SandboxSetting]
+        );
+
+        assert!(risks("1 + `id`").contains(&NixRisk::ShellSubstitution));
+        assert!(risks("1 + $(id)").contains(&NixRisk::ShellSubstitution));
+    }
+
+    #[test]
+    fn test_positions() {
+        let diagnostics = analyze_nix("{\n  a = 1;\n  b = with builtins;\n    exec [ ];\n}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, 5));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "line 4, column 5: builtins.exec runs code outside the evaluator"
+        );
+
+        // Columns count characters, not bytes
+        let diagnostics = analyze_nix("\"ünïcode\" + builtins.exec");
+        assert_eq!(diagnostics[0].column, 13);
+    }
+}
@@ -0,0 +1,751 @@
+//! Declarative authorization of tool calls.
+//!
+//! Profiles and tool groups decide which tools exist; a [`Policy`] decides
+//! whether a particular call may run, based on its arguments. The policy file
+//! configured as `[policy] file` is a list of rules evaluated in order before
+//! each tool call. The first rule whose tools and argument conditions match
+//! decides the outcome; calls matching no rule get the `default` action.
+//!
+//! ```toml
+//! # Outcome of calls no rule matches: "allow" (default), "deny" or
+//! # "require_confirmation"
+//! default = "allow"
+//!
+//! # Shells only with allowlisted packages
+//! [[rules]]
+//! tools = ["run_in_shell"]
+//! args.packages.one_of = ["python3", "jq", "ripgrep"]
+//! action = "allow"
+//!
+//! [[rules]]
+//! tools = ["run_in_shell"]
+//! action = "deny"
+//! reason = "only allowlisted packages may be used in shells"
+//!
+//! # Builds only from the local flake and nixpkgs
+//! [[rules]]
+//! tools = ["nix_build"]
+//! args.package.prefix = [".#", "nixpkgs#"]
+//! action = "allow"
+//!
+//! [[rules]]
+//! tools = ["nix_build"]
+//! action = "deny"
+//!
+//! # Deploy staging freely, ask before touching anything else
+//! [[rules]]
+//! tools = ["clan_machine_update"]
+//! args.machines.tagged = ["staging"]
+//! action = "allow"
+//!
+//! [[rules]]
+//! groups = ["clan_machines", "clan_backups"]
+//! action = "require_confirmation"
+//! ```
+//!
+//! A rule applies to the listed `tools` and every tool of the listed
+//! `groups`, or to all tools if it lists neither. Argument conditions, keyed
+//! by argument name, all have to hold; an argument that is missing or an
+//! empty list never satisfies a condition, and for list arguments every
+//! element has to:
+//!
+//! - `one_of`: the value is one of the given strings
+//! - `prefix`: the value starts with one of the given strings
+//! - `pattern`: the whole value matches the regular expression
+//! - `tagged`: the value names Clan machines that carry at least one of the
+//!   given tags in the call's flake
+//!
+//! Denied calls fail with [`POLICY_DENIED`]. Calls requiring confirmation
+//! ask the user like destructive Clan operations do (see
+//! [`Confirmation`]); tools in [`CONFIRMED_TOOLS`] already ask on every call
+//! and are left to do so. Every decision is recorded as an `AuthEvent`,
+//! confirmations as `DangerousOperation`s.
+
+use super::{validate_flake_ref, AuditLogger};
+use crate::common::config::ConfigError;
+use crate::common::confirmation::{
+    Confirmation, ConfirmationOutcome, DestructiveOperation, CONFIRMED_TOOLS,
+};
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
+use crate::common::tool_registry::ToolGroup;
+use regex::Regex;
+use rmcp::model::{CallToolResult, ErrorCode, JsonObject};
+use rmcp::ErrorData as McpError;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+/// JSON-RPC error code of calls denied by the policy.
+///
+/// Taken from the implementation-defined server error range; the error data
+/// carries the deciding rule and its reason.
+pub const POLICY_DENIED: ErrorCode = ErrorCode(-32003);
+
+/// Argument carrying a confirmation token on the repeated call.
+const CONFIRMATION_TOKEN: &str = "confirmation_token";
+
+/// How long looking up machine tags may take.
+const TAG_LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outcome of a policy decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Run the call
+    #[default]
+    Allow,
+    /// Reject the call
+    Deny,
+    /// Run the call once the user confirms it
+    RequireConfirmation,
+}
+
+impl PolicyAction {
+    /// Name used for this action in policy files.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PolicyAction::Allow => "allow",
+            PolicyAction::Deny => "deny",
+            PolicyAction::RequireConfirmation => "require_confirmation",
+        }
+    }
+}
+
+/// Regular expression that has to match a whole argument value.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&format!("^(?:{})$", pattern))
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Conditions on one argument of a tool call.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArgCondition {
+    /// Allowed values
+    pub one_of: Option<Vec<String>>,
+    /// Allowed value prefixes
+    pub prefix: Option<Vec<String>>,
+    /// Regular expression the whole value has to match
+    pub pattern: Option<Pattern>,
+    /// Clan machine tags, one of which every named machine has to carry
+    pub tagged: Option<Vec<String>>,
+}
+
+/// One rule of a [`Policy`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+    /// Tools the rule applies to
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Tool groups the rule applies to
+    #[serde(default)]
+    pub groups: Vec<ToolGroup>,
+    /// Conditions on the call's arguments, by argument name
+    #[serde(default)]
+    pub args: BTreeMap<String, ArgCondition>,
+    /// Outcome of calls matching this rule
+    pub action: PolicyAction,
+    /// Explanation shown to the client and recorded in the audit log
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl PolicyRule {
+    fn applies_to(&self, tool: &str) -> bool {
+        (self.tools.is_empty() && self.groups.is_empty())
+            || self.tools.iter().any(|t| t == tool)
+            || ToolGroup::of(tool).is_some_and(|group| self.groups.contains(&group))
+    }
+}
+
+/// Looks up which Clan machines carry a tag.
+pub trait MachineTags {
+    /// Machines tagged `tag` in `flake` (the default flake if `None`).
+    fn tagged(
+        &self,
+        flake: Option<&str>,
+        tag: &str,
+    ) -> impl Future<Output = Result<Vec<String>, String>> + Send;
+}
+
+/// [`MachineTags`] asking `clan machines list --tags`.
+pub struct ClanMachineTags {
+    workspace: Workspace,
+    default_flake: String,
+}
+
+impl ClanMachineTags {
+    /// Look up tags in flakes resolved against `workspace`.
+    pub fn new(workspace: Workspace, default_flake: impl Into<String>) -> Self {
+        Self {
+            workspace,
+            default_flake: default_flake.into(),
+        }
+    }
+}
+
+impl MachineTags for ClanMachineTags {
+    async fn tagged(&self, flake: Option<&str>, tag: &str) -> Result<Vec<String>, String> {
+        let flake = flake.unwrap_or(&self.default_flake);
+        validate_flake_ref(flake).map_err(|e| e.to_string())?;
+        let flake = self
+            .workspace
+            .resolve_flake(flake)
+            .await
+            .map_err(|e| e.message.to_string())?;
+
+        let output = tokio::time::timeout(
+            TAG_LOOKUP_TIMEOUT,
+            tokio::process::Command::new("clan")
+                .args(["machines", "list", "--flake", &flake, "--tags", tag])
+                .group_output(),
+        )
+        .await
+        .map_err(|_| "timed out listing machines".to_string())?
+        .map_err(|e| format!("failed to execute clan: {}", e))?;
+
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Outcome of evaluating a [`Policy`] for one call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// What to do with the call
+    pub action: PolicyAction,
+    /// Index of the deciding rule, `None` for the default action
+    pub rule: Option<usize>,
+    /// Reason given by the deciding rule
+    pub reason: Option<String>,
+}
+
+impl Decision {
+    fn describe(&self, tool: &str) -> String {
+        let source = match self.rule {
+            Some(index) => format!("rule {}", index + 1),
+            None => "default".to_string(),
+        };
+        match &self.reason {
+            Some(reason) => format!(
+                "policy {} {} ({}): {}",
+                source,
+                tool,
+                self.action.as_str(),
+                reason
+            ),
+            None => format!("policy {} {} ({})", source, tool, self.action.as_str()),
+        }
+    }
+}
+
+/// Ordered authorization rules for tool calls.
+///
+/// The default policy has no rules and allows every call.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Outcome of calls no rule matches
+    pub default: PolicyAction,
+    /// Rules, first match wins
+    pub rules: Vec<PolicyRule>,
+}
+
+impl Policy {
+    /// Read and validate a policy file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if the file cannot be read, is not a valid
+    /// policy or names unknown tools.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Read {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        Self::from_toml_str(&path.display().to_string(), &text)
+    }
+
+    /// Parse and validate a policy; `source` names it in errors.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if `text` is not a valid policy.
+    pub fn from_toml_str(source: &str, text: &str) -> Result<Self, ConfigError> {
+        let policy: Policy = toml::from_str(text).map_err(|e| ConfigError::Parse {
+            source: source.to_string(),
+            reason: e.to_string(),
+        })?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            let invalid = |field: &str, reason: String| ConfigError::Invalid {
+                field: format!("rules[{}].{}", index, field),
+                reason,
+            };
+
+            if let Some(tool) = rule.tools.iter().find(|t| ToolGroup::of(t).is_none()) {
+                return Err(invalid("tools", format!("unknown tool '{}'", tool)));
+            }
+            for (arg, condition) in &rule.args {
+                let lists = [
+                    ("one_of", &condition.one_of),
+                    ("prefix", &condition.prefix),
+                    ("tagged", &condition.tagged),
+                ];
+                if lists.iter().all(|(_, list)| list.is_none()) && condition.pattern.is_none() {
+                    return Err(invalid(
+                        &format!("args.{}", arg),
+                        "no condition given".to_string(),
+                    ));
+                }
+                if let Some((name, _)) = lists
+                    .iter()
+                    .find(|(_, list)| list.as_ref().is_some_and(|l| l.is_empty()))
+                {
+                    return Err(invalid(
+                        &format!("args.{}.{}", arg, name),
+                        "must not be empty".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether every call is allowed without evaluating anything.
+    pub fn allows_everything(&self) -> bool {
+        self.rules.is_empty() && self.default == PolicyAction::Allow
+    }
+
+    /// Decide what to do with a call to `tool` with `args`.
+    pub async fn evaluate(
+        &self,
+        tool: &str,
+        args: &JsonObject,
+        tags: &impl MachineTags,
+    ) -> Decision {
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.applies_to(tool) {
+                continue;
+            }
+
+            let mut matched = true;
+            for (arg, condition) in &rule.args {
+                if !check(condition, args, arg, tags).await {
+                    matched = false;
+                    break;
+                }
+            }
+            if matched {
+                return Decision {
+                    action: rule.action,
+                    rule: Some(index),
+                    reason: rule.reason.clone(),
+                };
+            }
+        }
+
+        Decision {
+            action: self.default,
+            rule: None,
+            reason: None,
+        }
+    }
+
+    /// Apply the policy to a call before it runs, recording the decision.
+    ///
+    /// Returns `Some(result)` if the call must not run and `result` should
+    /// be returned instead (the user has yet to confirm, or declined). A
+    /// confirmation token for the policy is removed from `args`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`POLICY_DENIED`] error if the policy denies the call, or an
+    /// invalid-params error for a bad confirmation token.
+    pub async fn authorize(
+        &self,
+        audit: &AuditLogger,
+        tool: &str,
+        args: &mut Option<JsonObject>,
+        confirmation: &Confirmation,
+        tags: &impl MachineTags,
+    ) -> Result<Option<CallToolResult>, McpError> {
+        if self.allows_everything() {
+            return Ok(None);
+        }
+
+        let self_confirming = CONFIRMED_TOOLS.contains(&tool);
+        let token = match args {
+            Some(args) if !self_confirming => args.remove(CONFIRMATION_TOKEN),
+            _ => None,
+        };
+        let arguments = args.clone().unwrap_or_default();
+        let decision = self.evaluate(tool, &arguments, tags).await;
+        let description = decision.describe(tool);
+
+        match decision.action {
+            PolicyAction::Allow => {
+                audit.log_auth_event(true, &description);
+                Ok(None)
+            }
+            PolicyAction::Deny => {
+                audit.log_auth_event(false, &description);
+                Err(McpError::new(
+                    POLICY_DENIED,
+                    format!(
+                        "Tool call denied by policy: {}",
+                        decision.reason.as_deref().unwrap_or(tool)
+                    ),
+                    Some(json!({
+                        "error": "policy_denied",
+                        "tool": tool,
+                        "rule": decision.rule.map(|index| index + 1),
+                        "reason": decision.reason,
+                    })),
+                ))
+            }
+            PolicyAction::RequireConfirmation if self_confirming => {
+                audit.log_auth_event(true, &format!("{}; confirmed by the tool", description));
+                Ok(None)
+            }
+            PolicyAction::RequireConfirmation => {
+                audit.log_auth_event(true, &description);
+                let operation = DestructiveOperation::new(
+                    static_tool_name(tool),
+                    format!(
+                        "Policy requires confirmation to run {}{}",
+                        tool,
+                        decision
+                            .reason
+                            .as_deref()
+                            .map(|reason| format!(": {}", reason))
+                            .unwrap_or_default()
+                    ),
+                )
+                .detail("Arguments", Value::Object(arguments).to_string());
+
+                let token = token.as_ref().map(|t| t.as_str().unwrap_or_default());
+                match confirmation.confirm(audit, &operation, token).await? {
+                    ConfirmationOutcome::Approved => Ok(None),
+                    ConfirmationOutcome::Declined(result) => Ok(Some(result)),
+                }
+            }
+        }
+    }
+}
+
+/// Whether `condition` holds for the argument `name` of `args`.
+async fn check(
+    condition: &ArgCondition,
+    args: &JsonObject,
+    name: &str,
+    tags: &impl MachineTags,
+) -> bool {
+    let values: Vec<String> = match args.get(name) {
+        None | Some(Value::Null) => return false,
+        Some(Value::Array(items)) => items.iter().map(value_text).collect(),
+        Some(value) => vec![value_text(value)],
+    };
+    // An empty list (`machines = []` updates every machine) is as unknown as
+    // a missing argument
+    if values.is_empty() {
+        return false;
+    }
+
+    let all = |accept: &dyn Fn(&str) -> bool| values.iter().all(|v| accept(v));
+    if let Some(allowed) = &condition.one_of {
+        if !all(&|v| allowed.iter().any(|a| a == v)) {
+            return false;
+        }
+    }
+    if let Some(prefixes) = &condition.prefix {
+        if !all(&|v| prefixes.iter().any(|p| v.starts_with(p.as_str()))) {
+            return false;
+        }
+    }
+    if let Some(Pattern(pattern)) = &condition.pattern {
+        if !all(&|v| pattern.is_match(v)) {
+            return false;
+        }
+    }
+    if let Some(wanted) = &condition.tagged {
+        let flake = args.get("flake").and_then(Value::as_str);
+        let mut tagged = Vec::new();
+        for tag in wanted {
+            match tags.tagged(flake, tag).await {
+                Ok(machines) => tagged.extend(machines),
+                Err(e) => {
+                    tracing::warn!(tag = %tag, error = %e, "Failed to look up machine tags");
+                    return false;
+                }
+            }
+        }
+        if !all(&|v| tagged.iter().any(|m| m == v)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The registered name of `tool`, as confirmations need a static name.
+fn static_tool_name(tool: &str) -> &'static str {
+    ToolGroup::of(tool)
+        .and_then(|group| group.tools().iter().find(|t| **t == tool).copied())
+        .unwrap_or("unknown_tool")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Machine tags from a fixed table.
+    struct Tags(HashMap<&'static str, Vec<String>>);
+
+    impl MachineTags for Tags {
+        async fn tagged(&self, _flake: Option<&str>, tag: &str) -> Result<Vec<String>, String> {
+            self.0
+                .get(tag)
+                .cloned()
+                .ok_or_else(|| format!("unknown tag {}", tag))
+        }
+    }
+
+    fn tags() -> Tags {
+        Tags(HashMap::from([
+            ("staging", vec!["stage1".to_string(), "stage2".to_string()]),
+            ("prod", vec!["web1".to_string()]),
+        ]))
+    }
+
+    fn policy() -> Policy {
+        Policy::from_toml_str(
+            "test",
+            r#"
+            [[rules]]
+            tools = ["run_in_shell"]
+            args.packages.one_of = ["python3", "jq"]
+            action = "allow"
+
+            [[rules]]
+            tools = ["run_in_shell"]
+            action = "deny"
+            reason = "only allowlisted packages"
+
+            [[rules]]
+            tools = ["nix_build"]
+            args.package.prefix = [".#", "nixpkgs#"]
+            action = "allow"
+
+            [[rules]]
+            tools = ["nix_build"]
+            action = "deny"
+
+            [[rules]]
+            tools = ["clan_machine_update"]
+            args.machines.tagged = ["staging"]
+            action = "allow"
+
+            [[rules]]
+            groups = ["clan_machines"]
+            action = "require_confirmation"
+            "#,
+        )
+        .unwrap()
+    }
+
+    async fn action(tool: &str, args: Value) -> PolicyAction {
+        let Value::Object(args) = args else {
+            panic!("arguments must be an object");
+        };
+        policy().evaluate(tool, &args, &tags()).await.action
+    }
+
+    #[tokio::test]
+    async fn test_argument_conditions() {
+        use PolicyAction::*;
+
+        assert_eq!(
+            action(
+                "run_in_shell",
+                json!({"packages": ["jq"], "command": "jq ."})
+            )
+            .await,
+            Allow
+        );
+        assert_eq!(
+            action("run_in_shell", json!({"packages": ["jq", "curl"]})).await,
+            Deny
+        );
+        assert_eq!(action("run_in_shell", json!({"command": "ls"})).await, Deny);
+
+        assert_eq!(
+            action("nix_build", json!({"package": ".#default"})).await,
+            Allow
+        );
+        assert_eq!(
+            action("nix_build", json!({"package": "nixpkgs#hello"})).await,
+            Allow
+        );
+        assert_eq!(
+            action("nix_build", json!({"package": "github:evil/flake#x"})).await,
+            Deny
+        );
+
+        assert_eq!(
+            action(
+                "clan_machine_update",
+                json!({"machines": ["stage1", "stage2"]})
+            )
+            .await,
+            Allow
+        );
+        assert_eq!(
+            action(
+                "clan_machine_update",
+                json!({"machines": ["stage1", "web1"]})
+            )
+            .await,
+            RequireConfirmation
+        );
+        // Omitting machines or passing none updates all of them
+        assert_eq!(
+            action("clan_machine_update", json!({})).await,
+            RequireConfirmation
+        );
+        assert_eq!(
+            action("clan_machine_update", json!({"machines": []})).await,
+            RequireConfirmation
+        );
+        assert_eq!(
+            action("clan_machine_delete", json!({"name": "stage1"})).await,
+            RequireConfirmation
+        );
+
+        // No rule matches
+        assert_eq!(action("nix_eval", json!({"expression": "1"})).await, Allow);
+    }
+
+    #[test]
+    fn test_invalid_policies_rejected() {
+        let invalid = [
+            "[[rules]]\ntools = [\"nix_bild\"]\naction = \"deny\"\n",
+            "[[rules]]\naction = \"maybe\"\n",
+            "[[rules]]\nargs.package = {}\naction = \"deny\"\n",
+            "[[rules]]\nargs.package.one_of = []\naction = \"deny\"\n",
+            "[[rules]]\nargs.package.pattern = \"(\"\naction = \"deny\"\n",
+            "[[rules]]\naction = \"deny\"\nextra = 1\n",
+        ];
+        for text in invalid {
+            assert!(Policy::from_toml_str("test", text).is_err(), "{}", text);
+        }
+
+        let err = Policy::from_toml_str("test", invalid[0]).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { ref field, .. } if field == "rules[0].tools"));
+    }
+
+    #[tokio::test]
+    async fn test_denial_is_structured_error() {
+        let mut args = Some(
+            json!({"package": "github:evil/flake#x"})
+                .as_object()
+                .unwrap()
+                .clone(),
+        );
+        let err = policy()
+            .authorize(
+                &AuditLogger::new(),
+                "nix_build",
+                &mut args,
+                &Confirmation::tokens_only(),
+                &tags(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.code, POLICY_DENIED);
+        assert_eq!(err.data.unwrap()["rule"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_with_token() {
+        let policy = Policy::from_toml_str(
+            "test",
+            "[[rules]]\ntools = [\"nix_eval\"]\naction = \"require_confirmation\"\n",
+        )
+        .unwrap();
+        let audit = AuditLogger::new();
+        let confirmation = Confirmation::tokens_only();
+        let call = json!({"expression": "builtins.currentTime"});
+
+        let mut args = Some(call.as_object().unwrap().clone());
+        let result = policy
+            .authorize(&audit, "nix_eval", &mut args, &confirmation, &tags())
+            .await
+            .unwrap()
+            .expect("call ran without confirmation");
+        let text = result.content[0].as_text().unwrap().text.clone();
+        let token = text
+            .split("confirmation_token=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+
+        let mut args = Some(call.as_object().unwrap().clone());
+        args.as_mut()
+            .unwrap()
+            .insert(CONFIRMATION_TOKEN.to_string(), token.into());
+        assert!(policy
+            .authorize(&audit, "nix_eval", &mut args, &confirmation, &tags())
+            .await
+            .unwrap()
+            .is_none());
+        // The token is not passed on to the tool
+        assert_eq!(args.unwrap(), *call.as_object().unwrap());
+
+        // Tools confirming themselves are left to do so
+        let policy = Policy::from_toml_str("test", "default = \"require_confirmation\"\n").unwrap();
+        let mut args = Some(json!({"name": "old"}).as_object().unwrap().clone());
+        assert!(policy
+            .authorize(
+                &audit,
+                "clan_machine_delete",
+                &mut args,
+                &confirmation,
+                &tags()
+            )
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
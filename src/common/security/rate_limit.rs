@@ -0,0 +1,342 @@
+//! Token-bucket rate limiting of tool calls.
+//!
+//! Each tool and each [`ToolGroup`] with a configured
+//! [`RateLimitSettings`] owns a bucket holding up to `burst` tokens that
+//! refills at `per_minute` tokens per minute. A call takes one token from the
+//! bucket of its tool and one from the bucket of its group; if either is empty
+//! the call is rejected with [`RATE_LIMITED`] before it runs, and the error
+//! data tells the client how long to wait.
+//!
+//! ```
+//! use onix_mcp::common::config::{RateLimitConfig, RateLimitSettings};
+//! use onix_mcp::common::security::RateLimiter;
+//!
+//! let mut config = RateLimitConfig::default();
+//! config
+//!     .tools
+//!     .insert("nix_build".to_string(), RateLimitSettings::new(1, 6));
+//! let limiter = RateLimiter::new(&config);
+//!
+//! assert!(limiter.try_acquire("nix_build").is_ok());
+//! let limited = limiter.try_acquire("nix_build").unwrap_err();
+//! assert_eq!(limited.retry_after_secs(), 10);
+//! assert!(limiter.try_acquire("nix_eval").is_ok());
+//! ```
+
+use super::AuditLogger;
+use crate::common::config::{RateLimitConfig, RateLimitSettings};
+use crate::common::tool_registry::ToolGroup;
+use rmcp::model::ErrorCode;
+use rmcp::ErrorData as McpError;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// JSON-RPC error code of calls rejected by a rate limit.
+///
+/// Taken from the implementation-defined server error range; the error data
+/// carries `retry_after_secs`.
+pub const RATE_LIMITED: ErrorCode = ErrorCode(-32029);
+
+/// Window over which recent calls are counted for the audit log.
+const RECENT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Upper bound on remembered call times per bucket.
+const MAX_RECENT_CALLS: usize = 10_000;
+
+/// A call rejected because a bucket was empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    /// Tool that was called
+    pub tool: String,
+    /// `"tool"` or `"group"`, depending on which limit was hit
+    pub scope: &'static str,
+    /// Name of the tool or group whose limit was hit
+    pub key: String,
+    /// The limit that was hit
+    pub limit: RateLimitSettings,
+    /// Time until the bucket holds a token again
+    pub retry_after: Duration,
+    /// Calls (including rejected ones) counted against the limit in the last minute
+    pub recent_calls: u32,
+}
+
+impl RateLimited {
+    /// [`retry_after`](Self::retry_after) rounded up to whole seconds.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rate limit exceeded for {} '{}' ({} calls per minute, burst {}); retry after {}s",
+            self.scope,
+            self.key,
+            self.limit.per_minute,
+            self.limit.burst,
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl From<RateLimited> for McpError {
+    fn from(limited: RateLimited) -> Self {
+        McpError::new(
+            RATE_LIMITED,
+            limited.to_string(),
+            Some(json!({
+                "error": "rate_limited",
+                "tool": limited.tool,
+                "scope": limited.scope,
+                "key": limited.key,
+                "burst": limited.limit.burst,
+                "per_minute": limited.limit.per_minute,
+                "retry_after_secs": limited.retry_after_secs(),
+                "retry_after_ms": limited.retry_after.as_millis() as u64,
+            })),
+        )
+    }
+}
+
+struct Bucket {
+    settings: RateLimitSettings,
+    tokens: f64,
+    updated: Instant,
+    recent: VecDeque<Instant>,
+}
+
+impl Bucket {
+    fn new(settings: RateLimitSettings, now: Instant) -> Self {
+        Self {
+            settings,
+            tokens: f64::from(settings.burst),
+            updated: now,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Tokens added per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.settings.per_minute) / 60.0
+    }
+
+    /// Add the tokens accrued since the last call and record this call.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate()).min(f64::from(self.settings.burst));
+        self.updated = now;
+
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) >= RECENT_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < MAX_RECENT_CALLS {
+            self.recent.push_back(now);
+        }
+    }
+
+    /// Time until a whole token is available.
+    fn retry_after(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate())
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    tools: HashMap<String, Bucket>,
+    groups: HashMap<ToolGroup, Bucket>,
+}
+
+/// Rate limits shared by every session of a server.
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Create full buckets for every configured limit.
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let now = Instant::now();
+        let buckets = Buckets {
+            tools: config
+                .tools
+                .iter()
+                .map(|(tool, settings)| (tool.clone(), Bucket::new(*settings, now)))
+                .collect(),
+            groups: config
+                .groups
+                .iter()
+                .map(|(group, settings)| (*group, Bucket::new(*settings, now)))
+                .collect(),
+        };
+        Self {
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    /// Take a token for a call to `tool`, logging a rejection to `audit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`RATE_LIMITED`] error with `retry_after_secs` in its data if
+    /// the tool's or its group's bucket is empty.
+    pub fn check(&self, audit: &AuditLogger, tool: &str) -> Result<(), McpError> {
+        self.try_acquire(tool).map_err(|limited| {
+            let operation = if limited.scope == "tool" {
+                tool.to_string()
+            } else {
+                format!("{} (group {})", tool, limited.key)
+            };
+            audit.log_rate_limit_exceeded(
+                &operation,
+                limited.limit.per_minute,
+                limited.recent_calls,
+            );
+            limited.into()
+        })
+    }
+
+    /// Take a token for a call to `tool` from its tool and group buckets.
+    ///
+    /// Tokens are only taken if both buckets have one, so a call rejected by
+    /// its group limit does not count against its tool limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns the limit with the longest wait if any bucket is empty.
+    pub fn try_acquire(&self, tool: &str) -> Result<(), RateLimited> {
+        self.try_acquire_at(tool, Instant::now())
+    }
+
+    fn try_acquire_at(&self, tool: &str, now: Instant) -> Result<(), RateLimited> {
+        let mut guard = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let buckets = &mut *guard;
+
+        let mut limits: Vec<(&'static str, String, &mut Bucket)> = Vec::with_capacity(2);
+        if let Some(bucket) = buckets.tools.get_mut(tool) {
+            limits.push(("tool", tool.to_string(), bucket));
+        }
+        if let Some(group) = ToolGroup::of(tool) {
+            if let Some(bucket) = buckets.groups.get_mut(&group) {
+                limits.push(("group", group.as_str().to_string(), bucket));
+            }
+        }
+
+        for (_, _, bucket) in limits.iter_mut() {
+            bucket.refill(now);
+        }
+
+        let exhausted = limits
+            .iter()
+            .filter(|(_, _, bucket)| bucket.tokens < 1.0)
+            .max_by_key(|(_, _, bucket)| bucket.retry_after());
+        if let Some((scope, key, bucket)) = exhausted {
+            return Err(RateLimited {
+                tool: tool.to_string(),
+                scope,
+                key: key.clone(),
+                limit: bucket.settings,
+                retry_after: bucket.retry_after(),
+                recent_calls: bucket.recent.len() as u32,
+            });
+        }
+
+        for (_, _, bucket) in limits {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(tools: &[(&str, u32, u32)], groups: &[(ToolGroup, u32, u32)]) -> RateLimiter {
+        let config = RateLimitConfig {
+            tools: tools
+                .iter()
+                .map(|(t, b, p)| (t.to_string(), RateLimitSettings::new(*b, *p)))
+                .collect(),
+            groups: groups
+                .iter()
+                .map(|(g, b, p)| (*g, RateLimitSettings::new(*b, *p)))
+                .collect(),
+        };
+        RateLimiter::new(&config)
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter(&[("nix_build", 2, 60)], &[]);
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("nix_build", start).is_ok());
+        assert!(limiter.try_acquire_at("nix_build", start).is_ok());
+        let limited = limiter.try_acquire_at("nix_build", start).unwrap_err();
+        assert_eq!(limited.scope, "tool");
+        assert_eq!(limited.retry_after_secs(), 1);
+        assert_eq!(limited.recent_calls, 3);
+
+        // One token per second
+        let later = start + Duration::from_millis(1500);
+        assert!(limiter.try_acquire_at("nix_build", later).is_ok());
+        assert!(limiter.try_acquire_at("nix_build", later).is_err());
+
+        // Refill is capped at the burst size
+        let much_later = later + Duration::from_secs(600);
+        assert!(limiter.try_acquire_at("nix_build", much_later).is_ok());
+        assert!(limiter.try_acquire_at("nix_build", much_later).is_ok());
+        assert!(limiter.try_acquire_at("nix_build", much_later).is_err());
+    }
+
+    #[test]
+    fn test_group_limit_shared_by_tools() {
+        let limiter = limiter(
+            &[("clan_machine_update", 5, 60)],
+            &[(ToolGroup::ClanMachines, 2, 6)],
+        );
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at("clan_machine_list", now).is_ok());
+        assert!(limiter.try_acquire_at("clan_machine_update", now).is_ok());
+        let limited = limiter
+            .try_acquire_at("clan_machine_update", now)
+            .unwrap_err();
+        assert_eq!(limited.scope, "group");
+        assert_eq!(limited.key, "clan_machines");
+        assert_eq!(limited.retry_after_secs(), 10);
+
+        // Group rejections do not use up the tool's own tokens
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.tools["clan_machine_update"].tokens, 4.0);
+        drop(buckets);
+
+        // Tools in other groups are unaffected
+        assert!(limiter.try_acquire_at("nix_eval", now).is_ok());
+    }
+
+    #[test]
+    fn test_rejection_is_structured_error() {
+        let limiter = limiter(&[("nix_eval", 1, 1)], &[]);
+        let audit = AuditLogger::new();
+
+        assert!(limiter.check(&audit, "nix_eval").is_ok());
+        let err = limiter.check(&audit, "nix_eval").unwrap_err();
+        assert_eq!(err.code, RATE_LIMITED);
+        assert!(err.message.contains("retry after"));
+
+        let data = err.data.unwrap();
+        assert_eq!(data["tool"], "nix_eval");
+        assert_eq!(data["scope"], "tool");
+        assert_eq!(data["per_minute"], 1);
+        let retry_after = data["retry_after_secs"].as_u64().unwrap();
+        assert!((59..=60).contains(&retry_after));
+    }
+}
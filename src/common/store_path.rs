@@ -0,0 +1,144 @@
+//! Validated Nix store paths.
+//!
+//! A [`StorePath`] is `/nix/store/<hash>-<name>`: a 32 character hash in
+//! Nix's base-32 alphabet followed by a name of at most 211 characters. Paths
+//! below a store object (`/nix/store/...-hello/bin/hello`) are not store paths.
+//!
+//! ```
+//! use onix_mcp::common::store_path::StorePath;
+//!
+//! let path = StorePath::parse("/nix/store/0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5-hello-2.12.1.drv").unwrap();
+//! assert_eq!(path.name(), "hello-2.12.1.drv");
+//! assert!(path.is_derivation());
+//! assert!(StorePath::parse("/nix/store/not-a-hash").is_err());
+//! ```
+
+use crate::common::security::ValidationError;
+use std::fmt;
+use std::str::FromStr;
+
+/// Directory holding the Nix store.
+pub const STORE_DIR: &str = "/nix/store";
+
+/// Length of the hash part of a store path.
+const HASH_LEN: usize = 32;
+
+/// Longest store path name Nix accepts.
+const MAX_NAME_LEN: usize = 211;
+
+/// Nix's base-32 alphabet, which omits `e`, `o`, `u` and `t`.
+const NIX_BASE32: &str = "0123456789abcdfghijklmnpqrsvwxyz";
+
+/// A path directly inside the Nix store.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StorePath {
+    hash: String,
+    name: String,
+}
+
+impl StorePath {
+    /// Parse an absolute store path.
+    pub fn parse(input: &str) -> Result<Self, ValidationError> {
+        let invalid = |expected: &str| ValidationError::InvalidFormat {
+            field: "store_path".to_string(),
+            expected: expected.to_string(),
+            got: input.to_string(),
+        };
+
+        let base = input
+            .strip_prefix(STORE_DIR)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or_else(|| invalid("path starting with /nix/store/"))?;
+        let (hash, name) = base
+            .split_once('-')
+            .ok_or_else(|| invalid("/nix/store/<hash>-<name>"))?;
+
+        if hash.len() != HASH_LEN || !hash.chars().all(|c| NIX_BASE32.contains(c)) {
+            return Err(invalid("32 character base-32 hash after /nix/store/"));
+        }
+        if !is_valid_name(name) {
+            return Err(invalid(
+                "store path name of letters, digits and +-._?= (at most 211 characters)",
+            ));
+        }
+
+        Ok(Self {
+            hash: hash.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Hash part, e.g. `0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5`.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Name part, e.g. `hello-2.12.1`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether this is a derivation (`.drv`) file.
+    pub fn is_derivation(&self) -> bool {
+        self.name.ends_with(".drv")
+    }
+}
+
+/// Whether `name` is a valid store path name, which is also the rule for
+/// derivation output names.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-._?=".contains(c))
+}
+
+impl FromStr for StorePath {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for StorePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}-{}", STORE_DIR, self.hash, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0c5vwqjmk1w5hp7xw7a8jc45fglx9vz5";
+
+    #[test]
+    fn test_parse_store_path() {
+        let input = format!("/nix/store/{}-hello-2.12.1", HASH);
+        let path = StorePath::parse(&input).unwrap();
+        assert_eq!(path.hash(), HASH);
+        assert_eq!(path.name(), "hello-2.12.1");
+        assert!(!path.is_derivation());
+        assert_eq!(path.to_string(), input);
+    }
+
+    #[test]
+    fn test_invalid_store_paths_rejected() {
+        for input in [
+            format!("/nix/store/{}", HASH),
+            format!("/nix/store/{}-", HASH),
+            format!("/nix/store/{}-.hidden", HASH),
+            format!("/nix/store/{}-hello/bin/hello", HASH),
+            format!("/nix/store/{}-hello world", HASH),
+            format!("/nix/store/{}-{}", HASH, "a".repeat(212)),
+            format!("/tmp/{}-hello", HASH),
+            "/nix/store/eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee-hello".to_string(),
+            "/nix/store/abc-hello".to_string(),
+        ] {
+            assert!(StorePath::parse(&input).is_err(), "accepted: {}", input);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Structured tool results.
+//!
+//! Tools that declare an `output_schema` return their result twice: as
+//! `structured_content` matching the schema, for agents and scripts that
+//! consume fields, and as human-readable text content for clients that only
+//! display text.
+//!
+//! ```ignore
+//! #[tool(
+//!     description = "Get the closure size of a package",
+//!     output_schema = output_schema::<ClosureSizeResult>()
+//! )]
+//! ```
+
+use rmcp::handler::server::tool::cached_schema_for_type;
+use rmcp::model::{CallToolResult, Content, JsonObject};
+use rmcp::schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// A typed tool result with a text rendering.
+pub trait ToolOutput: Serialize + JsonSchema {
+    /// Human-readable rendering used as the text content.
+    fn to_text(&self) -> String;
+
+    /// Build a successful `CallToolResult` carrying both text and structured content.
+    fn to_tool_result(&self) -> CallToolResult {
+        let mut result = CallToolResult::success(vec![Content::text(self.to_text())]);
+        result.structured_content = serde_json::to_value(self).ok();
+        result
+    }
+}
+
+/// JSON schema for a tool's `output_schema`.
+pub fn output_schema<T: JsonSchema + 'static>() -> Arc<JsonObject> {
+    cached_schema_for_type::<T>()
+}
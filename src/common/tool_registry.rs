@@ -1,7 +1,246 @@
 use crate::common::cache_registry::CacheRegistry;
+use crate::common::config::Config;
 use crate::common::security::AuditLogger;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// Groups of related tools, matching the tool modules held by [`ToolRegistry`].
+///
+/// Groups are the unit used to enable or disable tools in the configuration
+/// file (`[tools] enabled_groups`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolGroup {
+    Packages,
+    Build,
+    Develop,
+    Flakes,
+    Quality,
+    Info,
+    ClanMachines,
+    ClanBackups,
+    ClanAnalysis,
+    PreCommit,
+    Pueue,
+    Pexpect,
+    Audit,
+}
+
+impl ToolGroup {
+    /// All tool groups, in registry order.
+    pub const ALL: [ToolGroup; 13] = [
+        ToolGroup::Packages,
+        ToolGroup::Build,
+        ToolGroup::Develop,
+        ToolGroup::Flakes,
+        ToolGroup::Quality,
+        ToolGroup::Info,
+        ToolGroup::ClanMachines,
+        ToolGroup::ClanBackups,
+        ToolGroup::ClanAnalysis,
+        ToolGroup::PreCommit,
+        ToolGroup::Pueue,
+        ToolGroup::Pexpect,
+        ToolGroup::Audit,
+    ];
+
+    /// Name used for this group in configuration files.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ToolGroup::Packages => "packages",
+            ToolGroup::Build => "build",
+            ToolGroup::Develop => "develop",
+            ToolGroup::Flakes => "flakes",
+            ToolGroup::Quality => "quality",
+            ToolGroup::Info => "info",
+            ToolGroup::ClanMachines => "clan_machines",
+            ToolGroup::ClanBackups => "clan_backups",
+            ToolGroup::ClanAnalysis => "clan_analysis",
+            ToolGroup::PreCommit => "pre_commit",
+            ToolGroup::Pueue => "pueue",
+            ToolGroup::Pexpect => "pexpect",
+            ToolGroup::Audit => "audit",
+        }
+    }
+
+    /// Names of the MCP tools belonging to this group.
+    pub fn tools(self) -> &'static [&'static str] {
+        match self {
+            ToolGroup::Packages => &[
+                "search_packages",
+                "get_package_info",
+                "explain_package",
+                "find_command",
+                "nix_locate",
+                "comma",
+            ],
+            ToolGroup::Build => &[
+                "nix_build",
+                "why_depends",
+                "show_derivation",
+                "get_closure_size",
+                "get_build_log",
+                "diff_derivations",
+                "nixos_build",
+            ],
+            ToolGroup::Develop => &[
+                "search_options",
+                "nix_eval",
+                "run_in_shell",
+                "nix_run",
+                "nix_develop",
+                "nix_log",
+            ],
+            ToolGroup::Flakes => &["flake_metadata", "flake_show", "prefetch_url"],
+            ToolGroup::Quality => &["format_nix", "validate_nix", "lint_nix", "nix_fmt"],
+            ToolGroup::Info => &["ecosystem_tools", "nix_command_help"],
+            ToolGroup::ClanMachines => &[
+                "clan_machine_create",
+                "clan_machine_list",
+                "clan_machine_update",
+                "clan_machine_delete",
+                "clan_machine_install",
+                "clan_machine_build",
+            ],
+            ToolGroup::ClanBackups => &[
+                "clan_backup_create",
+                "clan_backup_list",
+                "clan_backup_restore",
+            ],
+            ToolGroup::ClanAnalysis => &[
+                "clan_analyze_secrets",
+                "clan_analyze_vars",
+                "clan_analyze_tags",
+                "clan_analyze_roster",
+                "clan_secrets_list",
+                "clan_flake_create",
+                "clan_vm_create",
+                "clan_help",
+            ],
+            ToolGroup::PreCommit => &[
+                "pre_commit_run",
+                "check_pre_commit_status",
+                "setup_pre_commit",
+            ],
+            ToolGroup::Pueue => &[
+                "pueue_add",
+                "pueue_status",
+                "pueue_log",
+                "pueue_wait",
+                "pueue_remove",
+                "pueue_clean",
+                "pueue_pause",
+                "pueue_start",
+            ],
+            ToolGroup::Pexpect => &["pexpect_start", "pexpect_send", "pexpect_close"],
+            ToolGroup::Audit => &["audit_query"],
+        }
+    }
+
+    /// Find the group a tool belongs to.
+    pub fn of(tool_name: &str) -> Option<ToolGroup> {
+        ToolGroup::ALL
+            .into_iter()
+            .find(|group| group.tools().contains(&tool_name))
+    }
+}
+
+impl std::fmt::Display for ToolGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Startup profile restricting which tools are exposed, based on their annotations.
+///
+/// Profiles are applied on top of `[tools] enabled_groups`: a tool is exposed
+/// only if its group is enabled and the profile allows it.
+///
+/// - `full`: every tool (default)
+/// - `readonly`: only tools annotated `read_only_hint = true`
+/// - `dev`: everything except tools annotated `destructive_hint = true`
+///   (no deploys, installs, deletes or restores)
+/// - `ops`: read-only tools plus builds and Clan machine/backup operations,
+///   but no arbitrary command execution (shells, pueue, pexpect)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Full,
+    Readonly,
+    Dev,
+    Ops,
+}
+
+impl Profile {
+    /// All profiles.
+    pub const ALL: [Profile; 4] = [Profile::Full, Profile::Readonly, Profile::Dev, Profile::Ops];
+
+    /// Name used for this profile in configuration files and on the command line.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Full => "full",
+            Profile::Readonly => "readonly",
+            Profile::Dev => "dev",
+            Profile::Ops => "ops",
+        }
+    }
+
+    /// Whether `tool` is exposed under this profile.
+    ///
+    /// Missing annotations take the MCP defaults, which are conservative: a
+    /// tool without `read_only_hint = true` is never considered read-only, and
+    /// a tool that is not read-only is destructive unless annotated
+    /// `destructive_hint = false`.
+    pub fn allows(self, tool: &rmcp::model::Tool) -> bool {
+        let read_only = tool
+            .annotations
+            .as_ref()
+            .and_then(|a| a.read_only_hint)
+            .unwrap_or(false);
+        let destructive = tool
+            .annotations
+            .as_ref()
+            .and_then(|a| a.destructive_hint)
+            .unwrap_or(!read_only);
+
+        match self {
+            Profile::Full => true,
+            Profile::Readonly => read_only,
+            Profile::Dev => !destructive,
+            Profile::Ops => {
+                read_only
+                    || matches!(
+                        ToolGroup::of(&tool.name),
+                        Some(ToolGroup::Build | ToolGroup::ClanMachines | ToolGroup::ClanBackups)
+                    )
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Profile::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown profile '{}' (expected one of: full, readonly, dev, ops)",
+                    s
+                )
+            })
+    }
+}
+
 /// Central registry for all tool modules in the MCP server.
 ///
 /// This struct consolidates all specialized tool implementations,
@@ -28,6 +267,9 @@ pub struct ToolRegistry {
     pub backup: Arc<crate::clan::BackupTools>,
     pub analysis: Arc<crate::clan::AnalysisTools>,
 
+    // Audit review tools
+    pub audit_log: Arc<crate::common::audit_tools::AuditTools>,
+
     // Prompts
     pub prompts: Arc<crate::prompts::NixPrompts>,
 }
@@ -38,31 +280,74 @@ impl ToolRegistry {
     /// # Arguments
     /// * `audit` - Shared audit logger for security logging
     /// * `caches` - Shared cache registry for all caching needs
-    pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>) -> Self {
+    /// * `config` - Server configuration (per-tool timeouts, default flakes)
+    pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>, config: Arc<Config>) -> Self {
         Self {
             // Development tools - only need audit
-            precommit: Arc::new(crate::dev::PreCommitTools::new(audit.clone())),
+            precommit: Arc::new(crate::dev::PreCommitTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
 
             // Process tools - only need audit
-            pexpect: Arc::new(crate::process::PexpectTools::new(audit.clone())),
-            pueue: Arc::new(crate::process::PueueTools::new(audit.clone())),
+            pexpect: Arc::new(crate::process::PexpectTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
+            pueue: Arc::new(crate::process::PueueTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
 
             // Nix info tools - only need audit
             info: Arc::new(crate::nix::InfoTools::new(audit.clone())),
 
             // Nix tools that use caching
-            package: Arc::new(crate::nix::PackageTools::new(audit.clone(), caches.clone())),
-            build: Arc::new(crate::nix::BuildTools::new(audit.clone(), caches.clone())),
-            develop: Arc::new(crate::nix::DevelopTools::new(audit.clone(), caches.clone())),
-            flake: Arc::new(crate::nix::FlakeTools::new(audit.clone(), caches.clone())),
+            package: Arc::new(crate::nix::PackageTools::with_config(
+                audit.clone(),
+                caches.clone(),
+                config.clone(),
+            )),
+            build: Arc::new(crate::nix::BuildTools::with_config(
+                audit.clone(),
+                caches.clone(),
+                config.clone(),
+            )),
+            develop: Arc::new(crate::nix::DevelopTools::with_config(
+                audit.clone(),
+                caches.clone(),
+                config.clone(),
+            )),
+            flake: Arc::new(crate::nix::FlakeTools::with_config(
+                audit.clone(),
+                caches.clone(),
+                config.clone(),
+            )),
 
             // Nix quality tools - only need audit
-            quality: Arc::new(crate::nix::QualityTools::new(audit.clone())),
+            quality: Arc::new(crate::nix::QualityTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
 
             // Clan infrastructure tools - only need audit
-            machine: Arc::new(crate::clan::MachineTools::new(audit.clone())),
-            backup: Arc::new(crate::clan::BackupTools::new(audit.clone())),
-            analysis: Arc::new(crate::clan::AnalysisTools::new(audit.clone())),
+            machine: Arc::new(crate::clan::MachineTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
+            backup: Arc::new(crate::clan::BackupTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
+            analysis: Arc::new(crate::clan::AnalysisTools::with_config(
+                audit.clone(),
+                config.clone(),
+            )),
+
+            // Audit review tools - read the configured audit trail
+            audit_log: Arc::new(crate::common::audit_tools::AuditTools::with_config(
+                audit, config,
+            )),
 
             // Prompts - no dependencies
             prompts: Arc::new(crate::prompts::NixPrompts::new()),
@@ -80,7 +365,7 @@ mod tests {
         let audit = audit_logger();
         let caches = Arc::new(CacheRegistry::new());
 
-        let registry = ToolRegistry::new(audit, caches);
+        let registry = ToolRegistry::new(audit, caches, Arc::new(Config::default()));
 
         // Verify all tool instances are initialized
         assert!(Arc::strong_count(&registry.precommit) >= 1);
@@ -95,6 +380,7 @@ mod tests {
         assert!(Arc::strong_count(&registry.machine) >= 1);
         assert!(Arc::strong_count(&registry.backup) >= 1);
         assert!(Arc::strong_count(&registry.analysis) >= 1);
+        assert!(Arc::strong_count(&registry.audit_log) >= 1);
         assert!(Arc::strong_count(&registry.prompts) >= 1);
     }
 
@@ -103,7 +389,7 @@ mod tests {
         let audit = audit_logger();
         let caches = Arc::new(CacheRegistry::new());
 
-        let registry1 = ToolRegistry::new(audit, caches);
+        let registry1 = ToolRegistry::new(audit, caches, Arc::new(Config::default()));
         let registry2 = registry1.clone();
 
         // Verify that cloning increases Arc reference counts
@@ -113,4 +399,24 @@ mod tests {
         // Verify both registries point to the same tool instances
         assert!(Arc::ptr_eq(&registry1.package, &registry2.package));
     }
+
+    #[test]
+    fn test_tool_group_lookup() {
+        assert_eq!(ToolGroup::of("nix_build"), Some(ToolGroup::Build));
+        assert_eq!(
+            ToolGroup::of("clan_machine_install"),
+            Some(ToolGroup::ClanMachines)
+        );
+        assert_eq!(ToolGroup::of("not_a_tool"), None);
+    }
+
+    #[test]
+    fn test_tool_groups_are_disjoint() {
+        let mut seen = std::collections::HashSet::new();
+        for group in ToolGroup::ALL {
+            for tool in group.tools() {
+                assert!(seen.insert(*tool), "{} listed in more than one group", tool);
+            }
+        }
+    }
 }
@@ -18,10 +18,11 @@
 //!
 //! ```no_run
 //! use onix_mcp::dev::{PreCommitTools, PreCommitRunArgs};
+//! use onix_mcp::common::security::audit_logger;
 //! use std::sync::Arc;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-//! let audit = Arc::new(/* audit logger */);
+//! let audit = audit_logger();
 //! let tools = PreCommitTools::new(audit);
 //!
 //! // Run pre-commit hooks on all files
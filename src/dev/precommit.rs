@@ -1,3 +1,6 @@
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
 use crate::common::security::audit::AuditLogger;
 use crate::dev::types::{CheckPreCommitStatusArgs, PreCommitRunArgs, SetupPreCommitArgs};
 use rmcp::handler::server::wrapper::Parameters;
@@ -54,6 +57,7 @@ use std::sync::Arc;
 /// ```no_run
 /// use onix_mcp::dev::PreCommitTools;
 /// use onix_mcp::dev::types::PreCommitRunArgs;
+/// use onix_mcp::common::roots::Workspace;
 /// use rmcp::handler::server::wrapper::Parameters;
 /// use std::sync::Arc;
 ///
@@ -62,12 +66,13 @@ use std::sync::Arc;
 /// let result = tools.pre_commit_run(Parameters(PreCommitRunArgs {
 ///     all_files: Some(true),
 ///     hook_ids: None,
-/// })).await?;
+/// }), Workspace::default()).await?;
 /// # Ok(())
 /// # }
 /// ```
 pub struct PreCommitTools {
     pub audit: Arc<AuditLogger>,
+    pub config: Arc<Config>,
 }
 
 impl PreCommitTools {
@@ -82,7 +87,12 @@ impl PreCommitTools {
     /// PreCommitTools does not use caching as hook status and execution
     /// results change frequently during development.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `PreCommitTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
     }
 }
 
@@ -98,18 +108,22 @@ impl PreCommitTools {
             all_files,
             hook_ids,
         }): Parameters<PreCommitRunArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
 
+        // Run in the client's active workspace
+        let project_dir = workspace.resolve_dir(None).await?;
+
         // Wrap tool logic with security
         audit_tool_execution(
             &self.audit,
             "pre_commit_run",
             Some(serde_json::json!({"all_files": &all_files, "hook_ids": &hook_ids})),
             || async {
-                with_timeout(&self.audit, "pre_commit_run", 300, || async {
+                with_timeout(&self.audit, "pre_commit_run", self.config.timeout("pre_commit_run", 300), || async {
                     let mut cmd = tokio::process::Command::new("pre-commit");
-                    cmd.arg("run");
+                    cmd.arg("run").current_dir(&project_dir);
 
                     if all_files.unwrap_or(false) {
                         cmd.arg("--all-files");
@@ -122,7 +136,7 @@ impl PreCommitTools {
                         }
                     }
 
-                    let output = cmd.output().await.map_err(|e| {
+                    let output = cmd.group_output().await.map_err(|e| {
                         McpError::internal_error(
                             format!("Failed to execute pre-commit: {}. Make sure you're in a git repository with pre-commit hooks installed (run 'nix develop' first).", e),
                             None,
@@ -171,9 +185,13 @@ impl PreCommitTools {
     pub async fn check_pre_commit_status(
         &self,
         Parameters(_args): Parameters<CheckPreCommitStatusArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::audit_tool_execution;
 
+        // Check the client's active workspace
+        let project_dir = workspace.resolve_dir(None).await?;
+
         audit_tool_execution(
             &self.audit,
             "check_pre_commit_status",
@@ -183,7 +201,7 @@ impl PreCommitTools {
                 let mut warnings = Vec::new();
 
                 // Check if .git directory exists
-                let git_exists = tokio::fs::metadata(".git").await.is_ok();
+                let git_exists = tokio::fs::metadata(project_dir.join(".git")).await.is_ok();
                 if !git_exists {
                     result.push_str("❌ Not a git repository (no .git directory found)\n");
                     return Ok(CallToolResult::success(vec![Content::text(result)]));
@@ -192,7 +210,7 @@ impl PreCommitTools {
                 // Check if pre-commit is installed (in PATH or via nix develop)
                 let pre_commit_check = tokio::process::Command::new("pre-commit")
                     .arg("--version")
-                    .output()
+                    .group_output()
                     .await;
 
                 let pre_commit_available = match pre_commit_check {
@@ -210,7 +228,7 @@ impl PreCommitTools {
                 };
 
                 // Check if .pre-commit-config.yaml exists
-                let config_exists = tokio::fs::metadata(".pre-commit-config.yaml").await.is_ok();
+                let config_exists = tokio::fs::metadata(project_dir.join(".pre-commit-config.yaml")).await.is_ok();
                 if config_exists {
                     result.push_str("\n✅ .pre-commit-config.yaml found\n");
                 } else {
@@ -219,7 +237,7 @@ impl PreCommitTools {
                 }
 
                 // Check if hooks are installed in .git/hooks/pre-commit
-                let hook_exists = tokio::fs::metadata(".git/hooks/pre-commit").await.is_ok();
+                let hook_exists = tokio::fs::metadata(project_dir.join(".git/hooks/pre-commit")).await.is_ok();
                 if hook_exists {
                     result.push_str("✅ Git pre-commit hook is installed\n");
                 } else {
@@ -265,9 +283,13 @@ impl PreCommitTools {
     pub async fn setup_pre_commit(
         &self,
         Parameters(SetupPreCommitArgs { install }): Parameters<SetupPreCommitArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::audit_tool_execution;
 
+        // Set up the client's active workspace
+        let project_dir = workspace.resolve_dir(None).await?;
+
         audit_tool_execution(
             &self.audit,
             "setup_pre_commit",
@@ -276,7 +298,7 @@ impl PreCommitTools {
                 let mut result = String::new();
 
                 // Check if .git directory exists
-                let git_exists = tokio::fs::metadata(".git").await.is_ok();
+                let git_exists = tokio::fs::metadata(project_dir.join(".git")).await.is_ok();
                 if !git_exists {
                     return Err(McpError::internal_error(
                         "Not a git repository. Initialize git first with 'git init'".to_string(),
@@ -285,7 +307,7 @@ impl PreCommitTools {
                 }
 
                 // Check if flake.nix exists
-                let flake_exists = tokio::fs::metadata("flake.nix").await.is_ok();
+                let flake_exists = tokio::fs::metadata(project_dir.join("flake.nix")).await.is_ok();
 
                 if flake_exists {
                     result.push_str("✅ flake.nix found\n\n");
@@ -307,7 +329,8 @@ impl PreCommitTools {
                     result.push_str("Installing pre-commit hooks...\n");
                     let install_output = tokio::process::Command::new("pre-commit")
                         .arg("install")
-                        .output()
+                        .current_dir(&project_dir)
+                        .group_output()
                         .await
                         .map_err(|e| {
                             McpError::internal_error(
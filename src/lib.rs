@@ -7,3 +7,4 @@ pub mod dev;
 pub mod nix;
 pub mod process;
 pub mod prompts;
+pub mod transport;
@@ -1,26 +1,237 @@
 use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use onix_mcp::common::config::Config;
+use onix_mcp::common::mcp_logging;
 use onix_mcp::common::nix_server::NixServer;
+use onix_mcp::common::security::audit_file::{self, FileAuditSink};
+use onix_mcp::common::security::{audit_logger, Policy};
+use onix_mcp::common::tool_registry::Profile;
 use rmcp::transport::stdio;
 use rmcp::ServiceExt;
-use tracing_subscriber::{self, EnvFilter};
+use std::sync::Arc;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
 
 /// Nix MCP Server - provides tools for Nix package management and development
 /// Run with: nix develop -c cargo run -p mcp-basic-server --features transport-io
 /// Test with: npx @modelcontextprotocol/inspector nix develop -c cargo run -p mcp-basic-server --features transport-io
+#[derive(Debug, Parser)]
+#[command(name = "onix-mcp", version, about)]
+struct Cli {
+    /// Additional configuration file, layered over the XDG config files
+    #[arg(long, value_name = "PATH", env = "ONIX_MCP_CONFIG", global = true)]
+    config: Option<std::path::PathBuf>,
+
+    /// Tool profile (full, readonly, dev, ops), overriding `[tools] profile`
+    #[arg(long, value_name = "PROFILE", env = "ONIX_MCP_PROFILE", global = true)]
+    profile: Option<Profile>,
+
+    /// Serve MCP on a Unix domain socket at this path instead of stdio
+    ///
+    /// Ignored when the process is started via systemd socket activation,
+    /// in which case the inherited socket is used.
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", env = "ONIX_MCP_SOCKET")]
+    socket: Option<std::path::PathBuf>,
+
+    /// Serve MCP over streamable HTTP on this address instead of stdio (e.g., 127.0.0.1:8080)
+    #[cfg(feature = "server-side-http")]
+    #[arg(long, value_name = "ADDR", env = "ONIX_MCP_HTTP_BIND")]
+    // `--socket` only exists on unix
+    #[cfg_attr(unix, arg(conflicts_with = "socket"))]
+    http: Option<std::net::SocketAddr>,
+
+    /// URL path for the HTTP MCP endpoint
+    #[cfg(feature = "server-side-http")]
+    #[arg(long, value_name = "PATH", default_value = "/mcp", requires = "http")]
+    http_path: String,
+
+    /// File containing the bearer token HTTP clients must present
+    ///
+    /// Required when `--http` binds a non-loopback address.
+    #[cfg(feature = "server-side-http")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "ONIX_MCP_HTTP_TOKEN_FILE",
+        requires = "http"
+    )]
+    http_token_file: Option<std::path::PathBuf>,
+
+    /// Browser origin allowed to call the HTTP endpoint (repeatable)
+    ///
+    /// Requests carrying any other `Origin` header are rejected.
+    #[cfg(feature = "server-side-http")]
+    #[arg(
+        long,
+        value_name = "ORIGIN",
+        env = "ONIX_MCP_HTTP_ALLOWED_ORIGINS",
+        value_delimiter = ',',
+        requires = "http"
+    )]
+    http_allowed_origin: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// One-shot commands.
+///
+/// Tool, resource and listing requests run against an in-process server
+/// instance and go through the same tool router, validation and audit logging
+/// as requests from an MCP client, so results can be reproduced from a terminal.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Call a tool and print its result
+    Call {
+        /// Tool name (e.g., get_closure_size, clan_analyze_tags)
+        tool: String,
+
+        /// Tool arguments as a JSON object
+        #[arg(long, value_name = "JSON", default_value = "{}")]
+        json: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// List the available tools
+    ListTools {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Read a resource (e.g., nix://commands/common, nix://package/ripgrep)
+    ReadResource {
+        /// Resource URI
+        uri: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Check the hash chain of the audit log and its rotated files
+    VerifyAudit {
+        /// Audit log file (default: `[audit] file` from the configuration)
+        #[arg(value_name = "PATH")]
+        path: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Print text content only
+    Text,
+    /// Print the full MCP result as JSON
+    Json,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize the tracing subscriber with stderr logging
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
-        .with_writer(std::io::stderr)
-        .with_ansi(false)
+    let cli = Cli::parse();
+
+    // Log to stderr, and forward this crate's events to MCP clients
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(false)
+                .with_filter(
+                    EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()),
+                ),
+        )
+        .with(mcp_logging::layer())
         .init();
 
+    // Load and validate configuration before serving anything
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(profile) = cli.profile {
+        config.tools.profile = profile;
+    }
+    tracing::info!(profile = %config.tools.profile, "Using tool profile");
+
+    if let Some(Command::VerifyAudit { path }) = &cli.command {
+        let Some(path) = path.as_ref().or(config.audit.file.as_ref()) else {
+            anyhow::bail!("no audit log given and `[audit] file` is not configured");
+        };
+        let report = audit_file::verify(path)?;
+        println!(
+            "{} records in {} files verified{}",
+            report.records,
+            report.files.len(),
+            if report.complete {
+                ""
+            } else {
+                " (older files were pruned)"
+            }
+        );
+        if let Some(hash) = report.last_hash {
+            println!("last hash: {}", hash);
+        }
+        return Ok(());
+    }
+
+    audit_logger().set_redactor(config.audit.redactor());
+    if let Some(path) = &config.audit.file {
+        let sink = FileAuditSink::open(path, config.audit.rotation())?;
+        audit_logger().add_sink(Arc::new(sink));
+        tracing::info!(path = %path.display(), "Writing audit log");
+    }
+    let policy = match &config.policy.file {
+        Some(path) => {
+            let policy = Policy::load(path)?;
+            tracing::info!(path = %path.display(), rules = policy.rules.len(), "Using tool call policy");
+            policy
+        }
+        None => Policy::default(),
+    };
+    let server = NixServer::with_config(Arc::new(config)).with_policy(policy);
+
+    if let Some(command) = cli.command {
+        return run_command(server, command).await;
+    }
+
     tracing::info!("Starting Nix MCP Server");
 
+    #[cfg(unix)]
+    {
+        use onix_mcp::transport::unix;
+
+        if let Some(listener) = unix::listener_from_env()? {
+            return unix::serve_listener(server, listener, shutdown_signal()).await;
+        }
+        if let Some(path) = cli.socket.as_deref() {
+            return unix::serve(server, path, shutdown_signal()).await;
+        }
+    }
+
+    #[cfg(feature = "server-side-http")]
+    if let Some(bind) = cli.http {
+        let bearer_token = match &cli.http_token_file {
+            Some(path) => Some(
+                std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("reading {}: {}", path.display(), e))?
+                    .trim()
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let http_config = onix_mcp::transport::http::HttpConfig {
+            bind,
+            path: cli.http_path,
+            bearer_token,
+            allowed_origins: cli.http_allowed_origin,
+            ..Default::default()
+        };
+        return onix_mcp::transport::http::serve(server, http_config, shutdown_signal()).await;
+    }
+
     // Create an instance of our Nix server
     #[cfg(feature = "transport-io")]
-    let service = NixServer::new().serve(stdio()).await.inspect_err(|e| {
+    let service = server.serve(stdio()).await.inspect_err(|e| {
         tracing::error!("serving error: {:?}", e);
     })?;
 
@@ -33,3 +244,107 @@ async fn main() -> Result<()> {
     service.waiting().await?;
     Ok(())
 }
+
+/// Run a one-shot CLI command and exit with a non-zero status on tool errors.
+async fn run_command(server: NixServer, command: Command) -> Result<()> {
+    use rmcp::model::{
+        CallToolRequestParam, RawContent, ReadResourceRequestParam, ResourceContents,
+    };
+
+    let client = onix_mcp::transport::local::connect(server).await?;
+
+    let failed = match command {
+        Command::Call { tool, json, format } => {
+            let arguments = match serde_json::from_str::<serde_json::Value>(&json)? {
+                serde_json::Value::Object(map) => Some(map),
+                _ => anyhow::bail!("--json must be a JSON object"),
+            };
+
+            let result = client
+                .call_tool(CallToolRequestParam {
+                    name: tool.into(),
+                    arguments,
+                })
+                .await?;
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+                OutputFormat::Text => {
+                    for content in &result.content {
+                        match &content.raw {
+                            RawContent::Text(text) => println!("{}", text.text),
+                            other => println!("{}", serde_json::to_string_pretty(other)?),
+                        }
+                    }
+                }
+            }
+            result.is_error.unwrap_or(false)
+        }
+        Command::ListTools { format } => {
+            let tools = client.list_all_tools().await?;
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&tools)?),
+                OutputFormat::Text => {
+                    for tool in &tools {
+                        let description = tool.description.as_deref().unwrap_or("");
+                        println!("{:<32} {}", tool.name, description);
+                    }
+                }
+            }
+            false
+        }
+        Command::ReadResource { uri, format } => {
+            let result = client
+                .read_resource(ReadResourceRequestParam { uri })
+                .await?;
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+                OutputFormat::Text => {
+                    for contents in &result.contents {
+                        match contents {
+                            ResourceContents::TextResourceContents { text, .. } => {
+                                println!("{}", text)
+                            }
+                            ResourceContents::BlobResourceContents { uri, .. } => {
+                                println!("<binary resource: {}>", uri)
+                            }
+                        }
+                    }
+                }
+            }
+            false
+        }
+        Command::VerifyAudit { .. } => unreachable!("handled before the server starts"),
+    };
+
+    let _ = client.cancel().await;
+
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Resolve when the process receives Ctrl-C or (on Unix) SIGTERM from systemd.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    tracing::info!("Shutdown signal received");
+}
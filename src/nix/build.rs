@@ -1,9 +1,18 @@
 use crate::common::cache_registry::CacheRegistry;
+use crate::common::caching::CachedExecutor;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::nix_tools_helpers::{format_size, parse_build_plan, parse_closure_size};
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::progress::{output_with_progress, ProgressReporter};
+use crate::common::revision::RevisionResolver;
+use crate::common::roots::Workspace;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::helpers::{
     audit_tool_execution, validation_error_to_mcp, with_timeout,
 };
-use crate::common::security::{validate_flake_ref, validate_package_name};
+use crate::common::security::{validate_flake_ref, validate_installable};
+use crate::common::structured::{output_schema, ToolOutput};
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{CallToolResult, Content};
 use rmcp::ErrorData as McpError;
@@ -11,8 +20,8 @@ use rmcp::{tool, tool_router};
 use std::sync::Arc;
 
 use super::types::{
-    DiffDerivationsArgs, GetBuildLogArgs, GetClosureSizeArgs, NixBuildArgs, NixosBuildArgs,
-    ShowDerivationArgs, WhyDependsArgs,
+    BuiltDerivation, ClosureSizeResult, DiffDerivationsArgs, GetBuildLogArgs, GetClosureSizeArgs,
+    NixBuildArgs, NixBuildResult, NixosBuildArgs, ShowDerivationArgs, WhyDependsArgs,
 };
 
 /// Tools for building packages and analyzing dependencies.
@@ -43,11 +52,13 @@ use super::types::{
 ///
 /// ```no_run
 /// use onix_mcp::nix::{BuildTools, NixBuildArgs};
+/// use onix_mcp::common::cache_registry::CacheRegistry;
+/// use onix_mcp::common::security::audit_logger;
 /// use std::sync::Arc;
 ///
 /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-/// let audit = Arc::new(/* audit logger */);
-/// let caches = Arc::new(/* cache registry */);
+/// let audit = audit_logger();
+/// let caches = Arc::new(CacheRegistry::new());
 /// let tools = BuildTools::new(audit, caches);
 ///
 /// // Dry-run build to see what would be built
@@ -61,6 +72,7 @@ use super::types::{
 pub struct BuildTools {
     audit: Arc<AuditLogger>,
     caches: Arc<CacheRegistry>,
+    config: Arc<Config>,
 }
 
 impl BuildTools {
@@ -71,19 +83,166 @@ impl BuildTools {
     /// * `audit` - Shared audit logger for security event logging
     /// * `caches` - Shared cache registry containing closure_size and derivation caches
     pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>) -> Self {
-        Self { audit, caches }
+        Self::with_config(audit, caches, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `BuildTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(
+        audit: Arc<AuditLogger>,
+        caches: Arc<CacheRegistry>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            audit,
+            caches,
+            config,
+        }
+    }
+}
+
+/// Parse the derivations and output paths printed by `nix build --json`.
+fn parse_built_derivations(json: &serde_json::Value) -> Vec<BuiltDerivation> {
+    json.as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let drv_path = item.get("drvPath")?.as_str()?.to_string();
+                    let outputs = item
+                        .get("outputs")
+                        .and_then(|v| v.as_object())
+                        .map(|outs| {
+                            outs.iter()
+                                .filter_map(|(name, path)| {
+                                    Some((name.clone(), path.as_str()?.to_string()))
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    Some(BuiltDerivation { drv_path, outputs })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Summarize the first derivation printed by `nix derivation show`.
+fn format_derivation(drv_json: &serde_json::Value) -> String {
+    let mut result = String::from("Derivation Details:\n\n");
+
+    // Get the first (and usually only) derivation
+    if let Some((drv_path, drv_info)) = drv_json.as_object().and_then(|obj| obj.iter().next()) {
+        result.push_str(&format!("Path: {}\n\n", drv_path));
+
+        if let Some(outputs) = drv_info.get("outputs").and_then(|v| v.as_object()) {
+            result.push_str("Outputs:\n");
+            for (name, info) in outputs {
+                result.push_str(&format!("  - {}\n", name));
+                if let Some(path) = info.get("path").and_then(|v| v.as_str()) {
+                    result.push_str(&format!("    Path: {}\n", path));
+                }
+            }
+            result.push('\n');
+        }
+
+        if let Some(inputs) = drv_info.get("inputDrvs").and_then(|v| v.as_object()) {
+            result.push_str(&format!(
+                "Build Dependencies: {} derivations\n",
+                inputs.len()
+            ));
+        }
+
+        if let Some(env) = drv_info.get("env").and_then(|v| v.as_object()) {
+            result.push_str("\nKey Environment Variables:\n");
+            for key in ["name", "version", "src", "builder", "system", "outputs"] {
+                if let Some(value) = env.get(key).and_then(|v| v.as_str()) {
+                    result.push_str(&format!("  {}: {}\n", key, value));
+                }
+            }
+        }
+
+        result.push_str("\nFull JSON available for detailed inspection.");
+    }
+
+    result
+}
+
+impl ToolOutput for NixBuildResult {
+    fn to_text(&self) -> String {
+        if !self.success {
+            let error = self.error.as_deref().unwrap_or_default();
+            return if self.dry_run {
+                format!("Dry-run build check failed:\n\n{}", error)
+            } else {
+                format!("Build failed:\n\n{}", error)
+            };
+        }
+
+        if self.dry_run {
+            let mut result = String::from("Dry-run completed successfully.\n\nBuild plan:\n");
+            if self.will_build.is_empty() && self.will_fetch.is_empty() {
+                result.push_str("Nothing to build or fetch; all outputs are available.\n");
+            }
+            if !self.will_build.is_empty() {
+                result.push_str(&format!(
+                    "Will build {} derivations:\n",
+                    self.will_build.len()
+                ));
+                for drv in &self.will_build {
+                    result.push_str(&format!("  {}\n", drv));
+                }
+            }
+            if !self.will_fetch.is_empty() {
+                result.push_str(&format!("Will fetch {} paths:\n", self.will_fetch.len()));
+                for path in &self.will_fetch {
+                    result.push_str(&format!("  {}\n", path));
+                }
+            }
+            return result;
+        }
+
+        let mut result = String::from("Build completed successfully!\n\n");
+        for drv in &self.outputs {
+            result.push_str(&format!("Derivation: {}\n", drv.drv_path));
+            result.push_str("Outputs:\n");
+            for (name, path) in &drv.outputs {
+                result.push_str(&format!("  {}: {}\n", name, path));
+            }
+        }
+        result.push_str("\nNo ./result symlink was created; use the store paths above.\n");
+        result
+    }
+}
+
+impl ToolOutput for ClosureSizeResult {
+    fn to_text(&self) -> String {
+        format!(
+            "Package: {}\nClosure Size: {} ({} bytes)\n\nThis includes the package and all its dependencies.",
+            self.package, self.human_size, self.closure_size_bytes
+        )
     }
 }
 
 #[tool_router]
 impl BuildTools {
-    #[tool(description = "Build a Nix package and show what will be built or the build output")]
+    #[tool(
+        description = "Build a Nix package and show what will be built or the build output",
+        output_schema = output_schema::<NixBuildResult>()
+    )]
     pub async fn nix_build(
         &self,
         Parameters(NixBuildArgs { package, dry_run }): Parameters<NixBuildArgs>,
+        progress: ProgressReporter,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package reference
-        validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
+        // Validate installable
+        let installable = validate_installable(&package).map_err(validation_error_to_mcp)?;
+
+        // Resolve against the client's workspace roots
+        let target = workspace
+            .resolve_installable(installable)
+            .await?
+            .to_string();
 
         // Execute with security features (audit logging + 300s timeout for builds)
         audit_tool_execution(
@@ -91,19 +250,29 @@ impl BuildTools {
             "nix_build",
             Some(serde_json::json!({"package": &package, "dry_run": dry_run})),
             || async {
-                with_timeout(&self.audit, "nix_build", 300, || async {
-                    let dry_run = dry_run.unwrap_or(false);
-
-                    let mut args = vec!["build"];
-                    if dry_run {
-                        args.push("--dry-run");
-                    }
-                    args.push(&package);
-                    args.push("--json");
+                let _slot = scheduler()
+                    .acquire(&self.audit, "nix_build", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "nix_build",
+                    self.config.timeout("nix_build", 300),
+                    || async {
+                        let dry_run = dry_run.unwrap_or(false);
+
+                        // The server's working directory is not the client's
+                        // project, so outputs are reported instead of linked
+                        let mut args = vec!["build", "--no-link"];
+                        if dry_run {
+                            args.push("--dry-run");
+                        }
+                        args.push(&target);
+                        args.push("--json");
 
-                    let output = tokio::process::Command::new("nix")
-                        .args(&args)
-                        .output()
+                        let output = output_with_progress(
+                            tokio::process::Command::new("nix").args(&args),
+                            &progress,
+                        )
                         .await
                         .map_err(|e| {
                             McpError::internal_error(
@@ -112,84 +281,41 @@ impl BuildTools {
                             )
                         })?;
 
-                    if !output.status.success() {
                         let stderr = String::from_utf8_lossy(&output.stderr);
-
-                        let error_msg = if dry_run {
-                            format!("Dry-run build check failed:\n\n{}", stderr)
-                        } else {
-                            format!("Build failed:\n\n{}", stderr)
+                        let mut result = NixBuildResult {
+                            package: package.clone(),
+                            dry_run,
+                            success: output.status.success(),
+                            outputs: Vec::new(),
+                            will_build: Vec::new(),
+                            will_fetch: Vec::new(),
+                            error: None,
                         };
 
-                        return Ok(CallToolResult::success(vec![Content::text(error_msg)]));
-                    }
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
+                        if !result.success {
+                            result.error = Some(stderr.to_string());
+                            return Ok(result.to_tool_result());
+                        }
 
-                    if dry_run {
-                        // For dry-run, parse what would be built
-                        let result = if let Ok(json_output) =
-                            serde_json::from_str::<serde_json::Value>(&stdout)
-                        {
-                            format!(
-                                "Dry-run completed successfully.\n\nBuild plan:\n{}",
-                                serde_json::to_string_pretty(&json_output)
-                                    .unwrap_or_else(|_| stdout.to_string())
-                            )
-                        } else {
-                            let stderr = String::from_utf8_lossy(&output.stderr);
-                            format!("Dry-run completed successfully.\n\n{}", stderr)
-                        };
-                        Ok(CallToolResult::success(vec![Content::text(result)]))
-                    } else {
-                        // For actual build, show the result
-                        if let Ok(json_output) = serde_json::from_str::<serde_json::Value>(&stdout)
+                        if let Ok(json_output) =
+                            serde_json::from_slice::<serde_json::Value>(&output.stdout)
                         {
-                            let mut result = String::from("Build completed successfully!\n\n");
-
-                            if let Some(arr) = json_output.as_array() {
-                                for item in arr {
-                                    if let Some(drv_path) =
-                                        item.get("drvPath").and_then(|v| v.as_str())
-                                    {
-                                        result.push_str(&format!("Derivation: {}\n", drv_path));
-                                    }
-                                    if let Some(out_paths) =
-                                        item.get("outputs").and_then(|v| v.as_object())
-                                    {
-                                        result.push_str("Outputs:\n");
-                                        for (name, path) in out_paths {
-                                            if let Some(path_str) = path.as_str() {
-                                                result.push_str(&format!(
-                                                    "  {}: {}\n",
-                                                    name, path_str
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-
-                            result.push_str("\nResult symlink created: ./result\n");
-                            Ok(CallToolResult::success(vec![Content::text(result)]))
-                        } else {
-                            Ok(CallToolResult::success(vec![Content::text(format!(
-                                "Build completed!\n\n{}",
-                                stdout
-                            ))]))
+                            result.outputs = parse_built_derivations(&json_output);
                         }
-                    }
-                })
+                        if dry_run {
+                            (result.will_build, result.will_fetch) = parse_build_plan(&stderr);
+                        }
+
+                        Ok(result.to_tool_result())
+                    },
+                )
                 .await
             },
         )
         .await
     }
 
-    #[tool(
-        description = "Explain why one package depends on another (show dependency chain)",
-        annotations(read_only_hint = true)
-    )]
+    #[tool(description = "Explain why one package depends on another (show dependency chain)")]
     pub async fn why_depends(
         &self,
         Parameters(WhyDependsArgs {
@@ -197,10 +323,17 @@ impl BuildTools {
             dependency,
             show_all,
         }): Parameters<WhyDependsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package names
-        validate_package_name(&package).map_err(validation_error_to_mcp)?;
-        validate_package_name(&dependency).map_err(validation_error_to_mcp)?;
+        // Validate installables and resolve them against the client's workspace roots
+        let package_installable = workspace
+            .resolve_installable(validate_installable(&package).map_err(validation_error_to_mcp)?)
+            .await?;
+        let dependency_installable = workspace
+            .resolve_installable(
+                validate_installable(&dependency).map_err(validation_error_to_mcp)?,
+            )
+            .await?;
 
         // Wrap tool logic with security
         audit_tool_execution(
@@ -208,132 +341,61 @@ impl BuildTools {
             "why_depends",
             Some(serde_json::json!({"package": &package, "dependency": &dependency})),
             || async {
-                with_timeout(&self.audit, "why_depends", 60, || async {
-                    let show_all = show_all.unwrap_or(false);
-
-                    // First, build the package to get its store path
-                    let build_output = tokio::process::Command::new("nix")
-                        .args(["build", &package, "--json", "--no-link"])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to build package: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    if !build_output.status.success() {
-                        let stderr = String::from_utf8_lossy(&build_output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to build package: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let stdout = String::from_utf8_lossy(&build_output.stdout);
-                    let build_json: serde_json::Value =
-                        serde_json::from_str(&stdout).map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to parse build output: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    let package_path = build_json
-                        .as_array()
-                        .and_then(|arr| arr.first())
-                        .and_then(|item| item.get("outputs"))
-                        .and_then(|outputs| outputs.get("out"))
-                        .and_then(|out| out.as_str())
-                        .ok_or_else(|| {
-                            McpError::internal_error(
-                                "Failed to get package output path".to_string(),
-                                None,
-                            )
-                        })?;
-
-                    // Build dependency to get its store path
-                    let dep_build_output = tokio::process::Command::new("nix")
-                        .args(["build", &dependency, "--json", "--no-link"])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to build dependency: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    if !dep_build_output.status.success() {
-                        let stderr = String::from_utf8_lossy(&dep_build_output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to build dependency: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let dep_stdout = String::from_utf8_lossy(&dep_build_output.stdout);
-                    let dep_json: serde_json::Value =
-                        serde_json::from_str(&dep_stdout).map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to parse dependency build output: {}", e),
-                                None,
-                            )
-                        })?;
+                let _slot = scheduler()
+                    .acquire(&self.audit, "why_depends", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "why_depends",
+                    self.config.timeout("why_depends", 60),
+                    || async {
+                        let show_all = show_all.unwrap_or(false);
+
+                        // Realise both to get their store paths
+                        let package_path = package_installable.output_path().await?.to_string();
+                        let dependency_path =
+                            dependency_installable.output_path().await?.to_string();
+
+                        // Now run nix why-depends
+                        let mut args = vec!["why-depends", &package_path, &dependency_path];
+                        if show_all {
+                            args.push("--all");
+                        }
 
-                    let dependency_path = dep_json
-                        .as_array()
-                        .and_then(|arr| arr.first())
-                        .and_then(|item| item.get("outputs"))
-                        .and_then(|outputs| outputs.get("out"))
-                        .and_then(|out| out.as_str())
-                        .ok_or_else(|| {
-                            McpError::internal_error(
-                                "Failed to get dependency output path".to_string(),
-                                None,
-                            )
-                        })?;
+                        let output = tokio::process::Command::new("nix")
+                            .args(&args)
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute nix why-depends: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
 
-                    // Now run nix why-depends
-                    let mut args = vec!["why-depends", package_path, dependency_path];
-                    if show_all {
-                        args.push("--all");
-                    }
+                            // Check if it's because there's no dependency
+                            if stderr.contains("does not depend on") {
+                                return Ok(CallToolResult::success(vec![Content::text(format!(
+                                    "{} does not depend on {}",
+                                    package, dependency
+                                ))]));
+                            }
 
-                    let output = tokio::process::Command::new("nix")
-                        .args(&args)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix why-depends: {}", e),
+                            return Err(McpError::internal_error(
+                                format!("why-depends failed: {}", stderr),
                                 None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-
-                        // Check if it's because there's no dependency
-                        if stderr.contains("does not depend on") {
-                            return Ok(CallToolResult::success(vec![Content::text(format!(
-                                "{} does not depend on {}",
-                                package, dependency
-                            ))]));
+                            ));
                         }
 
-                        return Err(McpError::internal_error(
-                            format!("why-depends failed: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let result = String::from_utf8_lossy(&output.stdout);
-                    Ok(CallToolResult::success(vec![Content::text(
-                        result.to_string(),
-                    )]))
-                })
+                        let result = String::from_utf8_lossy(&output.stdout);
+                        Ok(CallToolResult::success(vec![Content::text(
+                            result.to_string(),
+                        )]))
+                    },
+                )
                 .await
             },
         )
@@ -347,123 +409,72 @@ impl BuildTools {
     pub async fn show_derivation(
         &self,
         Parameters(ShowDerivationArgs { package }): Parameters<ShowDerivationArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package/flake reference
-        validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
-
-        // Create cache key (package is the only parameter)
-        let cache_key = package.clone();
-
-        // Check cache first
-        if let Some(cached_result) = self.caches.derivation.get(&cache_key) {
-            return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
-        }
-
-        // Clone cache and key for use in async closure
-        let derivation_cache = self.caches.derivation.clone();
-        let cache_key_clone = cache_key.clone();
-
-        // Wrap tool logic with security
-        audit_tool_execution(
-            &self.audit,
-            "show_derivation",
-            Some(serde_json::json!({"package": &package})),
-            || async move {
-                with_timeout(&self.audit, "show_derivation", 30, || async {
-                    let output = tokio::process::Command::new("nix")
-                        .args(["derivation", "show", &package])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix derivation show: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to show derivation: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-
-                    // Try to parse and format nicely
-                    if let Ok(drv_json) = serde_json::from_str::<serde_json::Value>(&stdout) {
-                        let mut result = String::from("Derivation Details:\n\n");
-
-                        // Get the first (and usually only) derivation
-                        if let Some(obj) = drv_json.as_object() {
-                            if let Some((drv_path, drv_info)) = obj.iter().next() {
-                                result.push_str(&format!("Path: {}\n\n", drv_path));
-
-                                if let Some(outputs) =
-                                    drv_info.get("outputs").and_then(|v| v.as_object())
-                                {
-                                    result.push_str("Outputs:\n");
-                                    for (name, info) in outputs {
-                                        result.push_str(&format!("  - {}\n", name));
-                                        if let Some(path) =
-                                            info.get("path").and_then(|v| v.as_str())
-                                        {
-                                            result.push_str(&format!("    Path: {}\n", path));
-                                        }
-                                    }
-                                    result.push('\n');
-                                }
-
-                                if let Some(inputs) =
-                                    drv_info.get("inputDrvs").and_then(|v| v.as_object())
-                                {
-                                    result.push_str(&format!(
-                                        "Build Dependencies: {} derivations\n",
-                                        inputs.len()
+        // Validate installable
+        let installable = validate_installable(&package).map_err(validation_error_to_mcp)?;
+
+        // Resolve against the client's workspace roots
+        let installable = workspace.resolve_installable(installable).await?;
+        let target = installable.to_string();
+
+        // Cache on the canonical spelling so that equivalent installables
+        // share an entry
+        let cached_executor = CachedExecutor::new(self.caches.derivation.clone());
+
+        cached_executor
+            .execute_with_string_cache(target.clone(), || async {
+                // Wrap tool logic with security
+                audit_tool_execution(
+                    &self.audit,
+                    "show_derivation",
+                    Some(serde_json::json!({"package": &package})),
+                    || async {
+                        let _slot = scheduler()
+                            .acquire(&self.audit, "show_derivation", CommandCategory::Evaluation)
+                            .await;
+                        with_timeout(
+                            &self.audit,
+                            "show_derivation",
+                            self.config.timeout("show_derivation", 30),
+                            || async {
+                                let output = tokio::process::Command::new("nix")
+                                    .args(["derivation", "show", &target])
+                                    .group_output()
+                                    .await
+                                    .map_err(|e| {
+                                        McpError::internal_error(
+                                            format!("Failed to execute nix derivation show: {}", e),
+                                            None,
+                                        )
+                                    })?;
+
+                                if !output.status.success() {
+                                    let stderr = String::from_utf8_lossy(&output.stderr);
+                                    return Err(McpError::internal_error(
+                                        format!("Failed to show derivation: {}", stderr),
+                                        None,
                                     ));
                                 }
 
-                                if let Some(env) = drv_info.get("env").and_then(|v| v.as_object()) {
-                                    result.push_str("\nKey Environment Variables:\n");
-                                    for key in
-                                        ["name", "version", "src", "builder", "system", "outputs"]
-                                            .iter()
-                                    {
-                                        if let Some(value) = env.get(*key).and_then(|v| v.as_str())
-                                        {
-                                            result.push_str(&format!("  {}: {}\n", key, value));
-                                        }
-                                    }
-                                }
-
-                                result.push_str("\nFull JSON available for detailed inspection.");
-                                // Only show first derivation in formatted view
-                            }
-                        }
-
-                        // Cache the result
-                        derivation_cache.insert(cache_key_clone.clone(), result.clone());
-
-                        Ok(CallToolResult::success(vec![Content::text(result)]))
-                    } else {
-                        let result = stdout.to_string();
-
-                        // Cache the result
-                        derivation_cache.insert(cache_key_clone, result.clone());
-
-                        Ok(CallToolResult::success(vec![Content::text(result)]))
-                    }
-                })
+                                let stdout = String::from_utf8_lossy(&output.stdout);
+                                Ok(match serde_json::from_str::<serde_json::Value>(&stdout) {
+                                    Ok(drv_json) => format_derivation(&drv_json),
+                                    Err(_) => stdout.to_string(),
+                                })
+                            },
+                        )
+                        .await
+                    },
+                )
                 .await
-            },
-        )
-        .await
+            })
+            .await
     }
 
     #[tool(
         description = "Get the closure size of a package (total size including all dependencies)",
-        annotations(read_only_hint = true)
+        output_schema = output_schema::<ClosureSizeResult>()
     )]
     pub async fn get_closure_size(
         &self,
@@ -471,104 +482,102 @@ impl BuildTools {
             package,
             human_readable,
         }): Parameters<GetClosureSizeArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package/flake reference
-        validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
-
-        // Create cache key including human_readable flag
-        let cache_key = format!("{}:{}", package, human_readable.unwrap_or(true));
+        // Validate installable
+        let installable = validate_installable(&package).map_err(validation_error_to_mcp)?;
 
-        // Check cache first
-        if let Some(cached_result) = self.caches.closure_size.get(&cache_key) {
-            return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
-        }
+        // Resolve against the client's workspace roots
+        let installable = workspace.resolve_installable(installable).await?;
 
-        // Clone cache and key for use in async closure
-        let closure_size_cache = self.caches.closure_size.clone();
-        let cache_key_clone = cache_key.clone();
+        let human_readable = human_readable.unwrap_or(true);
+        // Closures of store paths and pinned flakes never change
+        let revision = RevisionResolver::new(
+            "get_closure_size",
+            &self.audit,
+            self.config.timeout("get_closure_size", 60),
+            &self.caches.revision,
+        )
+        .of_installable(&installable, &workspace)
+        .await;
+        let cached_executor =
+            CachedExecutor::new(self.caches.closure_size.clone()).at_revision(revision);
 
         // Wrap tool logic with security
-        audit_tool_execution(&self.audit, "get_closure_size", Some(serde_json::json!({"package": &package})), || async move {
-            with_timeout(&self.audit, "get_closure_size", 60, || async {
-                let human_readable = human_readable.unwrap_or(true);
-
-                // First build the package to get its store path
-                let build_output = tokio::process::Command::new("nix")
-                    .args(["build", &package, "--json", "--no-link"])
-                    .output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to build package: {}", e), None))?;
-
-                if !build_output.status.success() {
-                    let stderr = String::from_utf8_lossy(&build_output.stderr);
-                    return Err(McpError::internal_error(format!("Failed to build package: {}", stderr), None));
-                }
-
-                let stdout = String::from_utf8_lossy(&build_output.stdout);
-                let build_json: serde_json::Value = serde_json::from_str(&stdout)
-                    .map_err(|e| McpError::internal_error(format!("Failed to parse build output: {}", e), None))?;
-
-                let package_path = build_json
-                    .as_array()
-                    .and_then(|arr| arr.first())
-                    .and_then(|item| item.get("outputs"))
-                    .and_then(|outputs| outputs.get("out"))
-                    .and_then(|out| out.as_str())
-                    .ok_or_else(|| McpError::internal_error("Failed to get package output path".to_string(), None))?;
-
-                // Get closure size using nix path-info
-                let mut args = vec!["path-info", "-S", package_path];
-                if !human_readable {
-                    args.push("--json");
-                }
-
-                let output = tokio::process::Command::new("nix")
-                    .args(&args)
-                    .output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to get path info: {}", e), None))?;
-
-                if !output.status.success() {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return Err(McpError::internal_error(format!("Failed to get closure size: {}", stderr), None));
-                }
-
-                let result_text = if human_readable {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    // Parse the output which is in format: /nix/store/... \t closure_size
-                    if let Some(line) = stdout.lines().next() {
-                        let parts: Vec<&str> = line.split_whitespace().collect();
-                        if parts.len() >= 2 {
-                            let closure_size: u64 = parts[1].parse().unwrap_or(0);
-                            let size_gb = closure_size as f64 / (1024.0 * 1024.0 * 1024.0);
-                            let size_mb = closure_size as f64 / (1024.0 * 1024.0);
-
-                            let human_size = if size_gb >= 1.0 {
-                                format!("{:.2} GB", size_gb)
-                            } else {
-                                format!("{:.2} MB", size_mb)
-                            };
-
-                            format!(
-                                "Package: {}\nClosure Size: {} ({} bytes)\n\nThis includes the package and all its dependencies.",
-                                package, human_size, closure_size
-                            )
-                        } else {
-                            stdout.to_string()
-                        }
-                    } else {
-                        "No size information available".to_string()
-                    }
-                } else {
-                    String::from_utf8_lossy(&output.stdout).to_string()
-                };
+        let mut result = cached_executor
+            .execute_with_structured_cache(installable.to_string(), || async {
+                audit_tool_execution(
+                    &self.audit,
+                    "get_closure_size",
+                    Some(serde_json::json!({"package": &package})),
+                    || async {
+                        let _slot = scheduler()
+                            .acquire(&self.audit, "get_closure_size", CommandCategory::Build)
+                            .await;
+                        with_timeout(
+                            &self.audit,
+                            "get_closure_size",
+                            self.config.timeout("get_closure_size", 60),
+                            || async {
+                                // Realise the package to get its store path
+                                let package_path = installable.output_path().await?.to_string();
+
+                                // Get closure size using nix path-info
+                                let output = tokio::process::Command::new("nix")
+                                    .args(["path-info", "-S", "--json", &package_path])
+                                    .group_output()
+                                    .await
+                                    .map_err(|e| {
+                                        McpError::internal_error(
+                                            format!("Failed to get path info: {}", e),
+                                            None,
+                                        )
+                                    })?;
+
+                                if !output.status.success() {
+                                    let stderr = String::from_utf8_lossy(&output.stderr);
+                                    return Err(McpError::internal_error(
+                                        format!("Failed to get closure size: {}", stderr),
+                                        None,
+                                    ));
+                                }
 
-                // Cache the result
-                closure_size_cache.insert(cache_key_clone, result_text.clone());
+                                let closure_size =
+                                    serde_json::from_slice::<serde_json::Value>(&output.stdout)
+                                        .ok()
+                                        .as_ref()
+                                        .and_then(parse_closure_size)
+                                        .ok_or_else(|| {
+                                            McpError::internal_error(
+                                                "No size information available".to_string(),
+                                                None,
+                                            )
+                                        })?;
+
+                                Ok(ClosureSizeResult {
+                                    package: package.clone(),
+                                    store_path: package_path,
+                                    closure_size_bytes: closure_size,
+                                    human_size: format_size(closure_size),
+                                })
+                            },
+                        )
+                        .await
+                    },
+                )
+                .await
+            })
+            .await?;
+
+        // Machine-readable text fallback for clients that ignore structured content
+        if !human_readable {
+            if let Some(structured) = &result.structured_content {
+                let json = serde_json::to_string_pretty(structured).unwrap_or_default();
+                result.content = vec![Content::text(json)];
+            }
+        }
 
-                Ok(CallToolResult::success(vec![Content::text(result_text)]))
-            }).await
-        }).await
+        Ok(result)
     }
 
     #[tool(
@@ -578,17 +587,24 @@ impl BuildTools {
     pub async fn get_build_log(
         &self,
         Parameters(GetBuildLogArgs { package }): Parameters<GetBuildLogArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package name
-        validate_package_name(&package).map_err(validation_error_to_mcp)?;
+        // Validate installable
+        let installable = validate_installable(&package).map_err(validation_error_to_mcp)?;
+
+        // Resolve against the client's workspace roots
+        let installable = workspace
+            .resolve_installable(installable)
+            .await?
+            .to_string();
 
         // Wrap tool logic with security
         audit_tool_execution(&self.audit, "get_build_log", Some(serde_json::json!({"package": &package})), || async {
-            with_timeout(&self.audit, "get_build_log", 30, || async {
+            with_timeout(&self.audit, "get_build_log", self.config.timeout("get_build_log", 30), || async {
                 // nix log can take either a package reference or a store path
                 let output = tokio::process::Command::new("nix")
-                    .args(["log", &package])
-                    .output()
+                    .args(["log", &installable])
+                    .group_output()
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to execute nix log: {}", e), None))?;
 
@@ -631,18 +647,26 @@ impl BuildTools {
             package_a,
             package_b,
         }): Parameters<DiffDerivationsArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package names
-        validate_package_name(&package_a).map_err(validation_error_to_mcp)?;
-        validate_package_name(&package_b).map_err(validation_error_to_mcp)?;
+        // Validate installables and resolve them against the client's workspace roots
+        let installable_a = workspace
+            .resolve_installable(validate_installable(&package_a).map_err(validation_error_to_mcp)?)
+            .await?;
+        let installable_b = workspace
+            .resolve_installable(validate_installable(&package_b).map_err(validation_error_to_mcp)?)
+            .await?;
 
         // Wrap tool logic with security
         audit_tool_execution(&self.audit, "diff_derivations", Some(serde_json::json!({"package_a": &package_a, "package_b": &package_b})), || async {
-            with_timeout(&self.audit, "diff_derivations", 60, || async {
+            let _slot = scheduler()
+                .acquire(&self.audit, "diff_derivations", CommandCategory::Evaluation)
+                .await;
+            with_timeout(&self.audit, "diff_derivations", self.config.timeout("diff_derivations", 60), || async {
                 // First, try to use nix-diff if available
                 let nix_diff_check = tokio::process::Command::new("nix-diff")
                     .arg("--version")
-                    .output()
+                    .group_output()
                     .await;
 
                 if nix_diff_check.is_err() {
@@ -652,53 +676,18 @@ impl BuildTools {
                     )]));
                 }
 
-                // Build both packages to get their derivation paths
-                let build_a = tokio::process::Command::new("nix")
-                    .args(["build", &package_a, "--json", "--no-link", "--dry-run"])
-                    .output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to build package A: {}", e), None))?;
-
-                if !build_a.status.success() {
-                    let stderr = String::from_utf8_lossy(&build_a.stderr);
-                    return Err(McpError::internal_error(format!("Failed to build package A: {}", stderr), None));
-                }
-
-                let build_b = tokio::process::Command::new("nix")
-                    .args(["build", &package_b, "--json", "--no-link", "--dry-run"])
-                    .output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to build package B: {}", e), None))?;
-
-                if !build_b.status.success() {
-                    let stderr = String::from_utf8_lossy(&build_b.stderr);
-                    return Err(McpError::internal_error(format!("Failed to build package B: {}", stderr), None));
-                }
-
-                // Parse derivation paths from JSON output
-                let json_a: serde_json::Value = serde_json::from_str(&String::from_utf8_lossy(&build_a.stdout))
-                    .map_err(|e| McpError::internal_error(format!("Failed to parse build output A: {}", e), None))?;
-                let json_b: serde_json::Value = serde_json::from_str(&String::from_utf8_lossy(&build_b.stdout))
-                    .map_err(|e| McpError::internal_error(format!("Failed to parse build output B: {}", e), None))?;
-
-                let drv_a = json_a
-                    .as_array()
-                    .and_then(|arr| arr.first())
-                    .and_then(|item| item.get("drvPath"))
-                    .and_then(|drv| drv.as_str())
-                    .ok_or_else(|| McpError::internal_error("Failed to get derivation path A".to_string(), None))?;
-
-                let drv_b = json_b
-                    .as_array()
-                    .and_then(|arr| arr.first())
-                    .and_then(|item| item.get("drvPath"))
-                    .and_then(|drv| drv.as_str())
-                    .ok_or_else(|| McpError::internal_error("Failed to get derivation path B".to_string(), None))?;
+                // Evaluate both packages to get their derivation paths
+                let drv_a = installable_a.derivations().await?.into_iter().next()
+                    .ok_or_else(|| McpError::internal_error("Failed to get derivation path A".to_string(), None))?
+                    .to_string();
+                let drv_b = installable_b.derivations().await?.into_iter().next()
+                    .ok_or_else(|| McpError::internal_error("Failed to get derivation path B".to_string(), None))?
+                    .to_string();
 
                 // Run nix-diff
                 let output = tokio::process::Command::new("nix-diff")
-                    .args([drv_a, drv_b])
-                    .output()
+                    .args([&drv_a, &drv_b])
+                    .group_output()
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to run nix-diff: {}", e), None))?;
 
@@ -726,53 +715,89 @@ impl BuildTools {
             flake,
             use_nom,
         }): Parameters<NixosBuildArgs>,
+        progress: ProgressReporter,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        let flake_str = flake.unwrap_or_else(|| ".".to_string());
-
-        audit_tool_execution(&self.audit, "nixos_build", Some(serde_json::json!({"machine": &machine, "flake": &flake_str})), || async {
-            with_timeout(&self.audit, "nixos_build", 300, || async {
-                let use_nom = use_nom.unwrap_or(false);
-                let build_target = format!("{}#nixosConfigurations.{}.config.system.build.toplevel", flake_str, machine);
-
-                let mut cmd = if use_nom {
-                    // Check if nom is available
-                    let nom_check = tokio::process::Command::new("which")
-                        .arg("nom")
-                        .output()
-                        .await;
-
-                    if nom_check.is_ok() && nom_check.unwrap().status.success() {
-                        let mut c = tokio::process::Command::new("nom");
-                        c.args(["build", &build_target]);
-                        c
-                    } else {
-                        let mut c = tokio::process::Command::new("nix");
-                        c.args(["build", &build_target]);
-                        c
-                    }
-                } else {
-                    let mut c = tokio::process::Command::new("nix");
-                    c.args(["build", &build_target]);
-                    c
-                };
+        let flake_str = flake.unwrap_or_else(|| self.config.flakes.default.clone());
+        validate_flake_ref(&flake_str).map_err(validation_error_to_mcp)?;
 
-                let output = cmd.output()
-                    .await
-                    .map_err(|e| McpError::internal_error(format!("Failed to execute build command: {}", e), None))?;
+        // Resolve against the client's workspace roots
+        let flake_str = workspace.resolve_flake(&flake_str).await?;
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+        audit_tool_execution(
+            &self.audit,
+            "nixos_build",
+            Some(serde_json::json!({"machine": &machine, "flake": &flake_str})),
+            || async {
+                let _slot = scheduler()
+                    .acquire(&self.audit, "nixos_build", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "nixos_build",
+                    self.config.timeout("nixos_build", 300),
+                    || async {
+                        let use_nom = use_nom.unwrap_or(false);
+                        let build_target = format!(
+                            "{}#nixosConfigurations.{}.config.system.build.toplevel",
+                            flake_str, machine
+                        );
+                        // Report the system path instead of linking ./result in the server's directory
+                        let build_args = ["build", "--no-link", "--print-out-paths", &build_target];
+
+                        let mut cmd = if use_nom {
+                            // Check if nom is available
+                            let nom_check = tokio::process::Command::new("which")
+                                .arg("nom")
+                                .group_output()
+                                .await;
+
+                            if nom_check.is_ok() && nom_check.unwrap().status.success() {
+                                let mut c = tokio::process::Command::new("nom");
+                                c.args(build_args);
+                                c
+                            } else {
+                                let mut c = tokio::process::Command::new("nix");
+                                c.args(build_args);
+                                c
+                            }
+                        } else {
+                            let mut c = tokio::process::Command::new("nix");
+                            c.args(build_args);
+                            c
+                        };
 
-                if !output.status.success() {
-                    return Ok(CallToolResult::success(vec![Content::text(
-                        format!("Build failed for NixOS configuration '{}':\n\n{}{}", machine, stdout, stderr)
-                    )]));
-                }
+                        let output =
+                            output_with_progress(&mut cmd, &progress)
+                                .await
+                                .map_err(|e| {
+                                    McpError::internal_error(
+                                        format!("Failed to execute build command: {}", e),
+                                        None,
+                                    )
+                                })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                Ok(CallToolResult::success(vec![Content::text(
-                    format!("Successfully built NixOS configuration '{}'.\n\n{}{}\n\nThe build result is in ./result/", machine, stdout, stderr)
-                )]))
-            }).await
-        }).await
+                        if !output.status.success() {
+                            return Ok(CallToolResult::success(vec![Content::text(format!(
+                                "Build failed for NixOS configuration '{}':\n\n{}{}",
+                                machine, stdout, stderr
+                            ))]));
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Successfully built NixOS configuration '{}'.\n\nSystem: {}\n{}",
+                            machine,
+                            stdout.trim(),
+                            stderr
+                        ))]))
+                    },
+                )
+                .await
+            },
+        )
+        .await
     }
 }
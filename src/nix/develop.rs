@@ -1,12 +1,16 @@
 use crate::common::cache_registry::CacheRegistry;
 use crate::common::caching::CachedExecutor;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::helpers::{
     audit_tool_execution, validation_error_to_mcp, with_timeout,
 };
 use crate::common::security::{
-    validate_command, validate_flake_ref, validate_nix_expression, validate_package_name,
-    validate_path,
+    validate_command, validate_flake_ref, validate_installable, validate_nix_expression,
+    validate_package_name, validate_path,
 };
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{CallToolResult, Content};
@@ -76,6 +80,7 @@ use super::types::{
 pub struct DevelopTools {
     audit: Arc<AuditLogger>,
     caches: Arc<CacheRegistry>,
+    config: Arc<Config>,
 }
 
 impl DevelopTools {
@@ -86,7 +91,20 @@ impl DevelopTools {
     /// * `audit` - Shared audit logger for security event logging
     /// * `caches` - Shared cache registry containing eval cache
     pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>) -> Self {
-        Self { audit, caches }
+        Self::with_config(audit, caches, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `DevelopTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(
+        audit: Arc<AuditLogger>,
+        caches: Arc<CacheRegistry>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            audit,
+            caches,
+            config,
+        }
     }
 }
 
@@ -109,39 +127,47 @@ impl DevelopTools {
             "search_options",
             Some(serde_json::json!({"query": &query})),
             || async {
-                with_timeout(&self.audit, "search_options", 30, || async {
-                    // Check if we're on NixOS and can query options directly
-                    let nixos_check = tokio::process::Command::new("sh")
-                        .arg("-c")
-                        .arg("test -f /etc/NIXOS")
-                        .output()
-                        .await;
-
-                    let on_nixos = nixos_check.map(|o| o.status.success()).unwrap_or(false);
-
-                    if on_nixos {
-                        // Try to search using nixos-option if available
-                        let output = tokio::process::Command::new("nixos-option")
-                            .arg(&query)
-                            .output()
+                let _slot = scheduler()
+                    .acquire(&self.audit, "search_options", CommandCategory::Evaluation)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "search_options",
+                    self.config.timeout("search_options", 30),
+                    || async {
+                        // Check if we're on NixOS and can query options directly
+                        let nixos_check = tokio::process::Command::new("sh")
+                            .arg("-c")
+                            .arg("test -f /etc/NIXOS")
+                            .group_output()
                             .await;
 
-                        if let Ok(output) = output {
-                            if output.status.success() {
-                                let stdout = String::from_utf8_lossy(&output.stdout);
-                                return Ok(CallToolResult::success(vec![Content::text(
-                                    stdout.to_string(),
-                                )]));
+                        let on_nixos = nixos_check.map(|o| o.status.success()).unwrap_or(false);
+
+                        if on_nixos {
+                            // Try to search using nixos-option if available
+                            let output = tokio::process::Command::new("nixos-option")
+                                .arg(&query)
+                                .group_output()
+                                .await;
+
+                            if let Ok(output) = output {
+                                if output.status.success() {
+                                    let stdout = String::from_utf8_lossy(&output.stdout);
+                                    return Ok(CallToolResult::success(vec![Content::text(
+                                        stdout.to_string(),
+                                    )]));
+                                }
                             }
                         }
-                    }
-
-                    // Provide helpful information with web search links
-                    use crate::common::nix_tools_helpers::format_option_search_response;
-                    Ok(CallToolResult::success(vec![Content::text(
-                        format_option_search_response(&query),
-                    )]))
-                })
+
+                        // Provide helpful information with web search links
+                        use crate::common::nix_tools_helpers::format_option_search_response;
+                        Ok(CallToolResult::success(vec![Content::text(
+                            format_option_search_response(&query),
+                        )]))
+                    },
+                )
                 .await
             },
         )
@@ -170,28 +196,36 @@ impl DevelopTools {
                     "nix_eval",
                     Some(serde_json::json!({"expression_length": expression_clone.len()})),
                     || async move {
-                        with_timeout(&audit_inner, "nix_eval", 30, || async {
-                            let output = tokio::process::Command::new("nix")
-                                .args(["eval", "--expr", &expression_clone])
-                                .output()
-                                .await
-                                .map_err(|e| {
-                                    McpError::internal_error(
-                                        format!("Failed to execute nix eval: {}", e),
+                        let _slot = scheduler()
+                            .acquire(&audit_inner, "nix_eval", CommandCategory::Evaluation)
+                            .await;
+                        with_timeout(
+                            &audit_inner,
+                            "nix_eval",
+                            self.config.timeout("nix_eval", 30),
+                            || async {
+                                let output = tokio::process::Command::new("nix")
+                                    .args(["eval", "--expr", &expression_clone])
+                                    .group_output()
+                                    .await
+                                    .map_err(|e| {
+                                        McpError::internal_error(
+                                            format!("Failed to execute nix eval: {}", e),
+                                            None,
+                                        )
+                                    })?;
+
+                                if !output.status.success() {
+                                    let stderr = String::from_utf8_lossy(&output.stderr);
+                                    return Err(McpError::internal_error(
+                                        format!("Evaluation failed: {}", stderr),
                                         None,
-                                    )
-                                })?;
+                                    ));
+                                }
 
-                            if !output.status.success() {
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                return Err(McpError::internal_error(
-                                    format!("Evaluation failed: {}", stderr),
-                                    None,
-                                ));
-                            }
-
-                            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-                        })
+                                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+                            },
+                        )
                         .await
                     },
                 )
@@ -230,63 +264,71 @@ impl DevelopTools {
             "run_in_shell",
             Some(serde_json::json!({"command": &command, "packages": &packages})),
             || async {
-                with_timeout(&self.audit, "run_in_shell", 120, || async {
-                    let use_flake = use_flake.unwrap_or(false);
-
-                    let output = if use_flake {
-                        // Use nix develop -c
-                        tokio::process::Command::new("nix")
-                            .args(["develop", "-c", "sh", "-c", &command])
-                            .output()
-                            .await
-                            .map_err(|e| {
-                                McpError::internal_error(
-                                    format!("Failed to run in dev shell: {}", e),
-                                    None,
-                                )
-                            })?
-                    } else {
-                        // Use nix-shell -p
-                        let package_args: Vec<String> = packages
-                            .iter()
-                            .flat_map(|pkg| vec!["-p".to_string(), pkg.clone()])
-                            .collect();
-
-                        let mut args = package_args;
-                        args.push("--run".to_string());
-                        args.push(command.clone());
-
-                        tokio::process::Command::new("nix-shell")
-                            .args(&args)
-                            .output()
-                            .await
-                            .map_err(|e| {
-                                McpError::internal_error(
-                                    format!("Failed to run in shell: {}", e),
-                                    None,
-                                )
-                            })?
-                    };
+                let _slot = scheduler()
+                    .acquire(&self.audit, "run_in_shell", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "run_in_shell",
+                    self.config.timeout("run_in_shell", 120),
+                    || async {
+                        let use_flake = use_flake.unwrap_or(false);
+
+                        let output = if use_flake {
+                            // Use nix develop -c
+                            tokio::process::Command::new("nix")
+                                .args(["develop", "-c", "sh", "-c", &command])
+                                .group_output()
+                                .await
+                                .map_err(|e| {
+                                    McpError::internal_error(
+                                        format!("Failed to run in dev shell: {}", e),
+                                        None,
+                                    )
+                                })?
+                        } else {
+                            // Use nix-shell -p
+                            let package_args: Vec<String> = packages
+                                .iter()
+                                .flat_map(|pkg| vec!["-p".to_string(), pkg.clone()])
+                                .collect();
+
+                            let mut args = package_args;
+                            args.push("--run".to_string());
+                            args.push(command.clone());
+
+                            tokio::process::Command::new("nix-shell")
+                                .args(&args)
+                                .group_output()
+                                .await
+                                .map_err(|e| {
+                                    McpError::internal_error(
+                                        format!("Failed to run in shell: {}", e),
+                                        None,
+                                    )
+                                })?
+                        };
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                    let result_text = if output.status.success() {
-                        format!(
-                            "Command executed successfully!\n\nOutput:\n{}{}",
-                            stdout, stderr
-                        )
-                    } else {
-                        format!(
-                            "Command failed with exit code: {:?}\n\nOutput:\n{}\n\nError:\n{}",
-                            output.status.code(),
-                            stdout,
-                            stderr
-                        )
-                    };
+                        let result_text = if output.status.success() {
+                            format!(
+                                "Command executed successfully!\n\nOutput:\n{}{}",
+                                stdout, stderr
+                            )
+                        } else {
+                            format!(
+                                "Command failed with exit code: {:?}\n\nOutput:\n{}\n\nError:\n{}",
+                                output.status.code(),
+                                stdout,
+                                stderr
+                            )
+                        };
 
-                    Ok(CallToolResult::success(vec![Content::text(result_text)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+                    },
+                )
                 .await
             },
         )
@@ -323,65 +365,70 @@ impl DevelopTools {
             "nix_log",
             Some(serde_json::json!({"store_path": &store_path, "grep_pattern": &grep_pattern})),
             || async {
-                with_timeout(&self.audit, "nix_log", 30, || async {
-                    // Use nix log with store path
-                    let output = tokio::process::Command::new("nix")
-                        .args(["log", &store_path])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix log: {}", e),
-                                None,
-                            )
-                        })?;
+                with_timeout(
+                    &self.audit,
+                    "nix_log",
+                    self.config.timeout("nix_log", 30),
+                    || async {
+                        // Use nix log with store path
+                        let output = tokio::process::Command::new("nix")
+                            .args(["log", &store_path])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute nix log: {}", e),
+                                    None,
+                                )
+                            })?;
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to get log: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let log = String::from_utf8_lossy(&output.stdout);
-
-                    // Apply grep filter if provided
-                    let result = if let Some(ref pattern) = grep_pattern {
-                        let filtered_lines: Vec<&str> = log
-                            .lines()
-                            .filter(|line| line.contains(pattern.as_str()))
-                            .collect();
-
-                        if filtered_lines.is_empty() {
-                            format!(
-                                "No lines matching '{}' found in log for {}",
-                                pattern, store_path
-                            )
-                        } else {
-                            format!(
-                                "Lines matching '{}' in {}:\n\n{}",
-                                pattern,
-                                store_path,
-                                filtered_lines.join("\n")
-                            )
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("Failed to get log: {}", stderr),
+                                None,
+                            ));
                         }
-                    } else {
-                        // Return full log, truncate if too long
-                        if log.len() > 50000 {
-                            let truncated = &log[..50000];
-                            format!(
-                                "{}\n\n... [Log truncated - showing first 50KB of {} KB total]",
-                                truncated,
-                                log.len() / 1024
-                            )
+
+                        let log = String::from_utf8_lossy(&output.stdout);
+
+                        // Apply grep filter if provided
+                        let result = if let Some(ref pattern) = grep_pattern {
+                            let filtered_lines: Vec<&str> = log
+                                .lines()
+                                .filter(|line| line.contains(pattern.as_str()))
+                                .collect();
+
+                            if filtered_lines.is_empty() {
+                                format!(
+                                    "No lines matching '{}' found in log for {}",
+                                    pattern, store_path
+                                )
+                            } else {
+                                format!(
+                                    "Lines matching '{}' in {}:\n\n{}",
+                                    pattern,
+                                    store_path,
+                                    filtered_lines.join("\n")
+                                )
+                            }
                         } else {
-                            log.to_string()
-                        }
-                    };
+                            // Return full log, truncate if too long
+                            if log.len() > 50000 {
+                                let truncated = &log[..50000];
+                                format!(
+                                    "{}\n\n... [Log truncated - showing first 50KB of {} KB total]",
+                                    truncated,
+                                    log.len() / 1024
+                                )
+                            } else {
+                                log.to_string()
+                            }
+                        };
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -395,9 +442,16 @@ impl DevelopTools {
     pub async fn nix_run(
         &self,
         Parameters(NixRunArgs { package, args }): Parameters<NixRunArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
-        // Validate package/flake reference (accepts nixpkgs#hello format)
-        validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
+        // Validate installable (accepts nixpkgs#hello format and store paths)
+        let installable = validate_installable(&package).map_err(validation_error_to_mcp)?;
+
+        // Resolve against the client's workspace roots
+        let installable = workspace
+            .resolve_installable(installable)
+            .await?
+            .to_string();
 
         // Wrap tool logic with security
         audit_tool_execution(
@@ -405,51 +459,62 @@ impl DevelopTools {
             "nix_run",
             Some(serde_json::json!({"package": &package, "args": &args})),
             || async {
-                with_timeout(&self.audit, "nix_run", 300, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run").arg(&package);
-
-                    if let Some(program_args) = args {
-                        cmd.arg("--");
-                        for arg in program_args {
-                            cmd.arg(arg);
+                let _slot = scheduler()
+                    .acquire(&self.audit, "nix_run", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "nix_run",
+                    self.config.timeout("nix_run", 300),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run").arg(&installable);
+
+                        if let Some(program_args) = args {
+                            cmd.arg("--");
+                            for arg in program_args {
+                                cmd.arg(arg);
+                            }
+                        }
+
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute nix run: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        let mut result = String::new();
+                        if !stdout.is_empty() {
+                            result.push_str("STDOUT:\n");
+                            result.push_str(&stdout);
+                            result.push('\n');
+                        }
+                        if !stderr.is_empty() {
+                            result.push_str("STDERR:\n");
+                            result.push_str(&stderr);
+                        }
+
+                        if result.is_empty() {
+                            result = format!(
+                                "Command completed successfully (exit code: {})",
+                                output.status.code().unwrap_or(0)
+                            );
+                        }
+
+                        if !output.status.success() {
+                            return Err(McpError::internal_error(
+                                format!("nix run failed: {}", result),
+                                None,
+                            ));
                         }
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(format!("Failed to execute nix run: {}", e), None)
-                    })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    let mut result = String::new();
-                    if !stdout.is_empty() {
-                        result.push_str("STDOUT:\n");
-                        result.push_str(&stdout);
-                        result.push('\n');
-                    }
-                    if !stderr.is_empty() {
-                        result.push_str("STDERR:\n");
-                        result.push_str(&stderr);
-                    }
-
-                    if result.is_empty() {
-                        result = format!(
-                            "Command completed successfully (exit code: {})",
-                            output.status.code().unwrap_or(0)
-                        );
-                    }
-
-                    if !output.status.success() {
-                        return Err(McpError::internal_error(
-                            format!("nix run failed: {}", result),
-                            None,
-                        ));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -467,12 +532,18 @@ impl DevelopTools {
             command,
             args,
         }): Parameters<NixDevelopArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate flake reference if provided
         if let Some(ref fref) = flake_ref {
             validate_flake_ref(fref).map_err(validation_error_to_mcp)?;
         }
 
+        // Resolve against the client's workspace roots (default: the active root)
+        let flake = workspace
+            .resolve_flake(flake_ref.as_deref().unwrap_or("."))
+            .await?;
+
         // Validate command
         validate_command(&command).map_err(validation_error_to_mcp)?;
 
@@ -482,59 +553,63 @@ impl DevelopTools {
             "nix_develop",
             Some(serde_json::json!({"flake_ref": &flake_ref, "command": &command, "args": &args})),
             || async {
-                with_timeout(&self.audit, "nix_develop", 300, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("develop");
+                let _slot = scheduler()
+                    .acquire(&self.audit, "nix_develop", CommandCategory::Build)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "nix_develop",
+                    self.config.timeout("nix_develop", 300),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("develop").arg(&flake);
+
+                        cmd.arg("-c").arg(&command);
+
+                        if let Some(command_args) = args {
+                            for arg in command_args {
+                                cmd.arg(arg);
+                            }
+                        }
 
-                    if let Some(ref fref) = flake_ref {
-                        cmd.arg(fref);
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute nix develop: {}", e),
+                                None,
+                            )
+                        })?;
 
-                    cmd.arg("-c").arg(&command);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
 
-                    if let Some(command_args) = args {
-                        for arg in command_args {
-                            cmd.arg(arg);
+                        let mut result = String::new();
+                        if !stdout.is_empty() {
+                            result.push_str("STDOUT:\n");
+                            result.push_str(&stdout);
+                            result.push('\n');
+                        }
+                        if !stderr.is_empty() {
+                            result.push_str("STDERR:\n");
+                            result.push_str(&stderr);
                         }
-                    }
 
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute nix develop: {}", e),
-                            None,
-                        )
-                    })?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    let mut result = String::new();
-                    if !stdout.is_empty() {
-                        result.push_str("STDOUT:\n");
-                        result.push_str(&stdout);
-                        result.push('\n');
-                    }
-                    if !stderr.is_empty() {
-                        result.push_str("STDERR:\n");
-                        result.push_str(&stderr);
-                    }
-
-                    if result.is_empty() {
-                        result = format!(
-                            "Command '{}' completed successfully in development environment",
-                            command
-                        );
-                    }
-
-                    if !output.status.success() {
-                        return Err(McpError::internal_error(
-                            format!("nix develop failed: {}", result),
-                            None,
-                        ));
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                        if result.is_empty() {
+                            result = format!(
+                                "Command '{}' completed successfully in development environment",
+                                command
+                            );
+                        }
+
+                        if !output.status.success() {
+                            return Err(McpError::internal_error(
+                                format!("nix develop failed: {}", result),
+                                None,
+                            ));
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -1,13 +1,18 @@
 use crate::common::cache_registry::CacheRegistry;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::roots::Workspace;
 use crate::common::security::helpers::validation_error_to_mcp;
 use crate::common::security::{validate_flake_ref, AuditLogger};
+use crate::common::structured::{output_schema, ToolOutput};
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{CallToolResult, Content};
 use rmcp::ErrorData as McpError;
 use rmcp::{tool, tool_router};
 use std::sync::Arc;
 
-use super::types::{FlakeMetadataArgs, FlakeShowArgs, PrefetchUrlArgs};
+use super::types::{FlakeMetadataArgs, FlakeMetadataResult, FlakeShowArgs, PrefetchUrlArgs};
 
 /// Tools for working with Nix flakes.
 ///
@@ -44,6 +49,7 @@ use super::types::{FlakeMetadataArgs, FlakeShowArgs, PrefetchUrlArgs};
 /// ```no_run
 /// use onix_mcp::nix::FlakeTools;
 /// use onix_mcp::nix::types::FlakeMetadataArgs;
+/// use onix_mcp::common::roots::Workspace;
 /// use rmcp::handler::server::wrapper::Parameters;
 /// use std::sync::Arc;
 ///
@@ -51,13 +57,14 @@ use super::types::{FlakeMetadataArgs, FlakeShowArgs, PrefetchUrlArgs};
 /// // Get metadata for a flake
 /// let result = tools.flake_metadata(Parameters(FlakeMetadataArgs {
 ///     flake_ref: "github:nixos/nixpkgs".to_string(),
-/// })).await?;
+/// }), Workspace::default()).await?;
 /// # Ok(())
 /// # }
 /// ```
 pub struct FlakeTools {
     audit: Arc<AuditLogger>,
     caches: Arc<CacheRegistry>,
+    config: Arc<Config>,
 }
 
 impl FlakeTools {
@@ -68,7 +75,44 @@ impl FlakeTools {
     /// * `audit` - Shared audit logger for security event logging
     /// * `caches` - Shared cache registry containing prefetch cache
     pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>) -> Self {
-        Self { audit, caches }
+        Self::with_config(audit, caches, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `FlakeTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(
+        audit: Arc<AuditLogger>,
+        caches: Arc<CacheRegistry>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            audit,
+            caches,
+            config,
+        }
+    }
+}
+
+impl ToolOutput for FlakeMetadataResult {
+    fn to_text(&self) -> String {
+        let mut info = Vec::new();
+
+        if let Some(description) = &self.description {
+            info.push(format!("Description: {}", description));
+        }
+        if let Some(url) = &self.url {
+            info.push(format!("URL: {}", url));
+        }
+        if let Some(rev) = &self.revision {
+            info.push(format!("Revision: {}", &rev[..12.min(rev.len())]));
+        }
+        if let Some(last_mod) = self.last_modified {
+            info.push(format!("Last Modified: {}", last_mod));
+        }
+        if !self.inputs.is_empty() {
+            info.push(format!("\nInputs: {}", self.inputs.join(", ")));
+        }
+
+        info.join("\n")
     }
 }
 
@@ -76,16 +120,20 @@ impl FlakeTools {
 impl FlakeTools {
     #[tool(
         description = "Get metadata about a flake (inputs, outputs, description)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true),
+        output_schema = output_schema::<FlakeMetadataResult>()
     )]
     pub async fn flake_metadata(
         &self,
         Parameters(FlakeMetadataArgs { flake_ref }): Parameters<FlakeMetadataArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
 
         // Validate flake reference
         validate_flake_ref(&flake_ref).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_ref = workspace.resolve_flake(&flake_ref).await?;
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(
@@ -93,72 +141,77 @@ impl FlakeTools {
             "flake_metadata",
             Some(serde_json::json!({"flake_ref": &flake_ref})),
             || async {
-                with_timeout(&self.audit, "flake_metadata", 30, || async {
-                    let output = tokio::process::Command::new("nix")
-                        .args(["flake", "metadata", "--json", &flake_ref])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to get flake metadata: {}", e),
+                let _slot = scheduler()
+                    .acquire(&self.audit, "flake_metadata", CommandCategory::Network)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "flake_metadata",
+                    self.config.timeout("flake_metadata", 30),
+                    || async {
+                        let output = tokio::process::Command::new("nix")
+                            .args(["flake", "metadata", "--json", &flake_ref])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to get flake metadata: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("Failed to read flake: {}", stderr),
                                 None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to read flake: {}", stderr),
-                            None,
-                        ));
-                    }
-
-                    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to parse metadata: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    let mut info = Vec::new();
-
-                    if let Some(description) = metadata.get("description").and_then(|v| v.as_str())
-                    {
-                        info.push(format!("Description: {}", description));
-                    }
-
-                    if let Some(url) = metadata.get("url").and_then(|v| v.as_str()) {
-                        info.push(format!("URL: {}", url));
-                    }
-
-                    if let Some(locked) = metadata.get("locked") {
-                        if let Some(rev) = locked.get("rev").and_then(|v| v.as_str()) {
-                            info.push(format!("Revision: {}", &rev[..12.min(rev.len())]));
+                            ));
                         }
-                        if let Some(last_mod) = locked.get("lastModified").and_then(|v| v.as_u64())
-                        {
-                            info.push(format!("Last Modified: {}", last_mod));
-                        }
-                    }
 
-                    if let Some(locks) = metadata.get("locks") {
-                        if let Some(nodes) = locks.get("nodes").and_then(|v| v.as_object()) {
-                            let inputs: Vec<String> = nodes
-                                .keys()
-                                .filter(|k| k.as_str() != "root")
-                                .map(|k| k.to_string())
-                                .collect();
-                            if !inputs.is_empty() {
-                                info.push(format!("\nInputs: {}", inputs.join(", ")));
-                            }
-                        }
-                    }
-
-                    Ok(CallToolResult::success(vec![Content::text(
-                        info.join("\n"),
-                    )]))
-                })
+                        let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to parse metadata: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let locked = metadata.get("locked");
+                        let inputs = metadata
+                            .pointer("/locks/nodes")
+                            .and_then(|v| v.as_object())
+                            .map(|nodes| {
+                                nodes
+                                    .keys()
+                                    .filter(|k| k.as_str() != "root")
+                                    .cloned()
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+
+                        let result = FlakeMetadataResult {
+                            flake_ref: flake_ref.clone(),
+                            description: metadata
+                                .get("description")
+                                .and_then(|v| v.as_str())
+                                .map(str::to_string),
+                            url: metadata
+                                .get("url")
+                                .and_then(|v| v.as_str())
+                                .map(str::to_string),
+                            revision: locked
+                                .and_then(|l| l.get("rev"))
+                                .and_then(|v| v.as_str())
+                                .map(str::to_string),
+                            last_modified: locked
+                                .and_then(|l| l.get("lastModified"))
+                                .and_then(|v| v.as_u64()),
+                            inputs,
+                        };
+
+                        Ok(result.to_tool_result())
+                    },
+                )
                 .await
             },
         )
@@ -172,13 +225,16 @@ impl FlakeTools {
     pub async fn flake_show(
         &self,
         Parameters(FlakeShowArgs { flake_ref }): Parameters<FlakeShowArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         use crate::common::security::helpers::{audit_tool_execution, with_timeout};
 
-        let flake_ref = flake_ref.unwrap_or_else(|| ".".to_string());
+        let flake_ref = flake_ref.unwrap_or_else(|| self.config.flakes.default.clone());
 
         // Validate flake reference
         validate_flake_ref(&flake_ref).map_err(validation_error_to_mcp)?;
+        // Resolve against the client's workspace roots
+        let flake_ref = workspace.resolve_flake(&flake_ref).await?;
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(
@@ -186,64 +242,73 @@ impl FlakeTools {
             "flake_show",
             Some(serde_json::json!({"flake_ref": &flake_ref})),
             || async {
-                with_timeout(&self.audit, "flake_show", 30, || async {
-                    let output = tokio::process::Command::new("nix")
-                        .args(["flake", "show", &flake_ref, "--json"])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix flake show: {}", e),
+                let _slot = scheduler()
+                    .acquire(&self.audit, "flake_show", CommandCategory::Evaluation)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "flake_show",
+                    self.config.timeout("flake_show", 30),
+                    || async {
+                        let output = tokio::process::Command::new("nix")
+                            .args(["flake", "show", &flake_ref, "--json"])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute nix flake show: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("Failed to show flake: {}", stderr),
                                 None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to show flake: {}", stderr),
-                            None,
-                        ));
-                    }
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-
-                    // Parse and format the flake structure
-                    if let Ok(flake_json) = serde_json::from_str::<serde_json::Value>(&stdout) {
-                        let mut result = format!("Flake Outputs for: {}\n\n", flake_ref);
-
-                        fn format_outputs(
-                            value: &serde_json::Value,
-                            prefix: String,
-                            result: &mut String,
-                        ) {
-                            if let serde_json::Value::Object(map) = value {
-                                for (key, val) in map {
-                                    if val.is_object()
-                                        && val.as_object().unwrap().contains_key("type")
-                                    {
-                                        let type_str = val["type"].as_str().unwrap_or("unknown");
-                                        result.push_str(&format!(
-                                            "{}  {}: {}\n",
-                                            prefix, key, type_str
-                                        ));
-                                    } else if val.is_object() {
-                                        result.push_str(&format!("{}{}:\n", prefix, key));
-                                        format_outputs(val, format!("{}  ", prefix), result);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+
+                        // Parse and format the flake structure
+                        if let Ok(flake_json) = serde_json::from_str::<serde_json::Value>(&stdout) {
+                            let mut result = format!("Flake Outputs for: {}\n\n", flake_ref);
+
+                            fn format_outputs(
+                                value: &serde_json::Value,
+                                prefix: String,
+                                result: &mut String,
+                            ) {
+                                if let serde_json::Value::Object(map) = value {
+                                    for (key, val) in map {
+                                        if val.is_object()
+                                            && val.as_object().unwrap().contains_key("type")
+                                        {
+                                            let type_str =
+                                                val["type"].as_str().unwrap_or("unknown");
+                                            result.push_str(&format!(
+                                                "{}  {}: {}\n",
+                                                prefix, key, type_str
+                                            ));
+                                        } else if val.is_object() {
+                                            result.push_str(&format!("{}{}:\n", prefix, key));
+                                            format_outputs(val, format!("{}  ", prefix), result);
+                                        }
                                     }
                                 }
                             }
-                        }
 
-                        format_outputs(&flake_json, String::new(), &mut result);
+                            format_outputs(&flake_json, String::new(), &mut result);
 
-                        Ok(CallToolResult::success(vec![Content::text(result)]))
-                    } else {
-                        Ok(CallToolResult::success(vec![Content::text(
-                            stdout.to_string(),
-                        )]))
-                    }
-                })
+                            Ok(CallToolResult::success(vec![Content::text(result)]))
+                        } else {
+                            Ok(CallToolResult::success(vec![Content::text(
+                                stdout.to_string(),
+                            )]))
+                        }
+                    },
+                )
                 .await
             },
         )
@@ -274,12 +339,15 @@ impl FlakeTools {
         let cache_key_clone = cache_key.clone();
 
         audit_tool_execution(&self.audit, "prefetch_url", Some(serde_json::json!({"url": &url})), || async move {
-            with_timeout(&self.audit, "prefetch_url", 60, || async {
+            let _slot = scheduler()
+                .acquire(&self.audit, "prefetch_url", CommandCategory::Network)
+                .await;
+            with_timeout(&self.audit, "prefetch_url", self.config.timeout("prefetch_url", 60), || async {
                 let _format = hash_format.unwrap_or_else(|| "sri".to_string());
 
                 let output = tokio::process::Command::new("nix")
                     .args(["store", "prefetch-file", &url])
-                    .output()
+                    .group_output()
                     .await
                     .map_err(|e| McpError::internal_error(format!("Failed to prefetch URL: {}", e), None))?;
 
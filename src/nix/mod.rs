@@ -35,12 +35,14 @@
 //!
 //! ```no_run
 //! use onix_mcp::nix::{PackageTools, SearchPackagesArgs};
+//! use onix_mcp::common::cache_registry::CacheRegistry;
+//! use onix_mcp::common::security::audit_logger;
 //! use std::sync::Arc;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Create package tools with caching
-//! let audit = Arc::new(/* audit logger */);
-//! let caches = Arc::new(/* cache registry */);
+//! let audit = audit_logger();
+//! let caches = Arc::new(CacheRegistry::new());
 //! let tools = PackageTools::new(audit, caches);
 //!
 //! // Search for packages
@@ -66,6 +68,10 @@ pub use flakes::FlakeTools;
 pub use info::InfoTools;
 pub use packages::PackageTools;
 pub use quality::QualityTools;
+pub use types::{
+    BuiltDerivation, ClosureSizeResult, FlakeMetadataResult, NixBuildResult, PackageMatch,
+    PackageSearchResult,
+};
 pub use types::{
     CommaArgs, DiffDerivationsArgs, EcosystemToolArgs, ExplainPackageArgs, FindCommandArgs,
     FlakeMetadataArgs, FlakeShowArgs, FormatNixArgs, GetBuildLogArgs, GetClosureSizeArgs,
@@ -1,10 +1,17 @@
 use crate::common::cache_registry::CacheRegistry;
 use crate::common::caching::CachedExecutor;
+use crate::common::command::{scheduler, CommandCategory};
+use crate::common::config::Config;
+use crate::common::flake_ref::FlakeRef;
+use crate::common::process_group::ProcessGroupExt;
+use crate::common::revision::RevisionResolver;
+use crate::common::roots::Workspace;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::helpers::{
     audit_tool_execution, validation_error_to_mcp, with_timeout,
 };
 use crate::common::security::{validate_command, validate_flake_ref, validate_package_name};
+use crate::common::structured::{output_schema, ToolOutput};
 use crate::common::tool_module::ToolModule;
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{CallToolResult, Content};
@@ -14,7 +21,7 @@ use std::sync::Arc;
 
 use super::types::{
     CommaArgs, ExplainPackageArgs, FindCommandArgs, GetPackageInfoArgs, NixLocateArgs,
-    SearchPackagesArgs,
+    PackageMatch, PackageSearchResult, SearchPackagesArgs,
 };
 
 /// Tools for searching, locating, and querying Nix packages.
@@ -29,6 +36,10 @@ use super::types::{
 /// - `package_info_cache`: 30-minute TTL for package metadata
 /// - `locate_cache`: 5-minute TTL for file location queries
 ///
+/// Search and package info results are keyed on the lock revision of the
+/// flake they come from (see [`RevisionResolver`]): they are recomputed
+/// after `nix flake update`, and never expire for pinned revisions.
+///
 /// # Security
 ///
 /// All inputs are validated before execution:
@@ -40,11 +51,13 @@ use super::types::{
 ///
 /// ```no_run
 /// use onix_mcp::nix::PackageTools;
+/// use onix_mcp::common::cache_registry::CacheRegistry;
+/// use onix_mcp::common::security::audit_logger;
 /// use std::sync::Arc;
 ///
 /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-/// let audit = Arc::new(/* audit logger */);
-/// let caches = Arc::new(/* cache registry */);
+/// let audit = audit_logger();
+/// let caches = Arc::new(CacheRegistry::new());
 /// let tools = PackageTools::new(audit, caches);
 ///
 /// // Search for packages
@@ -58,6 +71,7 @@ use super::types::{
 pub struct PackageTools {
     audit: Arc<AuditLogger>,
     caches: Arc<CacheRegistry>,
+    config: Arc<Config>,
 }
 
 impl PackageTools {
@@ -68,7 +82,20 @@ impl PackageTools {
     /// * `audit` - Shared audit logger for security event logging
     /// * `caches` - Shared cache registry containing search, package_info, and locate caches
     pub fn new(audit: Arc<AuditLogger>, caches: Arc<CacheRegistry>) -> Self {
-        Self { audit, caches }
+        Self::with_config(audit, caches, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `PackageTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(
+        audit: Arc<AuditLogger>,
+        caches: Arc<CacheRegistry>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            audit,
+            caches,
+            config,
+        }
     }
 }
 
@@ -82,11 +109,40 @@ impl ToolModule for PackageTools {
     }
 }
 
+impl ToolOutput for PackageSearchResult {
+    fn to_text(&self) -> String {
+        if self.packages.is_empty() {
+            return format!("No packages found matching '{}'", self.query);
+        }
+
+        let formatted: Vec<String> = self
+            .packages
+            .iter()
+            .map(|pkg| {
+                format!(
+                    "Package: {}\nVersion: {}\nDescription: {}\n",
+                    pkg.attr_path,
+                    pkg.version.as_deref().unwrap_or("unknown"),
+                    pkg.description.as_deref().unwrap_or("No description")
+                )
+            })
+            .collect();
+
+        format!(
+            "Found {} packages matching '{}':\n\n{}",
+            formatted.len(),
+            self.query,
+            formatted.join("\n")
+        )
+    }
+}
+
 #[tool_router]
 impl PackageTools {
     #[tool(
         description = "Search for packages in nixpkgs by name or description",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true),
+        output_schema = output_schema::<PackageSearchResult>()
     )]
     pub async fn search_packages(
         &self,
@@ -95,28 +151,47 @@ impl PackageTools {
         // Validate query input
         validate_package_name(&query).map_err(validation_error_to_mcp)?;
 
-        // Use cached executor with formatted cache key
-        let cached_executor = CachedExecutor::new(self.caches.search.clone());
+        // Use cached executor so cached hits keep their structured content,
+        // keyed on the nixpkgs revision being searched
+        let nixpkgs = self.config.flakes.nixpkgs.clone();
+        let mut cached_executor = CachedExecutor::new(self.caches.search.clone());
+        if let Ok(flake_ref) = FlakeRef::parse(&nixpkgs) {
+            // The configured nixpkgs belongs to the server, not the client's workspace
+            let revision = RevisionResolver::new(
+                "search_packages",
+                &self.audit,
+                self.config.timeout("search_packages", 30),
+                &self.caches.revision,
+            )
+            .of_flake(&flake_ref, &Workspace::default())
+            .await;
+            cached_executor = cached_executor.at_revision(revision);
+        }
         let audit = self.audit.clone();
         let query_clone = query.clone();
         let limit_value = limit.unwrap_or(10);
 
         cached_executor
-            .execute_with_formatted_cache(
-                vec![query.clone(), limit_value.to_string()],
-                || async move {
-                    let audit_inner = audit.clone();
-                    // Execute with security features (audit logging + timeout)
-                    audit_tool_execution(
-                        &audit,
-                        "search_packages",
-                        Some(serde_json::json!({"query": &query_clone})),
-                        || async move {
-                            with_timeout(&audit_inner, "search_packages", 30, || async {
+            .execute_with_structured_cache(format!("{}:{}", query, limit_value), || async move {
+                let audit_inner = audit.clone();
+                // Execute with security features (audit logging + timeout)
+                audit_tool_execution(
+                    &audit,
+                    "search_packages",
+                    Some(serde_json::json!({"query": &query_clone})),
+                    || async move {
+                        let _slot = scheduler()
+                            .acquire(&audit_inner, "search_packages", CommandCategory::Evaluation)
+                            .await;
+                        with_timeout(
+                            &audit_inner,
+                            "search_packages",
+                            self.config.timeout("search_packages", 30),
+                            || async {
                                 // Use nix search command
                                 let output = tokio::process::Command::new("nix")
-                                    .args(["search", "nixpkgs", &query_clone, "--json"])
-                                    .output()
+                                    .args(["search", &nixpkgs, &query_clone, "--json"])
+                                    .group_output()
                                     .await
                                     .map_err(|e| {
                                         McpError::internal_error(
@@ -142,45 +217,31 @@ impl PackageTools {
                                         )
                                     })?;
 
-                                // Format results nicely
-                                let mut formatted_results = Vec::new();
+                                let mut packages = Vec::new();
                                 if let Some(obj) = results.as_object() {
-                                    for (i, (pkg_path, info)) in obj.iter().enumerate() {
-                                        if i >= limit_value {
-                                            break;
-                                        }
-
-                                        let description = info["description"]
-                                            .as_str()
-                                            .unwrap_or("No description");
-                                        let version = info["version"].as_str().unwrap_or("unknown");
-
-                                        formatted_results.push(format!(
-                                            "Package: {}\nVersion: {}\nDescription: {}\n",
-                                            pkg_path, version, description
-                                        ));
+                                    for (attr_path, info) in obj.iter().take(limit_value) {
+                                        let field =
+                                            |name: &str| info[name].as_str().map(str::to_string);
+                                        packages.push(PackageMatch {
+                                            attr_path: attr_path.clone(),
+                                            pname: field("pname"),
+                                            version: field("version"),
+                                            description: field("description"),
+                                        });
                                     }
                                 }
 
-                                let result_text = if formatted_results.is_empty() {
-                                    format!("No packages found matching '{}'", query_clone)
-                                } else {
-                                    format!(
-                                        "Found {} packages matching '{}':\n\n{}",
-                                        formatted_results.len(),
-                                        query_clone,
-                                        formatted_results.join("\n")
-                                    )
-                                };
-
-                                Ok(result_text)
-                            })
-                            .await
-                        },
-                    )
-                    .await
-                },
-            )
+                                Ok(PackageSearchResult {
+                                    query: query_clone.clone(),
+                                    packages,
+                                })
+                            },
+                        )
+                        .await
+                    },
+                )
+                .await
+            })
             .await
     }
 
@@ -191,56 +252,72 @@ impl PackageTools {
     pub async fn get_package_info(
         &self,
         Parameters(GetPackageInfoArgs { package }): Parameters<GetPackageInfoArgs>,
+        workspace: Workspace,
     ) -> Result<CallToolResult, McpError> {
         // Validate package reference
-        validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
-
-        // Check cache first
-        if let Some(cached_result) = self.caches.package_info.get(&package) {
-            return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
-        }
+        let flake_ref = validate_flake_ref(&package).map_err(validation_error_to_mcp)?;
 
-        // Execute with security features (audit logging + timeout)
-        let package_info_cache = self.caches.package_info.clone();
-        let package_clone = package.clone();
+        // Resolve against the client's workspace roots
+        let flake_ref = workspace.resolve_flake_ref(&flake_ref).await?;
+        let installable = flake_ref.to_string();
 
-        audit_tool_execution(
-            &self.audit,
+        // Cache on the canonical reference at the flake's lock revision
+        let revision = RevisionResolver::new(
             "get_package_info",
-            Some(serde_json::json!({"package": &package})),
-            || async move {
-                with_timeout(&self.audit, "get_package_info", 30, || async {
-                    // Use nix eval to get package metadata
-                    let output = tokio::process::Command::new("nix")
-                        .args(["eval", &package, "--json"])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix eval: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("nix eval failed: {}", stderr),
-                            None,
-                        ));
-                    }
+            &self.audit,
+            self.config.timeout("get_package_info", 30),
+            &self.caches.revision,
+        )
+        .of_flake(&flake_ref, &workspace)
+        .await;
+        let cached_executor =
+            CachedExecutor::new(self.caches.package_info.clone()).at_revision(revision);
 
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        cached_executor
+            .execute_with_string_cache(installable.clone(), || async {
+                // Execute with security features (audit logging + timeout)
+                audit_tool_execution(
+                    &self.audit,
+                    "get_package_info",
+                    Some(serde_json::json!({"package": &package})),
+                    || async {
+                        let _slot = scheduler()
+                            .acquire(&self.audit, "get_package_info", CommandCategory::Evaluation)
+                            .await;
+                        with_timeout(
+                            &self.audit,
+                            "get_package_info",
+                            self.config.timeout("get_package_info", 30),
+                            || async {
+                                // Use nix eval to get package metadata
+                                let output = tokio::process::Command::new("nix")
+                                    .args(["eval", &installable, "--json"])
+                                    .group_output()
+                                    .await
+                                    .map_err(|e| {
+                                        McpError::internal_error(
+                                            format!("Failed to execute nix eval: {}", e),
+                                            None,
+                                        )
+                                    })?;
 
-                    // Cache the result
-                    package_info_cache.insert(package_clone, stdout.clone());
+                                if !output.status.success() {
+                                    let stderr = String::from_utf8_lossy(&output.stderr);
+                                    return Err(McpError::internal_error(
+                                        format!("nix eval failed: {}", stderr),
+                                        None,
+                                    ));
+                                }
 
-                    Ok(CallToolResult::success(vec![Content::text(stdout)]))
-                })
+                                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+                            },
+                        )
+                        .await
+                    },
+                )
                 .await
-            },
-        )
-        .await
+            })
+            .await
     }
 
     #[tool(
@@ -260,99 +337,111 @@ impl PackageTools {
             "explain_package",
             Some(serde_json::json!({"package": &package})),
             || async {
-                with_timeout(&self.audit, "explain_package", 30, || async {
-                    // Normalize package reference
-                    let pkg_ref = if package.contains('#') {
-                        package.clone()
-                    } else {
-                        format!("nixpkgs#{}", package)
-                    };
-
-                    // Get package metadata using nix eval
-                    let meta_attr = format!("{}.meta", pkg_ref);
-
-                    let output = tokio::process::Command::new("nix")
-                        .args(["eval", "--json", &meta_attr])
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to get package info: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to evaluate package: {}", stderr),
-                            None,
-                        ));
-                    }
+                let _slot = scheduler()
+                    .acquire(&self.audit, "explain_package", CommandCategory::Evaluation)
+                    .await;
+                with_timeout(
+                    &self.audit,
+                    "explain_package",
+                    self.config.timeout("explain_package", 30),
+                    || async {
+                        // Normalize package reference
+                        let pkg_ref = if package.contains('#') {
+                            package.clone()
+                        } else {
+                            format!("{}#{}", self.config.flakes.nixpkgs, package)
+                        };
 
-                    let meta: serde_json::Value =
-                        serde_json::from_slice(&output.stdout).map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to parse metadata: {}", e),
-                                None,
-                            )
-                        })?;
+                        // Get package metadata using nix eval
+                        let meta_attr = format!("{}.meta", pkg_ref);
 
-                    let mut info = Vec::new();
-                    info.push(format!("Package: {}", package));
+                        let output = tokio::process::Command::new("nix")
+                            .args(["eval", "--json", &meta_attr])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to get package info: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("Failed to evaluate package: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    if let Some(version) = meta.get("version").and_then(|v| v.as_str()) {
-                        info.push(format!("Version: {}", version));
-                    }
+                        let meta: serde_json::Value = serde_json::from_slice(&output.stdout)
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to parse metadata: {}", e),
+                                    None,
+                                )
+                            })?;
 
-                    if let Some(description) = meta.get("description").and_then(|v| v.as_str()) {
-                        info.push(format!("Description: {}", description));
-                    }
+                        let mut info = Vec::new();
+                        info.push(format!("Package: {}", package));
 
-                    if let Some(homepage) = meta.get("homepage").and_then(|v| v.as_str()) {
-                        info.push(format!("Homepage: {}", homepage));
-                    }
+                        if let Some(version) = meta.get("version").and_then(|v| v.as_str()) {
+                            info.push(format!("Version: {}", version));
+                        }
 
-                    if let Some(license) = meta.get("license") {
-                        if let Some(name) = license.get("spdxId").and_then(|v| v.as_str()) {
-                            info.push(format!("License: {}", name));
-                        } else if let Some(name) = license.get("fullName").and_then(|v| v.as_str())
+                        if let Some(description) = meta.get("description").and_then(|v| v.as_str())
                         {
-                            info.push(format!("License: {}", name));
+                            info.push(format!("Description: {}", description));
                         }
-                    }
 
-                    if let Some(platforms) = meta.get("platforms").and_then(|v| v.as_array()) {
-                        let platform_list: Vec<String> = platforms
-                            .iter()
-                            .filter_map(|p| p.as_str().map(String::from))
-                            .take(5)
-                            .collect();
-                        if !platform_list.is_empty() {
-                            info.push(format!(
-                                "Platforms: {} (showing first 5)",
-                                platform_list.join(", ")
-                            ));
+                        if let Some(homepage) = meta.get("homepage").and_then(|v| v.as_str()) {
+                            info.push(format!("Homepage: {}", homepage));
                         }
-                    }
 
-                    if let Some(maintainers) = meta.get("maintainers").and_then(|v| v.as_array()) {
-                        let maint_list: Vec<String> = maintainers
-                            .iter()
-                            .filter_map(|m| {
-                                m.get("name").and_then(|n| n.as_str()).map(String::from)
-                            })
-                            .take(3)
-                            .collect();
-                        if !maint_list.is_empty() {
-                            info.push(format!("Maintainers: {}", maint_list.join(", ")));
+                        if let Some(license) = meta.get("license") {
+                            if let Some(name) = license.get("spdxId").and_then(|v| v.as_str()) {
+                                info.push(format!("License: {}", name));
+                            } else if let Some(name) =
+                                license.get("fullName").and_then(|v| v.as_str())
+                            {
+                                info.push(format!("License: {}", name));
+                            }
                         }
-                    }
 
-                    Ok(CallToolResult::success(vec![Content::text(
-                        info.join("\n"),
-                    )]))
-                })
+                        if let Some(platforms) = meta.get("platforms").and_then(|v| v.as_array()) {
+                            let platform_list: Vec<String> = platforms
+                                .iter()
+                                .filter_map(|p| p.as_str().map(String::from))
+                                .take(5)
+                                .collect();
+                            if !platform_list.is_empty() {
+                                info.push(format!(
+                                    "Platforms: {} (showing first 5)",
+                                    platform_list.join(", ")
+                                ));
+                            }
+                        }
+
+                        if let Some(maintainers) =
+                            meta.get("maintainers").and_then(|v| v.as_array())
+                        {
+                            let maint_list: Vec<String> = maintainers
+                                .iter()
+                                .filter_map(|m| {
+                                    m.get("name").and_then(|n| n.as_str()).map(String::from)
+                                })
+                                .take(3)
+                                .collect();
+                            if !maint_list.is_empty() {
+                                info.push(format!("Maintainers: {}", maint_list.join(", ")));
+                            }
+                        }
+
+                        Ok(CallToolResult::success(vec![Content::text(
+                            info.join("\n"),
+                        )]))
+                    },
+                )
                 .await
             },
         )
@@ -372,11 +461,14 @@ impl PackageTools {
 
         // Wrap tool logic with security
         audit_tool_execution(&self.audit, "find_command", Some(serde_json::json!({"command": &command})), || async {
-            with_timeout(&self.audit, "find_command", 30, || async {
+            let _slot = scheduler()
+                .acquire(&self.audit, "find_command", CommandCategory::Evaluation)
+                .await;
+            with_timeout(&self.audit, "find_command", self.config.timeout("find_command", 30), || async {
                 // Try nix-locate first
                 let output = tokio::process::Command::new("nix-locate")
                     .args(["--top-level", "--whole-name", &format!("/bin/{}", command)])
-                    .output()
+                    .group_output()
                     .await;
 
                 match output {
@@ -435,85 +527,78 @@ impl PackageTools {
             ));
         }
 
-        // Create cache key including limit
-        let cache_key = format!("{}:{}", path, limit.unwrap_or(20));
+        let limit_value = limit.unwrap_or(20);
+        let cached_executor = CachedExecutor::new(self.caches.locate.clone());
 
-        // Check cache first
-        if let Some(cached_result) = self.caches.locate.get(&cache_key) {
-            return Ok(CallToolResult::success(vec![Content::text(cached_result)]));
-        }
+        cached_executor
+            .execute_with_formatted_cache([path.clone(), limit_value.to_string()], || async {
+                // Wrap tool logic with security
+                audit_tool_execution(
+                    &self.audit,
+                    "nix_locate",
+                    Some(serde_json::json!({"path": &path, "limit": &limit})),
+                    || async {
+                        let _slot = scheduler()
+                            .acquire(&self.audit, "nix_locate", CommandCategory::Evaluation)
+                            .await;
+                        with_timeout(&self.audit, "nix_locate", self.config.timeout("nix_locate", 60), || async {
+                            // Try local nix-locate first (needs pre-built database)
+                            let output = tokio::process::Command::new("nix-locate")
+                                .arg("--whole-name")
+                                .arg(&path)
+                                .group_output()
+                                .await
+                                .map_err(|e| {
+                                    McpError::internal_error(
+                                        format!("Failed to execute nix-locate: {}. Install with: nix-shell -p nix-index\nThen build database: nix-index", e),
+                                        None,
+                                    )
+                                })?;
+
+                            if !output.status.success() {
+                                let stderr = String::from_utf8_lossy(&output.stderr);
+                                if stderr.contains("command not found") || stderr.contains("No such file") {
+                                    return Ok(
+                                        "nix-locate is not available. Install it with: nix-shell -p nix-index\n\
+                                        Then build the database with: nix-index\n\
+                                        This may take several minutes on first run.".to_string()
+                                    );
+                                }
+                                return Err(McpError::internal_error(
+                                    format!("nix-locate failed: {}", stderr),
+                                    None,
+                                ));
+                            }
 
-        // Wrap tool logic with security
-        let locate_cache = self.caches.locate.clone();
-        let cache_key_clone = cache_key.clone();
+                            let stdout = String::from_utf8_lossy(&output.stdout);
+                            let lines: Vec<&str> = stdout.lines().collect();
 
-        audit_tool_execution(
-            &self.audit,
-            "nix_locate",
-            Some(serde_json::json!({"path": &path, "limit": &limit})),
-            || async move {
-                with_timeout(&self.audit, "nix_locate", 60, || async {
-                    // Try local nix-locate first (needs pre-built database)
-                    let output = tokio::process::Command::new("nix-locate")
-                        .arg("--whole-name")
-                        .arg(&path)
-                        .output()
-                        .await
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to execute nix-locate: {}. Install with: nix-shell -p nix-index\nThen build database: nix-index", e),
-                                None,
-                            )
-                        })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        if stderr.contains("command not found") || stderr.contains("No such file") {
-                            return Ok(CallToolResult::success(vec![Content::text(
-                                "nix-locate is not available. Install it with: nix-shell -p nix-index\n\
-                                Then build the database with: nix-index\n\
-                                This may take several minutes on first run.".to_string()
-                            )]));
-                        }
-                        return Err(McpError::internal_error(
-                            format!("nix-locate failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                            if lines.is_empty() {
+                                return Ok(format!("No packages found providing '{}'", path));
+                            }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let lines: Vec<&str> = stdout.lines().collect();
-
-                    let result = if lines.is_empty() {
-                        format!("No packages found providing '{}'", path)
-                    } else {
-                        let limit = limit.unwrap_or(20);
-                        let results: Vec<&str> = lines.iter().take(limit).copied().collect();
-                        let total = lines.len();
-
-                        let mut output =
-                            format!("Found {} package(s) providing '{}':\n\n", total, path);
-                        output.push_str(&results.join("\n"));
-
-                        if total > limit {
-                            output.push_str(&format!(
-                                "\n\n... and {} more results (showing top {})",
-                                total - limit,
-                                limit
-                            ));
-                        }
-                        output
-                    };
+                            let results: Vec<&str> = lines.iter().take(limit_value).copied().collect();
+                            let total = lines.len();
 
-                    // Cache the result
-                    locate_cache.insert(cache_key_clone, result.clone());
+                            let mut output =
+                                format!("Found {} package(s) providing '{}':\n\n", total, path);
+                            output.push_str(&results.join("\n"));
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                            if total > limit_value {
+                                output.push_str(&format!(
+                                    "\n\n... and {} more results (showing top {})",
+                                    total - limit_value,
+                                    limit_value
+                                ));
+                            }
+                            Ok(output)
+                        })
+                        .await
+                    },
+                )
                 .await
-            },
-        )
-        .await
+            })
+            .await
     }
 
     #[tool(
@@ -532,7 +617,10 @@ impl PackageTools {
             "comma",
             Some(serde_json::json!({"command": &command, "args": &args})),
             || async {
-                with_timeout(&self.audit, "comma", 300, || async {
+                let _slot = scheduler()
+                    .acquire(&self.audit, "comma", CommandCategory::Network)
+                    .await;
+                with_timeout(&self.audit, "comma", self.config.timeout("comma", 300), || async {
                     // Use the actual comma command
                     let mut cmd = tokio::process::Command::new(",");
                     cmd.arg(&command);
@@ -543,7 +631,7 @@ impl PackageTools {
                         }
                     }
 
-                    let output = cmd.output().await;
+                    let output = cmd.group_output().await;
 
                     match output {
                         Ok(output) => {
@@ -1,3 +1,5 @@
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::helpers::{
     audit_tool_execution, validation_error_to_mcp, with_timeout,
@@ -65,6 +67,7 @@ use super::types::{FormatNixArgs, LintNixArgs, NixFmtArgs, ValidateNixArgs};
 /// ```
 pub struct QualityTools {
     audit: Arc<AuditLogger>,
+    config: Arc<Config>,
 }
 
 impl QualityTools {
@@ -79,7 +82,12 @@ impl QualityTools {
     /// QualityTools does not use caching as code quality operations are
     /// fast and code changes frequently during development.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `QualityTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
     }
 }
 
@@ -100,13 +108,13 @@ impl QualityTools {
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(&self.audit, "format_nix", Some(serde_json::json!({"code_length": code.len()})), || async {
-            with_timeout(&self.audit, "format_nix", 30, || async {
+            with_timeout(&self.audit, "format_nix", self.config.timeout("format_nix", 30), || async {
                 // Try nixpkgs-fmt first, fallback to alejandra
                 let child = tokio::process::Command::new("nixpkgs-fmt")
                     .stdin(std::process::Stdio::piped())
                     .stdout(std::process::Stdio::piped())
                     .stderr(std::process::Stdio::piped())
-                    .spawn();
+                    .spawn_group();
 
                 let mut child = match child {
                     Ok(c) => c,
@@ -117,7 +125,7 @@ impl QualityTools {
                             .stdin(std::process::Stdio::piped())
                             .stdout(std::process::Stdio::piped())
                             .stderr(std::process::Stdio::piped())
-                            .spawn()
+                            .spawn_group()
                             .map_err(|e| McpError::internal_error(
                                 format!("Neither nixpkgs-fmt nor alejandra found. Install with: nix-shell -p nixpkgs-fmt\nError: {}", e),
                                 None
@@ -167,46 +175,54 @@ impl QualityTools {
             "nix_fmt",
             Some(serde_json::json!({"path": &path})),
             || async {
-                with_timeout(&self.audit, "nix_fmt", 60, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("fmt");
+                with_timeout(
+                    &self.audit,
+                    "nix_fmt",
+                    self.config.timeout("nix_fmt", 60),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("fmt");
+
+                        if let Some(p) = path {
+                            cmd.arg(p);
+                        }
 
-                    if let Some(p) = path {
-                        cmd.arg(p);
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute nix fmt: {}", e),
+                                None,
+                            )
+                        })?;
 
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(format!("Failed to execute nix fmt: {}", e), None)
-                    })?;
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("nix fmt failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    if !output.status.success() {
+                        let stdout = String::from_utf8_lossy(&output.stdout);
                         let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("nix fmt failed: {}", stderr),
-                            None,
-                        ));
-                    }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-
-                    let mut result = String::new();
-                    if !stdout.is_empty() {
-                        result.push_str(&stdout);
-                    }
-                    if !stderr.is_empty() {
-                        if !result.is_empty() {
-                            result.push('\n');
+                        let mut result = String::new();
+                        if !stdout.is_empty() {
+                            result.push_str(&stdout);
+                        }
+                        if !stderr.is_empty() {
+                            if !result.is_empty() {
+                                result.push('\n');
+                            }
+                            result.push_str(&stderr);
                         }
-                        result.push_str(&stderr);
-                    }
 
-                    if result.is_empty() {
-                        result = "Code formatted successfully".to_string();
-                    }
+                        if result.is_empty() {
+                            result = "Code formatted successfully".to_string();
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -232,38 +248,43 @@ impl QualityTools {
             "validate_nix",
             Some(serde_json::json!({"code_length": code.len()})),
             || async {
-                with_timeout(&self.audit, "validate_nix", 30, || async {
-                    // Use nix-instantiate --parse to validate syntax
-                    let child = tokio::process::Command::new("nix-instantiate")
-                        .args(["--parse", "-E"])
-                        .arg(&code)
-                        .stdin(std::process::Stdio::piped())
-                        .stdout(std::process::Stdio::piped())
-                        .stderr(std::process::Stdio::piped())
-                        .spawn()
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to spawn nix-instantiate: {}", e),
-                                None,
-                            )
+                with_timeout(
+                    &self.audit,
+                    "validate_nix",
+                    self.config.timeout("validate_nix", 30),
+                    || async {
+                        // Use nix-instantiate --parse to validate syntax
+                        let child = tokio::process::Command::new("nix-instantiate")
+                            .args(["--parse", "-E"])
+                            .arg(&code)
+                            .stdin(std::process::Stdio::piped())
+                            .stdout(std::process::Stdio::piped())
+                            .stderr(std::process::Stdio::piped())
+                            .spawn_group()
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to spawn nix-instantiate: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        let output = child.wait_with_output().await.map_err(|e| {
+                            McpError::internal_error(format!("Failed to validate: {}", e), None)
                         })?;
 
-                    let output = child.wait_with_output().await.map_err(|e| {
-                        McpError::internal_error(format!("Failed to validate: {}", e), None)
-                    })?;
-
-                    if output.status.success() {
-                        Ok(CallToolResult::success(vec![Content::text(
-                            "✓ Nix code is valid! No syntax errors found.".to_string(),
-                        )]))
-                    } else {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        Ok(CallToolResult::success(vec![Content::text(format!(
-                            "✗ Syntax errors found:\n\n{}",
-                            stderr
-                        ))]))
-                    }
-                })
+                        if output.status.success() {
+                            Ok(CallToolResult::success(vec![Content::text(
+                                "✓ Nix code is valid! No syntax errors found.".to_string(),
+                            )]))
+                        } else {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            Ok(CallToolResult::success(vec![Content::text(format!(
+                                "✗ Syntax errors found:\n\n{}",
+                                stderr
+                            ))]))
+                        }
+                    },
+                )
                 .await
             },
         )
@@ -285,7 +306,7 @@ impl QualityTools {
 
         // Execute with security features (audit logging + 30s timeout)
         audit_tool_execution(&self.audit, "lint_nix", Some(serde_json::json!({"code_length": code.len(), "linter": &linter})), || async {
-            with_timeout(&self.audit, "lint_nix", 30, || async {
+            with_timeout(&self.audit, "lint_nix", self.config.timeout("lint_nix", 30), || async {
                 let linter = linter.unwrap_or_else(|| "both".to_string());
                 let mut results = Vec::new();
 
@@ -300,7 +321,7 @@ impl QualityTools {
         if linter == "statix" || linter == "both" {
             let output = tokio::process::Command::new("statix")
                 .args(["check", temp_file.to_str().unwrap()])
-                .output()
+                .group_output()
                 .await;
 
             match output {
@@ -324,7 +345,7 @@ impl QualityTools {
         if linter == "deadnix" || linter == "both" {
             let output = tokio::process::Command::new("deadnix")
                 .arg(temp_file.to_str().unwrap())
-                .output()
+                .group_output()
                 .await;
 
             match output {
@@ -1,8 +1,9 @@
-//! Parameter types for Nix MCP tools.
+//! Parameter and result types for Nix MCP tools.
 //!
 //! This module defines all parameter types used by the Nix tools. Each type
 //! corresponds to a specific tool operation and includes field-level documentation
-//! with examples.
+//! with examples. Tools with an output schema also have a result type, returned
+//! as structured content alongside the text output.
 
 use rmcp::schemars;
 
@@ -205,7 +206,7 @@ pub struct CommaArgs {
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct NixBuildArgs {
-    /// Package to build (e.g., "nixpkgs#hello", ".#mypackage")
+    /// Installable to build (e.g., "nixpkgs#hello", ".#mypackage", "/nix/store/...-hello.drv^out")
     pub package: String,
     /// Perform a dry-run build to show what would be built
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -230,9 +231,9 @@ pub struct NixBuildArgs {
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct WhyDependsArgs {
-    /// Package that has the dependency (e.g., "nixpkgs#firefox", ".#result")
+    /// Installable that has the dependency (e.g., "nixpkgs#firefox", ".#result", a store path)
     pub package: String,
-    /// Dependency to explain (e.g., "nixpkgs#libx11")
+    /// Dependency to explain (e.g., "nixpkgs#libx11", a store path)
     pub dependency: String,
     /// Show all dependency paths, not just the shortest one
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -254,7 +255,7 @@ pub struct WhyDependsArgs {
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct ShowDerivationArgs {
-    /// Package to inspect (e.g., "nixpkgs#hello")
+    /// Installable to inspect (e.g., "nixpkgs#hello", "/nix/store/...-hello.drv")
     pub package: String,
 }
 
@@ -275,7 +276,7 @@ pub struct ShowDerivationArgs {
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct GetClosureSizeArgs {
-    /// Package to analyze (e.g., "nixpkgs#firefox", ".#myapp")
+    /// Installable to analyze (e.g., "nixpkgs#firefox", ".#myapp", a store path)
     pub package: String,
     /// Show human-readable sizes (e.g., "1.2 GB" instead of bytes)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -318,9 +319,9 @@ pub struct GetBuildLogArgs {
 /// ```
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct DiffDerivationsArgs {
-    /// First package to compare (e.g., "nixpkgs#firefox")
+    /// First installable to compare (e.g., "nixpkgs#firefox", "/nix/store/...-firefox.drv^out")
     pub package_a: String,
-    /// Second package to compare (e.g., "nixpkgs#firefox-esr")
+    /// Second installable to compare (e.g., "nixpkgs#firefox-esr")
     pub package_b: String,
 }
 
@@ -648,3 +649,93 @@ pub struct NixFmtArgs {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
 }
+
+// ===== Result Types =====
+
+/// Structured result of [`BuildTools::nix_build`](crate::nix::BuildTools::nix_build).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct NixBuildResult {
+    /// Installable that was built (e.g., "nixpkgs#hello")
+    pub package: String,
+    /// Whether this was a dry run
+    pub dry_run: bool,
+    /// Whether the build or dry-run check succeeded
+    pub success: bool,
+    /// Derivations and their output paths reported by `nix build --json`
+    pub outputs: Vec<BuiltDerivation>,
+    /// Derivations that would be built (dry runs only)
+    pub will_build: Vec<String>,
+    /// Store paths that would be fetched from a binary cache (dry runs only)
+    pub will_fetch: Vec<String>,
+    /// Error output if the build failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A derivation and its outputs, as reported by `nix build --json`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct BuiltDerivation {
+    /// Store path of the derivation (.drv)
+    pub drv_path: String,
+    /// Output name (e.g., "out", "dev") to store path
+    pub outputs: std::collections::BTreeMap<String, String>,
+}
+
+/// Structured result of [`BuildTools::get_closure_size`](crate::nix::BuildTools::get_closure_size).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct ClosureSizeResult {
+    /// Installable that was measured
+    pub package: String,
+    /// Store path of the package's `out` output
+    pub store_path: String,
+    /// Total size of the package and all its runtime dependencies in bytes
+    pub closure_size_bytes: u64,
+    /// Closure size formatted for display (e.g., "1.23 GB")
+    pub human_size: String,
+}
+
+/// Structured result of [`PackageTools::search_packages`](crate::nix::PackageTools::search_packages).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PackageSearchResult {
+    /// Search query
+    pub query: String,
+    /// Matching packages, up to the requested limit
+    pub packages: Vec<PackageMatch>,
+}
+
+/// A single package returned by `nix search`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PackageMatch {
+    /// Full attribute path (e.g., "legacyPackages.x86_64-linux.ripgrep")
+    pub attr_path: String,
+    /// Package name without version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pname: Option<String>,
+    /// Package version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Package description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Structured result of [`FlakeTools::flake_metadata`](crate::nix::FlakeTools::flake_metadata).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct FlakeMetadataResult {
+    /// Flake reference that was inspected
+    pub flake_ref: String,
+    /// Flake description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Resolved flake URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Locked git revision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Last modification time of the locked source (Unix timestamp)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<u64>,
+    /// Names of the flake's locked inputs
+    pub inputs: Vec<String>,
+}
@@ -27,10 +27,11 @@
 //!
 //! ```no_run
 //! use onix_mcp::process::{PueueTools, PueueAddArgs};
+//! use onix_mcp::common::security::audit_logger;
 //! use std::sync::Arc;
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-//! let audit = Arc::new(/* audit logger */);
+//! let audit = audit_logger();
 //! let tools = PueueTools::new(audit);
 //!
 //! // Add a long-running task to the queue
@@ -54,3 +55,4 @@ pub use types::{
     PexpectCloseArgs, PexpectSendArgs, PexpectStartArgs, PueueAddArgs, PueueCleanArgs,
     PueueLogArgs, PueuePauseArgs, PueueRemoveArgs, PueueStartArgs, PueueStatusArgs, PueueWaitArgs,
 };
+pub use types::{PueueStatusResult, PueueTask};
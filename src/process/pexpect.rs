@@ -1,3 +1,5 @@
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::{validate_command, validation_error_to_mcp};
 use crate::process::types::{PexpectCloseArgs, PexpectSendArgs, PexpectStartArgs};
@@ -74,6 +76,7 @@ use std::sync::Arc;
 /// ```
 pub struct PexpectTools {
     pub audit: Arc<AuditLogger>,
+    pub config: Arc<Config>,
 }
 
 impl PexpectTools {
@@ -88,7 +91,12 @@ impl PexpectTools {
     /// PexpectTools does not use caching as interactive sessions are
     /// stateful and ephemeral, requiring real-time interaction.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `PexpectTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
     }
 }
 
@@ -113,42 +121,47 @@ impl PexpectTools {
             "pexpect_start",
             Some(serde_json::json!({"command": &command, "args": &args})),
             || async {
-                with_timeout(&self.audit, "pexpect_start", 30, || async {
-                    // Use nix run to ensure pexpect-cli is available
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run")
-                        .arg("nixpkgs#python3Packages.pexpect-cli")
-                        .arg("--")
-                        .arg("--start")
-                        .arg(&command);
+                with_timeout(
+                    &self.audit,
+                    "pexpect_start",
+                    self.config.timeout("pexpect_start", 30),
+                    || async {
+                        // Use nix run to ensure pexpect-cli is available
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run")
+                            .arg("nixpkgs#python3Packages.pexpect-cli")
+                            .arg("--")
+                            .arg("--start")
+                            .arg(&command);
 
-                    if let Some(command_args) = args {
-                        for arg in command_args {
-                            cmd.arg(arg);
+                        if let Some(command_args) = args {
+                            for arg in command_args {
+                                cmd.arg(arg);
+                            }
                         }
-                    }
 
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pexpect-cli via nix run: {}", e),
-                            None,
-                        )
-                    })?;
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pexpect-cli via nix run: {}", e),
+                                None,
+                            )
+                        })?;
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pexpect-cli failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pexpect-cli failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Session started successfully. Session ID: {}",
-                        stdout.trim()
-                    ))]))
-                })
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Session started successfully. Session ID: {}",
+                            stdout.trim()
+                        ))]))
+                    },
+                )
                 .await
             },
         )
@@ -182,66 +195,71 @@ impl PexpectTools {
             "pexpect_send",
             Some(serde_json::json!({"session_id": &session_id, "code": &code})),
             || async {
-                with_timeout(&self.audit, "pexpect_send", 60, || async {
-                    use std::process::Stdio;
-                    use tokio::io::AsyncWriteExt;
-
-                    // Use nix run with stdin piping to avoid shell injection
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run")
-                        .arg("nixpkgs#python3Packages.pexpect-cli")
-                        .arg("--")
-                        .arg(&session_id)
-                        .stdin(Stdio::piped())
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped());
+                with_timeout(
+                    &self.audit,
+                    "pexpect_send",
+                    self.config.timeout("pexpect_send", 60),
+                    || async {
+                        use std::process::Stdio;
+                        use tokio::io::AsyncWriteExt;
 
-                    let mut child = cmd.spawn().map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to spawn pexpect-cli via nix run: {}", e),
-                            None,
-                        )
-                    })?;
+                        // Use nix run with stdin piping to avoid shell injection
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run")
+                            .arg("nixpkgs#python3Packages.pexpect-cli")
+                            .arg("--")
+                            .arg(&session_id)
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped());
 
-                    // Write code to stdin safely without shell interpolation
-                    if let Some(mut stdin) = child.stdin.take() {
-                        stdin.write_all(code.as_bytes()).await.map_err(|e| {
+                        let mut child = cmd.spawn_group().map_err(|e| {
                             McpError::internal_error(
-                                format!("Failed to write code to pexpect-cli stdin: {}", e),
+                                format!("Failed to spawn pexpect-cli via nix run: {}", e),
                                 None,
                             )
                         })?;
-                        drop(stdin); // Close stdin to signal EOF
-                    }
 
-                    let output = child.wait_with_output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pexpect-cli via nix run: {}", e),
-                            None,
-                        )
-                    })?;
+                        // Write code to stdin safely without shell interpolation
+                        if let Some(mut stdin) = child.stdin.take() {
+                            stdin.write_all(code.as_bytes()).await.map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to write code to pexpect-cli stdin: {}", e),
+                                    None,
+                                )
+                            })?;
+                            drop(stdin); // Close stdin to signal EOF
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                        let output = child.wait_with_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pexpect-cli via nix run: {}", e),
+                                None,
+                            )
+                        })?;
 
-                    let mut result = String::new();
-                    if !stdout.is_empty() {
-                        result.push_str(&stdout);
-                    }
-                    if !stderr.is_empty() {
-                        if !result.is_empty() {
-                            result.push('\n');
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+
+                        let mut result = String::new();
+                        if !stdout.is_empty() {
+                            result.push_str(&stdout);
+                        }
+                        if !stderr.is_empty() {
+                            if !result.is_empty() {
+                                result.push('\n');
+                            }
+                            result.push_str("STDERR:\n");
+                            result.push_str(&stderr);
                         }
-                        result.push_str("STDERR:\n");
-                        result.push_str(&stderr);
-                    }
 
-                    if result.is_empty() {
-                        result = "Command sent successfully (no output)".to_string();
-                    }
+                        if result.is_empty() {
+                            result = "Command sent successfully (no output)".to_string();
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result)]))
+                    },
+                )
                 .await
             },
         )
@@ -275,61 +293,66 @@ impl PexpectTools {
             "pexpect_close",
             Some(serde_json::json!({"session_id": &session_id})),
             || async {
-                with_timeout(&self.audit, "pexpect_close", 30, || async {
-                    use std::process::Stdio;
-                    use tokio::io::AsyncWriteExt;
+                with_timeout(
+                    &self.audit,
+                    "pexpect_close",
+                    self.config.timeout("pexpect_close", 30),
+                    || async {
+                        use std::process::Stdio;
+                        use tokio::io::AsyncWriteExt;
 
-                    // Use nix run with stdin piping to avoid shell injection
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run")
-                        .arg("nixpkgs#python3Packages.pexpect-cli")
-                        .arg("--")
-                        .arg(&session_id)
-                        .stdin(Stdio::piped())
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped());
+                        // Use nix run with stdin piping to avoid shell injection
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run")
+                            .arg("nixpkgs#python3Packages.pexpect-cli")
+                            .arg("--")
+                            .arg(&session_id)
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped());
 
-                    let mut child = cmd.spawn().map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to spawn pexpect-cli via nix run: {}", e),
-                            None,
-                        )
-                    })?;
-
-                    // Write close command to stdin safely
-                    if let Some(mut stdin) = child.stdin.take() {
-                        stdin.write_all(b"child.close()").await.map_err(|e| {
+                        let mut child = cmd.spawn_group().map_err(|e| {
                             McpError::internal_error(
-                                format!(
-                                    "Failed to write close command to pexpect-cli stdin: {}",
-                                    e
-                                ),
+                                format!("Failed to spawn pexpect-cli via nix run: {}", e),
                                 None,
                             )
                         })?;
-                        drop(stdin); // Close stdin to signal EOF
-                    }
 
-                    let output = child.wait_with_output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to close pexpect session via nix run: {}", e),
-                            None,
-                        )
-                    })?;
+                        // Write close command to stdin safely
+                        if let Some(mut stdin) = child.stdin.take() {
+                            stdin.write_all(b"child.close()").await.map_err(|e| {
+                                McpError::internal_error(
+                                    format!(
+                                        "Failed to write close command to pexpect-cli stdin: {}",
+                                        e
+                                    ),
+                                    None,
+                                )
+                            })?;
+                            drop(stdin); // Close stdin to signal EOF
+                        }
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("Failed to close session: {}", stderr),
-                            None,
-                        ));
-                    }
+                        let output = child.wait_with_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to close pexpect session via nix run: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("Failed to close session: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(format!(
-                        "Session {} closed successfully",
-                        session_id
-                    ))]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(format!(
+                            "Session {} closed successfully",
+                            session_id
+                        ))]))
+                    },
+                )
                 .await
             },
         )
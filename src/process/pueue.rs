@@ -1,8 +1,11 @@
+use crate::common::config::Config;
+use crate::common::process_group::ProcessGroupExt;
 use crate::common::security::audit::AuditLogger;
 use crate::common::security::{validate_command, validation_error_to_mcp};
+use crate::common::structured::{output_schema, ToolOutput};
 use crate::process::types::{
     PueueAddArgs, PueueCleanArgs, PueueLogArgs, PueuePauseArgs, PueueRemoveArgs, PueueStartArgs,
-    PueueStatusArgs, PueueWaitArgs,
+    PueueStatusArgs, PueueStatusResult, PueueTask, PueueWaitArgs,
 };
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{CallToolResult, Content};
@@ -69,6 +72,7 @@ use std::sync::Arc;
 /// ```
 pub struct PueueTools {
     pub audit: Arc<AuditLogger>,
+    pub config: Arc<Config>,
 }
 
 impl PueueTools {
@@ -83,10 +87,115 @@ impl PueueTools {
     /// PueueTools does not use caching as task queue state changes
     /// in real-time and must reflect current execution status.
     pub fn new(audit: Arc<AuditLogger>) -> Self {
-        Self { audit }
+        Self::with_config(audit, Arc::new(Config::default()))
+    }
+
+    /// Creates a new `PueueTools` instance using timeouts and defaults from `config`.
+    pub fn with_config(audit: Arc<AuditLogger>, config: Arc<Config>) -> Self {
+        Self { audit, config }
     }
 }
 
+impl ToolOutput for PueueStatusResult {
+    fn to_text(&self) -> String {
+        if self.tasks.is_empty() {
+            return "No pueue tasks.".to_string();
+        }
+
+        let mut result = format!(
+            "{:<5} {:<10} {:<12} {}\n",
+            "Id", "Status", "Result", "Command"
+        );
+        for task in &self.tasks {
+            let command = match &task.label {
+                Some(label) => format!("{} ({})", task.command, label),
+                None => task.command.clone(),
+            };
+            result.push_str(&format!(
+                "{:<5} {:<10} {:<12} {}\n",
+                task.id,
+                task.status,
+                task.result.as_deref().unwrap_or("-"),
+                command
+            ));
+        }
+        result
+    }
+}
+
+/// Convert `pueue status --json` output into a [`PueueStatusResult`].
+///
+/// Accepts the status formats of pueue 2 to 4: a plain string (`"Running"`),
+/// or a single-key object (`{"Done": ...}`) whose value may carry the result.
+/// When `ids` is given, only those tasks are kept.
+fn parse_pueue_status(status: &serde_json::Value, ids: Option<&[u64]>) -> PueueStatusResult {
+    fn result_text(value: &serde_json::Value) -> Option<String> {
+        match value {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Object(map) => {
+                let (kind, detail) = map.iter().next()?;
+                Some(match detail {
+                    serde_json::Value::Null => kind.clone(),
+                    serde_json::Value::String(s) => format!("{} ({})", kind, s),
+                    other => format!("{} ({})", kind, other),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    let mut tasks: Vec<PueueTask> = status
+        .get("tasks")
+        .and_then(|v| v.as_object())
+        .map(|tasks| {
+            tasks
+                .iter()
+                .filter_map(|(key, task)| {
+                    let id = task
+                        .get("id")
+                        .and_then(|v| v.as_u64())
+                        .or_else(|| key.parse().ok())?;
+                    let (state, details) = match task.get("status") {
+                        Some(serde_json::Value::String(s)) => (s.clone(), None),
+                        Some(serde_json::Value::Object(map)) => map
+                            .iter()
+                            .next()
+                            .map(|(k, v)| (k.clone(), Some(v)))
+                            .unwrap_or_else(|| ("Unknown".to_string(), None)),
+                        _ => ("Unknown".to_string(), None),
+                    };
+                    let result = task
+                        .get("result")
+                        .filter(|v| !v.is_null())
+                        .or_else(|| match details {
+                            Some(d) if d.is_object() => d.get("result"),
+                            other => other,
+                        })
+                        .and_then(result_text);
+                    let field =
+                        |name: &str| task.get(name).and_then(|v| v.as_str()).map(str::to_string);
+
+                    Some(PueueTask {
+                        id,
+                        command: field("command")
+                            .or_else(|| field("original_command"))
+                            .unwrap_or_default(),
+                        status: state,
+                        result,
+                        label: field("label"),
+                        group: field("group"),
+                        path: field("path"),
+                    })
+                })
+                .filter(|task| ids.is_none_or(|ids| ids.contains(&task.id)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    tasks.sort_by_key(|task| task.id);
+    PueueStatusResult { tasks }
+}
+
 #[tool_router]
 impl PueueTools {
     #[tool(
@@ -119,7 +228,7 @@ impl PueueTools {
             "pueue_add",
             Some(serde_json::json!({"command": &command, "args": &args, "working_directory": &working_directory, "label": &label})),
             || async {
-                with_timeout(&self.audit, "pueue_add", 30, || async {
+                with_timeout(&self.audit, "pueue_add", self.config.timeout("pueue_add", 30), || async {
                     // Use nix run to ensure pueue is available
                     let mut cmd = tokio::process::Command::new("nix");
                     cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("add");
@@ -141,7 +250,7 @@ impl PueueTools {
                         }
                     }
 
-                    let output = cmd.output().await.map_err(|e| {
+                    let output = cmd.group_output().await.map_err(|e| {
                         McpError::internal_error(
                             format!("Failed to execute pueue add via nix run: {}", e),
                             None,
@@ -169,7 +278,8 @@ impl PueueTools {
 
     #[tool(
         description = "Get the status of pueue tasks (all or specific task IDs)",
-        annotations(read_only_hint = true)
+        annotations(read_only_hint = true),
+        output_schema = output_schema::<PueueStatusResult>()
     )]
     pub async fn pueue_status(
         &self,
@@ -183,36 +293,60 @@ impl PueueTools {
             "pueue_status",
             Some(serde_json::json!({"task_ids": &task_ids})),
             || async {
-                with_timeout(&self.audit, "pueue_status", 30, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("status");
-
-                    if let Some(ids) = task_ids {
-                        for id in ids.split(',') {
-                            cmd.arg(id.trim());
+                with_timeout(
+                    &self.audit,
+                    "pueue_status",
+                    self.config.timeout("pueue_status", 30),
+                    || async {
+                        let ids: Option<Vec<u64>> = task_ids
+                            .as_deref()
+                            .map(|ids| {
+                                ids.split(',')
+                                    .map(|id| id.trim().parse::<u64>())
+                                    .collect::<Result<_, _>>()
+                            })
+                            .transpose()
+                            .map_err(|e| {
+                                McpError::invalid_params(
+                                    format!(
+                                        "Invalid task ID in '{}': {}",
+                                        task_ids.as_deref().unwrap_or_default(),
+                                        e
+                                    ),
+                                    None,
+                                )
+                            })?;
+
+                        let output = tokio::process::Command::new("nix")
+                            .args(["run", "nixpkgs#pueue", "--", "status", "--json"])
+                            .group_output()
+                            .await
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to execute pueue status: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pueue status failed: {}", stderr),
+                                None,
+                            ));
                         }
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue status: {}", e),
-                            None,
-                        )
-                    })?;
-
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pueue status failed: {}", stderr),
-                            None,
-                        ));
-                    }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    Ok(CallToolResult::success(vec![Content::text(
-                        stdout.to_string(),
-                    )]))
-                })
+                        let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+                            .map_err(|e| {
+                                McpError::internal_error(
+                                    format!("Failed to parse pueue status: {}", e),
+                                    None,
+                                )
+                            })?;
+
+                        Ok(parse_pueue_status(&status, ids.as_deref()).to_tool_result())
+                    },
+                )
                 .await
             },
         )
@@ -235,38 +369,43 @@ impl PueueTools {
             "pueue_log",
             Some(serde_json::json!({"task_id": &task_id, "lines": &lines})),
             || async {
-                with_timeout(&self.audit, "pueue_log", 30, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run")
-                        .arg("nixpkgs#pueue")
-                        .arg("--")
-                        .arg("log")
-                        .arg(task_id.to_string());
-
-                    if let Some(n) = lines {
-                        cmd.arg("--lines").arg(n.to_string());
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue log: {}", e),
-                            None,
-                        )
-                    })?;
+                with_timeout(
+                    &self.audit,
+                    "pueue_log",
+                    self.config.timeout("pueue_log", 30),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run")
+                            .arg("nixpkgs#pueue")
+                            .arg("--")
+                            .arg("log")
+                            .arg(task_id.to_string());
+
+                        if let Some(n) = lines {
+                            cmd.arg("--lines").arg(n.to_string());
+                        }
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pueue log failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pueue log: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pueue log failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    Ok(CallToolResult::success(vec![Content::text(
-                        stdout.to_string(),
-                    )]))
-                })
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        Ok(CallToolResult::success(vec![Content::text(
+                            stdout.to_string(),
+                        )]))
+                    },
+                )
                 .await
             },
         )
@@ -298,7 +437,7 @@ impl PueueTools {
             Some(serde_json::json!({"task_ids": &task_ids, "timeout": &timeout})),
             || async {
                 // Use custom timeout for wait command
-                let wait_timeout = timeout.unwrap_or(300);
+                let wait_timeout = timeout.unwrap_or(self.config.timeout("pueue_wait", 300));
 
                 let timeout_duration = tokio::time::Duration::from_secs(wait_timeout);
                 let result = tokio::time::timeout(timeout_duration, async {
@@ -309,7 +448,7 @@ impl PueueTools {
                         cmd.arg(id.trim());
                     }
 
-                    let output = cmd.output().await.map_err(|e| {
+                    let output = cmd.group_output().await.map_err(|e| {
                         McpError::internal_error(
                             format!("Failed to execute pueue wait: {}", e),
                             None,
@@ -370,37 +509,42 @@ impl PueueTools {
             "pueue_remove",
             Some(serde_json::json!({"task_ids": &task_ids})),
             || async {
-                with_timeout(&self.audit, "pueue_remove", 30, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("remove");
-
-                    for id in task_ids.split(',') {
-                        cmd.arg(id.trim());
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue remove: {}", e),
-                            None,
-                        )
-                    })?;
+                with_timeout(
+                    &self.audit,
+                    "pueue_remove",
+                    self.config.timeout("pueue_remove", 30),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("remove");
+
+                        for id in task_ids.split(',') {
+                            cmd.arg(id.trim());
+                        }
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pueue remove failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pueue remove: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pueue remove failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let mut result_text = stdout.to_string();
-                    if result_text.is_empty() {
-                        result_text = format!("Task(s) {} removed successfully", task_ids);
-                    }
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let mut result_text = stdout.to_string();
+                        if result_text.is_empty() {
+                            result_text = format!("Task(s) {} removed successfully", task_ids);
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result_text)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+                    },
+                )
                 .await
             },
         )
@@ -419,37 +563,42 @@ impl PueueTools {
 
         // Wrap tool logic with security
         audit_tool_execution(&self.audit, "pueue_clean", None, || async {
-            with_timeout(&self.audit, "pueue_clean", 30, || async {
-                let output = tokio::process::Command::new("nix")
-                    .arg("run")
-                    .arg("nixpkgs#pueue")
-                    .arg("--")
-                    .arg("clean")
-                    .output()
-                    .await
-                    .map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue clean: {}", e),
-                            None,
-                        )
-                    })?;
+            with_timeout(
+                &self.audit,
+                "pueue_clean",
+                self.config.timeout("pueue_clean", 30),
+                || async {
+                    let output = tokio::process::Command::new("nix")
+                        .arg("run")
+                        .arg("nixpkgs#pueue")
+                        .arg("--")
+                        .arg("clean")
+                        .group_output()
+                        .await
+                        .map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pueue clean: {}", e),
+                                None,
+                            )
+                        })?;
 
-                if !output.status.success() {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return Err(McpError::internal_error(
-                        format!("pueue clean failed: {}", stderr),
-                        None,
-                    ));
-                }
+                    if !output.status.success() {
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+                        return Err(McpError::internal_error(
+                            format!("pueue clean failed: {}", stderr),
+                            None,
+                        ));
+                    }
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let mut result_text = stdout.to_string();
-                if result_text.is_empty() {
-                    result_text = "Finished tasks cleaned successfully".to_string();
-                }
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let mut result_text = stdout.to_string();
+                    if result_text.is_empty() {
+                        result_text = "Finished tasks cleaned successfully".to_string();
+                    }
 
-                Ok(CallToolResult::success(vec![Content::text(result_text)]))
-            })
+                    Ok(CallToolResult::success(vec![Content::text(result_text)]))
+                },
+            )
             .await
         })
         .await
@@ -471,41 +620,46 @@ impl PueueTools {
             "pueue_pause",
             Some(serde_json::json!({"task_ids": &task_ids})),
             || async {
-                with_timeout(&self.audit, "pueue_pause", 30, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("pause");
-
-                    if let Some(ids) = task_ids {
-                        for id in ids.split(',') {
-                            cmd.arg(id.trim());
+                with_timeout(
+                    &self.audit,
+                    "pueue_pause",
+                    self.config.timeout("pueue_pause", 30),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("pause");
+
+                        if let Some(ids) = task_ids {
+                            for id in ids.split(',') {
+                                cmd.arg(id.trim());
+                            }
+                        } else {
+                            cmd.arg("--all");
                         }
-                    } else {
-                        cmd.arg("--all");
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue pause: {}", e),
-                            None,
-                        )
-                    })?;
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pueue pause failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pueue pause: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pueue pause failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let mut result_text = stdout.to_string();
-                    if result_text.is_empty() {
-                        result_text = "Task(s) paused successfully".to_string();
-                    }
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let mut result_text = stdout.to_string();
+                        if result_text.is_empty() {
+                            result_text = "Task(s) paused successfully".to_string();
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result_text)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+                    },
+                )
                 .await
             },
         )
@@ -528,44 +682,95 @@ impl PueueTools {
             "pueue_start",
             Some(serde_json::json!({"task_ids": &task_ids})),
             || async {
-                with_timeout(&self.audit, "pueue_start", 30, || async {
-                    let mut cmd = tokio::process::Command::new("nix");
-                    cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("start");
-
-                    if let Some(ids) = task_ids {
-                        for id in ids.split(',') {
-                            cmd.arg(id.trim());
+                with_timeout(
+                    &self.audit,
+                    "pueue_start",
+                    self.config.timeout("pueue_start", 30),
+                    || async {
+                        let mut cmd = tokio::process::Command::new("nix");
+                        cmd.arg("run").arg("nixpkgs#pueue").arg("--").arg("start");
+
+                        if let Some(ids) = task_ids {
+                            for id in ids.split(',') {
+                                cmd.arg(id.trim());
+                            }
+                        } else {
+                            cmd.arg("--all");
                         }
-                    } else {
-                        cmd.arg("--all");
-                    }
-
-                    let output = cmd.output().await.map_err(|e| {
-                        McpError::internal_error(
-                            format!("Failed to execute pueue start: {}", e),
-                            None,
-                        )
-                    })?;
 
-                    if !output.status.success() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        return Err(McpError::internal_error(
-                            format!("pueue start failed: {}", stderr),
-                            None,
-                        ));
-                    }
+                        let output = cmd.group_output().await.map_err(|e| {
+                            McpError::internal_error(
+                                format!("Failed to execute pueue start: {}", e),
+                                None,
+                            )
+                        })?;
+
+                        if !output.status.success() {
+                            let stderr = String::from_utf8_lossy(&output.stderr);
+                            return Err(McpError::internal_error(
+                                format!("pueue start failed: {}", stderr),
+                                None,
+                            ));
+                        }
 
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let mut result_text = stdout.to_string();
-                    if result_text.is_empty() {
-                        result_text = "Task(s) started successfully".to_string();
-                    }
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let mut result_text = stdout.to_string();
+                        if result_text.is_empty() {
+                            result_text = "Task(s) started successfully".to_string();
+                        }
 
-                    Ok(CallToolResult::success(vec![Content::text(result_text)]))
-                })
+                        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+                    },
+                )
                 .await
             },
         )
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pueue_status_formats() {
+        let status = serde_json::json!({
+            "tasks": {
+                "2": {"id": 2, "command": "make", "path": "/src", "label": null, "group": "default",
+                      "status": {"Done": {"start": "t0", "end": "t1", "result": {"Failed": 1}}}},
+                "0": {"id": 0, "command": "sleep 60", "path": "/tmp", "label": "nap", "group": "default",
+                      "status": "Running"},
+                "1": {"id": 1, "original_command": "ls", "group": "default",
+                      "status": {"Done": "Success"}}
+            }
+        });
+
+        let result = parse_pueue_status(&status, None);
+        let summary: Vec<_> = result
+            .tasks
+            .iter()
+            .map(|t| {
+                (
+                    t.id,
+                    t.command.as_str(),
+                    t.status.as_str(),
+                    t.result.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "sleep 60", "Running", None),
+                (1, "ls", "Done", Some("Success")),
+                (2, "make", "Done", Some("Failed (1)")),
+            ]
+        );
+        assert_eq!(result.tasks[0].label.as_deref(), Some("nap"));
+
+        let filtered = parse_pueue_status(&status, Some(&[2]));
+        assert_eq!(filtered.tasks.len(), 1);
+        assert_eq!(filtered.tasks[0].id, 2);
+    }
+}
@@ -1,8 +1,9 @@
-//! Parameter types for process management MCP tools.
+//! Parameter and result types for process management MCP tools.
 //!
 //! This module defines parameter types for managing background tasks (pueue)
 //! and interactive sessions (pexpect). Each type corresponds to a specific
-//! operation and includes field-level documentation with examples.
+//! operation and includes field-level documentation with examples. Tools with
+//! an output schema also have a result type.
 
 use rmcp::schemars;
 
@@ -265,3 +266,35 @@ pub struct PueueStartArgs {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub task_ids: Option<String>,
 }
+
+// ===== Result Types =====
+
+/// Structured result of [`PueueTools::pueue_status`](crate::process::PueueTools::pueue_status).
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PueueStatusResult {
+    /// Tasks in the queue, ordered by ID
+    pub tasks: Vec<PueueTask>,
+}
+
+/// A single task in the pueue queue.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct PueueTask {
+    /// Task ID
+    pub id: u64,
+    /// Command being run
+    pub command: String,
+    /// Task status (e.g., "Queued", "Running", "Paused", "Done")
+    pub status: String,
+    /// Outcome of a finished task (e.g., "Success", "Failed (1)")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// Task label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Group the task belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Working directory of the task
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
@@ -16,6 +16,12 @@ impl NixPrompts {
     }
 }
 
+impl Default for NixPrompts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[prompt_router]
 impl NixPrompts {
     /// Generate a nix flake template based on requirements
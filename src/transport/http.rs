@@ -0,0 +1,384 @@
+//! Streamable HTTP transport for the MCP server.
+//!
+//! Serves [`NixServer`] using the MCP streamable-HTTP protocol (JSON-RPC over
+//! HTTP POST with SSE response streams). Each client that sends an
+//! `initialize` request is assigned an `Mcp-Session-Id`, and all subsequent
+//! requests carrying that header are routed to the same session.
+//!
+//! # Access Control
+//!
+//! Tools such as `run_in_shell` and the Clan deploy tools execute commands on
+//! the host, so every request passes a guard first:
+//!
+//! - Requests with an `Origin` header are rejected with `403` unless the
+//!   origin is listed in [`HttpConfig::allowed_origins`]. This stops web
+//!   pages from reaching the server through DNS rebinding.
+//! - When [`HttpConfig::bearer_token`] is set, requests must carry
+//!   `Authorization: Bearer <token>` or are rejected with `401`.
+//!
+//! [`serve`] refuses to listen on a non-loopback address without a token.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use onix_mcp::common::nix_server::NixServer;
+//! use onix_mcp::transport::http::{serve, HttpConfig};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let config = HttpConfig {
+//!     bind: "127.0.0.1:8080".parse()?,
+//!     ..HttpConfig::default()
+//! };
+//!
+//! serve(NixServer::new(), config, async {
+//!     let _ = tokio::signal::ctrl_c().await;
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::nix_server::NixServer;
+use crate::common::security::AuditLogger;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use rmcp::transport::streamable_http_server::{
+    session::local::{LocalSessionManager, SessionConfig},
+    StreamableHttpServerConfig, StreamableHttpService,
+};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configuration for the streamable HTTP transport.
+#[derive(Clone)]
+pub struct HttpConfig {
+    /// Address to listen on (e.g., `127.0.0.1:8080`, `0.0.0.0:8080`)
+    pub bind: SocketAddr,
+    /// URL path the MCP endpoint is mounted under (default: `/mcp`)
+    pub path: String,
+    /// Interval for SSE keep-alive pings (None disables pings)
+    pub sse_keep_alive: Option<Duration>,
+    /// Close sessions that have been idle for this long (None keeps them forever)
+    pub session_idle_timeout: Option<Duration>,
+    /// Token clients must present as `Authorization: Bearer <token>` (None disables auth)
+    pub bearer_token: Option<String>,
+    /// Values of the `Origin` header that are accepted (e.g., `https://example.com`)
+    pub allowed_origins: Vec<String>,
+}
+
+impl HttpConfig {
+    /// Check that the configuration is safe to serve.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token is empty, or if `bind` is not a loopback
+    /// address and no token is configured.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match &self.bearer_token {
+            Some(token) if token.is_empty() => anyhow::bail!("the HTTP bearer token is empty"),
+            Some(_) => Ok(()),
+            None if self.bind.ip().is_loopback() => Ok(()),
+            None => anyhow::bail!(
+                "refusing to serve HTTP on non-loopback address {} without a bearer token",
+                self.bind
+            ),
+        }
+    }
+}
+
+impl std::fmt::Debug for HttpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpConfig")
+            .field("bind", &self.bind)
+            .field("path", &self.path)
+            .field("sse_keep_alive", &self.sse_keep_alive)
+            .field("session_idle_timeout", &self.session_idle_timeout)
+            .field(
+                "bearer_token",
+                &self.bearer_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("allowed_origins", &self.allowed_origins)
+            .finish()
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            path: "/mcp".to_string(),
+            sse_keep_alive: Some(Duration::from_secs(15)),
+            session_idle_timeout: Some(Duration::from_secs(30 * 60)),
+            bearer_token: None,
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+/// Build the axum router exposing `server` under `config.path`.
+///
+/// Every new MCP session receives [a clone](NixServer::for_session) of `server`,
+/// so the tool registry, caches and audit logger are shared between all
+/// connected clients. Requests pass the [access guard](self#access-control)
+/// before reaching the MCP service.
+pub fn router(server: NixServer, config: &HttpConfig) -> axum::Router {
+    let guard = Guard {
+        bearer_token: config.bearer_token.clone().map(Arc::from),
+        allowed_origins: config.allowed_origins.clone().into(),
+        audit: server.audit().clone(),
+    };
+
+    let session_manager = LocalSessionManager {
+        session_config: SessionConfig {
+            keep_alive: config.session_idle_timeout,
+            ..SessionConfig::default()
+        },
+        ..LocalSessionManager::default()
+    };
+
+    let service = StreamableHttpService::new(
+        move || Ok(server.for_session()),
+        Arc::new(session_manager),
+        StreamableHttpServerConfig {
+            sse_keep_alive: config.sse_keep_alive,
+            stateful_mode: true,
+        },
+    );
+
+    axum::Router::new()
+        .nest_service(&config.path, service)
+        .layer(middleware::from_fn_with_state(guard, check_access))
+}
+
+/// Credentials and origins accepted by [`check_access`].
+#[derive(Clone)]
+struct Guard {
+    bearer_token: Option<Arc<str>>,
+    allowed_origins: Arc<[String]>,
+    audit: Arc<AuditLogger>,
+}
+
+impl Guard {
+    /// Reason to reject a request with `headers`, with its status code.
+    fn reject(&self, headers: &HeaderMap) -> Option<(StatusCode, &'static str)> {
+        if let Some(origin) = headers.get(header::ORIGIN) {
+            let allowed = origin.to_str().is_ok_and(|origin| {
+                let origin = origin.trim_end_matches('/');
+                self.allowed_origins
+                    .iter()
+                    .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+            });
+            if !allowed {
+                return Some((StatusCode::FORBIDDEN, "origin not allowed"));
+            }
+        }
+
+        if let Some(token) = &self.bearer_token {
+            let presented = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !presented.is_some_and(|presented| constant_time_eq(presented, token)) {
+                return Some((StatusCode::UNAUTHORIZED, "missing or invalid bearer token"));
+            }
+        }
+        None
+    }
+}
+
+/// Middleware rejecting requests that fail the [`Guard`].
+async fn check_access(State(guard): State<Guard>, request: Request, next: Next) -> Response {
+    match guard.reject(request.headers()) {
+        Some((status, reason)) => {
+            guard
+                .audit
+                .log_auth_event(false, &format!("HTTP request rejected: {}", reason));
+            let mut response = (status, reason).into_response();
+            if status == StatusCode::UNAUTHORIZED {
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Bearer"),
+                );
+            }
+            response
+        }
+        None => next.run(request).await,
+    }
+}
+
+/// Compare `a` and `b` in time independent of where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Serve `server` over streamable HTTP until `shutdown` resolves.
+///
+/// # Errors
+///
+/// Returns an error if the configuration fails [`HttpConfig::validate`], the
+/// listener cannot be bound or the HTTP server fails.
+pub async fn serve<F>(server: NixServer, config: HttpConfig, shutdown: F) -> anyhow::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    config.validate()?;
+    let listener = tokio::net::TcpListener::bind(config.bind).await?;
+    let local_addr = listener.local_addr()?;
+
+    tracing::info!(
+        address = %local_addr,
+        path = %config.path,
+        auth = config.bearer_token.is_some(),
+        "Serving MCP over streamable HTTP"
+    );
+
+    axum::serve(listener, router(server, &config))
+        .with_graceful_shutdown(shutdown)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_config_default() {
+        let config = HttpConfig::default();
+        assert_eq!(config.path, "/mcp");
+        assert!(config.bind.ip().is_loopback());
+        assert!(config.sse_keep_alive.is_some());
+    }
+
+    /// Serve `config` on an ephemeral loopback port.
+    async fn spawn(config: HttpConfig) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(NixServer::new(), &config);
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (addr, handle)
+    }
+
+    /// Send an `initialize` request with `headers` and return the raw response
+    /// once the result arrived or the connection closed.
+    async fn initialize(addr: SocketAddr, headers: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "0.0.0"}
+            }
+        })
+        .to_string();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST /mcp HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Accept: application/json, text/event-stream\r\nContent-Length: {}\r\n\
+             {}Connection: close\r\n\r\n{}",
+            addr,
+            body.len(),
+            headers,
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 4096];
+        // Read until the initialize result has arrived (the SSE stream stays open)
+        while !String::from_utf8_lossy(&response).contains("serverInfo") {
+            let n = tokio::time::timeout(Duration::from_secs(10), stream.read(&mut buf))
+                .await
+                .expect("timed out waiting for initialize response")
+                .unwrap();
+            if n == 0 {
+                break;
+            }
+            response.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_http_serve_initialize() {
+        let (addr, handle) = spawn(HttpConfig::default()).await;
+
+        let response = initialize(addr, "").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.to_lowercase().contains("mcp-session-id"));
+        assert!(response.contains("serverInfo"));
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_http_bearer_token_required() {
+        let (addr, handle) = spawn(HttpConfig {
+            bearer_token: Some("s3cret".to_string()),
+            ..HttpConfig::default()
+        })
+        .await;
+
+        let response = initialize(addr, "").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        assert!(response.to_lowercase().contains("www-authenticate: bearer"));
+
+        let response = initialize(addr, "Authorization: Bearer wrong\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+
+        let response = initialize(addr, "Authorization: Bearer s3cret\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_http_origin_allowlist() {
+        let (addr, handle) = spawn(HttpConfig {
+            allowed_origins: vec!["https://tools.example.com".to_string()],
+            ..HttpConfig::default()
+        })
+        .await;
+
+        let response = initialize(addr, "Origin: http://attacker.example\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        let response = initialize(addr, "Origin: https://tools.example.com\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        handle.abort();
+    }
+
+    #[test]
+    fn test_non_loopback_bind_requires_token() {
+        let mut config = HttpConfig {
+            bind: "0.0.0.0:8080".parse().unwrap(),
+            ..HttpConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.bearer_token = Some(String::new());
+        assert!(config.validate().is_err());
+
+        config.bearer_token = Some("s3cret".to_string());
+        assert!(config.validate().is_ok());
+        assert!(!format!("{:?}", config).contains("s3cret"));
+        assert!(HttpConfig::default().validate().is_ok());
+    }
+}
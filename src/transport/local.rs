@@ -0,0 +1,129 @@
+//! In-process transport for driving [`NixServer`] from the command line.
+//!
+//! [`connect`] runs a server session and an MCP client on the two ends of an
+//! in-memory duplex pipe. Requests sent through the returned client follow the
+//! exact same path as requests from a real MCP client (tool router, security
+//! validation, audit logging), which makes it suitable for reproducing agent
+//! behaviour from scripts.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use onix_mcp::common::nix_server::NixServer;
+//! use onix_mcp::transport::local;
+//! use rmcp::model::CallToolRequestParam;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let client = local::connect(NixServer::new()).await?;
+//!
+//! let result = client
+//!     .call_tool(CallToolRequestParam {
+//!         name: "get_closure_size".into(),
+//!         arguments: serde_json::json!({"package": "nixpkgs#hello"})
+//!             .as_object()
+//!             .cloned(),
+//!     })
+//!     .await?;
+//! println!("{:?}", result);
+//!
+//! client.cancel().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::nix_server::NixServer;
+use rmcp::service::RunningService;
+use rmcp::{RoleClient, ServiceExt};
+
+/// Buffer size of the in-memory pipe between client and server.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Start an in-process MCP session for `server` and return a connected client.
+///
+/// # Errors
+///
+/// Returns an error if the MCP initialization handshake fails.
+pub async fn connect(server: NixServer) -> anyhow::Result<RunningService<RoleClient, ()>> {
+    let (server_io, client_io) = tokio::io::duplex(PIPE_CAPACITY);
+
+    tokio::spawn(async move {
+        match server.serve(server_io).await {
+            Ok(service) => {
+                let _ = service.waiting().await;
+            }
+            Err(e) => tracing::warn!("In-process MCP session failed: {:?}", e),
+        }
+    });
+
+    Ok(().serve(client_io).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::{CallToolRequestParam, ReadResourceRequestParam, ResourceContents};
+
+    #[tokio::test]
+    async fn test_local_list_tools() {
+        let client = connect(NixServer::new()).await.unwrap();
+        let tools = client.list_all_tools().await.unwrap();
+
+        assert!(tools.iter().any(|t| t.name == "search_packages"));
+        assert!(tools.iter().any(|t| t.name == "get_closure_size"));
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_read_resource() {
+        let client = connect(NixServer::new()).await.unwrap();
+        let result = client
+            .read_resource(ReadResourceRequestParam {
+                uri: "nix://commands/common".to_string(),
+            })
+            .await
+            .unwrap();
+
+        match &result.contents[0] {
+            ResourceContents::TextResourceContents { text, .. } => {
+                assert!(text.contains("nix"));
+            }
+            other => panic!("unexpected resource contents: {:?}", other),
+        }
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_call_tool_validation_error() {
+        let client = connect(NixServer::new()).await.unwrap();
+        let result = client
+            .call_tool(CallToolRequestParam {
+                name: "search_packages".into(),
+                arguments: serde_json::json!({"query": "foo; rm -rf /"})
+                    .as_object()
+                    .cloned(),
+            })
+            .await;
+
+        // Validation happens server-side, exactly as for a remote client
+        assert!(result.is_err());
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_call_unknown_tool() {
+        let client = connect(NixServer::new()).await.unwrap();
+        let result = client
+            .call_tool(CallToolRequestParam {
+                name: "does_not_exist".into(),
+                arguments: None,
+            })
+            .await;
+
+        assert!(result.is_err());
+
+        client.cancel().await.unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+//! Network transports for serving [`NixServer`](crate::common::nix_server::NixServer).
+//!
+//! The default transport is stdio, which `main.rs` wires up directly via
+//! `rmcp::transport::stdio`. This module contains the additional transports
+//! that let a single long-running server be shared by several MCP clients.
+//!
+//! # Modules
+//!
+//! - `http` - Streamable HTTP (SSE) transport with per-client sessions
+//!   (requires the `server-side-http` feature)
+//! - [`unix`] - Unix domain socket transport with systemd socket activation
+//! - [`local`] - In-process client/server pair used by the CLI subcommands
+//!
+//! # Session Model
+//!
+//! Every connected client gets its own MCP session backed by a clone of a
+//! single template `NixServer`. Clones share the [`ToolRegistry`], the
+//! [`CacheRegistry`] and the [`AuditLogger`], so expensive cached results are
+//! reused across the whole team while protocol state stays per-client.
+//!
+//! [`ToolRegistry`]: crate::common::tool_registry::ToolRegistry
+//! [`CacheRegistry`]: crate::common::cache_registry::CacheRegistry
+//! [`AuditLogger`]: crate::common::security::AuditLogger
+
+#[cfg(feature = "server-side-http")]
+pub mod http;
+pub mod local;
+#[cfg(unix)]
+pub mod unix;
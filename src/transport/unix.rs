@@ -0,0 +1,259 @@
+//! Unix domain socket transport with systemd socket activation.
+//!
+//! Each accepted connection is served as an independent MCP session over the
+//! stream (newline-delimited JSON-RPC, the same framing as stdio), backed by a
+//! clone of the template [`NixServer`].
+//!
+//! # Socket Activation
+//!
+//! When started by systemd with a `.socket` unit (`Accept=false`), the
+//! listening socket is inherited as file descriptor 3 and announced via the
+//! `LISTEN_PID`/`LISTEN_FDS` environment variables. [`listener_from_env`]
+//! picks it up, and [`serve_listener`] sends `READY=1` to the service manager
+//! once it starts accepting connections (required for `Type=notify`).
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use onix_mcp::common::nix_server::NixServer;
+//! use onix_mcp::transport::unix;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let shutdown = async {
+//!     let _ = tokio::signal::ctrl_c().await;
+//! };
+//!
+//! match unix::listener_from_env()? {
+//!     Some(listener) => unix::serve_listener(NixServer::new(), listener, shutdown).await?,
+//!     None => unix::serve(NixServer::new(), "/run/onix-mcp/socket", shutdown).await?,
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::common::nix_server::NixServer;
+use rmcp::ServiceExt;
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::UnixListener;
+
+/// File mode applied to sockets bound by [`serve`] (matches `SocketMode` in nix/module.nix).
+pub const SOCKET_MODE: u32 = 0o660;
+
+/// Take the listening socket passed by systemd socket activation, if any.
+///
+/// Returns `Ok(None)` when the process was not socket-activated. The
+/// activation environment variables are unset so child processes (nix,
+/// clan, ...) do not mistake the descriptor for their own.
+///
+/// # Errors
+///
+/// Returns an error if `LISTEN_FDS` is malformed or the descriptor cannot be
+/// registered with the tokio reactor.
+pub fn listener_from_env() -> io::Result<Option<UnixListener>> {
+    let Some(fd) = sd_notify::listen_fds()?.next() else {
+        return Ok(None);
+    };
+
+    // SAFETY: systemd guarantees the descriptor is open and owned by this
+    // process; listen_fds() only yields it once since the env vars are unset.
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true)?;
+
+    tracing::info!(fd, "Using socket-activated listener from systemd");
+    UnixListener::from_std(listener).map(Some)
+}
+
+/// Bind a Unix socket at `path` and serve MCP sessions until `shutdown` resolves.
+///
+/// A stale socket file left behind by a previous run is replaced; any other
+/// kind of file at `path` is treated as an error. The socket file is removed
+/// again on shutdown.
+///
+/// # Errors
+///
+/// Returns an error if the socket cannot be bound.
+pub async fn serve<P, F>(server: NixServer, path: P, shutdown: F) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+    F: Future<Output = ()> + Send,
+{
+    let path = path.as_ref();
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(SOCKET_MODE))?;
+
+    tracing::info!(path = %path.display(), "Serving MCP over Unix socket");
+
+    let result = serve_listener(server, listener, shutdown).await;
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+/// Pause after an accept error caused by resource exhaustion.
+const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Whether an accept error only concerns the connection being accepted.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Accept connections on `listener` until `shutdown` resolves.
+///
+/// Every connection is handled on its own task with a
+/// [session clone](NixServer::for_session) of `server`, so
+/// tool and cache state is shared while MCP sessions stay independent.
+///
+/// Failed accepts are logged and retried, after a pause if the process is
+/// out of resources (file descriptors, memory), so one bad accept does not
+/// stop the server.
+pub async fn serve_listener<F>(
+    server: NixServer,
+    listener: UnixListener,
+    shutdown: F,
+) -> anyhow::Result<()>
+where
+    F: Future<Output = ()> + Send,
+{
+    // Tell systemd we are ready (no-op when NOTIFY_SOCKET is unset)
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        tracing::warn!("Failed to notify service manager: {}", e);
+    }
+
+    tokio::pin!(shutdown);
+    let mut connection_id: u64 = 0;
+
+    loop {
+        let stream = tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    if !is_connection_error(&e) {
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    }
+                    continue;
+                }
+            },
+        };
+
+        connection_id += 1;
+        let server = server.for_session();
+
+        tokio::spawn(async move {
+            tracing::info!(connection_id, "Accepted MCP connection");
+            match server.serve(stream).await {
+                Ok(service) => {
+                    if let Err(e) = service.waiting().await {
+                        tracing::warn!(connection_id, "MCP session ended with error: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(connection_id, "MCP session failed to initialize: {:?}", e);
+                }
+            }
+            tracing::info!(connection_id, "MCP connection closed");
+        });
+    }
+
+    let _ = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    async fn initialize(path: &Path) -> String {
+        let stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        let (read, mut write) = stream.into_split();
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "0.0.0"}
+            }
+        });
+        write
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        BufReader::new(read).read_line(&mut line).await.unwrap();
+        line
+    }
+
+    #[test]
+    fn test_listener_from_env_not_activated() {
+        // The test runner is never socket-activated
+        assert!(listener_from_env().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unix_serve_multiple_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("socket");
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let serve_path = path.clone();
+        let handle = tokio::spawn(async move {
+            serve(NixServer::new(), &serve_path, async {
+                let _ = rx.await;
+            })
+            .await
+        });
+
+        // Wait for the socket to appear
+        for _ in 0..100 {
+            if path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+
+        let (first, second) = tokio::join!(initialize(&path), initialize(&path));
+        assert!(first.contains("serverInfo"), "{}", first);
+        assert!(second.contains("serverInfo"), "{}", second);
+
+        tx.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_unix_serve_rejects_non_socket_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("not-a-socket");
+        std::fs::write(&path, "data").unwrap();
+
+        let result = serve(NixServer::new(), &path, async {}).await;
+        assert!(result.is_err());
+        assert!(path.exists());
+    }
+}
@@ -2,6 +2,8 @@
 /// These tests verify that tools properly handle error conditions
 /// and return appropriate error messages without panicking
 use onix_mcp::common::cache_registry::CacheRegistry;
+use onix_mcp::common::progress::ProgressReporter;
+use onix_mcp::common::roots::Workspace;
 use onix_mcp::common::security::audit_logger;
 use onix_mcp::nix::{BuildTools, DevelopTools, FlakeTools, InfoTools, PackageTools, QualityTools};
 use onix_mcp::process::{PexpectTools, PueueTools};
@@ -76,9 +78,12 @@ async fn test_get_package_info_empty_package() {
     let tools = PackageTools::new(audit, caches);
 
     let result = tools
-        .get_package_info(Parameters(onix_mcp::nix::GetPackageInfoArgs {
-            package: "".to_string(),
-        }))
+        .get_package_info(
+            Parameters(onix_mcp::nix::GetPackageInfoArgs {
+                package: "".to_string(),
+            }),
+            Workspace::default(),
+        )
         .await;
 
     assert!(result.is_err(), "Empty package name should be rejected");
@@ -126,10 +131,14 @@ async fn test_nix_build_empty_package() {
     let tools = BuildTools::new(audit, caches);
 
     let result = tools
-        .nix_build(Parameters(onix_mcp::nix::NixBuildArgs {
-            package: "".to_string(),
-            dry_run: Some(true),
-        }))
+        .nix_build(
+            Parameters(onix_mcp::nix::NixBuildArgs {
+                package: "".to_string(),
+                dry_run: Some(true),
+            }),
+            ProgressReporter::disabled(),
+            Workspace::default(),
+        )
         .await;
 
     assert!(result.is_err(), "Empty package should be rejected");
@@ -142,10 +151,14 @@ async fn test_nix_build_injection_attempt() {
     let tools = BuildTools::new(audit, caches);
 
     let result = tools
-        .nix_build(Parameters(onix_mcp::nix::NixBuildArgs {
-            package: "nixpkgs#hello;rm -rf /".to_string(),
-            dry_run: Some(true),
-        }))
+        .nix_build(
+            Parameters(onix_mcp::nix::NixBuildArgs {
+                package: "nixpkgs#hello;rm -rf /".to_string(),
+                dry_run: Some(true),
+            }),
+            ProgressReporter::disabled(),
+            Workspace::default(),
+        )
         .await;
 
     assert!(result.is_err(), "Injection attempt should be rejected");
@@ -158,10 +171,13 @@ async fn test_get_closure_size_invalid_package() {
     let tools = BuildTools::new(audit, caches);
 
     let result = tools
-        .get_closure_size(Parameters(onix_mcp::nix::GetClosureSizeArgs {
-            package: "".to_string(),
-            human_readable: Some(true),
-        }))
+        .get_closure_size(
+            Parameters(onix_mcp::nix::GetClosureSizeArgs {
+                package: "".to_string(),
+                human_readable: Some(true),
+            }),
+            Workspace::default(),
+        )
         .await;
 
     assert!(result.is_err(), "Invalid package should be rejected");
@@ -174,9 +190,12 @@ async fn test_show_derivation_injection() {
     let tools = BuildTools::new(audit, caches);
 
     let result = tools
-        .show_derivation(Parameters(onix_mcp::nix::ShowDerivationArgs {
-            package: "package`whoami`".to_string(),
-        }))
+        .show_derivation(
+            Parameters(onix_mcp::nix::ShowDerivationArgs {
+                package: "package`whoami`".to_string(),
+            }),
+            Workspace::default(),
+        )
         .await;
 
     assert!(
@@ -194,9 +213,12 @@ async fn test_flake_metadata_empty_ref() {
     let tools = FlakeTools::new(audit, caches);
 
     let result = tools
-        .flake_metadata(Parameters(onix_mcp::nix::FlakeMetadataArgs {
-            flake_ref: "".to_string(),
-        }))
+        .flake_metadata(
+            Parameters(onix_mcp::nix::FlakeMetadataArgs {
+                flake_ref: "".to_string(),
+            }),
+            Workspace::default(),
+        )
         .await;
 
     assert!(result.is_err(), "Empty flake ref should be rejected");
@@ -217,9 +239,12 @@ async fn test_flake_metadata_injection() {
 
     for ref_str in malicious_refs {
         let result = tools
-            .flake_metadata(Parameters(onix_mcp::nix::FlakeMetadataArgs {
-                flake_ref: ref_str.to_string(),
-            }))
+            .flake_metadata(
+                Parameters(onix_mcp::nix::FlakeMetadataArgs {
+                    flake_ref: ref_str.to_string(),
+                }),
+                Workspace::default(),
+            )
             .await;
 
         assert!(
@@ -540,9 +565,12 @@ async fn test_null_byte_injection_various_tools() {
     // Test null byte in flake ref
     let flake_tools = FlakeTools::new(audit.clone(), caches.clone());
     let result = flake_tools
-        .flake_metadata(Parameters(onix_mcp::nix::FlakeMetadataArgs {
-            flake_ref: "nixpkgs\0poison".to_string(),
-        }))
+        .flake_metadata(
+            Parameters(onix_mcp::nix::FlakeMetadataArgs {
+                flake_ref: "nixpkgs\0poison".to_string(),
+            }),
+            Workspace::default(),
+        )
         .await;
     assert!(result.is_err(), "Null byte should be rejected in flake ref");
 